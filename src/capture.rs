@@ -0,0 +1,117 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use patchwork_dmg::video::{Image, PaletteSet, Recorder};
+use patchwork_dmg::GameBoy;
+use crate::cli::Args;
+
+/// Screenshots and recordings of the screen, taken from the emulator's framebuffer rather than the
+/// window, so they are at the LCD's own resolution times `--capture-scale` whatever size the window
+/// is, and in the palettes the screen is being drawn in. Those started from the keyboard are named
+/// after the ROM and numbered, in the capture directory.
+pub struct Capture {
+    dir: PathBuf,
+    stem: String,
+    scale: u32,
+    palettes: PaletteSet,
+    recording: Option<(Recorder, PathBuf)>,
+}
+
+impl Capture {
+    pub fn new(args: &Args, palettes: PaletteSet) -> Self {
+        let stem = args.rom().file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "rom".to_string());
+        Capture {
+            dir: args.capture_dir.clone().unwrap_or_default(),
+            stem,
+            scale: args.capture_scale,
+            palettes,
+            recording: None,
+        }
+    }
+
+    /// Change the palettes captures are coloured with, such as when the screen's are changed.
+    pub fn set_palettes(&mut self, palettes: PaletteSet) {
+        self.palettes = palettes;
+    }
+
+    /// The screen as it is now.
+    fn image(&self, gb: &GameBoy) -> Image {
+        Image::from_framebuffer(gb.framebuffer(), &self.palettes).scaled(self.scale)
+    }
+
+    /// The first path in the capture directory which isn't taken for any of the given extensions.
+    fn next_path(&self, extensions: &[&str]) -> PathBuf {
+        (1..)
+            .map(|n| self.dir.join(format!("{}-{}", self.stem, n)))
+            .find(|path| extensions.iter().all(|ext| !path.with_extension(ext).exists()))
+            .unwrap()
+            .with_extension(extensions[0])
+    }
+
+    pub fn save_screenshot(&self, gb: &GameBoy, path: &Path) -> Result<()> {
+        self.image(gb).save_png(path).with_context(|| format!("Couldn't save screenshot {}", path.display()))
+    }
+
+    /// Save a screenshot under the next free name, returning where it went.
+    pub fn screenshot(&self, gb: &GameBoy) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir).with_context(|| format!("Couldn't create capture directory {}", self.dir.display()))?;
+        let path = self.next_path(&["png"]);
+        self.save_screenshot(gb, &path)?;
+        Ok(path)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Start recording to the given path, with sound at the rate the Game Boy produces it.
+    pub fn start_recording(&mut self, gb: &GameBoy, path: &Path) -> Result<()> {
+        let recorder = Recorder::new(path, gb.sample_rate()).with_context(|| format!("Couldn't start recording to {}", path.display()))?;
+        self.recording = Some((recorder, path.to_path_buf()));
+        Ok(())
+    }
+
+    /// Finish the recording, if there is one, returning where it went.
+    pub fn stop_recording(&mut self) -> Result<Option<PathBuf>> {
+        match self.recording.take() {
+            Some((recorder, path)) => {
+                recorder.finish().with_context(|| format!("Couldn't finish recording {}", path.display()))?;
+                Ok(Some(path))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Start recording to a GIF under the next free name, or stop the recording in progress.
+    pub fn toggle_recording(&mut self, gb: &GameBoy) -> Result<()> {
+        if let Some(path) = self.stop_recording()? {
+            println!("Saved recording to {}", path.display());
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir).with_context(|| format!("Couldn't create capture directory {}", self.dir.display()))?;
+        let path = self.next_path(&["gif", "wav"]);
+        self.start_recording(gb, &path)?;
+        println!("Recording to {}", path.display());
+        Ok(())
+    }
+
+    /// Add the frame just run, and the sound that came with it, to the recording if there is one.
+    /// If writing fails, the recording is abandoned.
+    pub fn frame(&mut self, gb: &GameBoy, samples: &[f32]) -> Result<()> {
+        if !self.is_recording() {
+            return Ok(());
+        }
+        let image = self.image(gb);
+        let (recorder, path) = match &mut self.recording {
+            Some(recording) => recording,
+            None => return Ok(()),
+        };
+        let result = recorder.add_frame(&image).and_then(|()| recorder.add_audio(samples));
+        if let Err(e) = result {
+            let path = path.clone();
+            self.recording = None;
+            return Err(e).with_context(|| format!("Recording to {} stopped, as writing it failed", path.display()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use sdl2::pixels::Color;
+use patchwork_dmg::debug::parse_number;
+use patchwork_dmg::testing::{DEFAULT_SCREENSHOT_FRAMES, DEFAULT_TIMEOUT_SECONDS};
+use patchwork_dmg::video::{GBPalette, PaletteSet};
+
+/// Patchwork DMG: a Game Boy emulator.
+#[derive(Debug, Parser)]
+#[command(version, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// The ROM to run.
+    #[arg(required = true)]
+    pub rom: Option<PathBuf>,
+
+    /// A DMG boot ROM to run before the cartridge. Without one, the emulator starts from the state
+    /// the boot ROM leaves behind.
+    #[arg(long, value_name = "PATH")]
+    pub boot_rom: Option<PathBuf>,
+
+    /// How many times larger than the 160x144 LCD the window should be.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub scale: u32,
+
+    /// The colours to draw the four shades with. P cycles through these at runtime.
+    #[arg(long, value_enum, default_value_t = PaletteChoice::Grey)]
+    pub palette: PaletteChoice,
+
+    /// A file of colours to draw with instead, with separate palettes for the background and each
+    /// of the sprite palettes if wanted. It is added to the palettes P cycles through.
+    #[arg(long, value_name = "PATH")]
+    pub palette_file: Option<PathBuf>,
+
+    /// Open the tile viewer, showing the tiles in VRAM, alongside the game. V opens and closes it.
+    #[arg(long)]
+    pub tile_viewer: bool,
+
+    /// Start in fullscreen.
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Don't play any sound.
+    #[arg(long)]
+    pub mute: bool,
+
+    /// How fast to run, relative to a real Game Boy.
+    #[arg(long, default_value_t = 1.0, value_parser = parse_speed)]
+    pub speed: f64,
+
+    /// Wait for the display's vertical blank before presenting each frame, to avoid tearing. Faster
+    /// speeds then run several frames for each one shown.
+    #[arg(long)]
+    pub vsync: bool,
+
+    /// How fast to run whilst the fast-forward key (Tab) is held.
+    #[arg(long, default_value_t = 4.0, value_parser = parse_speed, value_name = "SPEED")]
+    pub fast_forward: f64,
+
+    /// How fast to run whilst the slow-motion key (`) is held.
+    #[arg(long, default_value_t = 0.25, value_parser = parse_speed, value_name = "SPEED")]
+    pub slow_motion: f64,
+
+    /// How many seconds of history to keep for rewinding (with R). 0 turns rewinding off.
+    #[arg(long, default_value_t = 10.0, value_name = "SECONDS", value_parser = parse_rewind_seconds)]
+    pub rewind_seconds: f64,
+
+    /// How many frames apart the snapshots used for rewinding are taken. Rewinding still goes back
+    /// a frame at a time, with each snapshot shown for this many frames, so larger values save
+    /// memory at the cost of a jerkier rewind.
+    #[arg(long, default_value_t = 2, value_name = "FRAMES", value_parser = clap::value_parser!(u32).range(1..))]
+    pub rewind_interval: u32,
+
+    /// Run without a window or sound, for the number of frames given by --frames.
+    #[arg(long, requires = "frames")]
+    pub headless: bool,
+
+    /// Start in the terminal debugger instead of opening a window.
+    #[arg(long, conflicts_with = "headless")]
+    pub debug: bool,
+
+    /// Log the CPU state before every instruction to this file, in the Gameboy Doctor format.
+    #[arg(long, value_name = "PATH")]
+    pub trace: Option<PathBuf>,
+
+    /// Only trace instructions at addresses in this range, e.g. 0x0100-0x3FFF.
+    #[arg(long, value_name = "START-END", value_parser = parse_address_range, requires = "trace")]
+    pub trace_range: Option<RangeInclusive<u16>>,
+
+    /// How many instructions to run before tracing starts.
+    #[arg(long, value_name = "COUNT", default_value_t = 0, requires = "trace")]
+    pub trace_skip: u64,
+
+    /// The most instructions to trace.
+    #[arg(long, value_name = "COUNT", requires = "trace")]
+    pub trace_limit: Option<u64>,
+
+    /// Make LY (0xFF44) always read 0x90 whilst tracing, as it does in Gameboy Doctor's reference
+    /// logs, so that games waiting for a line don't go out of step with them.
+    #[arg(long, requires = "trace")]
+    pub trace_stub_ly: bool,
+
+    /// Record the joypad input from power-on into this movie file, until the emulator exits.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["play_movie", "debug"])]
+    pub record_movie: Option<PathBuf>,
+
+    /// Start the movie being recorded from this save state (such as a .ss1 file) instead of
+    /// power-on.
+    #[arg(long, value_name = "STATE", requires = "record_movie")]
+    pub movie_start: Option<PathBuf>,
+
+    /// Play back a movie, reporting any frame which doesn't go as it did when it was recorded.
+    /// With --headless, the emulator stops at the end of the movie.
+    #[arg(long, value_name = "PATH", conflicts_with = "debug")]
+    pub play_movie: Option<PathBuf>,
+
+    /// How many frames to run for before exiting.
+    #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(u64).range(1..))]
+    pub frames: Option<u64>,
+
+    /// Save the screen as a PNG when the emulator exits.
+    #[arg(long, value_name = "PATH")]
+    pub screenshot: Option<PathBuf>,
+
+    /// Record from the start until the emulator exits: to an animated GIF if PATH ends in .gif,
+    /// and otherwise to raw 8-bit RGB frames. The sound is saved alongside as a WAV.
+    #[arg(long, value_name = "PATH", conflicts_with = "debug")]
+    pub record: Option<PathBuf>,
+
+    /// How many times larger than the 160x144 LCD screenshots and recordings should be.
+    #[arg(long, default_value_t = 1, value_name = "SCALE", value_parser = clap::value_parser!(u32).range(1..=16))]
+    pub capture_scale: u32,
+
+    /// Where screenshots (F12) and recordings (F11) taken from the keyboard are saved. By default
+    /// they are saved in the current directory.
+    #[arg(long, value_name = "DIR")]
+    pub capture_dir: Option<PathBuf>,
+
+    /// Where to keep battery saves. By default they are kept next to the ROM.
+    #[arg(long, value_name = "DIR")]
+    pub save_dir: Option<PathBuf>,
+
+    /// Plug in a link cable, and wait for another emulator to connect to it on this TCP address
+    /// (such as 127.0.0.1:5000) or Unix socket path before starting.
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_link_address, conflicts_with = "link_connect")]
+    pub link_listen: Option<LinkAddress>,
+
+    /// Plug in a link cable to another emulator listening on this TCP address or Unix socket path.
+    #[arg(long, value_name = "ADDRESS", value_parser = parse_link_address)]
+    pub link_connect: Option<LinkAddress>,
+
+    /// How long to wait for the other emulator to answer each byte sent over the link cable. Bytes
+    /// it is too slow to answer read as 0xFF, but the cable stays connected. It can be anywhere from
+    /// a millisecond to a minute.
+    #[arg(long, default_value = "2", value_name = "SECONDS", value_parser = parse_timeout)]
+    pub link_timeout: Duration,
+}
+
+/// Where the link cable's other end is.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkAddress {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl fmt::Display for LinkAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkAddress::Tcp(addr) => write!(f, "{}", addr),
+            #[cfg(unix)]
+            LinkAddress::Unix(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+impl Args {
+    /// The palettes P cycles through, with their names: the built-in ones, then the palette file if
+    /// there is one. Also returns which to start with.
+    pub fn palettes(&self) -> Result<(Vec<(String, PaletteSet)>, usize)> {
+        let mut palettes: Vec<(String, PaletteSet)> = PaletteChoice::value_variants().iter()
+            .map(|choice| (choice.to_possible_value().unwrap().get_name().to_string(), choice.palette()))
+            .collect();
+        let mut current = PaletteChoice::value_variants().iter().position(|&choice| choice == self.palette).unwrap();
+        if let Some(path) = &self.palette_file {
+            let palette = PaletteSet::load(path).with_context(|| format!("Couldn't load palette file {}", path.display()))?;
+            current = palettes.len();
+            palettes.push((path.display().to_string(), palette));
+        }
+        Ok((palettes, current))
+    }
+
+    /// The ROM to run. Only subcommands can be used without one, so there always is one when
+    /// running a game.
+    pub fn rom(&self) -> &Path {
+        self.rom.as_deref().expect("a ROM is required without a subcommand")
+    }
+}
+
+/// Tools which work on ROMs and logs rather than running a game.
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Disassemble a 16 KiB bank of a ROM.
+    Disasm {
+        rom: PathBuf,
+
+        /// The bank to disassemble. Bank 0 is shown at 0x0000, and any other bank at 0x4000, where
+        /// it is switched in.
+        #[arg(long, default_value_t = 0)]
+        bank: usize,
+    },
+
+    /// Run a ROM without a window, comparing its trace against a reference log (in the Gameboy
+    /// Doctor format) and stopping at the first difference.
+    TraceDiff {
+        rom: PathBuf,
+
+        /// The log to compare against.
+        reference: PathBuf,
+
+        /// How many instructions to show either side of a difference.
+        #[arg(long, default_value_t = 5)]
+        context: usize,
+
+        /// The most frames to run for, in case the emulator gets stuck.
+        #[arg(long, default_value_t = 36_000)]
+        frames: u64,
+    },
+
+    /// Run test ROMs without a window, reporting whether each passed.
+    Test {
+        #[arg(required = true)]
+        roms: Vec<PathBuf>,
+
+        /// How many seconds of emulated time each ROM is given to finish.
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_TIMEOUT_SECONDS)]
+        timeout: u64,
+
+        /// Print what each ROM sent over the serial port.
+        #[arg(long)]
+        verbose: bool,
+    },
+
+    /// Run a ROM without a window for a number of frames, then save the screen as a PNG.
+    Screenshot {
+        rom: PathBuf,
+
+        /// Where to save the screenshot.
+        #[arg(short, long, default_value = "screenshot.png")]
+        output: PathBuf,
+
+        /// How many frames to run before taking the screenshot.
+        #[arg(long, default_value_t = DEFAULT_SCREENSHOT_FRAMES)]
+        frames: u32,
+
+        /// A reference image to compare the screenshot against, pixel for pixel. If they differ,
+        /// an image showing where is saved alongside the screenshot, and the command fails.
+        #[arg(long, value_name = "PNG")]
+        reference: Option<PathBuf>,
+    },
+}
+
+/// The built-in palettes, from lightest shade to darkest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PaletteChoice {
+    /// Plain greyscale.
+    Grey,
+    /// The yellowish green of the original DMG screen.
+    Green,
+    /// The neutral tones of the Game Boy Pocket.
+    Pocket,
+    /// Pure black and white, with greys far apart, for telling the shades apart easily.
+    HighContrast,
+}
+
+impl PaletteChoice {
+    pub fn palette(self) -> PaletteSet {
+        PaletteSet::uniform(match self {
+            PaletteChoice::Grey => GBPalette::new(
+                Color::RGB(255, 255, 255),
+                Color::RGB(190, 190, 190),
+                Color::RGB(130, 130, 130),
+                Color::RGB(82, 82, 82),
+            ),
+            PaletteChoice::Green => GBPalette::new(
+                Color::RGB(155, 188, 15),
+                Color::RGB(139, 172, 15),
+                Color::RGB(48, 98, 48),
+                Color::RGB(15, 56, 15),
+            ),
+            PaletteChoice::Pocket => GBPalette::new(
+                Color::RGB(196, 207, 161),
+                Color::RGB(139, 149, 109),
+                Color::RGB(77, 83, 60),
+                Color::RGB(31, 31, 31),
+            ),
+            PaletteChoice::HighContrast => GBPalette::new(
+                Color::RGB(255, 255, 255),
+                Color::RGB(170, 170, 170),
+                Color::RGB(85, 85, 85),
+                Color::RGB(0, 0, 0),
+            ),
+        })
+    }
+}
+
+fn parse_speed(s: &str) -> Result<f64, String> {
+    let speed: f64 = s.parse().map_err(|_| format!("`{}` isn't a number", s))?;
+    if speed > 0.0 && speed <= 16.0 {
+        Ok(speed)
+    } else {
+        Err("the speed must be greater than 0 and at most 16".to_string())
+    }
+}
+
+fn parse_rewind_seconds(s: &str) -> Result<f64, String> {
+    let seconds: f64 = s.parse().map_err(|_| format!("`{}` isn't a number", s))?;
+    if seconds >= 0.0 && seconds.is_finite() {
+        Ok(seconds)
+    } else {
+        Err("the rewind history must be a finite number of seconds, 0 or more".to_string())
+    }
+}
+
+/// The shortest link cable timeout; any shorter and sockets may round it down to no timeout at all.
+const MIN_LINK_TIMEOUT: Duration = Duration::from_millis(1);
+const MAX_LINK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Parse a timeout in seconds, which has to be long enough that the socket can wait for it at all,
+/// and short enough not to leave the emulator looking hung.
+fn parse_timeout(s: &str) -> Result<Duration, String> {
+    let seconds: f64 = s.parse().map_err(|_| format!("`{}` isn't a number", s))?;
+    match Duration::try_from_secs_f64(seconds) {
+        Ok(timeout) if (MIN_LINK_TIMEOUT..=MAX_LINK_TIMEOUT).contains(&timeout) => Ok(timeout),
+        _ => Err(format!(
+            "the timeout must be between {} and {} seconds",
+            MIN_LINK_TIMEOUT.as_secs_f64(),
+            MAX_LINK_TIMEOUT.as_secs_f64(),
+        )),
+    }
+}
+
+/// Parse a link cable address: `host:port` for TCP, and anything else, such as `link.sock` or
+/// `/tmp/link`, as the path of a Unix socket.
+fn parse_link_address(s: &str) -> Result<LinkAddress, String> {
+    if s.contains(':') && !s.contains('/') {
+        return Ok(LinkAddress::Tcp(s.to_string()));
+    }
+    #[cfg(unix)]
+    return Ok(LinkAddress::Unix(PathBuf::from(s)));
+    #[cfg(not(unix))]
+    Err("expected a TCP address such as 127.0.0.1:5000".to_string())
+}
+
+/// Parse an inclusive range of addresses such as `0x0100-0x3FFF`.
+fn parse_address_range(s: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = s.split_once('-').ok_or("expected a range such as 0x0100-0x3FFF")?;
+    let start = parse_number(start.trim()).map_err(|e| e.to_string())?;
+    let end = parse_number(end.trim()).map_err(|e| e.to_string())?;
+    if start > end {
+        return Err("the start of the range is after the end".to_string());
+    }
+    Ok(start..=end)
+}
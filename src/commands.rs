@@ -0,0 +1,105 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use anyhow::{bail, Context, Result};
+use patchwork_dmg::debug::disassemble;
+use patchwork_dmg::testing::{TestResult, TestRunner};
+use patchwork_dmg::video::Image;
+use patchwork_dmg::{Cartridge, GameBoy, CYCLES_PER_FRAME};
+use crate::cli::Command;
+use crate::trace_diff::{self, Outcome};
+
+/// The size of a switchable ROM bank.
+const ROM_BANK_SIZE: usize = 0x4000;
+
+/// Run one of the subcommands.
+pub fn run(command: &Command) -> Result<()> {
+    match command {
+        Command::Disasm { rom, bank } => disasm(rom, *bank),
+        Command::TraceDiff { rom, reference, context, frames } => trace_diff(rom, reference, *context, *frames),
+        Command::Test { roms, timeout, verbose } => test(roms, *timeout, *verbose),
+        Command::Screenshot { rom, output, frames, reference } => screenshot(rom, output, *frames, reference.as_deref()),
+    }
+}
+
+/// Print the disassembly of a whole ROM bank, with each line starting with the bank number.
+fn disasm(path: &Path, bank: usize) -> Result<()> {
+    let rom = fs::read(path).with_context(|| format!("Couldn't read ROM {}", path.display()))?;
+    let banks = rom.len().div_ceil(ROM_BANK_SIZE);
+    if bank >= banks {
+        bail!("{} only has {} banks (0 to {})", path.display(), banks, banks.saturating_sub(1));
+    }
+
+    let base = if bank == 0 { 0 } else { ROM_BANK_SIZE as u16 };
+    let offset = bank * ROM_BANK_SIZE;
+    // An instruction at the end of the bank can run past it, into whatever follows in the file.
+    let read = |addr: u16| rom.get(offset + (addr - base) as usize).copied().unwrap_or(0xFF);
+    let mut addr = base;
+    while ((addr - base) as usize) < ROM_BANK_SIZE {
+        let dis = disassemble(read, addr);
+        println!("{:02X}:{}", bank, dis);
+        addr = dis.next_addr();
+    }
+    Ok(())
+}
+
+/// Compare the trace of a ROM against a reference log, failing if they differ.
+fn trace_diff(rom: &Path, reference: &Path, context: usize, frames: u64) -> Result<()> {
+    let mut gb = GameBoy::new(Cartridge::from_file(rom)?);
+    let file = File::open(reference).with_context(|| format!("Couldn't open reference log {}", reference.display()))?;
+    let outcome = trace_diff::compare(&mut gb, BufReader::new(file), context, frames * CYCLES_PER_FRAME as u64)?;
+    trace_diff::report(&outcome);
+    match outcome {
+        Outcome::Matched(_) => Ok(()),
+        _ => bail!("The traces differ"),
+    }
+}
+
+/// Run each test ROM in turn, failing if any of them didn't pass.
+fn test(roms: &[PathBuf], timeout: u64, verbose: bool) -> Result<()> {
+    let mut failed = 0;
+    for rom in roms {
+        let outcome = TestRunner::new(Cartridge::from_file(rom)?).with_timeout(timeout).run();
+        println!("{:?}: {}", outcome.result, rom.display());
+        if verbose {
+            println!("{}", outcome.serial.trim_end());
+        }
+        if outcome.result != TestResult::Passed {
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("{} of {} test ROMs didn't pass", failed, roms.len());
+    }
+    Ok(())
+}
+
+/// Save the screen after the given number of frames, and compare it against the reference image
+/// if there is one.
+fn screenshot(rom: &Path, output: &Path, frames: u32, reference: Option<&Path>) -> Result<()> {
+    let mut runner = TestRunner::new(Cartridge::from_file(rom)?);
+    runner.run_frames(frames);
+    let screenshot = runner.screenshot();
+    screenshot.save_png(output).with_context(|| format!("Couldn't save screenshot {}", output.display()))?;
+    println!("Saved screenshot to {}", output.display());
+    if runner.gameboy().cpu().is_locked() {
+        bail!("The CPU locked up on an illegal opcode");
+    }
+
+    let path = match reference {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+    let reference = Image::load_png(path).with_context(|| format!("Couldn't load reference image {}", path.display()))?;
+    match screenshot.diff(&reference) {
+        None => {
+            println!("The screenshot matches {}", path.display());
+            Ok(())
+        }
+        Some(diff) => {
+            let diff_path = output.with_extension("diff.png");
+            diff.image.save_png(&diff_path).with_context(|| format!("Couldn't save diff image {}", diff_path.display()))?;
+            bail!("{} pixels differ from {}; see {}", diff.differing, path.display(), diff_path.display())
+        }
+    }
+}
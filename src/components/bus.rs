@@ -0,0 +1,364 @@
+use crate::components::cartridge::Cartridge;
+use crate::components::dmg_apu::{APU, APU_END, APU_START};
+use crate::components::dmg_cpu::{IE_ADDR, IF_ADDR, SERIAL_INTERRUPT, TIMER_INTERRUPT};
+use crate::components::dmg_ppu::{PPU, LY_ADDR, OAM_SIZE};
+use crate::components::joypad::{Joypad, P1_ADDR};
+use crate::components::serial::{Serial, SB_ADDR, SC_ADDR};
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::components::timer::{Timer, DIV_ADDR, TAC_ADDR};
+
+/// Writing to this register starts an OAM DMA transfer from the page given by the value written.
+pub const DMA_ADDR: u16 = 0xFF46;
+/// Writing a non-zero value to this register unmaps the boot ROM until the next reset.
+pub const BOOT_ROM_DISABLE_ADDR: u16 = 0xFF50;
+
+/// An OAM DMA transfer in progress.
+struct Dma {
+    /// The address bytes are being copied from.
+    source: u16,
+    /// The next byte of OAM to be written.
+    index: usize,
+    /// M-cycles left before the transfer starts.
+    delay: u8,
+}
+
+/// # Memory bus
+/// Everything the CPU can reach through the address space: memory, the memory-mapped I/O
+/// registers and the components behind them.
+///
+/// The bus is also what keeps those components in step with the CPU. The CPU calls `tick` once for
+/// every M-cycle it spends, whether that cycle accesses memory or not, so the timer, PPU, APU,
+/// serial port and DMA see every read and write happen at the right time.
+pub struct Bus {
+    /// The backing store for everything not handled by a component (WRAM, HRAM, IE and IF, and any
+    /// I/O register which isn't emulated yet). Without a cartridge, ROM and external RAM are also
+    /// read from here.
+    pub memory: Box<[u8]>,
+    /// The cartridge, mapped to 0x0000-0x7FFF and 0xA000-0xBFFF.
+    pub cartridge: Option<Cartridge>,
+    /// The joypad, mapped to P1 (0xFF00).
+    pub joypad: Joypad,
+    /// The serial port, mapped to SB (0xFF01) and SC (0xFF02).
+    pub serial: Serial,
+    /// The timer, mapped to 0xFF04-0xFF07.
+    pub timer: Timer,
+    /// The PPU, which owns VRAM, OAM and the LCD registers.
+    pub ppu: PPU,
+    /// The APU, mapped to 0xFF10-0xFF3F.
+    pub apu: APU,
+    /// The boot ROM, if one has been supplied.
+    boot_rom: Option<Vec<u8>>,
+    /// Whether the boot ROM is currently mapped over 0x0000-0x00FF.
+    boot_rom_mapped: bool,
+    /// The last value written to the DMA register.
+    dma_reg: u8,
+    /// The OAM DMA transfer in progress, if any.
+    dma: Option<Dma>,
+    /// Whether the whole address space is plain memory, with no components mapped in or ticked.
+    /// This is only for running the CPU on its own, as per-instruction test suites expect.
+    pub(crate) flat: bool,
+    /// Whether LY reads as 0x90 whatever line is being drawn, as it does in the emulator Gameboy
+    /// Doctor's reference logs are made with.
+    pub(crate) stub_ly: bool,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus {
+            memory: vec![0; 0x10000].into_boxed_slice(),
+            cartridge: None,
+            joypad: Joypad::new(),
+            serial: Serial::new(),
+            timer: Timer::new(),
+            ppu: PPU::new(),
+            apu: APU::new(),
+            boot_rom: None,
+            boot_rom_mapped: false,
+            dma_reg: 0xFF,
+            dma: None,
+            flat: false,
+            stub_ly: false,
+        }
+    }
+
+    /// Map a boot ROM over the start of the address space.
+    pub fn map_boot_rom(&mut self, rom: Vec<u8>) {
+        self.boot_rom = Some(rom);
+        self.boot_rom_mapped = true;
+    }
+
+    /// Read a single byte from the address space, taking memory-mapped I/O into account.
+    /// This has no side effects, so is also safe to use for inspecting memory.
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            _ if self.flat => self.memory[addr as usize],
+            0x0000..=0x00FF if self.boot_rom_mapped => match &self.boot_rom {
+                Some(rom) => rom[addr as usize],
+                None => self.memory[addr as usize],
+            },
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => match &self.cartridge {
+                Some(cartridge) => cartridge.read(addr),
+                None => self.memory[addr as usize],
+            },
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.read(addr),
+            // Echo RAM mirrors WRAM.
+            0xE000..=0xFDFF => self.memory[(addr - 0x2000) as usize],
+            // Nothing is mapped here.
+            0xFEA0..=0xFEFF => 0xFF,
+            P1_ADDR => self.joypad.read(),
+            SB_ADDR | SC_ADDR => self.serial.read(addr),
+            DIV_ADDR..=TAC_ADDR => self.timer.read(addr),
+            // Only the lower five bits of IF are used; the rest read back as set.
+            IF_ADDR => self.memory[addr as usize] | 0b1110_0000,
+            APU_START..=APU_END => self.apu.read(addr),
+            DMA_ADDR => self.dma_reg,
+            LY_ADDR if self.stub_ly => 0x90,
+            0xFF40..=0xFF4B => self.ppu.read(addr),
+            BOOT_ROM_DISABLE_ADDR => 0xFF,
+            _ => self.memory[addr as usize],
+        }
+    }
+
+    /// Write a single byte to the address space, taking memory-mapped I/O into account.
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            _ if self.flat => self.memory[addr as usize] = val,
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => match &mut self.cartridge {
+                Some(cartridge) => cartridge.write(addr, val),
+                None => self.memory[addr as usize] = val,
+            },
+            0x8000..=0x9FFF | 0xFE00..=0xFE9F => self.ppu.write(addr, val),
+            0xE000..=0xFDFF => self.memory[(addr - 0x2000) as usize] = val,
+            0xFEA0..=0xFEFF => {}
+            P1_ADDR => self.joypad.write(val),
+            SB_ADDR | SC_ADDR => self.serial.write(addr, val),
+            DIV_ADDR..=TAC_ADDR => self.timer.write(addr, val),
+            IF_ADDR => self.memory[addr as usize] = val & 0b0001_1111,
+            APU_START..=APU_END => self.apu.write(addr, val),
+            DMA_ADDR => {
+                self.dma_reg = val;
+                // Starting a transfer whilst one is running restarts it from the new source.
+                self.dma = Some(Dma { source: (val as u16) << 8, index: 0, delay: 1 });
+            }
+            0xFF40..=0xFF4B => self.ppu.write(addr, val),
+            // Once unmapped, the boot ROM can't be mapped back in.
+            BOOT_ROM_DISABLE_ADDR => {
+                if val != 0 {
+                    self.boot_rom_mapped = false;
+                }
+            }
+            _ => self.memory[addr as usize] = val,
+        }
+    }
+
+    /// Advance every component on the bus by the given number of T-cycles, which should be a
+    /// multiple of four. Any interrupts raised along the way are requested in IF.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.flat {
+            return;
+        }
+        for _ in 0..cycles / 4 {
+            let mut interrupts = 0;
+            if self.timer.tick(4) {
+                interrupts |= TIMER_INTERRUPT;
+            }
+            if self.serial.tick(4) {
+                interrupts |= SERIAL_INTERRUPT;
+            }
+            interrupts |= self.ppu.tick(4);
+            self.apu.tick(4);
+            if let Some(cartridge) = &mut self.cartridge {
+                cartridge.tick(4);
+            }
+            self.tick_dma();
+            self.request_interrupt(interrupts);
+        }
+    }
+
+    /// Copy the next byte of an OAM DMA transfer, one of which happens every M-cycle.
+    fn tick_dma(&mut self) {
+        let finished = match &mut self.dma {
+            None => return,
+            Some(dma) if dma.delay > 0 => {
+                dma.delay -= 1;
+                false
+            }
+            Some(dma) => {
+                let (source, index) = (dma.source, dma.index);
+                dma.index += 1;
+                // DMA can't read through the PPU's access restrictions, so VRAM is read as is.
+                let val = match source + index as u16 {
+                    addr @ 0x8000..=0x9FFF => self.ppu.read_vram(addr),
+                    addr => self.read(addr),
+                };
+                self.ppu.write_oam(index, val);
+                index + 1 == OAM_SIZE
+            }
+        };
+        if finished {
+            self.dma = None;
+        }
+    }
+
+    /// Returns true whilst an OAM DMA transfer is copying bytes, during which the CPU can only
+    /// reach HRAM.
+    pub fn dma_active(&self) -> bool {
+        matches!(&self.dma, Some(dma) if dma.delay == 0)
+    }
+
+    /// Set the given bit(s) in the interrupt flag register.
+    pub fn request_interrupt(&mut self, interrupt: u8) {
+        self.memory[IF_ADDR as usize] |= interrupt;
+    }
+
+    /// Clear the given bit(s) in the interrupt flag register, once an interrupt has been serviced.
+    pub fn clear_interrupt(&mut self, interrupt: u8) {
+        self.memory[IF_ADDR as usize] &= !interrupt;
+    }
+
+    /// The interrupts which have been both requested and enabled.
+    pub fn pending_interrupts(&self) -> u8 {
+        self.memory[IE_ADDR as usize] & self.memory[IF_ADDR as usize] & 0b0001_1111
+    }
+}
+
+impl Snapshot for Bus {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.memory);
+        w.bool(self.boot_rom_mapped);
+        w.u8(self.dma_reg);
+        match &self.dma {
+            Some(dma) => {
+                w.bool(true);
+                w.u16(dma.source);
+                w.u8(dma.index as u8);
+                w.u8(dma.delay);
+            }
+            None => w.bool(false),
+        }
+        self.timer.save_state(w);
+        self.serial.save_state(w);
+        self.ppu.save_state(w);
+        self.apu.save_state(w);
+        self.joypad.save_state(w);
+        match &self.cartridge {
+            Some(cartridge) => {
+                w.bool(true);
+                cartridge.save_state(w);
+            }
+            None => w.bool(false),
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.memory)?;
+        self.boot_rom_mapped = r.bool()?;
+        self.dma_reg = r.u8()?;
+        self.dma = if r.bool()? {
+            let source = r.u16()?;
+            let index = r.u8()? as usize;
+            let delay = r.u8()?;
+            if index >= OAM_SIZE {
+                return Err(StateError::Corrupt("OAM DMA index out of range"));
+            }
+            Some(Dma { source, index, delay })
+        } else {
+            None
+        };
+        self.timer.load_state(r)?;
+        self.serial.load_state(r)?;
+        self.ppu.load_state(r)?;
+        self.apu.load_state(r)?;
+        self.joypad.load_state(r)?;
+        match (r.bool()?, &mut self.cartridge) {
+            (true, Some(cartridge)) => cartridge.load_state(r),
+            (false, None) => Ok(()),
+            _ => Err(StateError::Corrupt("cartridge presence doesn't match")),
+        }
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Bus::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::dmg_ppu::OAM_START;
+    use crate::components::timer::TIMA_ADDR;
+
+    #[test]
+    fn echo_ram() {
+        let mut bus = Bus::new();
+        bus.write(0xC123, 0x45);
+        assert_eq!(bus.read(0xE123), 0x45);
+        bus.write(0xE124, 0x67);
+        assert_eq!(bus.read(0xC124), 0x67);
+    }
+
+    #[test]
+    fn io_routing() {
+        let mut bus = Bus::new();
+        bus.write(TAC_ADDR, 0b101);
+        bus.write(IF_ADDR, 0xFF);
+        assert_eq!(bus.read(IF_ADDR), 0xFF);
+        bus.clear_interrupt(0xFF);
+        assert_eq!(bus.read(IF_ADDR), 0xE0);
+        bus.tick(16);
+        assert_eq!(bus.read(TIMA_ADDR), 1);
+        bus.write(P1_ADDR, 0x20);
+        assert_eq!(bus.read(P1_ADDR), 0xEF);
+        bus.write(0xFF26, 0x80);
+        assert_eq!(bus.read(0xFF26), 0xF0);
+    }
+
+    #[test]
+    fn cartridge_mapping() {
+        let mut bus = Bus::new();
+        let mut rom = vec![0; 0x8000];
+        rom[0x0150] = 0x12;
+        rom[0x4000] = 0x34;
+        bus.cartridge = Some(Cartridge::from_bytes(rom).unwrap());
+        assert_eq!(bus.read(0x0150), 0x12);
+        assert_eq!(bus.read(0x4000), 0x34);
+        // ROM can't be written, and there's no external RAM.
+        bus.write(0x0150, 0x56);
+        assert_eq!(bus.read(0x0150), 0x12);
+        assert_eq!(bus.read(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn timer_interrupt() {
+        let mut bus = Bus::new();
+        bus.write(IE_ADDR, TIMER_INTERRUPT);
+        bus.write(TIMA_ADDR, 0xFF);
+        bus.write(TAC_ADDR, 0b101);
+        bus.tick(16);
+        assert_eq!(bus.pending_interrupts(), 0);
+        bus.tick(4);
+        assert_eq!(bus.pending_interrupts(), TIMER_INTERRUPT);
+    }
+
+    #[test]
+    fn oam_dma() {
+        let mut bus = Bus::new();
+        for i in 0..OAM_SIZE {
+            bus.write(0xC000 + i as u16, i as u8);
+        }
+        bus.write(DMA_ADDR, 0xC0);
+        assert_eq!(bus.read(DMA_ADDR), 0xC0);
+        // The transfer starts after a one M-cycle delay.
+        bus.tick(4);
+        assert!(bus.dma_active());
+        bus.tick(4 * (OAM_SIZE as u32 - 1));
+        assert!(bus.dma_active());
+        bus.tick(4);
+        assert!(!bus.dma_active());
+        for i in 0..OAM_SIZE {
+            assert_eq!(bus.read(OAM_START + i as u16), i as u8);
+        }
+    }
+}
@@ -0,0 +1,635 @@
+use std::fs;
+use std::path::Path;
+use anyhow::{Context, Result};
+use thiserror::Error;
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// The address of the cartridge title in the header.
+const TITLE_ADDR: usize = 0x0134;
+/// The title takes up to 16 bytes, although later cartridges use the end of it for other things.
+const TITLE_LEN: usize = 16;
+/// The address of the cartridge type (which MBC it uses, and whether it has RAM or a battery).
+const TYPE_ADDR: usize = 0x0147;
+/// The address of the ROM size code.
+const ROM_SIZE_ADDR: usize = 0x0148;
+/// The address of the RAM size code.
+const RAM_SIZE_ADDR: usize = 0x0149;
+/// The address of the header checksum, computed over 0x0134-0x014C.
+const HEADER_CHECKSUM_ADDR: usize = 0x014D;
+/// The address of the global checksum, the sum of every other byte of the ROM.
+const GLOBAL_CHECKSUM_ADDR: usize = 0x014E;
+
+/// ROM banks are always 16KiB.
+const ROM_BANK_SIZE: usize = 0x4000;
+/// RAM banks are always 8KiB.
+const RAM_BANK_SIZE: usize = 0x2000;
+/// MBC2 has 512 half-bytes of RAM built in.
+const MBC2_RAM_SIZE: usize = 0x200;
+
+/// The MBC3 real time clock counts in seconds of the CPU's 4.194304MHz clock.
+const CYCLES_PER_SECOND: u32 = 4_194_304;
+/// The bits of each clock register that exist: seconds, minutes, hours, lower day and upper
+/// day/flags.
+const RTC_MASKS: [u8; 5] = [0x3F, 0x3F, 0x1F, 0xFF, 0xC1];
+
+#[derive(Debug, Error)]
+pub enum CartridgeError {
+    #[error("The ROM is only {0} bytes, which is too small to hold a cartridge header")]
+    TooSmall(usize),
+    #[error("Unsupported cartridge type {0:#04x}")]
+    UnsupportedType(u8),
+    #[error("Unknown ROM size code {0:#04x}")]
+    UnknownRomSize(u8),
+    #[error("Unknown RAM size code {0:#04x}")]
+    UnknownRamSize(u8),
+    #[error("Save data is {0} bytes, but the cartridge has {1} bytes of RAM")]
+    SaveSizeMismatch(usize, usize),
+}
+
+/// The memory bank controller (MBC) on the cartridge, along with its banking registers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mbc {
+    /// 32KiB of ROM with no banking, and optionally 8KiB of RAM.
+    None,
+    Mbc1 {
+        /// The lower five bits of the ROM bank.
+        rom_bank: u8,
+        /// Two bits which select either the RAM bank or the upper bits of the ROM bank.
+        upper: u8,
+        /// In advanced banking mode, `upper` also applies to 0x0000-0x3FFF and to RAM.
+        advanced: bool,
+    },
+    Mbc2 {
+        rom_bank: u8,
+    },
+    Mbc3 {
+        rom_bank: u8,
+        /// 0x00-0x03 selects a RAM bank, and 0x08-0x0C one of the clock registers.
+        ram_bank: u8,
+        rtc: Rtc,
+    },
+    Mbc5 {
+        rom_bank: u16,
+        ram_bank: u8,
+    },
+}
+
+/// The real time clock found on some MBC3 cartridges.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Rtc {
+    /// The live clock registers: seconds, minutes, hours, lower day and upper day/flags.
+    regs: [u8; 5],
+    /// The clock registers as they were when last latched, which is what the CPU reads.
+    latched: [u8; 5],
+    /// The last value written to the latch register; writing 0x00 then 0x01 latches the clock.
+    latch_reg: u8,
+    /// T-cycles since the seconds register last went up.
+    cycles: u32,
+}
+
+impl Rtc {
+    /// Advance the clock, unless it has been halted (bit 6 of the upper day register).
+    fn tick(&mut self, cycles: u32) {
+        if self.regs[4] & 0b0100_0000 != 0 {
+            return;
+        }
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_SECOND {
+            self.cycles -= CYCLES_PER_SECOND;
+            self.advance_second();
+        }
+    }
+
+    fn advance_second(&mut self) {
+        if !self.count(0, 60) || !self.count(1, 60) || !self.count(2, 24) {
+            return;
+        }
+        let day = ((self.regs[4] as u16 & 1) << 8 | self.regs[3] as u16) + 1;
+        self.regs[3] = day as u8;
+        // The ninth bit of the day counter, and the carry bit once it overflows.
+        self.regs[4] = (self.regs[4] & 0b1111_1110) | ((day >> 8) & 1) as u8;
+        if day > 0x1FF {
+            self.regs[4] |= 0b1000_0000;
+        }
+    }
+
+    /// Count a register up, returning true if it rolled over from `limit - 1` to 0. A value past the
+    /// limit, which only a game can set, keeps counting up to the top of the register and wraps
+    /// to 0 without carrying into the next register, as it does on the real clock.
+    fn count(&mut self, index: usize, limit: u8) -> bool {
+        let reg = &mut self.regs[index];
+        if *reg == limit - 1 {
+            *reg = 0;
+            return true;
+        }
+        *reg = reg.wrapping_add(1) & RTC_MASKS[index];
+        false
+    }
+
+    fn write_latch(&mut self, val: u8) {
+        if self.latch_reg == 0x00 && val == 0x01 {
+            self.latched = self.regs;
+        }
+        self.latch_reg = val;
+    }
+}
+
+/// # Cartridge
+/// A Game Boy cartridge: its ROM, any RAM it has and the memory bank controller (MBC) which maps
+/// them into 0x0000-0x7FFF and 0xA000-0xBFFF. Cartridges without an MBC, as well as MBC1, MBC2,
+/// MBC3 (including its real time clock) and MBC5 are supported.
+///
+/// Cartridges with a battery keep their RAM when the Game Boy is switched off. The contents can be
+/// got at with `ram` and restored with `load_ram`, so that they can be kept in a save file.
+pub struct Cartridge {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    mbc: Mbc,
+    /// Whether RAM (and the clock) can currently be accessed.
+    ram_enabled: bool,
+    /// Whether the cartridge has a battery to keep RAM alive.
+    battery: bool,
+    title: String,
+}
+
+impl Cartridge {
+    /// Create a cartridge from the contents of a ROM file, working out how it is mapped from its
+    /// header.
+    pub fn from_bytes(mut rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        if rom.len() <= GLOBAL_CHECKSUM_ADDR + 1 {
+            return Err(CartridgeError::TooSmall(rom.len()));
+        }
+
+        let cartridge_type = rom[TYPE_ADDR];
+        let (mbc, battery) = match cartridge_type {
+            0x00 | 0x08 => (Mbc::None, false),
+            0x09 => (Mbc::None, true),
+            0x01 | 0x02 => (Mbc::Mbc1 { rom_bank: 1, upper: 0, advanced: false }, false),
+            0x03 => (Mbc::Mbc1 { rom_bank: 1, upper: 0, advanced: false }, true),
+            0x05 => (Mbc::Mbc2 { rom_bank: 1 }, false),
+            0x06 => (Mbc::Mbc2 { rom_bank: 1 }, true),
+            0x11 | 0x12 => (Mbc::Mbc3 { rom_bank: 1, ram_bank: 0, rtc: Rtc::default() }, false),
+            0x0F | 0x10 | 0x13 => (Mbc::Mbc3 { rom_bank: 1, ram_bank: 0, rtc: Rtc::default() }, true),
+            0x19 | 0x1A | 0x1C | 0x1D => (Mbc::Mbc5 { rom_bank: 1, ram_bank: 0 }, false),
+            0x1B | 0x1E => (Mbc::Mbc5 { rom_bank: 1, ram_bank: 0 }, true),
+            _ => return Err(CartridgeError::UnsupportedType(cartridge_type)),
+        };
+
+        let rom_size_code = rom[ROM_SIZE_ADDR];
+        if rom_size_code > 0x08 {
+            return Err(CartridgeError::UnknownRomSize(rom_size_code));
+        }
+        // Pad out truncated ROMs, so that banks past the end read as open bus.
+        let rom_size = (ROM_BANK_SIZE * 2) << rom_size_code;
+        if rom.len() < rom_size {
+            rom.resize(rom_size, 0xFF);
+        }
+
+        let ram_size = match (&mbc, rom[RAM_SIZE_ADDR]) {
+            (Mbc::Mbc2 { .. }, _) => MBC2_RAM_SIZE,
+            (_, 0x00) => 0,
+            (_, 0x01) => 0x800,
+            (_, 0x02) => RAM_BANK_SIZE,
+            (_, 0x03) => RAM_BANK_SIZE * 4,
+            (_, 0x04) => RAM_BANK_SIZE * 16,
+            (_, 0x05) => RAM_BANK_SIZE * 8,
+            (_, code) => return Err(CartridgeError::UnknownRamSize(code)),
+        };
+
+        let title = rom[TITLE_ADDR..TITLE_ADDR + TITLE_LEN].iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| if c.is_ascii_graphic() || c == b' ' { c as char } else { '?' })
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+
+        Ok(Cartridge {
+            rom,
+            ram: vec![0; ram_size],
+            mbc,
+            ram_enabled: false,
+            battery,
+            title,
+        })
+    }
+
+    /// Load a cartridge from a ROM file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let rom = fs::read(path).with_context(|| format!("Couldn't read ROM file {}", path.display()))?;
+        Cartridge::from_bytes(rom).with_context(|| format!("Couldn't load ROM file {}", path.display()))
+    }
+
+    /// The title from the cartridge header.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Whether the cartridge has battery-backed RAM which should be kept in a save file.
+    pub fn has_battery(&self) -> bool {
+        self.battery && !self.ram.is_empty()
+    }
+
+    /// The global checksum from the header, which identifies the ROM well enough to tell
+    /// whether save data belongs to it.
+    pub fn global_checksum(&self) -> u16 {
+        (self.rom[GLOBAL_CHECKSUM_ADDR] as u16) << 8 | self.rom[GLOBAL_CHECKSUM_ADDR + 1] as u16
+    }
+
+    /// Returns true if the header checksum matches the header, as the boot ROM checks.
+    pub fn header_checksum_valid(&self) -> bool {
+        let checksum = self.rom[TITLE_ADDR..HEADER_CHECKSUM_ADDR].iter()
+            .fold(0u8, |acc, &b| acc.wrapping_sub(b).wrapping_sub(1));
+        checksum == self.rom[HEADER_CHECKSUM_ADDR]
+    }
+
+    /// The contents of cartridge RAM.
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Restore the contents of cartridge RAM, such as from a save file.
+    pub fn load_ram(&mut self, data: &[u8]) -> Result<(), CartridgeError> {
+        if data.len() != self.ram.len() {
+            return Err(CartridgeError::SaveSizeMismatch(data.len(), self.ram.len()));
+        }
+        self.ram.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Put the MBC back into the state it powers up in. RAM is left as it is.
+    pub fn reset(&mut self) {
+        self.ram_enabled = false;
+        match &mut self.mbc {
+            Mbc::None => {}
+            Mbc::Mbc1 { rom_bank, upper, advanced } => {
+                *rom_bank = 1;
+                *upper = 0;
+                *advanced = false;
+            }
+            Mbc::Mbc2 { rom_bank } => *rom_bank = 1,
+            Mbc::Mbc3 { rom_bank, ram_bank, .. } => {
+                *rom_bank = 1;
+                *ram_bank = 0;
+            }
+            Mbc::Mbc5 { rom_bank, ram_bank } => {
+                *rom_bank = 1;
+                *ram_bank = 0;
+            }
+        }
+    }
+
+    /// Advance the real time clock, if the cartridge has one.
+    pub fn tick(&mut self, cycles: u32) {
+        if let Mbc::Mbc3 { rtc, .. } = &mut self.mbc {
+            rtc.tick(cycles);
+        }
+    }
+
+    /// The ROM banks mapped into 0x0000-0x3FFF and 0x4000-0x7FFF.
+    fn rom_banks(&self) -> (usize, usize) {
+        match &self.mbc {
+            Mbc::None => (0, 1),
+            Mbc::Mbc1 { rom_bank, upper, advanced } => {
+                let high = (*upper as usize) << 5;
+                let low = if *advanced { high } else { 0 };
+                (low, high | *rom_bank as usize)
+            }
+            Mbc::Mbc2 { rom_bank } => (0, *rom_bank as usize),
+            Mbc::Mbc3 { rom_bank, .. } => (0, *rom_bank as usize),
+            Mbc::Mbc5 { rom_bank, .. } => (0, *rom_bank as usize),
+        }
+    }
+
+    /// The offset into cartridge RAM for an address in 0xA000-0xBFFF.
+    fn ram_offset(&self, addr: u16) -> usize {
+        let bank = match &self.mbc {
+            Mbc::Mbc1 { upper, advanced: true, .. } => *upper as usize,
+            Mbc::Mbc3 { ram_bank, .. } => *ram_bank as usize,
+            Mbc::Mbc5 { ram_bank, .. } => *ram_bank as usize,
+            _ => 0,
+        };
+        (bank * RAM_BANK_SIZE + (addr - 0xA000) as usize) % self.ram.len()
+    }
+
+    /// Read from the cartridge's half of the address space (0x0000-0x7FFF and 0xA000-0xBFFF).
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x7FFF => {
+                let (low, high) = self.rom_banks();
+                let bank = if addr < 0x4000 { low } else { high };
+                let offset = bank * ROM_BANK_SIZE + (addr as usize % ROM_BANK_SIZE);
+                self.rom[offset % self.rom.len()]
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return 0xFF;
+                }
+                match &self.mbc {
+                    Mbc::Mbc3 { ram_bank: reg @ 0x08..=0x0C, rtc, .. } => rtc.latched[(*reg - 0x08) as usize],
+                    // MBC2 RAM is only four bits wide, and is repeated across the whole area.
+                    Mbc::Mbc2 { .. } => self.ram[(addr as usize) % MBC2_RAM_SIZE] | 0xF0,
+                    _ if self.ram.is_empty() => 0xFF,
+                    _ => self.ram[self.ram_offset(addr)],
+                }
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Write to the cartridge. Writes to ROM go to the MBC's registers.
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x7FFF => self.write_register(addr, val),
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return;
+                }
+                match &mut self.mbc {
+                    Mbc::Mbc3 { ram_bank: reg @ 0x08..=0x0C, rtc, .. } => {
+                        let index = (*reg - 0x08) as usize;
+                        let val = val & RTC_MASKS[index];
+                        rtc.regs[index] = val;
+                        rtc.latched[index] = val;
+                        if index == 0 {
+                            // Writing the seconds resets the sub-second counter.
+                            rtc.cycles = 0;
+                        }
+                    }
+                    Mbc::Mbc2 { .. } => self.ram[(addr as usize) % MBC2_RAM_SIZE] = val & 0x0F,
+                    _ if self.ram.is_empty() => {}
+                    _ => {
+                        let offset = self.ram_offset(addr);
+                        self.ram[offset] = val;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, val: u8) {
+        let ram_enabled = &mut self.ram_enabled;
+        match &mut self.mbc {
+            Mbc::None => {}
+            Mbc::Mbc1 { rom_bank, upper, advanced } => match addr {
+                0x0000..=0x1FFF => *ram_enabled = val & 0x0F == 0x0A,
+                // Bank 0 can't be selected here; asking for it gives bank 1.
+                0x2000..=0x3FFF => *rom_bank = (val & 0x1F).max(1),
+                0x4000..=0x5FFF => *upper = val & 0b11,
+                _ => *advanced = val & 1 == 1,
+            },
+            // MBC2 uses bit 8 of the address to tell its two registers apart.
+            Mbc::Mbc2 { rom_bank } => if addr < 0x4000 {
+                if addr & 0x0100 == 0 {
+                    *ram_enabled = val & 0x0F == 0x0A;
+                } else {
+                    *rom_bank = (val & 0x0F).max(1);
+                }
+            },
+            Mbc::Mbc3 { rom_bank, ram_bank, rtc } => match addr {
+                0x0000..=0x1FFF => *ram_enabled = val & 0x0F == 0x0A,
+                0x2000..=0x3FFF => *rom_bank = (val & 0x7F).max(1),
+                0x4000..=0x5FFF => *ram_bank = val,
+                _ => rtc.write_latch(val),
+            },
+            Mbc::Mbc5 { rom_bank, ram_bank } => match addr {
+                0x0000..=0x1FFF => *ram_enabled = val & 0x0F == 0x0A,
+                // Unlike the others, MBC5 can map bank 0 into 0x4000-0x7FFF.
+                0x2000..=0x2FFF => *rom_bank = (*rom_bank & 0x100) | val as u16,
+                0x3000..=0x3FFF => *rom_bank = (*rom_bank & 0xFF) | ((val as u16 & 1) << 8),
+                0x4000..=0x5FFF => *ram_bank = val & 0x0F,
+                _ => {}
+            },
+        }
+    }
+}
+
+impl Snapshot for Rtc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.regs);
+        w.bytes(&self.latched);
+        w.u8(self.latch_reg);
+        w.u32(self.cycles);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.regs)?;
+        r.bytes(&mut self.latched)?;
+        self.latch_reg = r.u8()?;
+        self.cycles = r.u32()?;
+        Ok(())
+    }
+}
+
+/// Only RAM and the MBC's registers are saved; the ROM is whatever cartridge is inserted.
+impl Snapshot for Cartridge {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.ram);
+        w.bool(self.ram_enabled);
+        match &self.mbc {
+            Mbc::None => w.u8(0),
+            Mbc::Mbc1 { rom_bank, upper, advanced } => {
+                w.u8(1);
+                w.u8(*rom_bank);
+                w.u8(*upper);
+                w.bool(*advanced);
+            }
+            Mbc::Mbc2 { rom_bank } => {
+                w.u8(2);
+                w.u8(*rom_bank);
+            }
+            Mbc::Mbc3 { rom_bank, ram_bank, rtc } => {
+                w.u8(3);
+                w.u8(*rom_bank);
+                w.u8(*ram_bank);
+                rtc.save_state(w);
+            }
+            Mbc::Mbc5 { rom_bank, ram_bank } => {
+                w.u8(5);
+                w.u16(*rom_bank);
+                w.u8(*ram_bank);
+            }
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.ram)?;
+        self.ram_enabled = r.bool()?;
+        let kind = r.u8()?;
+        match &mut self.mbc {
+            Mbc::None if kind == 0 => {}
+            Mbc::Mbc1 { rom_bank, upper, advanced } if kind == 1 => {
+                *rom_bank = r.u8()?;
+                *upper = r.u8()?;
+                *advanced = r.bool()?;
+            }
+            Mbc::Mbc2 { rom_bank } if kind == 2 => *rom_bank = r.u8()?,
+            Mbc::Mbc3 { rom_bank, ram_bank, rtc } if kind == 3 => {
+                *rom_bank = r.u8()?;
+                *ram_bank = r.u8()?;
+                rtc.load_state(r)?;
+            }
+            Mbc::Mbc5 { rom_bank, ram_bank } if kind == 5 => {
+                *rom_bank = r.u16()?;
+                *ram_bank = r.u8()?;
+            }
+            _ => return Err(StateError::Corrupt("the cartridge has a different MBC")),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a ROM with the given header fields, where every bank starts with its own number.
+    fn rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = vec![0; (ROM_BANK_SIZE * 2) << rom_size];
+        for (bank, chunk) in rom.chunks_mut(ROM_BANK_SIZE).enumerate() {
+            chunk[0] = bank as u8;
+        }
+        rom[TITLE_ADDR..TITLE_ADDR + 4].copy_from_slice(b"TEST");
+        rom[TYPE_ADDR] = cartridge_type;
+        rom[ROM_SIZE_ADDR] = rom_size;
+        rom[RAM_SIZE_ADDR] = ram_size;
+        rom
+    }
+
+    #[test]
+    fn header() {
+        let mut data = rom(0x03, 0x02, 0x03);
+        data[GLOBAL_CHECKSUM_ADDR] = 0x12;
+        data[GLOBAL_CHECKSUM_ADDR + 1] = 0x34;
+        let checksum = data[TITLE_ADDR..HEADER_CHECKSUM_ADDR].iter()
+            .fold(0u8, |acc, &b| acc.wrapping_sub(b).wrapping_sub(1));
+        data[HEADER_CHECKSUM_ADDR] = checksum;
+        let cart = Cartridge::from_bytes(data).unwrap();
+        assert_eq!(cart.title(), "TEST");
+        assert!(cart.has_battery());
+        assert_eq!(cart.ram().len(), RAM_BANK_SIZE * 4);
+        assert_eq!(cart.global_checksum(), 0x1234);
+        assert!(cart.header_checksum_valid());
+        assert!(matches!(Cartridge::from_bytes(vec![0; 0x100]), Err(CartridgeError::TooSmall(_))));
+        assert!(matches!(Cartridge::from_bytes(rom(0xFC, 0, 0)), Err(CartridgeError::UnsupportedType(0xFC))));
+    }
+
+    #[test]
+    fn mbc1_banking() {
+        let mut cart = Cartridge::from_bytes(rom(0x01, 0x06, 0x00)).unwrap();
+        assert_eq!(cart.read(0x4000), 1);
+        cart.write(0x2000, 0x05);
+        assert_eq!(cart.read(0x4000), 5);
+        // Bank 0 maps to bank 1.
+        cart.write(0x2000, 0x00);
+        assert_eq!(cart.read(0x4000), 1);
+        // The upper bits select banks past 0x1F.
+        cart.write(0x2000, 0x02);
+        cart.write(0x4000, 0x01);
+        assert_eq!(cart.read(0x4000), 0x22);
+        assert_eq!(cart.read(0x0000), 0);
+        // In advanced mode, they apply to the first bank too.
+        cart.write(0x6000, 0x01);
+        assert_eq!(cart.read(0x0000), 0x20);
+    }
+
+    #[test]
+    fn ram_enable() {
+        let mut cart = Cartridge::from_bytes(rom(0x1B, 0x01, 0x03)).unwrap();
+        cart.write(0xA000, 0x42);
+        assert_eq!(cart.read(0xA000), 0xFF);
+        cart.write(0x0000, 0x0A);
+        cart.write(0xA000, 0x42);
+        cart.write(0x4000, 0x02);
+        cart.write(0xA000, 0x43);
+        assert_eq!(cart.read(0xA000), 0x43);
+        cart.write(0x4000, 0x00);
+        assert_eq!(cart.read(0xA000), 0x42);
+        assert_eq!(cart.ram()[RAM_BANK_SIZE * 2], 0x43);
+        cart.write(0x0000, 0x00);
+        assert_eq!(cart.read(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn mbc2_ram() {
+        let mut cart = Cartridge::from_bytes(rom(0x06, 0x01, 0x00)).unwrap();
+        assert_eq!(cart.ram().len(), MBC2_RAM_SIZE);
+        cart.write(0x0000, 0x0A);
+        cart.write(0xA001, 0xAB);
+        // Only the lower four bits are stored, and the area is repeated.
+        assert_eq!(cart.read(0xA201), 0xFB);
+        cart.write(0x0100, 0x03);
+        assert_eq!(cart.read(0x4000), 3);
+    }
+
+    #[test]
+    fn mbc5_banking() {
+        let mut cart = Cartridge::from_bytes(rom(0x19, 0x08, 0x00)).unwrap();
+        cart.write(0x2000, 0x00);
+        assert_eq!(cart.read(0x4000), 0);
+        cart.write(0x2000, 0x34);
+        cart.write(0x3000, 0x01);
+        assert_eq!(cart.read(0x4000), 0x34);
+        assert_eq!(cart.rom_banks(), (0, 0x134));
+    }
+
+    #[test]
+    fn mbc3_rtc() {
+        let mut cart = Cartridge::from_bytes(rom(0x10, 0x01, 0x03)).unwrap();
+        cart.write(0x0000, 0x0A);
+        cart.tick(CYCLES_PER_SECOND * 61);
+        // Nothing can be read until the clock is latched.
+        cart.write(0x4000, 0x08);
+        assert_eq!(cart.read(0xA000), 0);
+        cart.write(0x6000, 0x00);
+        cart.write(0x6000, 0x01);
+        assert_eq!(cart.read(0xA000), 1);
+        cart.write(0x4000, 0x09);
+        assert_eq!(cart.read(0xA000), 1);
+    }
+
+    #[test]
+    fn rtc_out_of_range() {
+        let mut cart = Cartridge::from_bytes(rom(0x10, 0x01, 0x03)).unwrap();
+        cart.write(0x0000, 0x0A);
+        let clock = |cart: &mut Cartridge| -> Vec<u8> {
+            cart.write(0x6000, 0x00);
+            cart.write(0x6000, 0x01);
+            (0x08..=0x0C).map(|reg| {
+                cart.write(0x4000, reg);
+                cart.read(0xA000)
+            }).collect()
+        };
+        for reg in 0x08..=0x0C {
+            cart.write(0x4000, reg);
+            cart.write(0xA000, 0xFF);
+        }
+        assert_eq!(clock(&mut cart), RTC_MASKS);
+
+        // Start the clock again, leaving the day at 0x1FF.
+        cart.write(0xA000, 0x01);
+        let run = |cart: &mut Cartridge, seconds: u32| (0..seconds).for_each(|_| cart.tick(CYCLES_PER_SECOND));
+        // The seconds wrap from 63 to 0 without carrying into the minutes.
+        run(&mut cart, 1);
+        assert_eq!(clock(&mut cart), [0, 0x3F, 0x1F, 0xFF, 0x01]);
+        run(&mut cart, 60);
+        assert_eq!(clock(&mut cart), [0, 0, 0x1F, 0xFF, 0x01]);
+        run(&mut cart, 60 * 60);
+        assert_eq!(clock(&mut cart), [0, 0, 0, 0xFF, 0x01]);
+        // A day later the day counter overflows, setting the carry bit.
+        run(&mut cart, 24 * 60 * 60);
+        assert_eq!(clock(&mut cart), [0, 0, 0, 0, 0x80]);
+    }
+
+    #[test]
+    fn save_data() {
+        let mut cart = Cartridge::from_bytes(rom(0x03, 0x01, 0x02)).unwrap();
+        assert!(cart.load_ram(&[0; 16]).is_err());
+        let mut save = vec![0; RAM_BANK_SIZE];
+        save[0] = 0x99;
+        cart.load_ram(&save).unwrap();
+        cart.write(0x0000, 0x0A);
+        assert_eq!(cart.read(0xA000), 0x99);
+    }
+}
@@ -0,0 +1,743 @@
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// The first of the sound registers (NR10).
+pub const APU_START: u16 = 0xFF10;
+/// The sound on/off register (NR52).
+pub const NR52_ADDR: u16 = 0xFF26;
+/// The start of wave RAM, which holds 32 4-bit samples for the wave channel.
+pub const WAVE_RAM_START: u16 = 0xFF30;
+/// The last address belonging to the APU.
+pub const APU_END: u16 = 0xFF3F;
+
+/// The CPU clock, which everything in the APU is derived from.
+const CPU_HZ: u32 = 4_194_304;
+/// The frame sequencer clocks the length counters, sweep and envelopes at 512Hz.
+const FRAME_SEQUENCER_PERIOD: u32 = CPU_HZ / 512;
+/// The default rate at which samples are produced.
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+/// Unused bits of each register from NR10 to NR52 read back as set.
+const READ_MASKS: [u8; 0x17] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70,             // NR50-NR52
+];
+
+/// The waveforms for each of the four square wave duty cycles: 12.5%, 25%, 50% and 75%.
+const DUTY_PATTERNS: [u8; 4] = [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// The base divisors for the noise channel's clock.
+const NOISE_DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// Counts down to silence a channel after a set time, if enabled.
+#[derive(Default)]
+struct Length {
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    /// Clocked by the frame sequencer. Returns true if the channel should now be silenced.
+    fn clock(&mut self) -> bool {
+        if self.enabled && self.counter > 0 {
+            self.counter -= 1;
+            return self.counter == 0;
+        }
+        false
+    }
+
+    /// Triggering a channel with an expired length counter starts it again from the maximum.
+    fn trigger(&mut self, max: u16) {
+        if self.counter == 0 {
+            self.counter = max;
+        }
+    }
+}
+
+/// Fades a channel's volume up or down over time.
+#[derive(Default)]
+struct Envelope {
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    /// Clocked by the frame sequencer, using the settings in NRx2.
+    fn clock(&mut self, nrx2: u8) {
+        let period = nrx2 & 0b111;
+        if period == 0 {
+            return;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer == 0 {
+            self.timer = period;
+            if nrx2 & 0b1000 != 0 && self.volume < 15 {
+                self.volume += 1;
+            } else if nrx2 & 0b1000 == 0 && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    fn trigger(&mut self, nrx2: u8) {
+        self.volume = nrx2 >> 4;
+        self.timer = nrx2 & 0b111;
+    }
+}
+
+/// One of the two square wave channels. Only the first has a frequency sweep.
+#[derive(Default)]
+struct Square {
+    enabled: bool,
+    /// T-cycles until the next step through the waveform.
+    timer: u32,
+    /// How far through the eight step waveform the channel is.
+    position: u8,
+    length: Length,
+    envelope: Envelope,
+    /// The frequency the sweep is working from.
+    shadow_frequency: u16,
+    sweep_timer: u8,
+    sweep_enabled: bool,
+}
+
+/// The wave channel, which plays back the 32 samples in wave RAM.
+#[derive(Default)]
+struct Wave {
+    enabled: bool,
+    timer: u32,
+    position: u8,
+    length: Length,
+}
+
+/// The noise channel, driven by a linear feedback shift register.
+#[derive(Default)]
+struct Noise {
+    enabled: bool,
+    timer: u32,
+    lfsr: u16,
+    length: Length,
+    envelope: Envelope,
+}
+
+/// # Game Boy APU
+/// The APU generates the Game Boy's sound from four channels: two square waves (the first with a
+/// frequency sweep), a wave channel playing user-defined samples and a noise channel. They are
+/// controlled through the registers at 0xFF10-0xFF26, with the wave samples at 0xFF30-0xFF3F.
+///
+/// The mixed output is sampled at a fixed rate into a buffer of interleaved stereo samples, ready
+/// to be handed to an audio device.
+pub struct APU {
+    /// The raw values of NR10-NR52, which the channels take their settings from.
+    regs: [u8; 0x17],
+    wave_ram: [u8; 16],
+    /// NR52 bit 7: turning the APU off silences it and clears every register.
+    power: bool,
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    /// T-cycles until the frame sequencer next steps.
+    frame_sequencer_timer: u32,
+    /// Which of the frame sequencer's eight steps comes next.
+    frame_sequencer_step: u8,
+    /// The rate at which samples are produced.
+    sample_rate: u32,
+    /// Goes up by the sample rate every T-cycle; a sample is taken each time it passes CPU_HZ.
+    sample_clock: u32,
+    /// Interleaved left and right samples, from -1.0 to 1.0. If nobody takes them, only about the
+    /// last second's worth are kept.
+    samples: Vec<f32>,
+}
+
+impl APU {
+    pub fn new() -> Self {
+        APU {
+            regs: [0; 0x17],
+            wave_ram: [0; 16],
+            power: false,
+            square1: Square::default(),
+            square2: Square::default(),
+            wave: Wave::default(),
+            noise: Noise::default(),
+            frame_sequencer_timer: FRAME_SEQUENCER_PERIOD,
+            frame_sequencer_step: 0,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            sample_clock: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Set the rate at which samples are produced.
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.sample_rate = rate;
+    }
+
+    /// The rate at which samples are produced.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Take every sample produced since this was last called, as interleaved left and right
+    /// samples.
+    pub fn take_samples(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.samples)
+    }
+
+    fn reg(&self, addr: u16) -> u8 {
+        self.regs[(addr - APU_START) as usize]
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            NR52_ADDR => {
+                (self.power as u8) << 7
+                    | READ_MASKS[(NR52_ADDR - APU_START) as usize]
+                    | (self.noise.enabled as u8) << 3
+                    | (self.wave.enabled as u8) << 2
+                    | (self.square2.enabled as u8) << 1
+                    | self.square1.enabled as u8
+            }
+            APU_START..=0xFF25 => self.reg(addr) | READ_MASKS[(addr - APU_START) as usize],
+            WAVE_RAM_START..=APU_END => self.wave_ram[(addr - WAVE_RAM_START) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            NR52_ADDR => {
+                let power = val & 0b1000_0000 != 0;
+                if self.power && !power {
+                    // Switching off clears every register and silences every channel.
+                    let (sample_rate, samples) = (self.sample_rate, self.take_samples());
+                    let wave_ram = self.wave_ram;
+                    *self = APU::new();
+                    self.sample_rate = sample_rate;
+                    self.samples = samples;
+                    self.wave_ram = wave_ram;
+                } else if !self.power && power {
+                    self.frame_sequencer_step = 0;
+                }
+                self.power = power;
+            }
+            WAVE_RAM_START..=APU_END => self.wave_ram[(addr - WAVE_RAM_START) as usize] = val,
+            // Whilst the APU is off, the registers can't be written.
+            APU_START..=0xFF25 if self.power => {
+                self.regs[(addr - APU_START) as usize] = val;
+                self.write_channel(addr, val);
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply the side effects of writing a channel's registers.
+    fn write_channel(&mut self, addr: u16, val: u8) {
+        let trigger = val & 0b1000_0000 != 0;
+        let length_enable = val & 0b0100_0000 != 0;
+        match addr {
+            0xFF11 => self.square1.length.counter = 64 - (val & 0x3F) as u16,
+            0xFF12 if val & 0xF8 == 0 => self.square1.enabled = false,
+            0xFF14 => {
+                self.square1.length.enabled = length_enable;
+                if trigger {
+                    self.trigger_square1();
+                }
+            }
+            0xFF16 => self.square2.length.counter = 64 - (val & 0x3F) as u16,
+            0xFF17 if val & 0xF8 == 0 => self.square2.enabled = false,
+            0xFF19 => {
+                self.square2.length.enabled = length_enable;
+                if trigger {
+                    let nr22 = self.reg(0xFF17);
+                    let frequency = self.frequency(0xFF18);
+                    let square = &mut self.square2;
+                    square.enabled = nr22 & 0xF8 != 0;
+                    square.length.trigger(64);
+                    square.timer = (2048 - frequency as u32) * 4;
+                    square.envelope.trigger(nr22);
+                }
+            }
+            0xFF1A if val & 0x80 == 0 => self.wave.enabled = false,
+            0xFF1B => self.wave.length.counter = 256 - val as u16,
+            0xFF1E => {
+                self.wave.length.enabled = length_enable;
+                if trigger {
+                    let frequency = self.frequency(0xFF1D);
+                    self.wave.enabled = self.reg(0xFF1A) & 0x80 != 0;
+                    self.wave.length.trigger(256);
+                    self.wave.timer = (2048 - frequency as u32) * 2;
+                    self.wave.position = 0;
+                }
+            }
+            0xFF20 => self.noise.length.counter = 64 - (val & 0x3F) as u16,
+            0xFF21 if val & 0xF8 == 0 => self.noise.enabled = false,
+            0xFF23 => {
+                self.noise.length.enabled = length_enable;
+                if trigger {
+                    let nr42 = self.reg(0xFF21);
+                    let period = self.noise_period();
+                    let noise = &mut self.noise;
+                    noise.enabled = nr42 & 0xF8 != 0;
+                    noise.length.trigger(64);
+                    noise.timer = period;
+                    noise.lfsr = 0x7FFF;
+                    noise.envelope.trigger(nr42);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn trigger_square1(&mut self) {
+        let nr10 = self.reg(0xFF10);
+        let nr12 = self.reg(0xFF12);
+        let frequency = self.frequency(0xFF13);
+        let square = &mut self.square1;
+        square.enabled = nr12 & 0xF8 != 0;
+        square.length.trigger(64);
+        square.timer = (2048 - frequency as u32) * 4;
+        square.envelope.trigger(nr12);
+
+        let period = (nr10 >> 4) & 0b111;
+        let shift = nr10 & 0b111;
+        square.shadow_frequency = frequency;
+        square.sweep_timer = if period == 0 { 8 } else { period };
+        square.sweep_enabled = period != 0 || shift != 0;
+        if shift != 0 && self.sweep_calculation() > 2047 {
+            self.square1.enabled = false;
+        }
+    }
+
+    /// Work out the next frequency of the sweep.
+    fn sweep_calculation(&self) -> u16 {
+        let nr10 = self.reg(0xFF10);
+        let shadow = self.square1.shadow_frequency;
+        let delta = shadow >> (nr10 & 0b111);
+        if nr10 & 0b1000 != 0 {
+            shadow.wrapping_sub(delta)
+        } else {
+            shadow + delta
+        }
+    }
+
+    fn clock_sweep(&mut self) {
+        let nr10 = self.reg(0xFF10);
+        let period = (nr10 >> 4) & 0b111;
+        let square = &mut self.square1;
+        square.sweep_timer = square.sweep_timer.saturating_sub(1);
+        if square.sweep_timer > 0 {
+            return;
+        }
+        square.sweep_timer = if period == 0 { 8 } else { period };
+        if !square.sweep_enabled || period == 0 {
+            return;
+        }
+        let frequency = self.sweep_calculation();
+        if frequency > 2047 {
+            self.square1.enabled = false;
+        } else if nr10 & 0b111 != 0 {
+            self.square1.shadow_frequency = frequency;
+            self.regs[(0xFF13 - APU_START) as usize] = frequency as u8;
+            let nr14 = &mut self.regs[(0xFF14 - APU_START) as usize];
+            *nr14 = (*nr14 & 0b1111_1000) | (frequency >> 8) as u8;
+            // The new frequency is checked for overflow again straight away.
+            if self.sweep_calculation() > 2047 {
+                self.square1.enabled = false;
+            }
+        }
+    }
+
+    /// The 11-bit frequency held in a channel's NRx3 and NRx4.
+    fn frequency(&self, nrx3: u16) -> u16 {
+        (self.reg(nrx3 + 1) as u16 & 0b111) << 8 | self.reg(nrx3) as u16
+    }
+
+    fn noise_period(&self) -> u32 {
+        let nr43 = self.reg(0xFF22);
+        NOISE_DIVISORS[(nr43 & 0b111) as usize] << (nr43 >> 4)
+    }
+
+    /// Advance the APU by the given number of T-cycles.
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            if self.power {
+                self.step();
+            }
+            self.sample_clock += self.sample_rate;
+            if self.sample_clock >= CPU_HZ {
+                self.sample_clock -= CPU_HZ;
+                let (left, right) = self.mix();
+                self.samples.push(left);
+                self.samples.push(right);
+                self.drop_old_samples();
+            }
+        }
+    }
+
+    /// Throw away the oldest samples once more than a second and a quarter have piled up, leaving
+    /// a second's worth, so that running without anything playing the sound doesn't use up memory.
+    /// They go in a batch so that this stays cheap.
+    fn drop_old_samples(&mut self) {
+        let keep = self.sample_rate as usize * 2;
+        if self.samples.len() > keep + keep / 4 {
+            self.samples.drain(..self.samples.len() - keep);
+        }
+    }
+
+    /// Advance every channel and the frame sequencer by a single T-cycle.
+    fn step(&mut self) {
+        self.frame_sequencer_timer -= 1;
+        if self.frame_sequencer_timer == 0 {
+            self.frame_sequencer_timer = FRAME_SEQUENCER_PERIOD;
+            self.clock_frame_sequencer();
+        }
+
+        let frequency = self.frequency(0xFF13);
+        step_square(&mut self.square1, frequency);
+        let frequency = self.frequency(0xFF18);
+        step_square(&mut self.square2, frequency);
+
+        self.wave.timer = self.wave.timer.saturating_sub(1);
+        if self.wave.timer == 0 {
+            self.wave.timer = (2048 - self.frequency(0xFF1D) as u32) * 2;
+            self.wave.position = (self.wave.position + 1) % 32;
+        }
+
+        self.noise.timer = self.noise.timer.saturating_sub(1);
+        if self.noise.timer == 0 {
+            self.noise.timer = self.noise_period();
+            let lfsr = self.noise.lfsr;
+            let bit = (lfsr ^ (lfsr >> 1)) & 1;
+            self.noise.lfsr = (lfsr >> 1) | (bit << 14);
+            // In 7-bit mode, the feedback also goes into bit 6.
+            if self.reg(0xFF22) & 0b1000 != 0 {
+                self.noise.lfsr = (self.noise.lfsr & !(1 << 6)) | (bit << 6);
+            }
+        }
+    }
+
+    fn clock_frame_sequencer(&mut self) {
+        let step = self.frame_sequencer_step;
+        self.frame_sequencer_step = (step + 1) % 8;
+        if step & 1 == 0 {
+            if self.square1.length.clock() {
+                self.square1.enabled = false;
+            }
+            if self.square2.length.clock() {
+                self.square2.enabled = false;
+            }
+            if self.wave.length.clock() {
+                self.wave.enabled = false;
+            }
+            if self.noise.length.clock() {
+                self.noise.enabled = false;
+            }
+        }
+        if step == 2 || step == 6 {
+            self.clock_sweep();
+        }
+        if step == 7 {
+            self.square1.envelope.clock(self.reg(0xFF12));
+            self.square2.envelope.clock(self.reg(0xFF17));
+            self.noise.envelope.clock(self.reg(0xFF21));
+        }
+    }
+
+    /// The current output of each channel, from 0 to 15, or None if its DAC is off.
+    fn channel_outputs(&self) -> [Option<u8>; 4] {
+        let square = |square: &Square, nrx1: u8, nrx2: u8| {
+            if nrx2 & 0xF8 == 0 {
+                return None;
+            }
+            let high = DUTY_PATTERNS[(nrx1 >> 6) as usize] >> (7 - square.position) & 1 == 1;
+            Some(if square.enabled && high { square.envelope.volume } else { 0 })
+        };
+
+        let wave = if self.reg(0xFF1A) & 0x80 == 0 {
+            None
+        } else {
+            let byte = self.wave_ram[(self.wave.position / 2) as usize];
+            let sample = if self.wave.position & 1 == 0 { byte >> 4 } else { byte & 0x0F };
+            let shift = match (self.reg(0xFF1C) >> 5) & 0b11 {
+                0 => 4,
+                code => code - 1,
+            };
+            Some(if self.wave.enabled { sample >> shift } else { 0 })
+        };
+
+        let noise = if self.reg(0xFF21) & 0xF8 == 0 {
+            None
+        } else {
+            let high = self.noise.lfsr & 1 == 0;
+            Some(if self.noise.enabled && high { self.noise.envelope.volume } else { 0 })
+        };
+
+        [
+            square(&self.square1, self.reg(0xFF11), self.reg(0xFF12)),
+            square(&self.square2, self.reg(0xFF16), self.reg(0xFF17)),
+            wave,
+            noise,
+        ]
+    }
+
+    /// Mix the channels down into a left and right sample, using NR50 and NR51.
+    fn mix(&self) -> (f32, f32) {
+        if !self.power {
+            return (0.0, 0.0);
+        }
+        let nr50 = self.reg(0xFF24);
+        let nr51 = self.reg(0xFF25);
+        let (mut left, mut right) = (0.0, 0.0);
+        for (i, output) in self.channel_outputs().iter().enumerate() {
+            // Each DAC turns its channel's 0-15 into an analogue level from -1 to 1.
+            let analogue = match output {
+                Some(level) => *level as f32 / 7.5 - 1.0,
+                None => continue,
+            };
+            if nr51 & (0x10 << i) != 0 {
+                left += analogue;
+            }
+            if nr51 & (0x01 << i) != 0 {
+                right += analogue;
+            }
+        }
+        let left_volume = (((nr50 >> 4) & 0b111) + 1) as f32 / 8.0;
+        let right_volume = ((nr50 & 0b111) + 1) as f32 / 8.0;
+        (left * left_volume / 4.0, right * right_volume / 4.0)
+    }
+}
+
+/// Advance a square channel by a single T-cycle.
+fn step_square(square: &mut Square, frequency: u16) {
+    square.timer = square.timer.saturating_sub(1);
+    if square.timer == 0 {
+        square.timer = (2048 - frequency as u32) * 4;
+        square.position = (square.position + 1) % 8;
+    }
+}
+
+impl Snapshot for Length {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.counter);
+        w.bool(self.enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.counter = r.u16()?;
+        self.enabled = r.bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Envelope {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.volume);
+        w.u8(self.timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.volume = r.u8()? & 0x0F;
+        self.timer = r.u8()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Square {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.u32(self.timer);
+        w.u8(self.position);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+        w.u16(self.shadow_frequency);
+        w.u8(self.sweep_timer);
+        w.bool(self.sweep_enabled);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.bool()?;
+        self.timer = r.u32()?;
+        self.position = r.u8()? % 8;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)?;
+        self.shadow_frequency = r.u16()?;
+        self.sweep_timer = r.u8()?;
+        self.sweep_enabled = r.bool()?;
+        Ok(())
+    }
+}
+
+impl Snapshot for Wave {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.u32(self.timer);
+        w.u8(self.position);
+        self.length.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.bool()?;
+        self.timer = r.u32()?;
+        self.position = r.u8()? % 32;
+        self.length.load_state(r)
+    }
+}
+
+impl Snapshot for Noise {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bool(self.enabled);
+        w.u32(self.timer);
+        w.u16(self.lfsr);
+        self.length.save_state(w);
+        self.envelope.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = r.bool()?;
+        self.timer = r.u32()?;
+        self.lfsr = r.u16()?;
+        self.length.load_state(r)?;
+        self.envelope.load_state(r)
+    }
+}
+
+/// The sample rate and any samples waiting to be taken are left alone, as they belong to whatever
+/// is playing the sound rather than to the emulated hardware.
+impl Snapshot for APU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.regs);
+        w.bytes(&self.wave_ram);
+        w.bool(self.power);
+        self.square1.save_state(w);
+        self.square2.save_state(w);
+        self.wave.save_state(w);
+        self.noise.save_state(w);
+        w.u32(self.frame_sequencer_timer);
+        w.u8(self.frame_sequencer_step);
+        w.u32(self.sample_clock);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.regs)?;
+        r.bytes(&mut self.wave_ram)?;
+        self.power = r.bool()?;
+        self.square1.load_state(r)?;
+        self.square2.load_state(r)?;
+        self.wave.load_state(r)?;
+        self.noise.load_state(r)?;
+        self.frame_sequencer_timer = r.u32()?;
+        self.frame_sequencer_step = r.u8()? % 8;
+        self.sample_clock = r.u32()?;
+        if self.frame_sequencer_timer == 0 || self.frame_sequencer_timer > FRAME_SEQUENCER_PERIOD {
+            return Err(StateError::Corrupt("frame sequencer timer out of range"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for APU {
+    fn default() -> Self {
+        APU::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers() {
+        let mut apu = APU::new();
+        // Nothing can be written whilst the APU is off.
+        apu.write(0xFF12, 0xF3);
+        assert_eq!(apu.read(0xFF12), 0x00);
+        apu.write(NR52_ADDR, 0x80);
+        assert_eq!(apu.read(NR52_ADDR), 0xF0);
+        apu.write(0xFF12, 0xF3);
+        apu.write(0xFF11, 0x80);
+        assert_eq!(apu.read(0xFF12), 0xF3);
+        assert_eq!(apu.read(0xFF11), 0xBF);
+        // Wave RAM is always accessible, and survives turning the APU off.
+        apu.write(WAVE_RAM_START, 0x12);
+        apu.write(NR52_ADDR, 0x00);
+        assert_eq!(apu.read(0xFF12), 0x00);
+        assert_eq!(apu.read(WAVE_RAM_START), 0x12);
+    }
+
+    #[test]
+    fn trigger_and_length() {
+        let mut apu = APU::new();
+        apu.write(NR52_ADDR, 0x80);
+        apu.write(0xFF17, 0xF0);
+        // A length of one frame sequencer step.
+        apu.write(0xFF16, 0x3F);
+        apu.write(0xFF19, 0xC0);
+        assert_eq!(apu.read(NR52_ADDR) & 0b10, 0b10);
+        apu.tick(FRAME_SEQUENCER_PERIOD);
+        assert_eq!(apu.read(NR52_ADDR) & 0b10, 0);
+    }
+
+    #[test]
+    fn dac_off_disables() {
+        let mut apu = APU::new();
+        apu.write(NR52_ADDR, 0x80);
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x80);
+        assert_eq!(apu.read(NR52_ADDR) & 1, 1);
+        apu.write(0xFF12, 0x00);
+        assert_eq!(apu.read(NR52_ADDR) & 1, 0);
+    }
+
+    #[test]
+    fn sweep_overflow() {
+        let mut apu = APU::new();
+        apu.write(NR52_ADDR, 0x80);
+        apu.write(0xFF12, 0xF0);
+        // Increasing sweep with a shift of 1, from a frequency that overflows straight away.
+        apu.write(0xFF10, 0x11);
+        apu.write(0xFF13, 0xFF);
+        apu.write(0xFF14, 0x87);
+        assert_eq!(apu.read(NR52_ADDR) & 1, 0);
+    }
+
+    #[test]
+    fn sample_rate() {
+        let mut apu = APU::new();
+        apu.set_sample_rate(32_768);
+        apu.tick(CPU_HZ / 64);
+        // One sixty-fourth of a second, in stereo.
+        assert_eq!(apu.take_samples().len(), 512 * 2);
+        assert!(apu.take_samples().is_empty());
+    }
+
+    #[test]
+    fn samples_left_untaken() {
+        let mut apu = APU::new();
+        apu.set_sample_rate(32_768);
+        apu.tick(CPU_HZ * 5);
+        // Only the last second or so is kept.
+        let kept = apu.take_samples().len();
+        assert!((32_768 * 2..=32_768 * 2 * 5 / 4).contains(&kept), "{} samples kept", kept);
+    }
+
+    #[test]
+    fn square_output() {
+        let mut apu = APU::new();
+        apu.write(NR52_ADDR, 0x80);
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0x11);
+        apu.write(0xFF12, 0xF0);
+        // 50% duty at the highest frequency.
+        apu.write(0xFF11, 0x80);
+        apu.write(0xFF13, 0xFF);
+        apu.write(0xFF14, 0x87);
+        apu.tick(CPU_HZ / 100);
+        let samples = apu.take_samples();
+        assert!(samples.iter().any(|&s| s > 0.0));
+        assert!(samples.iter().any(|&s| s < 0.0));
+        assert!(samples.iter().all(|&s| (-1.0..=1.0).contains(&s)));
+    }
+}
@@ -1,9 +1,13 @@
 use std::{error, fmt};
 use std::convert::TryFrom;
 use std::fmt::Formatter;
-use crate::components::register::{BitResult, RegPair};
-use std::num::Wrapping;
-use std::ops::Add;
+use crate::components::bus::{Bus, DMA_ADDR};
+use crate::components::opcodes::{lookup, Condition, Instruction, Mnemonic, Operand};
+use crate::components::register::{Reg16, Reg8, RegisterFile};
+use crate::components::serial::SerialLink;
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter};
+use crate::debugger::{WatchHit, Watchpoint};
+use crate::tracer::Tracer;
 use anyhow::{anyhow, Result}; // Used for anyhow's Result type for all fallible functions in our program. Imports the macro as well.
 use thiserror::Error;
 // Allows us to create custom error types.
@@ -11,148 +15,109 @@ use ux;
 use ux::u4;
 
 pub struct CPU {
-    /// The accumulator register.
-    a: u8,
-    /// The stack pointer.
-    sp: u16,
-    /// The program counter.
-    pc: u16,
+    /// The register file, holding A, F, BC, DE, HL, SP and PC.
+    regs: RegisterFile,
     /// The instruction register. Stores the current instruction.
     ir: u16,
     /// The memory address register. Stores the address that memory must either be read/written from/to.
     mar: u16,
     /// The memory data register. Stores the data retrieved from memory.
     mdr: u16,
-    /// The BC register pair.
-    bc: RegPair,
-    /// The DE register pair.
-    de: RegPair,
-    /// The HL register pair.
-    hl: RegPair,
-    /// The status flag(s) register. This is defined as a Flags struct.
-    flags: Flags,
-    /// The LCD control register.
-    lcd_reg: LCDReg,
-    /// The total memory access space of the DMG unit.
-    pub memory: [u16; 65536],
+    /// The memory bus, through which the CPU reaches memory and every other component.
+    pub(crate) bus: Bus,
     /// The number of cycles clocked so far.
-    pub cycles: u32,
+    pub cycles: u64,
+    /// The interrupt master enable flag (IME).
+    ime: bool,
+    /// Set by EI, which only enables interrupts after the following instruction.
+    ime_scheduled: bool,
+    /// Set by HALT until an interrupt becomes pending.
+    halted: bool,
+    /// Set when HALT is executed with IME clear and an interrupt already pending, in which case the
+    /// next opcode is read twice.
+    halt_bug: bool,
+    /// Set once an illegal opcode has been executed, which hangs the CPU.
+    locked: bool,
+    /// The memory accesses the debugger is waiting for. Only set whilst the debugger is running.
+    pub(crate) watchpoints: Vec<Watchpoint>,
+    /// The first access to set off one of `watchpoints`, until the debugger takes it.
+    pub(crate) watch_hit: Option<WatchHit>,
+    /// Logs each instruction before it runs, if tracing is on.
+    pub(crate) tracer: Option<Tracer>,
+    /// Set when LD B,B is executed, until taken by `take_ld_b_b`.
+    ld_b_b: bool,
+    /// Every M-cycle spent, if they are being recorded for testing.
+    pub(crate) cycle_log: Option<Vec<BusCycle>>,
 }
 
-/// Representation of the status flags within the CPU.
-/// - (Z) Zero flag
-/// - (N) Subtraction flag for BCD
-/// - (H) Half-carry flag for BCD
-/// - (C) Carry flag
-struct Flags {
-    /// Z flag
-    pub zero: bool,
-    /// N flag, for BCD
-    pub subtraction: bool,
-    /// H flag, for BCD
-    pub half_carry: bool,
-    /// C flag
-    pub carry: bool,
-}
-
-impl Flags {
-    pub fn new() -> Self {
-        Flags {
-            zero: false,
-            subtraction: false,
-            half_carry: false,
-            carry: false
-        }
-    }
-
-    pub fn test_flags(&self, sample: &Flags) -> bool {
-        self.zero == sample.zero &&
-        self.subtraction == sample.subtraction &&
-        self.half_carry == sample.half_carry &&
-        self.carry == sample.carry
-    }
-
-    pub fn reset(&mut self) {
-        self.zero = false;
-        self.subtraction = false;
-        self.half_carry = false;
-        self.carry = false;
-    }
-}
-
-/// Representation of the LCD control register.
-struct LCDReg {
-    /// Bit 7 - LCD Display Enable (0=Off, 1=On)
-    pub lcd_enable: bool,
-    /// Bit 6 - Window Tile Map Display Select (0=9800-9BFF, 1=9C00-9FFF)
-    pub window_display_select: bool,
-    /// Bit 5 - Window Display Enable (0=Off, 1=On)
-    pub window_enable: bool,
-    /// Bit 4 - BG & Window Tile Data Select (0=8800-97FF, 1=8000-8FFF)
-    pub bg_window_select: bool,
-    /// Bit 3 - BG Tile Map Display Select (0=9800-9BFF, 1=9C00-9FFF)
-    pub bg_tile_data_select: bool,
-    /// Bit 2 - OBJ (Sprite) Size (0=8x8, 1=8x16)
-    pub sprite_size: bool,
-    /// Bit 1 - OBJ (Sprite) Display Enable (0=Off, 1=On)
-    pub sprite_enable: bool,
-    /// Bit 0 - BG Display (for CGB see below) (0=Off, 1=On)
-    pub bg_display_cgb: bool,
-}
-
-impl LCDReg {
-    pub fn new() -> Self {
-        LCDReg {
-            lcd_enable: false,
-            window_display_select: false,
-            window_enable: false,
-            bg_window_select: false,
-            bg_tile_data_select: false,
-            sprite_size: false,
-            sprite_enable: false,
-            bg_display_cgb: false
-        }
-    }
-}
-
-// Lifetime parameter used here as we need to know the lifetime of the register-pair we are borrowing from.
-pub enum AddressingMode<'a> {
-    Implied, // Stuff like CPL and LD SP,IY
-    ImmediateEight, /// Found in the next instruction.
-    ImmediateSixteen, /// Found in the next two instructions.
-    UnsignedEight, /// Used particularly for 0xE0 and 0xF0, where an offset of 0xFF00 is used.
-    AddressSixteen(u16),
-    SignedEight,
-    RegisterPairDirect(&'a RegPair),
-    RegisterDirect(&'a RegPair, bool),
-}
-
-/// This is used to specify which register pair we choose to operate on.
-/// It alleviates the need for a mutable reference to a register pair whilst also having a mutable reference to self (the CPU).
-enum RegisterPairs {
-    BC,
-    DE,
-    HL,
-}
-
-enum Registers {
-    A,
-    B,
-    C,
-    D,
-    E,
-    H,
-    L,
-    SP,
-}
-
-enum RotateDirection {
-    Left,
-    Right,
+/// What the CPU did with the bus during a single M-cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BusCycle {
+    Idle,
+    Read(u16, u8),
+    Write(u16, u8),
 }
 
+/// The address of the interrupt flag register (IF).
+pub const IF_ADDR: u16 = 0xFF0F;
+/// The address of the interrupt enable register (IE).
+pub const IE_ADDR: u16 = 0xFFFF;
+/// The bit within IF which requests the V-blank interrupt.
+pub const VBLANK_INTERRUPT: u8 = 0b0000_0001;
+/// The bit within IF which requests the LCD STAT interrupt.
+pub const STAT_INTERRUPT: u8 = 0b0000_0010;
+/// The bit within IF which requests the timer interrupt.
+pub const TIMER_INTERRUPT: u8 = 0b0000_0100;
+/// The bit within IF which requests the serial interrupt.
+pub const SERIAL_INTERRUPT: u8 = 0b0000_1000;
+/// The bit within IF which requests the joypad interrupt.
+pub const JOYPAD_INTERRUPT: u8 = 0b0001_0000;
+/// The DMG boot ROM is 256 bytes, mapped over the start of the cartridge.
+pub const BOOT_ROM_SIZE: usize = 0x100;
+
+/// The values left in the I/O registers by the DMG boot ROM once it hands over to the cartridge.
+/// DIV and DMA aren't included, as writing to them doesn't set their value.
+const POST_BOOT_IO: [(u16, u8); 34] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF26, 0xF1), // NR52
+    (0xFF40, 0x91), // LCDC
+    (0xFF41, 0x85), // STAT
+    (0xFF47, 0xFC), // BGP
+    (0xFF48, 0xFF), // OBP0
+    (0xFF49, 0xFF), // OBP1
+    (0xFFFF, 0x00), // IE
+];
+
+/// Raised when an instruction can't be executed, such as when the opcode table pairs a mnemonic
+/// with operands its handler doesn't understand.
 #[derive(Debug, Clone)]
-struct OpcodeError {
+pub struct OpcodeError {
     info: String,
     opcode: u8,
 }
@@ -164,6 +129,11 @@ impl OpcodeError {
             opcode
         }
     }
+
+    /// The opcode which couldn't be executed.
+    pub fn opcode(&self) -> u8 {
+        self.opcode
+    }
 }
 
 impl fmt::Display for OpcodeError {
@@ -176,690 +146,758 @@ impl error::Error for OpcodeError {}
 
 impl CPU {
 
+    /// Create a CPU with every register and all of memory cleared, and the PC at 0x0000.
+    /// This is neither the state at power-on nor the state after booting; it is mostly useful for
+    /// testing individual instructions. See `CPU::post_boot` and `CPU::with_boot_rom`.
     pub fn new() -> Self {
         CPU {
-            a: 0,
-            sp: 0,
-            pc: 0,
+            regs: RegisterFile::new(),
             ir: 0,
             mar: 0,
             mdr: 0,
-            bc: RegPair::new(),
-            de: RegPair::new(),
-            hl: RegPair::new(),
-            flags: Flags::new(),
-            lcd_reg: LCDReg::new(),
-            memory: [0; 65536],
-            cycles: 0
+            bus: Bus::new(),
+            cycles: 0,
+            ime: false,
+            ime_scheduled: false,
+            halted: false,
+            halt_bug: false,
+            locked: false,
+            watchpoints: Vec::new(),
+            watch_hit: None,
+            tracer: None,
+            ld_b_b: false,
+            cycle_log: None,
         }
     }
 
+    /// Create a CPU in the state the DMG boot ROM leaves it in when it hands over to the cartridge,
+    /// with the PC at the cartridge entry point (0x0100).
+    pub fn post_boot() -> Self {
+        let mut cpu = CPU::new();
+        cpu.regs.set16(Reg16::AF, 0x01B0);
+        cpu.regs.set16(Reg16::BC, 0x0013);
+        cpu.regs.set16(Reg16::DE, 0x00D8);
+        cpu.regs.set16(Reg16::HL, 0x014D);
+        cpu.regs.set16(Reg16::SP, 0xFFFE);
+        cpu.regs.set16(Reg16::PC, 0x0100);
+        for &(addr, val) in POST_BOOT_IO.iter() {
+            cpu.write_byte(addr, val);
+        }
+        cpu.bus.timer.set_counter(0xABCC);
+        cpu
+    }
+
+    /// Create a CPU which will start by running the given boot ROM from 0x0000. The boot ROM stays
+    /// mapped over the start of the cartridge until it writes to 0xFF50.
+    pub fn with_boot_rom(rom: &[u8]) -> Result<Self> {
+        if rom.len() != BOOT_ROM_SIZE {
+            return Err(anyhow!(BootRomError(rom.len())));
+        }
+        let mut cpu = CPU::new();
+        cpu.bus.map_boot_rom(rom.to_vec());
+        Ok(cpu)
+    }
+
+    /// Plug a device into the link port, replacing whatever was connected before.
+    pub fn set_serial_link(&mut self, link: Box<dyn SerialLink + Send>) {
+        self.bus.serial.set_link(link);
+    }
+
+    /// Start logging every instruction with the given tracer, replacing any tracer already set.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stop tracing, handing back the tracer.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Returns true if LD B,B has been executed since this was last called. LD B,B does nothing,
+    /// which makes it the conventional software breakpoint; Mooneye's tests run it once they have
+    /// finished.
+    pub fn take_ld_b_b(&mut self) -> bool {
+        std::mem::take(&mut self.ld_b_b)
+    }
+
+    /// The register file, for inspecting the CPU's state.
+    pub fn registers(&self) -> &RegisterFile {
+        &self.regs
+    }
+
+    /// The register file, for changing the CPU's state from outside, such as in a debugger.
+    pub fn registers_mut(&mut self) -> &mut RegisterFile {
+        &mut self.regs
+    }
+
+    /// The interrupt master enable flag (IME).
+    pub fn ime(&self) -> bool {
+        self.ime
+    }
+
+    /// Returns true whilst the CPU is halted, waiting for an interrupt.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Returns true once an illegal opcode has hung the CPU.
+    pub fn is_locked(&self) -> bool {
+        self.locked
+    }
+
+    /// Run the CPU for a single instruction, or for the time it takes to service an interrupt.
+    /// The rest of the system is ticked along with every M-cycle as it happens, so by the time this
+    /// returns everything is up to date with the CPU.
     pub fn cycle(&mut self) {
-        // Fetch opcode
-        self.ir = self.memory[self.pc as usize];
-        // println!("Opcode found: {:#2x}", self.ir);
-        // Program counter is incremented to enable operand reading.
-        self.pc += 1;
-        // Decode the opcode and execute.
-        self.decode_execute();
-    }
-
-    /// Given the stored opcode, this function will decode this using pattern matching and will hence
-    fn decode_execute(&mut self) {
-        // Match on the current opcode.
-        match self.ir {
-            0x00 => { self.pc += 0; self.cycles += 4; }  // NOP
-            0x01 => {
-                // LD BC,d16
-                self.mdr = self.read_memory(AddressingMode::ImmediateSixteen);
-                self.ld_reg_pair(RegisterPairs::BC);
-            }
-            0x02 => {
-                // LD (BC), A
-                self.mdr = self.a as u16;
-                self.mar = self.bc.get_wide();
-                self.ld_memory();
-                self.pc += 0;
-                self.cycles += 8;
-            }
-            0x03 => { self.bc.set_wide(self.bc.get_wide() + 1); self.pc += 0; self.cycles += 8; }  // INC BC
-            0x04 => { self.inc_reg_8(Registers::B).unwrap(); self.pc += 0; self.cycles += 4; }  // INC B
-            0x05 => { self.dec_reg_8(Registers::B).unwrap(); self.pc += 0; self.cycles += 4;}  // DEC B
-            0x06 => {
-                // LD B,d8
-                self.mdr = self.read_memory(AddressingMode::ImmediateEight);
-                self.bc.set_high_bin(self.mdr as u8);
-                self.pc += 1;
-                self.cycles += 8;
-            }
-            0x07 => { self.rotate_a(RotateDirection::Left, false); self.pc += 0; self.cycles += 4; }  // RLCA
-            0x08 => {
-                // LD (a16),SP
-                // Load the lower byte of SP at a16.
-                self.mdr = (self.sp << 8) >> 8;
-                self.mar = self.read_memory(AddressingMode::ImmediateSixteen);
-                // println!("CPU MDR: {}, CPU MAR: {}", self.mdr, self.mar);
-                self.ld_memory();
-                // Load the upper byte of SP at a16 + 1;
-                self.mdr = self.sp >> 8;
-                self.mar = self.mar + 1;
-                // println!("CPU MDR: {}, CPU MAR: {}", self.mdr, self.mar);
-                self.ld_memory();
-                // Increment PC and cycles accordingly.
-                self.pc += 2;
-                self.cycles += 20;
-            }
-            0x09 => {}  // ADD HL,BC
-            0x0A => {
-                // LD A,(BC)
-                // Collect address and data
-                self.mar = self.bc.get_wide();
-                self.mdr = self.read_memory(AddressingMode::ImmediateSixteen);
-
-                // Load bits into A.
-                self.a = self.mdr as u8;
-
-                // Increment PC and cycles as appropriate.
-                self.pc += 0;
-                self.cycles += 8;
-            }
-            0x0B => { self.bc.set_wide(self.bc.get_wide() - 1); self.pc += 0; self.cycles += 8; }  // DEC BC
-            0x0C => { self.inc_reg_8(Registers::C).unwrap(); self.pc += 0; self.cycles += 8; }  // INC C
-            0x0D => { self.dec_reg_8(Registers::C).unwrap(); self.pc += 0; self.cycles += 8; }  // DEC C
-            0x0E => {
-                // LD C,d8
-                self.mdr = self.read_memory(AddressingMode::ImmediateEight);
-                self.bc.set_low_bin(self.mdr as u8);
-                self.pc += 1;
-                self.cycles += 8;
-            }
-            0x0F => { self.rotate_a(RotateDirection::Right, false); self.pc += 0; self.cycles += 4; }  // RRCA
-
-            0x10 => {}
-            0x11 => {
-                // LD DE,d16
-                self.mdr = self.read_memory(AddressingMode::ImmediateSixteen);
-                println!("PC: {}", self.pc);
-                self.ld_reg_pair(RegisterPairs::DE);
-                println!("PC: {}", self.pc);
+        let start = self.cycles;
+        if self.handle_interrupts() {
+            // An interrupt was serviced, or the CPU is halted and there's nothing to do.
+        } else {
+            // EI only takes effect once the instruction following it has been fetched.
+            if self.ime_scheduled {
+                self.ime_scheduled = false;
+                self.ime = true;
             }
-            0x12 => {
-                self.mar = self.bc.get_wide();
-                self.memory[self.mar as usize] = self.a as u16;
-                self.pc += 0;
-                self.cycles += 8;
+            if let Some(tracer) = &mut self.tracer {
+                let bus = &self.bus;
+                tracer.trace(&self.regs, |addr| bus.read(addr));
             }
-            0x13 => {}
-            0x14 => { self.inc_reg_8(Registers::D).unwrap(); self.pc += 0; self.cycles += 4; } // INC D
-            0x15 => { self.dec_reg_8(Registers::D).unwrap(); self.pc += 0; self.cycles += 4; } //  DEC D
-            0x16 => {
-                // LD D, d8
-                self.mdr = self.read_memory(AddressingMode::ImmediateEight);
-                self.de.set_high_bin(self.mdr as u8);
-                self.pc += 1;
-                self.cycles += 8;
+            // Fetch opcode
+            self.ir = self.read_cycle(self.regs.pc) as u16;
+            // println!("Opcode found: {:#2x}", self.ir);
+            // Program counter is incremented to enable operand reading, unless the HALT bug has
+            // caused it to miss the increment.
+            if self.halt_bug {
+                self.halt_bug = false;
+            } else {
+                self.regs.pc = self.regs.pc.wrapping_add(1);
             }
-            0x17 => {
-                // RLA
+            // Decode the opcode and execute.
+            self.decode_execute(start);
+        }
+    }
 
-            }
-            0x18 => {}
-            0x19 => {}
-            0x1A => {}
-            0x1B => { self.bc.set_wide(self.de.get_wide() - 1); self.pc += 0; self.cycles += 8; } // DEC DE
-            0x1C => { self.inc_reg_8(Registers::E).unwrap(); self.pc += 0; self.cycles += 8; } // INC E
-            0x1D => { self.dec_reg_8(Registers::E).unwrap(); self.pc += 0; self.cycles += 8;} // DEC E
-            0x1E => {
-                // LD E, d8
-                self.mdr = self.read_memory(AddressingMode::ImmediateEight);
-                self.de.set_low_bin(self.mdr as u8);
-                self.pc += 1;
-                self.cycles += 8;
-            }
-            0x1F => {}
+    /// Wake the CPU from HALT and service any pending interrupt, if interrupts are enabled.
+    /// Returns true if this took the place of executing an instruction.
+    fn handle_interrupts(&mut self) -> bool {
+        if self.locked {
+            // An illegal opcode has hung the CPU; the only way out is a reset.
+            self.idle();
+            return true;
+        }
 
-            0x20 => {}
-            0x21 => {
-                // LD HL,d16
-                self.mdr = self.read_memory(AddressingMode::ImmediateSixteen);
-                self.ld_reg_pair(RegisterPairs::HL);
-            }
-            0x22 => {
-                self.mar = self.de.get_wide();
-                self.memory[self.mar as usize] = self.a as u16;
-                self.pc += 0;
-                self.cycles += 8;
-            }
-            0x23 => {}
-            0x24 => { self.inc_reg_8(Registers::H).unwrap(); self.pc += 0; self.cycles += 4; } // INC H
-            0x25 => {}
-            0x26 => {}
-            0x27 => {}
-            0x28 => {}
-            0x29 => {}
-            0x2A => {}
-            0x2B => { self.hl.set_wide(self.hl.get_wide() - 1); self.pc += 0; self.cycles += 8; } // DEC HL
-            0x2C => { self.inc_reg_8(Registers::L).unwrap(); self.pc += 0; self.cycles += 4;} // INC L
-            0x2D => { self.dec_reg_8(Registers::L).unwrap(); self.pc += 0; self.cycles += 4; } // DEC L
-            0x2E => {
-                // LD L, d8
-                self.mdr = self.read_memory(AddressingMode::ImmediateEight);
-                self.hl.set_high_bin(self.mdr as u8);
-                self.pc += 1;
-                self.cycles += 8;
+        let pending = self.bus.pending_interrupts();
+        if self.halted {
+            if pending == 0 {
+                self.idle();
+                return true;
             }
-            0x2F => {}
-
-            0x30 => {}
-            0x31 => {
-                // LD HL,d16
-                self.mdr = self.read_memory(AddressingMode::ImmediateSixteen);
-                self.sp = self.mdr;
-                self.pc += 2;
-                self.cycles += 12;
-            }
-            0x32 => {}
-            0x33 => {}
-            0x34 => {}
-            0x35 => {}
-            0x36 => {}
-            0x37 => {}
-            0x38 => {}
-            0x39 => {}
-            0x3A => {}
-            0x3B => {}
-            0x3C => {
-                let (a_val, flags) = self.inc_a_sp(&self.a).unwrap();
-                self.a = a_val;
-                self.flags = flags;
-                self.pc += 0;
-                self.cycles += 12;
-            }
-            0x3D => {}
-            0x3E => {}
-            0x3F => {}
-
-            0x40 => {}
-            0x41 => {}
-            0x42 => {}
-            0x43 => {}
-            0x44 => {}
-            0x45 => {}
-            0x46 => {}
-            0x47 => {}
-            0x48 => {}
-            0x49 => {}
-            0x4A => {}
-            0x4B => {}
-            0x4C => {}
-            0x4D => {}
-            0x4E => {}
-            0x4F => {}
-
-            0x50 => {}
-            0x51 => {}
-            0x52 => {}
-            0x53 => {}
-            0x54 => {}
-            0x55 => {}
-            0x56 => {}
-            0x57 => {}
-            0x58 => {}
-            0x59 => {}
-            0x5A => {}
-            0x5B => {}
-            0x5C => {}
-            0x5D => {}
-            0x5E => {}
-            0x5F => {}
-
-            0x60 => {}
-            0x61 => {}
-            0x62 => {}
-            0x63 => {}
-            0x64 => {}
-            0x65 => {}
-            0x66 => {}
-            0x67 => {}
-            0x68 => {}
-            0x69 => {}
-            0x6A => {}
-            0x6B => {}
-            0x6C => {}
-            0x6D => {}
-            0x6E => {}
-            0x6F => {}
-
-            0x70 => {}
-            0x71 => {}
-            0x72 => {}
-            0x73 => {}
-            0x74 => {}
-            0x75 => {}
-            0x76 => {}
-            0x77 => {}
-            0x78 => {}
-            0x79 => {}
-            0x7A => {}
-            0x7B => {}
-            0x7C => {}
-            0x7D => {}
-            0x7E => {}
-            0x7F => {}
-
-            0x80 => {}
-            0x81 => {}
-            0x82 => {}
-            0x83 => {}
-            0x84 => {}
-            0x85 => {}
-            0x86 => {}
-            0x87 => {}
-            0x88 => {}
-            0x89 => {}
-            0x8A => {}
-            0x8B => {}
-            0x8C => {}
-            0x8D => {}
-            0x8E => {}
-            0x8F => {}
-
-            0x90 => {}
-            0x91 => {}
-            0x92 => {}
-            0x93 => {}
-            0x94 => {}
-            0x95 => {}
-            0x96 => {}
-            0x97 => {}
-            0x98 => {}
-            0x99 => {}
-            0x9A => {}
-            0x9B => {}
-            0x9C => {}
-            0x9D => {}
-            0x9E => {}
-            0x9F => {}
-
-            0xA0 => {}
-            0xA1 => {}
-            0xA2 => {}
-            0xA3 => {}
-            0xA4 => {}
-            0xA5 => {}
-            0xA6 => {}
-            0xA7 => {}
-            0xA8 => {}
-            0xA9 => {}
-            0xAA => {}
-            0xAB => {}
-            0xAC => {}
-            0xAD => {}
-            0xAE => {}
-            0xAF => {}
-
-            0xB0 => {}
-            0xB1 => {}
-            0xB2 => {}
-            0xB3 => {}
-            0xB4 => {}
-            0xB5 => {}
-            0xB6 => {}
-            0xB7 => {}
-            0xB8 => {}
-            0xB9 => {}
-            0xBA => {}
-            0xBB => {}
-            0xBC => {}
-            0xBD => {}
-            0xBE => {}
-            0xBF => {}
-
-            0xC0 => {}
-            0xC1 => {}
-            0xC2 => {}
-            0xC3 => {}
-            0xC4 => {}
-            0xC5 => {}
-            0xC6 => {}
-            0xC7 => {}
-            0xC8 => {}
-            0xC9 => {}
-            0xCA => {}
-            0xCB => {}
-            0xCC => {}
-            0xCD => {}
-            0xCE => {}
-            0xCF => {}
-
-            0xD0 => {}
-            0xD1 => {}
-            0xD2 => {}
-            0xD3 => {}
-            0xD4 => {}
-            0xD5 => {}
-            0xD6 => {}
-            0xD7 => {}
-            0xD8 => {}
-            0xD9 => {}
-            0xDA => {}
-            0xDB => {}
-            0xDC => {}
-            0xDD => {}
-            0xDE => {}
-            0xDF => {}
-
-            0xE0 => {}
-            0xE1 => {}
-            0xE2 => {}
-            0xE3 => {}
-            0xE4 => {}
-            0xE5 => {}
-            0xE6 => {}
-            0xE7 => {}
-            0xE8 => {}
-            0xE9 => {}
-            0xEA => {}
-            0xEB => {}
-            0xEC => {}
-            0xED => {}
-            0xEE => {}
-            0xEF => {}
-
-            0xF0 => {}
-            0xF1 => {}
-            0xF2 => {}
-            0xF3 => {}
-            0xF4 => {}
-            0xF5 => {}
-            0xF6 => {}
-            0xF7 => {}
-            0xF8 => {}
-            0xF9 => {}
-            0xFA => {}
-            0xFB => {}
-            0xFC => {}
-            0xFD => {}
-            0xFE => {}
-            0xFF => {}
-            _ => {
-                // NOP
+            // Any pending interrupt will wake the CPU, even if IME is clear.
+            self.halted = false;
+        }
+
+        if !self.ime || pending == 0 {
+            return false;
+        }
+
+        // Dispatch takes five M-cycles: two internal, two to push the PC and one to jump.
+        self.ime = false;
+        self.idle();
+        self.idle();
+        self.regs.sp = self.regs.sp.wrapping_sub(1);
+        self.write_cycle(self.regs.sp, msb(self.regs.pc));
+        // The interrupt to service is only decided after the high byte has been pushed, so if that
+        // push overwrote IE then the interrupt can be cancelled, leaving the CPU to jump to 0x0000.
+        let pending = self.bus.pending_interrupts();
+        self.regs.sp = self.regs.sp.wrapping_sub(1);
+        self.write_cycle(self.regs.sp, lsb(self.regs.pc));
+        self.regs.pc = if pending == 0 {
+            0x0000
+        } else {
+            // The lowest bit has the highest priority.
+            let bit = pending.trailing_zeros() as u16;
+            self.bus.clear_interrupt(1 << bit);
+            0x0040 + bit * 8
+        };
+        self.idle();
+        true
+    }
+
+    /// Spend an M-cycle which doesn't access memory, ticking the rest of the system along with it.
+    fn idle(&mut self) {
+        self.cycles += 4;
+        self.bus.tick(4);
+        if let Some(log) = &mut self.cycle_log {
+            log.push(BusCycle::Idle);
+        }
+    }
+
+    /// Record the access made during the M-cycle just spent, if cycles are being recorded.
+    fn log_access(&mut self, access: BusCycle) {
+        if let Some(cycle) = self.cycle_log.as_mut().and_then(|log| log.last_mut()) {
+            *cycle = access;
+        }
+    }
+
+    /// Spend an M-cycle reading a byte from the bus. Whilst OAM DMA is running, the CPU can only
+    /// reach HRAM.
+    fn read_cycle(&mut self, addr: u16) -> u8 {
+        self.idle();
+        if self.bus.dma_active() && !(0xFF80..=0xFFFE).contains(&addr) {
+            return 0xFF;
+        }
+        let val = self.bus.read(addr);
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, val, false);
+        }
+        self.log_access(BusCycle::Read(addr, val));
+        val
+    }
+
+    /// Spend an M-cycle writing a byte to the bus.
+    fn write_cycle(&mut self, addr: u16, val: u8) {
+        self.idle();
+        if self.bus.dma_active() && !(0xFF80..=0xFFFE).contains(&addr) && addr != DMA_ADDR {
+            return;
+        }
+        if !self.watchpoints.is_empty() {
+            self.check_watchpoints(addr, val, true);
+        }
+        self.log_access(BusCycle::Write(addr, val));
+        self.bus.write(addr, val);
+    }
+
+    /// Note the access if it sets off a watchpoint and nothing else has since the debugger last
+    /// looked.
+    fn check_watchpoints(&mut self, addr: u16, value: u8, write: bool) {
+        if self.watch_hit.is_none() && self.watchpoints.iter().any(|wp| wp.matches(addr, write)) {
+            self.watch_hit = Some(WatchHit { addr, value, write });
+        }
+    }
+
+    /// Read a single byte from the address space, taking memory-mapped I/O into account.
+    /// No time passes; this is for inspecting memory rather than for use by instructions.
+    pub fn read_byte(&self, addr: u16) -> u8 {
+        self.bus.read(addr)
+    }
+
+    /// Write a single byte to the address space, taking memory-mapped I/O into account.
+    /// No time passes; this is for setting memory up rather than for use by instructions.
+    pub fn write_byte(&mut self, addr: u16, val: u8) {
+        self.bus.write(addr, val);
+    }
+
+    /// Look up the opcode in the instruction register, fetch its operands into the MDR and execute
+    /// it. The instruction table determines how many operand bytes to fetch and how long the
+    /// instruction takes, so the handlers themselves only need to implement what it does.
+    ///
+    /// Each memory access takes its own M-cycle as it happens. Handlers only spend internal
+    /// M-cycles where they come before an access; any left over at the end of the instruction are
+    /// made up here from the table, starting from the cycle count `start` at which it was fetched.
+    fn decode_execute(&mut self, start: u64) {
+        let mut opcode = self.ir as u8;
+        let prefixed = opcode == 0xCB;
+        if prefixed {
+            // The real opcode follows the prefix; keep both in the IR.
+            opcode = self.read_cycle(self.regs.pc);
+            self.regs.pc = self.regs.pc.wrapping_add(1);
+            self.ir = 0xCB00 | opcode as u16;
+        }
+        let instr = lookup(opcode, prefixed);
+
+        // Operands are stored little-endian straight after the opcode.
+        let operand_len = instr.length - if prefixed { 2 } else { 1 };
+        self.mdr = 0;
+        if instr.mnemonic == Mnemonic::Stop {
+            // The byte after STOP is skipped over without being read.
+            self.regs.pc = self.regs.pc.wrapping_add(operand_len as u16);
+        } else {
+            for i in 0..operand_len {
+                self.mdr |= (self.read_cycle(self.regs.pc) as u16) << (8 * i);
+                self.regs.pc = self.regs.pc.wrapping_add(1);
             }
         }
 
+        // The opcode table decides the operands, so a handler turning them down is a bug in one or
+        // the other.
+        let taken = self.execute(instr)
+            .unwrap_or_else(|e| panic!("Couldn't execute opcode ${:02X} ({}): {}", self.ir, instr.mnemonic, e));
+        if self.ir == 0x40 {
+            self.ld_b_b = true;
+        }
+        let end = start + if taken { instr.branch_cycles } else { instr.cycles } as u64;
+        debug_assert!(self.cycles <= end, "{} took longer than the opcode table allows", instr.mnemonic);
+        while self.cycles < end {
+            self.idle();
+        }
     }
 
-    /// Will return the correct value from memory that shall be stored in the memory data register.
-    /// This function will not modify the PC (indicated by the non-mutable reference to self).
-    /// This decision has been made so that read_memory() can be used to read a value directly as well
-    /// as for debugging purposes.
-    pub fn read_memory(&self, mode: AddressingMode) -> u16 {
-        let mem_val: u16 = match mode {
-            AddressingMode::Implied => {
-                // We leave the CPU exactly as it was before.
-                // We may later on "automate" the procedure of setting the MDR based on the result
-                // of reading memory, and hence it is ideal to return the original value of the MDR.
-                self.mdr
+    /// Carry out an instruction whose operands have already been fetched into the MDR.
+    /// Returns true if a conditional branch was taken, so that the right timing can be applied.
+    fn execute(&mut self, instr: &Instruction) -> Result<bool, OpcodeError> {
+        let operands = instr.operands;
+        match instr.mnemonic {
+            Mnemonic::Nop => {}
+            Mnemonic::Ld | Mnemonic::Ldh => match operands {
+                // LD HL,SP+e8
+                [Operand::R16(Reg16::HL), Operand::SpOffset] => {
+                    let val = self.add_sp_offset();
+                    self.regs.set16(Reg16::HL, val);
+                }
+                // LD (a16),SP stores SP little-endian.
+                [Operand::A16, Operand::R16(Reg16::SP)] => {
+                    self.mar = self.mdr;
+                    self.write_cycle(self.mar, lsb(self.regs.sp));
+                    self.write_cycle(self.mar.wrapping_add(1), msb(self.regs.sp));
+                }
+                // LD rr,n16 and LD SP,HL
+                [Operand::R16(dst), src] => {
+                    let val = self.read_operand_16(*src)?;
+                    self.regs.set16(*dst, val);
+                }
+                [dst, src] => {
+                    let val = self.read_operand_8(*src)?;
+                    self.write_operand_8(*dst, val)?;
+                }
+                _ => return Err(self.operand_error(instr)),
+            },
+            Mnemonic::Inc => match operands {
+                // 16-bit increments don't affect the flags.
+                [Operand::R16(reg)] => self.regs.set16(*reg, self.regs.get16(*reg).wrapping_add(1)),
+                [target] => {
+                    let val = self.read_operand_8(*target)?;
+                    let result = self.inc_8(val);
+                    self.write_operand_8(*target, result)?;
+                }
+                _ => return Err(self.operand_error(instr)),
+            },
+            Mnemonic::Dec => match operands {
+                [Operand::R16(reg)] => self.regs.set16(*reg, self.regs.get16(*reg).wrapping_sub(1)),
+                [target] => {
+                    let val = self.read_operand_8(*target)?;
+                    let result = self.dec_8(val);
+                    self.write_operand_8(*target, result)?;
+                }
+                _ => return Err(self.operand_error(instr)),
+            },
+            Mnemonic::Add => match operands {
+                [Operand::R16(Reg16::HL), Operand::R16(src)] => self.add_hl(self.regs.get16(*src)),
+                [Operand::R16(Reg16::SP), Operand::S8] => self.regs.sp = self.add_sp_offset(),
+                [_, src] => {
+                    let val = self.read_operand_8(*src)?;
+                    self.add_8(val, false);
+                }
+                _ => return Err(self.operand_error(instr)),
+            },
+            Mnemonic::Adc | Mnemonic::Sub | Mnemonic::Sbc | Mnemonic::And |
+            Mnemonic::Xor | Mnemonic::Or | Mnemonic::Cp => {
+                // The accumulator is always the destination, so only the source needs reading.
+                let val = match operands {
+                    [_, src] => self.read_operand_8(*src)?,
+                    _ => return Err(self.operand_error(instr)),
+                };
+                let carry = self.regs.flags.carry;
+                match instr.mnemonic {
+                    Mnemonic::Adc => self.add_8(val, carry),
+                    Mnemonic::Sub => self.regs.a = self.sub_8(val, false),
+                    Mnemonic::Sbc => self.regs.a = self.sub_8(val, carry),
+                    // CP is a subtraction which throws away the result.
+                    Mnemonic::Cp => { self.sub_8(val, false); }
+                    _ => self.logic_8(instr.mnemonic, val),
+                }
             }
-            AddressingMode::ImmediateEight => {
-                self.memory[self.pc as usize] as u16
+            Mnemonic::Rlca | Mnemonic::Rrca | Mnemonic::Rla | Mnemonic::Rra => {
+                let op = match instr.mnemonic {
+                    Mnemonic::Rlca => Mnemonic::Rlc,
+                    Mnemonic::Rrca => Mnemonic::Rrc,
+                    Mnemonic::Rla => Mnemonic::Rl,
+                    _ => Mnemonic::Rr,
+                };
+                self.regs.a = self.shift_8(op, self.regs.a);
+                // Unlike their CB-prefixed counterparts, these always clear Z.
+                self.regs.flags.zero = false;
             }
-            AddressingMode::ImmediateSixteen => {
-                // Upper bytes are in first byte of memory.
-                let mut val = self.memory[(self.pc + 1) as usize];
-                // We now collect the upper bytes from the second byte in memory.
-                // println!("{:0x}", val);
-                val <<= 8;
-                val = val + self.memory[self.pc as usize];
-                // println!("{:0x}", val);
-                // We now combine the two bytes together.
-                val
+            Mnemonic::Daa => self.decimal_adjust(),
+            Mnemonic::Cpl => {
+                self.regs.a = !self.regs.a;
+                self.regs.flags.subtraction = true;
+                self.regs.flags.half_carry = true;
             }
-            AddressingMode::UnsignedEight => {
-                // This mode only uses the operand as an offset for 0xFF00, and hence we only need to add the value to
-                self.memory[(0xFF00 + self.pc) as usize]
+            Mnemonic::Scf | Mnemonic::Ccf => {
+                self.regs.flags.carry = instr.mnemonic == Mnemonic::Scf || !self.regs.flags.carry;
+                self.regs.flags.subtraction = false;
+                self.regs.flags.half_carry = false;
             }
-            AddressingMode::AddressSixteen(val) => {
-                self.memory[val as usize]
+            Mnemonic::Jr => {
+                if !self.condition_met(operands) {
+                    return Ok(false);
+                }
+                // The offset is relative to the end of this instruction, which the PC already points at.
+                let offset = self.mdr as u8 as i8;
+                self.regs.pc = self.regs.pc.wrapping_add(offset as u16);
+                return Ok(true);
             }
-            AddressingMode::SignedEight => {
-                // This will take the signed operand in memory, and convert it from TC to an unsigned 16 bit integer.
-                from_signed_byte(self.memory[self.pc as usize] as u8) as u16
+            Mnemonic::Jp => {
+                if let [Operand::R16(Reg16::HL)] = operands {
+                    self.regs.pc = self.regs.get16(Reg16::HL);
+                    return Ok(true);
+                }
+                if !self.condition_met(operands) {
+                    return Ok(false);
+                }
+                self.regs.pc = self.mdr;
+                return Ok(true);
             }
-            AddressingMode::RegisterPairDirect(reg) => {
-                self.memory[reg.get_wide() as usize]
+            Mnemonic::Call => {
+                if !self.condition_met(operands) {
+                    return Ok(false);
+                }
+                self.push_16(self.regs.pc);
+                self.regs.pc = self.mdr;
+                return Ok(true);
+            }
+            Mnemonic::Ret => {
+                if instr.is_conditional() {
+                    // Checking the condition takes an M-cycle of its own.
+                    self.idle();
+                }
+                if !self.condition_met(operands) {
+                    return Ok(false);
+                }
+                self.regs.pc = self.pop_16();
+                return Ok(true);
             }
-            AddressingMode::RegisterDirect(reg, is_high) => {
-                // We will read from the memory address in either the high or low byte of the RegPair
-                if is_high {
-                    // println!("Reg value is {:#04x}", reg.get_high());
-                    self.memory[reg.get_high() as usize]
+            Mnemonic::Reti => {
+                self.regs.pc = self.pop_16();
+                // Unlike EI, RETI enables interrupts straight away.
+                self.ime = true;
+            }
+            Mnemonic::Rst => match operands {
+                [Operand::Vector(vector)] => {
+                    self.push_16(self.regs.pc);
+                    self.regs.pc = *vector as u16;
+                }
+                _ => return Err(self.operand_error(instr)),
+            },
+            Mnemonic::Push => match operands {
+                [Operand::R16(reg)] => self.push_reg_16(*reg),
+                _ => return Err(self.operand_error(instr)),
+            },
+            Mnemonic::Pop => match operands {
+                [Operand::R16(reg)] => self.pop_reg_16(*reg),
+                _ => return Err(self.operand_error(instr)),
+            },
+            // STOP is only really used to switch speeds on the CGB. On the DMG it would wait for a
+            // button press in a low power mode, which isn't emulated, so it is treated as a NOP.
+            Mnemonic::Stop => {}
+            Mnemonic::Halt => {
+                let pending = self.bus.pending_interrupts();
+                if !self.ime && pending != 0 {
+                    // With interrupts disabled and one already pending, HALT exits immediately and
+                    // the CPU fails to increment the PC after fetching the next opcode.
+                    self.halt_bug = true;
                 } else {
-                    // println!("Reg value is {:#04x}", reg.get_low());
-                    self.memory[reg.get_low() as usize]
+                    self.halted = true;
                 }
             }
-        };
-
-        mem_val
+            Mnemonic::Di => {
+                self.ime = false;
+                self.ime_scheduled = false;
+            }
+            Mnemonic::Ei => self.ime_scheduled = true,
+            // These are a real instruction on the DMG: the CPU hangs until it is reset.
+            Mnemonic::Illegal => self.locked = true,
+            Mnemonic::Rlc | Mnemonic::Rrc | Mnemonic::Rl | Mnemonic::Rr |
+            Mnemonic::Sla | Mnemonic::Sra | Mnemonic::Swap | Mnemonic::Srl => match operands {
+                [target] => {
+                    let val = self.read_operand_8(*target)?;
+                    let result = self.shift_8(instr.mnemonic, val);
+                    self.write_operand_8(*target, result)?;
+                }
+                _ => return Err(self.operand_error(instr)),
+            },
+            Mnemonic::Bit | Mnemonic::Res | Mnemonic::Set => match operands {
+                [Operand::Bit(bit), target] => {
+                    let mask = 1u8 << bit;
+                    let val = self.read_operand_8(*target)?;
+                    match instr.mnemonic {
+                        Mnemonic::Bit => {
+                            self.regs.flags.zero = (val & mask) == 0;
+                            self.regs.flags.subtraction = false;
+                            self.regs.flags.half_carry = true;
+                        }
+                        Mnemonic::Res => self.write_operand_8(*target, val & !mask)?,
+                        _ => self.write_operand_8(*target, val | mask)?,
+                    }
+                }
+                _ => return Err(self.operand_error(instr)),
+            },
+            // The prefix is dealt with when decoding, so it can never be executed by itself.
+            Mnemonic::Prefix => return Err(self.operand_error(instr)),
+        }
+        Ok(false)
     }
 
-    /*  OPCODES BEGIN HERE. */
+    fn operand_error(&self, instr: &Instruction) -> OpcodeError {
+        OpcodeError::new(format!("Invalid operands {:?} for {}", instr.operands, instr.mnemonic), self.ir as u8)
+    }
 
-    /// Load a value stored in the MDR into a register pair.
-    /// Example: LD BC, d16.
-    /// This function will read memory whilst advancing the PC accordingly.
-    fn ld_reg_pair(&mut self, reg: RegisterPairs) -> BitResult {
-        // Load the MDR value appropriately.
-        // self.mdr = self.read_memory(ImmediateSixteen);
-
-        // The MDR must already have been initialised!
-        // Set the CPU RP appropriately.
-        let res = match reg {
-            RegisterPairs::BC => { self.bc.set_wide(self.mdr) }
-            RegisterPairs::DE => { self.de.set_wide(self.mdr) }
-            RegisterPairs::HL => { self.hl.set_wide(self.mdr) }
-        };
-        // Advance the PC and the cycles appropriately.
-        self.pc += 2;
-        self.cycles += 12;
-        res
-    }
-
-    /// Load a value stored in the MDR into the memory address stored in MAR.
-    /// Example: LD (BC), A. Where A has been stored in the MDR.
-    /// PC += 1, Cycles += 8.
-    fn ld_memory(&mut self) {
-        // Load the value in the MDR into the memory address stored in MAR.
-        self.memory[self.mar as usize] = self.mdr;
-        // Increment cycles and PC appropriately.
-        self.pc += 1;
-        self.cycles += 8;
-    }
-
-    fn get_regpair(&self, reg: Registers) -> Option<&RegPair> {
-        let ret = match reg {
-            Registers::B => { Some(&self.bc) }
-            Registers::C => { Some(&self.bc) }
-            Registers::D => { Some(&self.de) }
-            Registers::E => { Some(&self.de) }
-            Registers::H => { Some(&self.hl) }
-            Registers::L => { Some(&self.hl) }
-            _ => { None }
-        };
+    /// Check the branch condition (if any) among an instruction's operands.
+    fn condition_met(&self, operands: &[Operand]) -> bool {
+        let flags = &self.regs.flags;
+        match operands.first() {
+            Some(Operand::Cond(Condition::NotZero)) => !flags.zero,
+            Some(Operand::Cond(Condition::Zero)) => flags.zero,
+            Some(Operand::Cond(Condition::NotCarry)) => !flags.carry,
+            Some(Operand::Cond(Condition::Carry)) => flags.carry,
+            // Unconditional
+            _ => true,
+        }
+    }
 
-        ret
-    }
-
-    /// Increment the value stored in one half-register (i.e. a single register).
-    /// It will increment the BCD value inside this register and hence the result will be stored as BCD too.
-    fn inc_reg_8(&mut self, reg: Registers) -> Result<u8, OpcodeError> {
-        // Match the correct RegisterPair and store the correct reference.
-        let mut high = false;
-        let register_target: Option<&mut RegPair> = match reg {
-            Registers::B => { high = true; Some(&mut self.bc) }
-            Registers::C => { high = false; Some(&mut self.bc) }
-            Registers::D => { high = true; Some(&mut self.de) }
-            Registers::E => { high = false; Some(&mut self.de) }
-            Registers::H => { high = true; Some(&mut self.hl) }
-            Registers::L => { high = false; Some(&mut self.hl) }
-            _ => { None }
+    /// Read an 8-bit operand. Operands in memory are read through the MAR.
+    fn read_operand_8(&mut self, operand: Operand) -> Result<u8, OpcodeError> {
+        let val = match operand {
+            Operand::R8(reg) => self.regs.get8(reg),
+            Operand::D8 => self.mdr as u8,
+            _ => {
+                self.mar = self.operand_address(operand)?;
+                self.read_cycle(self.mar)
+            }
         };
+        Ok(val)
+    }
 
-        // let mut register_target = self.get_regpair(reg);
-
-        // Check if this was used appropriately.
-        return if register_target.is_none() {
-            Err(OpcodeError::new("Attempted to increment the A or SP register.".to_string(), self.ir as u8))
-        } else {
-            // Create our local register target from within a register pair.
-            let target = register_target.unwrap();
-
-            // Create a local copy of the old value.
-            let oldval = if high {
-                RegPair::bcd_to_decimal(target.get_high())
-            } else {
-                RegPair::bcd_to_decimal(target.get_low())
-            };
-
-            // Adjust the correct register from within a register pair.
-            if high {
-                target.set_high_bcd(oldval + 1).unwrap();
-                // Toggle zero flag as appropriate.
-                self.flags.zero = RegPair::bcd_to_decimal(target.get_high()) == 0;
-            } else {
-                target.set_low_bcd(oldval + 1).unwrap();
-                // Toggle zero flag as appropriate.
-                self.flags.zero = RegPair::bcd_to_decimal(target.get_low()) == 0;
+    /// Write an 8-bit operand. Operands in memory are written through the MAR.
+    fn write_operand_8(&mut self, operand: Operand, val: u8) -> Result<(), OpcodeError> {
+        match operand {
+            Operand::R8(reg) => self.regs.set8(reg, val),
+            _ => {
+                self.mar = self.operand_address(operand)?;
+                self.write_cycle(self.mar, val);
             }
-            // This instruction always sets the subtraction flag to false;
-            self.flags.subtraction = false;
-            // Toggle carry flag as appropriate.
-            // We need to toggle a half carry if the 0th bit of the oldval is set yet incrementing resulted in an overall zero.
-            // This is the only way we would have caused a carry on the third bit.
-            self.flags.half_carry = ((oldval & 0b0001) == 0b0001) && self.flags.zero;
-            Ok(oldval + 1)
         }
+        Ok(())
     }
 
-    /// Decrement a value in an r8 register. This is functionally very similar to inc_reg_8 except
-    /// we use a negative number as an operand to increment the value by.
-    fn dec_reg_8(&mut self, reg:Registers) -> Result<u8, OpcodeError> {
-        // Match the correct RegisterPair and store the correct reference.
-        let mut high = false;
-        let regtarg: Option<&mut RegPair> = match reg {
-            Registers::B => { high = true; Some(&mut self.bc) }
-            Registers::C => { high = false; Some(&mut self.bc) }
-            Registers::D => { high = true; Some(&mut self.de) }
-            Registers::E => { high = false; Some(&mut self.de) }
-            Registers::H => { high = true; Some(&mut self.hl) }
-            Registers::L => { high = false; Some(&mut self.hl) }
-            _ => { None }
-        };
+    /// Read a 16-bit operand.
+    fn read_operand_16(&mut self, operand: Operand) -> Result<u16, OpcodeError> {
+        match operand {
+            Operand::R16(reg) => Ok(self.regs.get16(reg)),
+            Operand::D16 => Ok(self.mdr),
+            _ => Err(OpcodeError::new(format!("{:?} is not a 16-bit operand", operand), self.ir as u8)),
+        }
+    }
 
-        // Check if this was used appropriately.
-        return if regtarg.is_none() {
-            Err(OpcodeError::new("Attempted to increment the A or SP register.".to_string(), self.ir as u8))
-        } else {
-            // Create our local register target from within a register pair.
-            let target = regtarg.unwrap();
+    /// Work out the address of an operand held in memory. (HL+) and (HL-) adjust HL as a side effect.
+    fn operand_address(&mut self, operand: Operand) -> Result<u16, OpcodeError> {
+        let addr = match operand {
+            Operand::Ind(reg) => self.regs.get16(reg),
+            Operand::HlInc => {
+                let hl = self.regs.get16(Reg16::HL);
+                self.regs.set16(Reg16::HL, hl.wrapping_add(1));
+                hl
+            }
+            Operand::HlDec => {
+                let hl = self.regs.get16(Reg16::HL);
+                self.regs.set16(Reg16::HL, hl.wrapping_sub(1));
+                hl
+            }
+            Operand::IndC => 0xFF00 | self.regs.get8(Reg8::C) as u16,
+            Operand::A8 => 0xFF00 | (self.mdr & 0xFF),
+            Operand::A16 => self.mdr,
+            _ => return Err(OpcodeError::new(format!("{:?} is not a memory operand", operand), self.ir as u8)),
+        };
+        Ok(addr)
+    }
 
-            // Create a local copy of the old value.
-            let oldval = if high {
-                RegPair::bcd_to_decimal(target.get_high())
-            } else {
-                RegPair::bcd_to_decimal(target.get_low())
-            };
+    /*  OPCODES BEGIN HERE. */
 
-            let old_wrapped = Wrapping(oldval);
-            let operand_wrapped = Wrapping(0b1111_1111);
-            let decr = if old_wrapped.0 != 0 {
-                get_magnitude_tc(old_wrapped.add(operand_wrapped).0 as i8)
-            } else {
-                0xF // Sneaky shortcut.
-            };
+    /// Increment an 8-bit value.
+    /// Z is set if the result wraps to zero and H if there was a carry out of bit 3; C is unaffected.
+    fn inc_8(&mut self, val: u8) -> u8 {
+        let result = val.wrapping_add(1);
+        self.regs.flags.zero = result == 0;
+        // This instruction always sets the subtraction flag to false;
+        self.regs.flags.subtraction = false;
+        // There is a carry from bit 3 only if the lower nibble was all ones beforehand.
+        self.regs.flags.half_carry = (val & 0x0F) == 0x0F;
+        result
+    }
 
-            // println!("The decreased value will be {}, aka {:#2b}, where the old value was {}", decr, decr, oldval);
+    /// Decrement an 8-bit value.
+    /// Z is set if the result is zero and H if there was a borrow from bit 4; C is unaffected.
+    fn dec_8(&mut self, val: u8) -> u8 {
+        let result = val.wrapping_sub(1);
+        self.regs.flags.zero = result == 0;
+        // Set the subtraction flag appropriately.
+        self.regs.flags.subtraction = true;
+        // There is a borrow from bit 4 only if the lower nibble was all zeroes beforehand.
+        self.regs.flags.half_carry = (val & 0x0F) == 0x00;
+        result
+    }
 
+    /// Add a value (and optionally the carry flag) to the accumulator. Used by ADD and ADC.
+    fn add_8(&mut self, val: u8, carry: bool) {
+        let a = self.regs.a;
+        let carry = carry as u8;
+        let result = a.wrapping_add(val).wrapping_add(carry);
+        self.regs.flags.zero = result == 0;
+        self.regs.flags.subtraction = false;
+        self.regs.flags.half_carry = (a & 0x0F) + (val & 0x0F) + carry > 0x0F;
+        self.regs.flags.carry = (a as u16) + (val as u16) + (carry as u16) > 0xFF;
+        self.regs.a = result;
+    }
 
-            // Adjust the correct register from within a register pair.
-            if high {
-                target.set_high_bcd(decr).unwrap();
-                // Toggle zero flag as appropriate.
-                self.flags.zero = RegPair::bcd_to_decimal(target.get_high()) == 0;
-            } else {
-                target.set_low_bcd(decr).unwrap();
-                // Toggle zero flag as appropriate.
-                self.flags.zero = RegPair::bcd_to_decimal(target.get_low()) == 0;
-            }
-            // Set the subtraction flag appropriately.
-            self.flags.subtraction = true;
+    /// Subtract a value (and optionally the carry flag) from the accumulator, returning the result.
+    /// Used by SUB, SBC and CP, the last of which doesn't store the result.
+    fn sub_8(&mut self, val: u8, carry: bool) -> u8 {
+        let a = self.regs.a;
+        let carry = carry as u8;
+        let result = a.wrapping_sub(val).wrapping_sub(carry);
+        self.regs.flags.zero = result == 0;
+        self.regs.flags.subtraction = true;
+        self.regs.flags.half_carry = (a & 0x0F) < (val & 0x0F) + carry;
+        self.regs.flags.carry = (a as u16) < (val as u16) + (carry as u16);
+        result
+    }
 
-            // Toggle carry flag as appropriate.
-            // This checks if we had a carry from bit 3 to bit 4.
-            self.flags.half_carry = (decr & 0x10) == 0x10;
-            Ok(decr)
-        }
+    /// Perform a bitwise operation (AND, XOR or OR) on the accumulator.
+    fn logic_8(&mut self, op: Mnemonic, val: u8) {
+        self.regs.a = match op {
+            Mnemonic::And => self.regs.a & val,
+            Mnemonic::Xor => self.regs.a ^ val,
+            _ => self.regs.a | val,
+        };
+        self.regs.flags.zero = self.regs.a == 0;
+        self.regs.flags.subtraction = false;
+        // For whatever reason, AND always sets the half carry flag.
+        self.regs.flags.half_carry = op == Mnemonic::And;
+        self.regs.flags.carry = false;
     }
 
-    /// Increment either the a or the sp register.
-    fn inc_a_sp(&self, target: &u8) -> Result<(u8, Flags)> {
-        // Log the old value.
-        let old_value = *target;
+    /// Add a 16-bit value to HL. H is set on a carry out of bit 11 and C on a carry out of bit 15;
+    /// Z is unaffected.
+    fn add_hl(&mut self, val: u16) {
+        let hl = self.regs.get16(Reg16::HL);
+        self.regs.flags.subtraction = false;
+        self.regs.flags.half_carry = (hl & 0x0FFF) + (val & 0x0FFF) > 0x0FFF;
+        self.regs.flags.carry = (hl as u32) + (val as u32) > 0xFFFF;
+        self.regs.set16(Reg16::HL, hl.wrapping_add(val));
+    }
 
-        // Adjust the value.
-        // *target = old_value + 1;
+    /// Work out SP plus the signed offset in the MDR. Used by ADD SP,e8 and LD HL,SP+e8, both of
+    /// which set H and C from an unsigned addition of the offset to the lower byte of SP.
+    fn add_sp_offset(&mut self) -> u16 {
+        let sp = self.regs.sp;
+        let offset = self.mdr & 0xFF;
+        self.regs.flags.zero = false;
+        self.regs.flags.subtraction = false;
+        self.regs.flags.half_carry = (sp & 0x000F) + (offset & 0x000F) > 0x000F;
+        self.regs.flags.carry = (sp & 0x00FF) + offset > 0x00FF;
+        sp.wrapping_add(offset as u8 as i8 as u16)
+    }
 
-        // Create custom flags.
-        let mut flags = Flags::new();
-        flags.carry = self.flags.carry;
-        // Adjust the zero flag.
-        flags.zero = (old_value + 1) == 0;
+    /// Perform one of the CB-prefixed rotates or shifts on a value, returning the result.
+    /// Z is set if the result is zero and C holds the bit shifted out (cleared by SWAP).
+    fn shift_8(&mut self, op: Mnemonic, val: u8) -> u8 {
+        let carry_in = self.regs.flags.carry as u8;
+        let (result, carry) = match op {
+            Mnemonic::Rlc => (val.rotate_left(1), val & 0x80 != 0),
+            Mnemonic::Rrc => (val.rotate_right(1), val & 0x01 != 0),
+            // RL and RR rotate through the carry flag.
+            Mnemonic::Rl => ((val << 1) | carry_in, val & 0x80 != 0),
+            Mnemonic::Rr => ((val >> 1) | (carry_in << 7), val & 0x01 != 0),
+            Mnemonic::Sla => (val << 1, val & 0x80 != 0),
+            // SRA keeps the sign bit, whereas SRL shifts in a zero.
+            Mnemonic::Sra => ((val >> 1) | (val & 0x80), val & 0x01 != 0),
+            Mnemonic::Srl => (val >> 1, val & 0x01 != 0),
+            _ => (val.rotate_left(4), false), // SWAP
+        };
+        self.regs.flags.zero = result == 0;
+        self.regs.flags.subtraction = false;
+        self.regs.flags.half_carry = false;
+        self.regs.flags.carry = carry;
+        result
+    }
 
-        // This instruction always sets the subtraction flag to false;
-        flags.subtraction = false;
-
-        // Toggle carry flag as appropriate.
-        // We need to toggle a half carry if the 0th bit of the old value is set yet incrementing resulted in an overall zero.
-        // This is the only way we would have caused a carry on the third bit.
-        flags.half_carry = ((old_value & 0b0001) == 0b0001) && flags.zero;
-
-        Ok((old_value + 1, flags))
-    }
-
-    fn rotate_a(&mut self, dir: RotateDirection, through_carry: bool) {
-        match dir {
-            RotateDirection::Left => {
-                // Check if we must also rotate through carry.
-                if !through_carry {
-                    // Toggle the carry flag to match bit 7 prior to a rotate.
-                    self.flags.carry = (self.a & 0b1000_0000) == 0b1000_0000;
-                    self.a = self.a << 1;
-                    if self.flags.carry {
-                        self.a = self.a | 0b0000_0001;
-                    };
-                }
+    /// Adjust the accumulator so that it holds the correct BCD result of the previous addition or
+    /// subtraction (DAA). Registers always hold plain binary values; this is the only place BCD is
+    /// dealt with, using N to tell whether the last operation was a subtraction and H/C to tell
+    /// which digits overflowed.
+    fn decimal_adjust(&mut self) {
+        let mut a = self.regs.a;
+        let mut carry = self.regs.flags.carry;
+        if !self.regs.flags.subtraction {
+            // After an addition, a digit needs correcting if it carried or is no longer 0-9.
+            if carry || a > 0x99 {
+                a = a.wrapping_add(0x60);
+                carry = true;
             }
-            RotateDirection::Right => {
-                if !through_carry {
-                    // Toggle the carry flag to match bit 7 prior to a rotate.
-                    self.flags.carry = (self.a & 0b0000_0001) == 0b0000_0001;
-                    self.a = self.a >> 1;
-                    if self.flags.carry {
-                        self.a = self.a | 0b1000_0000;
-                    };
-                }
+            if self.regs.flags.half_carry || (a & 0x0F) > 0x09 {
+                a = a.wrapping_add(0x06);
+            }
+        } else {
+            // After a subtraction, only the digits which borrowed need correcting.
+            if carry {
+                a = a.wrapping_sub(0x60);
+            }
+            if self.regs.flags.half_carry {
+                a = a.wrapping_sub(0x06);
             }
         }
+
+        self.regs.a = a;
+        self.regs.flags.zero = a == 0;
+        self.regs.flags.half_carry = false;
+        self.regs.flags.carry = carry;
+    }
+
+    /// Push a 16-bit value onto the stack. The high byte is written first, at SP - 1.
+    /// Every push is preceded by an internal M-cycle, in which SP is decremented.
+    fn push_16(&mut self, val: u16) {
+        self.idle();
+        self.regs.sp = self.regs.sp.wrapping_sub(1);
+        self.write_cycle(self.regs.sp, msb(val));
+        self.regs.sp = self.regs.sp.wrapping_sub(1);
+        self.write_cycle(self.regs.sp, lsb(val));
+    }
+
+    /// Pop a 16-bit value off the stack.
+    fn pop_16(&mut self) -> u16 {
+        let low = self.read_cycle(self.regs.sp) as u16;
+        self.regs.sp = self.regs.sp.wrapping_add(1);
+        let high = self.read_cycle(self.regs.sp) as u16;
+        self.regs.sp = self.regs.sp.wrapping_add(1);
+        (high << 8) | low
+    }
+
+    /// Push a 16-bit register onto the stack.
+    /// Example: PUSH BC.
+    fn push_reg_16(&mut self, reg: Reg16) {
+        self.push_16(self.regs.get16(reg));
+    }
+
+    /// Pop a 16-bit register off the stack. Popping into AF will discard the lower nibble of F.
+    /// Example: POP BC.
+    fn pop_reg_16(&mut self, reg: Reg16) {
+        let val = self.pop_16();
+        self.regs.set16(reg, val);
     }
 
-    fn write_bytes(&mut self, bytes: &[u16], index: usize) -> Result<()>{
+    /// Copy bytes straight into memory from `index` onwards, for setting up tests.
+    #[cfg(test)]
+    fn write_bytes(&mut self, bytes: &[u8], index: usize) -> Result<()>{
         if (index + bytes.len()) > 65536 {
-            return Err(anyhow!(MemoryError("BIG NUMBER")));
+            return Err(anyhow!("{} bytes don't fit in memory from {:#06x}", bytes.len(), index));
         }
 
         for i in 0..bytes.len() {
-            self.memory[i + index] = bytes[i];
+            self.bus.memory[i + index] = bytes[i];
         }
         Ok(())
     }
@@ -873,37 +911,52 @@ pub fn lsb(v: u16) -> u8 {
     ((v << 8) >> 8) as u8
 }
 
-/// Will convert an 8-bit number represented in TC to an 8-bit signed number.
-pub fn from_signed_byte(tc: u8) -> i8 {
-    // Check if we have a negative number.
-    if (tc & 0b1000_000) == 0b1000_000 {
-        // Find out what this number is the negative of.
-        let flipped = !tc + 1;
-        flipped as i8 * -1
-    } else { // Non-negative number, so return the number as it is.
-        tc as i8
+impl Snapshot for CPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        for &reg in [Reg16::AF, Reg16::BC, Reg16::DE, Reg16::HL, Reg16::SP, Reg16::PC].iter() {
+            w.u16(self.regs.get16(reg));
+        }
+        w.u16(self.ir);
+        w.u16(self.mar);
+        w.u16(self.mdr);
+        w.u64(self.cycles);
+        w.bool(self.ime);
+        w.bool(self.ime_scheduled);
+        w.bool(self.halted);
+        w.bool(self.halt_bug);
+        w.bool(self.locked);
+        self.bus.save_state(w);
     }
-}
 
-/// Will convert an 8-bit signed number to an 8-bit unsigned number represented in TC.
-pub fn get_magnitude_tc(from: i8) -> u8 {
-    // Check if we have a negative number.
-    if from < 0 {
-        (from * -1) as u8
-    } else {
-        from as u8
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        for &reg in [Reg16::AF, Reg16::BC, Reg16::DE, Reg16::HL, Reg16::SP, Reg16::PC].iter() {
+            let val = r.u16()?;
+            self.regs.set16(reg, val);
+        }
+        self.ir = r.u16()?;
+        self.mar = r.u16()?;
+        self.mdr = r.u16()?;
+        self.cycles = r.u64()?;
+        self.ime = r.bool()?;
+        self.ime_scheduled = r.bool()?;
+        self.halted = r.bool()?;
+        self.halt_bug = r.bool()?;
+        self.locked = r.bool()?;
+        self.bus.load_state(r)
     }
 }
 
 #[derive(Debug, Error)]
-#[error("Attempted to write to invalid memory index {0}")]
-pub struct MemoryError(pub &'static str);
+#[error("The boot ROM must be exactly 256 bytes, but {0} bytes were given")]
+pub struct BootRomError(pub usize);
 
 #[cfg(test)]
 mod tests {
     use std::fmt::format;
     use super::*;
-    use crate::components::dmg_cpu::AddressingMode::*;
+    use crate::components::bus::BOOT_ROM_DISABLE_ADDR;
+    use crate::components::serial::{CaptureLink, SB_ADDR, SC_ADDR};
+    use crate::components::register::Flags;
 
     #[test]
     fn msb_lsb() {
@@ -912,113 +965,251 @@ mod tests {
     }
 
     #[test]
-    fn from_tc() {
-        assert_eq!(-59, from_signed_byte(0b1100_0101));
-        assert_eq!(-90, from_signed_byte(0b1010_0110));
-        assert_eq!(32, from_signed_byte(0b0010_0000));
+    fn write_bytes() {
+        let mut cpu = CPU::new();
+        cpu.write_bytes(&[0xA, 0xB, 0xC, 0xD], 0).unwrap();
+        assert_eq!(cpu.bus.memory[0..4], [0xA, 0xB, 0xC, 0xD]);
+        cpu.write_bytes(&[0xA, 0xB, 0xC, 0xD, 0xE], 1).unwrap();
+        assert_eq!(cpu.bus.memory[1..6], [0xA, 0xB, 0xC, 0xD, 0xE]);
     }
 
     #[test]
-    fn to_tc() {
-        assert_eq!(0b0010_0000, get_magnitude_tc(32));
-        assert_eq!(90, get_magnitude_tc(-90));
-        // This fails as a function because the computer already stores these as
+    fn serial_transfer() {
+        let mut cpu = CPU::new();
+        let link = CaptureLink::new();
+        cpu.set_serial_link(Box::new(link.clone()));
+        cpu.write_byte(SB_ADDR, b'A');
+        cpu.write_byte(SC_ADDR, 0x81);
+        // A transfer takes 4096 T-cycles, which is 1024 NOPs.
+        for _ in 0..1023 {
+            cpu.cycle();
+        }
+        assert_eq!(cpu.read_byte(IF_ADDR) & SERIAL_INTERRUPT, 0);
+        assert!(link.output().is_empty());
+        cpu.cycle();
+        assert_eq!(cpu.read_byte(IF_ADDR) & SERIAL_INTERRUPT, SERIAL_INTERRUPT);
+        assert_eq!(link.output_string(), "A");
+        assert_eq!(cpu.read_byte(SC_ADDR), 0x7F);
     }
 
     #[test]
-    fn immediate_memory_read() {
-        let mut cpu = CPU::new();
-        cpu.memory[0] = 0xCD;
-        cpu.memory[1] = 0xAB;
-        cpu.memory[2] = 0b1110_0010; // -30;
-        cpu.memory[0xFF00 + 2] = 0xFEE2;
-        assert_eq!(0xCD, cpu.read_memory(ImmediateEight)); // 8-bit immediate reading, such as with opcode 0x06: LD B, d8
-        assert_eq!(0xABCD, cpu.read_memory(ImmediateSixteen)); // 16-bit immediate reading, such as with opcode LD HL, d16
-        cpu.cycle();
+    fn post_boot() {
+        let cpu = CPU::post_boot();
+        assert_eq!(cpu.regs.a, 0x01);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: true, carry: true }));
+        assert_eq!(cpu.regs.bc.get_wide(), 0x0013);
+        assert_eq!(cpu.regs.de.get_wide(), 0x00D8);
+        assert_eq!(cpu.regs.hl.get_wide(), 0x014D);
+        assert_eq!(cpu.regs.sp, 0xFFFE);
+        assert_eq!(cpu.regs.pc, 0x0100);
+        assert_eq!(cpu.read_byte(SC_ADDR), 0x7E);
+        assert_eq!(cpu.read_byte(0xFF40), 0x91);
+        assert_eq!(cpu.read_byte(0xFF47), 0xFC);
+    }
+
+    #[test]
+    fn boot_rom_mapping() {
+        assert!(CPU::with_boot_rom(&[0; 255]).is_err());
+        let mut rom = [0; BOOT_ROM_SIZE];
+        rom[0] = 0x06; // LD B, d8
+        rom[1] = 0x42;
+        let mut cpu = CPU::with_boot_rom(&rom).unwrap();
+        cpu.bus.memory[0] = 0x00;
+        cpu.bus.memory[1] = 0x00;
+        assert_eq!(cpu.regs.pc, 0x0000);
+        // The boot ROM is read in place of the cartridge.
         cpu.cycle();
-        assert_eq!(0xFEE2, cpu.read_memory(UnsignedEight)); // Come back and look at def for this addressing type.
+        assert_eq!(cpu.regs.bc.get_high(), 0x42);
+        assert_eq!(cpu.read_byte(0x0001), 0x42);
+        // Writing zero leaves it mapped.
+        cpu.write_byte(BOOT_ROM_DISABLE_ADDR, 0);
+        assert_eq!(cpu.read_byte(0x0001), 0x42);
+        cpu.write_byte(BOOT_ROM_DISABLE_ADDR, 1);
+        assert_eq!(cpu.read_byte(0x0001), 0x00);
+        // Once unmapped, it stays unmapped.
+        cpu.write_byte(BOOT_ROM_DISABLE_ADDR, 0);
+        assert_eq!(cpu.read_byte(0x0001), 0x00);
+    }
 
-        // Register(pair) Direct mode
-        let mut reg = RegPair::new();
-        reg.set_wide(0x1346);
-        cpu.memory[0x1346] = 1334;
-        cpu.memory[0x13] = 15;
-        cpu.memory[0x46] = 32;
-        assert_eq!(1334, cpu.read_memory(RegisterPairDirect(&reg)));
-        assert_eq!(15, cpu.read_memory(RegisterDirect(&reg, true)));
-        assert_eq!(32, cpu.read_memory(RegisterDirect(&reg, false)));
+    /// Every instruction which doesn't branch should advance the PC by its length, and take as
+    /// many cycles as the opcode table says it does.
+    #[test]
+    fn table_timing() {
+        for prefixed in [false, true].iter().cloned() {
+            for opcode in 0..=0xFFu8 {
+                let instr = lookup(opcode, prefixed);
+                let branches = match instr.mnemonic {
+                    Mnemonic::Jr | Mnemonic::Jp | Mnemonic::Call | Mnemonic::Ret |
+                    Mnemonic::Reti | Mnemonic::Rst | Mnemonic::Halt | Mnemonic::Prefix |
+                    Mnemonic::Illegal => true,
+                    _ => false,
+                };
+                if branches {
+                    continue;
+                }
+                let mut cpu = CPU::new();
+                cpu.regs.pc = 0xC000;
+                cpu.regs.sp = 0xDFF0;
+                cpu.regs.set16(Reg16::HL, 0xD000);
+                if prefixed {
+                    cpu.write_bytes(&[0xCB, opcode], 0xC000).unwrap();
+                } else {
+                    cpu.write_bytes(&[opcode], 0xC000).unwrap();
+                }
+                cpu.cycle();
+                assert_eq!(cpu.regs.pc, 0xC000 + instr.length as u16, "length of {:#04x} ({})", opcode, instr.mnemonic);
+                assert_eq!(cpu.cycles, instr.cycles as u64, "cycles of {:#04x} ({})", opcode, instr.mnemonic);
+            }
+        }
     }
 
     #[test]
-    fn write_bytes() {
+    fn cb_prefixed() {
         let mut cpu = CPU::new();
-        cpu.write_bytes(&[0xA, 0xB, 0xC, 0xD], 0).unwrap();
-        assert_eq!(cpu.memory[0..4], [0xA, 0xB, 0xC, 0xD]);
-        cpu.write_bytes(&[0xA, 0xB, 0xC, 0xD, 0xE], 1).unwrap();
-        assert_eq!(cpu.memory[1..6], [0xA, 0xB, 0xC, 0xD, 0xE]);
+        // SWAP A, BIT 7,A, SET 0,(HL), SRA B
+        cpu.write_bytes(&[0xCB, 0x37, 0xCB, 0x7F, 0xCB, 0xC6, 0xCB, 0x28], 0).unwrap();
+        cpu.regs.a = 0x1F;
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.regs.set8(Reg8::B, 0x81);
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0xF1);
+        assert_eq!(cpu.ir, 0xCB37);
+        cpu.cycle();
+        assert!(!cpu.regs.flags.zero);
+        assert!(cpu.regs.flags.half_carry);
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xC000], 0x01);
+        cpu.cycle();
+        assert_eq!(cpu.regs.get8(Reg8::B), 0xC0);
+        assert!(cpu.regs.flags.carry);
+        assert_eq!(cpu.regs.pc, 8);
+        assert_eq!(cpu.cycles, 8 + 8 + 16 + 8);
     }
-}
-
-#[cfg(test)]
-mod opcodes {
-    use crate::components::dmg_cpu::CPU;
-    use crate::components::register::RegPair;
 
+    /// Memory accesses should happen on the M-cycle they would on hardware, with the timer having
+    /// been ticked for every M-cycle before them.
     #[test]
-    fn ld_r16_d16() {
+    fn access_timing() {
+        let mut cpu = CPU::new();
+        // LD A,(HL), LD A,(0xFF04)
+        cpu.write_bytes(&[0x7E, 0xFA, 0x04, 0xFF], 0).unwrap();
+        cpu.regs.set16(Reg16::HL, 0xFF04);
+        // DIV will next go up 12 T-cycles from now.
+        cpu.bus.timer.set_counter(0x00F4);
+        // The read happens on the second M-cycle, before DIV goes up.
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x00);
+        cpu.bus.timer.set_counter(0x00F4);
+        // The read happens on the fourth M-cycle, after DIV goes up.
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x01);
+    }
 
+    #[test]
+    fn dma_blocks_cpu() {
+        let mut cpu = CPU::new();
+        // LD (0xFF46),A, LD B,(HL), LD C,(HL) as HRAM can still be read, LD D,(HL) once it's over
+        cpu.write_bytes(&[0xEA, 0x46, 0xFF], 0xFF80).unwrap();
+        cpu.write_bytes(&[0x46, 0x4E], 0xFF83).unwrap();
+        cpu.regs.pc = 0xFF80;
+        cpu.regs.a = 0xC0;
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.bus.memory[0xC000] = 0x12;
+        cpu.cycle();
+        cpu.cycle();
+        assert_eq!(cpu.regs.get8(Reg8::B), 0xFF);
+        cpu.regs.set16(Reg16::HL, 0xFF80);
+        cpu.cycle();
+        assert_eq!(cpu.regs.get8(Reg8::C), 0xEA);
+        for _ in 0..160 {
+            cpu.bus.tick(4);
+        }
+        assert_eq!(cpu.bus.read(0xFE00), 0x12);
     }
 
+    /// If pushing the PC during interrupt dispatch overwrites IE, the interrupt is cancelled and
+    /// the CPU jumps to 0x0000 instead.
     #[test]
-    fn ld_d16_r8() {
+    fn interrupt_cancelled_by_push() {
+        let mut cpu = CPU::new();
+        cpu.regs.pc = 0x0200;
+        cpu.regs.sp = 0x0000;
+        cpu.ime = true;
+        cpu.bus.memory[IE_ADDR as usize] = TIMER_INTERRUPT;
+        cpu.bus.memory[IF_ADDR as usize] = TIMER_INTERRUPT;
+        // The high byte of the PC (0x02) is pushed to 0xFFFF, clearing the timer bit in IE.
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x0000);
+        assert_eq!(cpu.cycles, 20);
+        // The interrupt is still requested.
+        assert_eq!(cpu.read_byte(IF_ADDR) & TIMER_INTERRUPT, TIMER_INTERRUPT);
+    }
 
+    #[test]
+    fn illegal_opcode_locks() {
+        let mut cpu = CPU::new();
+        cpu.write_bytes(&[0xD3, 0x3C], 0).unwrap();
+        cpu.bus.memory[IE_ADDR as usize] = VBLANK_INTERRUPT;
+        cpu.bus.memory[IF_ADDR as usize] = VBLANK_INTERRUPT;
+        for _ in 0..10 {
+            cpu.cycle();
+        }
+        // Not even an interrupt will get the CPU going again.
+        assert_eq!(cpu.regs.pc, 1);
+        assert_eq!(cpu.regs.a, 0);
     }
+}
+
+#[cfg(test)]
+mod opcodes {
+    use crate::components::dmg_cpu::CPU;
+    use crate::components::register::RegPair;
 
     #[test]
     fn inc_r16() {
         let mut cpu = CPU::new();
-        cpu.memory[0] = 0x03;
-        cpu.memory[1] = 0x03;
-        cpu.memory[2] = 0x03;
-        cpu.memory[3] = 0x03;
+        cpu.bus.memory[0] = 0x03;
+        cpu.bus.memory[1] = 0x03;
+        cpu.bus.memory[2] = 0x03;
+        cpu.bus.memory[3] = 0x03;
         cpu.cycle();
-        assert_eq!(1, cpu.bc.get_wide());
+        assert_eq!(1, cpu.regs.bc.get_wide());
         cpu.cycle();
-        assert_eq!(2, cpu.bc.get_wide());
+        assert_eq!(2, cpu.regs.bc.get_wide());
         cpu.cycle();
-        assert_eq!(3, cpu.bc.get_wide());
+        assert_eq!(3, cpu.regs.bc.get_wide());
         cpu.cycle();
-        assert_eq!(4, cpu.bc.get_wide());
+        assert_eq!(4, cpu.regs.bc.get_wide());
         for i in 4..10 {
-            cpu.memory[i] = 0x0B;
+            cpu.bus.memory[i] = 0x0B;
         }
         cpu.cycle();
-        assert_eq!(3, cpu.bc.get_wide());
+        assert_eq!(3, cpu.regs.bc.get_wide());
         cpu.cycle();
-        assert_eq!(2, cpu.bc.get_wide());
+        assert_eq!(2, cpu.regs.bc.get_wide());
         cpu.cycle();
-        assert_eq!(1, cpu.bc.get_wide());
+        assert_eq!(1, cpu.regs.bc.get_wide());
         cpu.cycle();
-        assert_eq!(0, cpu.bc.get_wide());
+        assert_eq!(0, cpu.regs.bc.get_wide());
         cpu.cycle();
-        assert_eq!(0xFF, cpu.bc.get_wide());
+        assert_eq!(0xFFFF, cpu.regs.bc.get_wide());
     }
 
     #[test]
     fn load_r8_d8() {
         let mut cpu = CPU::new();
-        cpu.memory[0] = 0x06; // LD B, d8
-        cpu.memory[1] = 0xAB;
-        cpu.memory[2] = 0x06;
-        cpu.memory[3] = 0x01;
-        cpu.memory[4] = 0x06;
-        cpu.memory[5] = 0x00;
+        cpu.bus.memory[0] = 0x06; // LD B, d8
+        cpu.bus.memory[1] = 0xAB;
+        cpu.bus.memory[2] = 0x06;
+        cpu.bus.memory[3] = 0x01;
+        cpu.bus.memory[4] = 0x06;
+        cpu.bus.memory[5] = 0x00;
         cpu.cycle();
-        assert_eq!(0xAB, cpu.bc.get_high());
+        assert_eq!(0xAB, cpu.regs.bc.get_high());
         cpu.cycle();
-        assert_eq!(0x01, cpu.bc.get_high());
+        assert_eq!(0x01, cpu.regs.bc.get_high());
         cpu.cycle();
-        assert_eq!(0x00, cpu.bc.get_high());
+        assert_eq!(0x00, cpu.regs.bc.get_high());
     }
 
     #[test]
@@ -1030,33 +1221,34 @@ mod opcodes {
     #[test]
     fn ld_a16_sp() {
         let mut cpu = CPU::new();
-        cpu.sp = 0xABCD;
-        cpu.memory[0] = 0x08;
-        cpu.memory[1] = 0x04;
-        cpu.memory[2] = 0x00; // Sets the address to 0x0004.
+        cpu.regs.sp = 0xABCD;
+        cpu.bus.memory[0] = 0x08;
+        cpu.bus.memory[1] = 0x04;
+        cpu.bus.memory[2] = 0x00; // Sets the address to 0x0004.
         cpu.cycle(); // We expect m[0x0004]: AB; m[0x0005]: CD.
-        assert_eq!(0xCD, cpu.memory[0x0004]);
-        assert_eq!(0xAB, cpu.memory[0x0005]);
+        assert_eq!(0xCD, cpu.bus.memory[0x0004]);
+        assert_eq!(0xAB, cpu.bus.memory[0x0005]);
     }
 }
 
 #[cfg(test)]
 /// Instruction tests, grouped by specific categories of opcodes.
 mod opcode_category_tests {
-    use crate::components::dmg_cpu::{CPU, Flags};
+    use crate::components::dmg_cpu::{CPU, IE_ADDR, IF_ADDR, TIMER_INTERRUPT, VBLANK_INTERRUPT};
+    use crate::components::register::{Flags, Reg16, Reg8};
     use crate::components::register::RegPair;
 
     #[test]
     /// Opcode 0x00
     fn nop() {
         let mut cpu = CPU::new();
-        cpu.memory[0] = 0x00;
+        cpu.bus.memory[0] = 0x00;
         cpu.cycle();
-        assert_eq!(cpu.pc, 1);
-        assert_eq!(cpu.bc.get_wide(), 0);
-        assert_eq!(cpu.de.get_wide(), 0);
-        assert_eq!(cpu.hl.get_wide(), 0);
-        assert_eq!(cpu.sp, 0);
+        assert_eq!(cpu.regs.pc, 1);
+        assert_eq!(cpu.regs.bc.get_wide(), 0);
+        assert_eq!(cpu.regs.de.get_wide(), 0);
+        assert_eq!(cpu.regs.hl.get_wide(), 0);
+        assert_eq!(cpu.regs.sp, 0);
     }
 
     #[test]
@@ -1065,43 +1257,43 @@ mod opcode_category_tests {
     ///
     fn ld_r16_d16() {
         let mut cpu = CPU::new();
-        cpu.memory[0] = 0x01; // LD BC, d16. Will spell out 0xABCD
-        cpu.memory[1] = 0xCD; // Lower bytes of 0xABCD
-        cpu.memory[2] = 0xAB; // Lower bytes of 0xABCD
+        cpu.bus.memory[0] = 0x01; // LD BC, d16. Will spell out 0xABCD
+        cpu.bus.memory[1] = 0xCD; // Lower bytes of 0xABCD
+        cpu.bus.memory[2] = 0xAB; // Lower bytes of 0xABCD
         cpu.cycle();
-        assert_eq!(cpu.bc.get_wide(), 0xABCD);
-        cpu.memory[3] = 0x11; // LD DE, d16
-        cpu.memory[4] = 0xEF;
-        cpu.memory[5] = 0xCD;
+        assert_eq!(cpu.regs.bc.get_wide(), 0xABCD);
+        cpu.bus.memory[3] = 0x11; // LD DE, d16
+        cpu.bus.memory[4] = 0xEF;
+        cpu.bus.memory[5] = 0xCD;
         cpu.cycle();
-        assert_eq!(cpu.de.get_wide(), 0xCDEF);
-        cpu.memory[6] = 0x21; // LD HL, d16
-        cpu.memory[7] = 0xBB;
-        cpu.memory[8] = 0xAA;
+        assert_eq!(cpu.regs.de.get_wide(), 0xCDEF);
+        cpu.bus.memory[6] = 0x21; // LD HL, d16
+        cpu.bus.memory[7] = 0xBB;
+        cpu.bus.memory[8] = 0xAA;
         cpu.cycle();
-        assert_eq!(cpu.hl.get_wide(), 0xAABB);
+        assert_eq!(cpu.regs.hl.get_wide(), 0xAABB);
         cpu.write_bytes(&[0x31, 0xBB, 0xAA], 9).unwrap(); // LD SP d16
         cpu.cycle();
-        assert_eq!(cpu.sp, 0xAABB);
+        assert_eq!(cpu.regs.sp, 0xAABB);
     }
 
     #[test]
     fn ld_r16_a() {
         let mut cpu = CPU::new();
-        cpu.a = 0xAB;
+        cpu.regs.a = 0xAB;
         // Load addresses into BC and DE, then store the accumulator value
         // into these memory addresses.
         // BC = 0x000A, DE = 0x000C, HL = 0x000F
         let instr = &[0x01, 0x0A, 0x00, 0x11, 0x0C, 0x00, 0x02, 0x12];
-        cpu.write_bytes(instr, 1).unwrap();
+        cpu.write_bytes(instr, 0).unwrap();
         cpu.cycle(); // LD BC, d16
         cpu.cycle(); // LD DE, d16
         cpu.cycle(); // LD (BC), A
         println!("IR: {}", cpu.ir);
-        assert_eq!(cpu.memory[0x000A], 0xAB);
+        assert_eq!(cpu.bus.memory[0x000A], 0xAB);
         cpu.cycle(); // LD (DE), A
-        assert_eq!(cpu.memory[0x000A], 0xAB);
-        assert_eq!(cpu.memory[0x000C], 0xAB);
+        assert_eq!(cpu.bus.memory[0x000A], 0xAB);
+        assert_eq!(cpu.bus.memory[0x000C], 0xAB);
     }
 
     #[test]
@@ -1114,25 +1306,25 @@ mod opcode_category_tests {
         cpu.cycle(); // INC DE
         cpu.cycle(); // INC HL
         cpu.cycle(); // INC SP
-        assert_eq!(cpu.bc.get_wide(), 0x01);
-        assert_eq!(cpu.de.get_wide(), 0x01);
-        assert_eq!(cpu.hl.get_wide(), 0x01);
-        assert_eq!(cpu.sp, 0x01);
-        assert_eq!(cpu.flags.test_flags(&flags), true);
-        cpu.bc.set_wide(0xFF);
-        cpu.de.set_wide(0xFF);
-        cpu.hl.set_wide(0xFF);
-        cpu.sp = 0xFF;
+        assert_eq!(cpu.regs.bc.get_wide(), 0x01);
+        assert_eq!(cpu.regs.de.get_wide(), 0x01);
+        assert_eq!(cpu.regs.hl.get_wide(), 0x01);
+        assert_eq!(cpu.regs.sp, 0x01);
+        assert_eq!(cpu.regs.flags.test_flags(&flags), true);
+        cpu.regs.bc.set_wide(0xFFFF);
+        cpu.regs.de.set_wide(0xFFFF);
+        cpu.regs.hl.set_wide(0xFFFF);
+        cpu.regs.sp = 0xFFFF;
         // Test wrapping
         cpu.cycle(); // INC BC
         cpu.cycle(); // INC DE
         cpu.cycle(); // INC HL
         cpu.cycle(); // INC SP
-        assert_eq!(cpu.bc.get_wide(), 0x00);
-        assert_eq!(cpu.de.get_wide(), 0x00);
-        assert_eq!(cpu.hl.get_wide(), 0x00);
-        assert_eq!(cpu.sp, 0x00);
-        assert_eq!(cpu.flags.test_flags(&flags), true);
+        assert_eq!(cpu.regs.bc.get_wide(), 0x00);
+        assert_eq!(cpu.regs.de.get_wide(), 0x00);
+        assert_eq!(cpu.regs.hl.get_wide(), 0x00);
+        assert_eq!(cpu.regs.sp, 0x00);
+        assert_eq!(cpu.regs.flags.test_flags(&flags), true);
     }
 
     #[test]
@@ -1146,246 +1338,855 @@ mod opcode_category_tests {
         for _ in 0..14 {
             cpu.cycle();
         }
-        assert_eq!(cpu.a, 2);
-        assert_eq!(cpu.bc.get_high(), 2);
-        assert_eq!(cpu.bc.get_low(), 2);
-        assert_eq!(cpu.de.get_high(), 2);
-        assert_eq!(cpu.de.get_low(), 2);
-        assert_eq!(cpu.hl.get_high(), 2);
-        assert_eq!(cpu.hl.get_low(), 2);
-
-        cpu.a = 0xFF;
-        // TODO: Check flags are toggled.
+        assert_eq!(cpu.regs.a, 2);
+        assert_eq!(cpu.regs.bc.get_high(), 2);
+        assert_eq!(cpu.regs.bc.get_low(), 2);
+        assert_eq!(cpu.regs.de.get_high(), 2);
+        assert_eq!(cpu.regs.de.get_low(), 2);
+        assert_eq!(cpu.regs.hl.get_high(), 2);
+        assert_eq!(cpu.regs.hl.get_low(), 2);
+
+        // Registers hold binary values, so 0x09 + 1 = 0x0A rather than BCD 0x10.
+        cpu.regs.set8(Reg8::B, 0x09);
+        cpu.write_bytes(&[0x04, 0x04, 0x3C], 14).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.get8(Reg8::B), 0x0A);
+        assert!(cpu.regs.flags.test_flags(&Flags::new()));
+        // Carry out of bit 3.
+        cpu.regs.set8(Reg8::B, 0x0F);
+        cpu.cycle();
+        assert_eq!(cpu.regs.get8(Reg8::B), 0x10);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: false, half_carry: true, carry: false }));
+        // Wrapping to zero leaves the carry flag alone.
+        cpu.regs.a = 0xFF;
+        cpu.regs.flags.carry = true;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x00);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: true, carry: true }));
     }
 
     #[test]
-    fn dec_r8() {}
+    fn dec_r8() {
+        let mut cpu = CPU::new();
+        // DEC B, DEC D, DEC H, DEC C, DEC E, DEC L, DEC A
+        let instr = &[0x05, 0x15, 0x25, 0x0D, 0x1D, 0x2D, 0x3D];
+        cpu.write_bytes(instr, 0).unwrap();
+        cpu.write_bytes(instr, 7).unwrap();
+        for _ in 0..7 {
+            cpu.cycle();
+            // Decrementing zero wraps around, borrowing from bit 4.
+            assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: true, carry: false }));
+        }
+        for reg in [Reg8::A, Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L].iter() {
+            assert_eq!(cpu.regs.get8(*reg), 0xFF);
+        }
+        for _ in 0..7 {
+            cpu.cycle();
+            assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: false, carry: false }));
+        }
+        assert_eq!(cpu.regs.a, 0xFE);
+
+        // Reaching zero sets Z, and the carry flag is left alone.
+        cpu.regs.set8(Reg8::B, 0x01);
+        cpu.regs.flags.carry = true;
+        cpu.write_bytes(&[0x05], 14).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.get8(Reg8::B), 0x00);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: true, half_carry: false, carry: true }));
+
+        // Binary, not BCD: 0x10 - 1 = 0x0F.
+        cpu.regs.set8(Reg8::B, 0x10);
+        cpu.write_bytes(&[0x05], 15).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.get8(Reg8::B), 0x0F);
+        assert!(cpu.regs.flags.half_carry);
+    }
 
     #[test]
     fn ld_r8_d8() {
         let mut cpu = CPU::new();
-        cpu.memory[0] = 0x06; // LD B, d8
-        cpu.memory[1] = 0xAB;
-        cpu.memory[2] = 0x06;
-        cpu.memory[3] = 0x01;
-        cpu.memory[4] = 0x06;
-        cpu.memory[5] = 0x00;
+        cpu.bus.memory[0] = 0x06; // LD B, d8
+        cpu.bus.memory[1] = 0xAB;
+        cpu.bus.memory[2] = 0x06;
+        cpu.bus.memory[3] = 0x01;
+        cpu.bus.memory[4] = 0x06;
+        cpu.bus.memory[5] = 0x00;
         cpu.cycle();
-        assert_eq!(0xAB, cpu.bc.get_high());
+        assert_eq!(0xAB, cpu.regs.bc.get_high());
         cpu.cycle();
-        assert_eq!(0x01, cpu.bc.get_high());
+        assert_eq!(0x01, cpu.regs.bc.get_high());
         cpu.cycle();
-        assert_eq!(0x00, cpu.bc.get_high());
+        assert_eq!(0x00, cpu.regs.bc.get_high());
     }
 
     #[test]
-    fn rlca() {}
-
-    #[test]
-    fn ld_a16_sp() {}
+    fn rlca() {
+        let mut cpu = CPU::new();
+        cpu.write_bytes(&[0x07, 0x07], 0).unwrap();
+        cpu.regs.a = 0b1000_0101;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0b0000_1011);
+        assert!(cpu.regs.flags.carry);
+        // Z is always cleared, even when the result is zero.
+        cpu.regs.a = 0;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0);
+        assert!(cpu.regs.flags.test_flags(&Flags::new()));
+    }
 
     #[test]
-    fn add_r16_r16() {}
+    fn add_r16_r16() {
+        let mut cpu = CPU::new();
+        // ADD HL,BC, ADD HL,HL
+        cpu.write_bytes(&[0x09, 0x29], 0).unwrap();
+        cpu.regs.flags.zero = true;
+        cpu.regs.set16(Reg16::HL, 0x8FFF);
+        cpu.regs.set16(Reg16::BC, 0x0001);
+        cpu.cycle();
+        assert_eq!(cpu.regs.get16(Reg16::HL), 0x9000);
+        // Z is unaffected.
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: true, carry: false }));
+        cpu.cycle();
+        assert_eq!(cpu.regs.get16(Reg16::HL), 0x2000);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: false, carry: true }));
+        assert_eq!(cpu.cycles, 16);
+    }
 
     #[test]
-    fn rrca() {}
+    fn rrca() {
+        let mut cpu = CPU::new();
+        cpu.bus.memory[0] = 0x0F;
+        cpu.regs.a = 0b0000_0011;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0b1000_0001);
+        assert!(cpu.regs.flags.carry);
+    }
 
     // 1x
     #[test]
-    fn stop() {}
+    fn stop() {
+        let mut cpu = CPU::new();
+        // STOP is followed by a padding byte, which must be skipped.
+        cpu.write_bytes(&[0x10, 0x00], 0).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 2);
+    }
 
     #[test]
-    fn rla() {}
+    fn rla() {
+        let mut cpu = CPU::new();
+        cpu.write_bytes(&[0x17, 0x17], 0).unwrap();
+        cpu.regs.a = 0b1000_0000;
+        cpu.cycle();
+        // Bit 7 goes into the carry, and the old carry into bit 0.
+        assert_eq!(cpu.regs.a, 0);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: false, half_carry: false, carry: true }));
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 1);
+        assert!(!cpu.regs.flags.carry);
+    }
 
     #[test]
-    fn jr_s8() {}
+    fn jr_s8() {
+        let mut cpu = CPU::new();
+        // JR +2 over two NOPs, then JR -4 back to the start.
+        cpu.write_bytes(&[0x18, 0x02, 0x00, 0x00, 0x18, 0xFA], 0).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 4);
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0);
+        assert_eq!(cpu.cycles, 24);
+    }
 
     #[test]
-    fn rra() {}
+    fn rra() {
+        let mut cpu = CPU::new();
+        cpu.bus.memory[0] = 0x1F;
+        cpu.regs.a = 0b0000_0010;
+        cpu.regs.flags.carry = true;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0b1000_0001);
+        assert!(!cpu.regs.flags.carry);
+    }
 
     // 2x
     #[test]
-    fn jr_b_s8() {}
+    fn jr_b_s8() {
+        let mut cpu = CPU::new();
+        // JR NZ,+2, JR Z,+2
+        cpu.write_bytes(&[0x20, 0x02, 0x28, 0x02], 0).unwrap();
+        cpu.regs.flags.zero = true;
+        cpu.cycle();
+        // Not taken: 8 cycles.
+        assert_eq!(cpu.regs.pc, 2);
+        assert_eq!(cpu.cycles, 8);
+        cpu.cycle();
+        // Taken: 12 cycles.
+        assert_eq!(cpu.regs.pc, 6);
+        assert_eq!(cpu.cycles, 20);
+    }
 
     #[test]
-    fn ld_ri_r8() {}
+    fn ld_ri_r8() {
+        let mut cpu = CPU::new();
+        // LD (HL+),A, LD (HL-),A
+        cpu.write_bytes(&[0x22, 0x32], 0).unwrap();
+        cpu.regs.a = 0x42;
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xC000], 0x42);
+        assert_eq!(cpu.regs.get16(Reg16::HL), 0xC001);
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xC001], 0x42);
+        assert_eq!(cpu.regs.get16(Reg16::HL), 0xC000);
+    }
 
     #[test]
-    fn daa() {}
+    fn daa() {
+        // (a, n, h, c) before DAA, followed by (a, z, c) after.
+        let cases = [
+            // 0x45 + 0x38 = 0x7D, which should read as 83.
+            ((0x7D, false, false, false), (0x83, false, false)),
+            // 0x09 + 0x08 = 0x11 with a half carry, which should read as 17.
+            ((0x11, false, true, false), (0x17, false, false)),
+            // 0x99 + 0x01 = 0x9A, which should read as 00 with a carry.
+            ((0x9A, false, false, false), (0x00, true, true)),
+            // 0x90 + 0x90 = 0x20 with a carry, which should read as 80 with a carry.
+            ((0x20, false, false, true), (0x80, false, true)),
+            // 0x47 - 0x28 = 0x1F with a half borrow, which should read as 19.
+            ((0x1F, true, true, false), (0x19, false, false)),
+            // 0x10 - 0x20 = 0xF0 with a borrow, which should read as 90 with a borrow.
+            ((0xF0, true, false, true), (0x90, false, true)),
+            // 0x00 - 0x00 needs no adjustment.
+            ((0x00, true, false, false), (0x00, true, false)),
+        ];
+        for &((a, n, h, c), (result, z, carry)) in cases.iter() {
+            let mut cpu = CPU::new();
+            cpu.bus.memory[0] = 0x27;
+            cpu.regs.a = a;
+            cpu.regs.flags = Flags { zero: false, subtraction: n, half_carry: h, carry: c };
+            cpu.cycle();
+            assert_eq!(cpu.regs.a, result, "DAA of {:#04x}", a);
+            assert!(cpu.regs.flags.test_flags(&Flags { zero: z, subtraction: n, half_carry: false, carry }));
+        }
+    }
 
     #[test]
-    fn ld_r8_ri16() {}
+    fn ld_r8_ri16() {
+        let mut cpu = CPU::new();
+        // LD A,(HL+), LD A,(HL-)
+        cpu.write_bytes(&[0x2A, 0x3A], 0).unwrap();
+        cpu.write_bytes(&[0x11, 0x22], 0xC000).unwrap();
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x11);
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x22);
+        assert_eq!(cpu.regs.get16(Reg16::HL), 0xC000);
+    }
 
     #[test]
-    fn cpl() {}
+    fn cpl() {
+        let mut cpu = CPU::new();
+        cpu.bus.memory[0] = 0x2F;
+        cpu.regs.a = 0b1010_0101;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0b0101_1010);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: true, carry: false }));
+    }
 
     // 3x
     #[test]
-    fn ld_sp_d16() {}
+    fn ld_sp_d16() {
+        let mut cpu = CPU::new();
+        cpu.write_bytes(&[0x31, 0xFE, 0xFF], 0).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.sp, 0xFFFE);
+        assert_eq!(cpu.cycles, 12);
+    }
 
     #[test]
-    fn inc_rd16() {}
+    fn inc_rd16() {
+        let mut cpu = CPU::new();
+        // INC (HL)
+        cpu.bus.memory[0] = 0x34;
+        cpu.bus.memory[0xC000] = 0xFF;
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xC000], 0x00);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: true, carry: false }));
+        assert_eq!(cpu.cycles, 12);
+    }
 
     #[test]
-    fn dec_rd16() {}
+    fn dec_rd16() {
+        let mut cpu = CPU::new();
+        // DEC (HL)
+        cpu.bus.memory[0] = 0x35;
+        cpu.bus.memory[0xC000] = 0x10;
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xC000], 0x0F);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: true, carry: false }));
+    }
 
     #[test]
-    fn ld_rd16_d8() {}
+    fn ld_rd16_d8() {
+        let mut cpu = CPU::new();
+        // LD (HL),d8
+        cpu.write_bytes(&[0x36, 0x99], 0).unwrap();
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xC000], 0x99);
+        assert_eq!(cpu.cycles, 12);
+    }
 
     #[test]
-    fn scf() {}
+    fn scf() {
+        let mut cpu = CPU::new();
+        cpu.bus.memory[0] = 0x37;
+        cpu.regs.flags = Flags { zero: true, subtraction: true, half_carry: true, carry: false };
+        cpu.cycle();
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: false, carry: true }));
+    }
 
     #[test]
-    fn ccf() {}
+    fn ccf() {
+        let mut cpu = CPU::new();
+        cpu.write_bytes(&[0x3F, 0x3F], 0).unwrap();
+        cpu.regs.flags.carry = true;
+        cpu.cycle();
+        assert!(!cpu.regs.flags.carry);
+        cpu.cycle();
+        assert!(cpu.regs.flags.carry);
+    }
 
     // 4x
     #[test]
-    fn ld_r8_r8() {}
+    fn ld_r8_r8() {
+        let mut cpu = CPU::new();
+        // LD B,A, LD C,B, LD L,C
+        cpu.write_bytes(&[0x47, 0x48, 0x69], 0).unwrap();
+        cpu.regs.a = 0x5A;
+        for _ in 0..3 {
+            cpu.cycle();
+        }
+        assert_eq!(cpu.regs.get8(Reg8::B), 0x5A);
+        assert_eq!(cpu.regs.get8(Reg8::C), 0x5A);
+        assert_eq!(cpu.regs.get8(Reg8::L), 0x5A);
+        assert_eq!(cpu.cycles, 12);
+    }
 
     #[test]
-    fn ld_r8_rd16() {}
+    fn ld_r8_rd16() {
+        let mut cpu = CPU::new();
+        // LD D,(HL), LD (HL),E
+        cpu.write_bytes(&[0x56, 0x73], 0).unwrap();
+        cpu.bus.memory[0xC000] = 0x77;
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.regs.set8(Reg8::E, 0x33);
+        cpu.cycle();
+        assert_eq!(cpu.regs.get8(Reg8::D), 0x77);
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xC000], 0x33);
+        assert_eq!(cpu.cycles, 16);
+    }
 
     // 5x
     #[test]
-    fn halt() {}
+    fn halt() {
+        let mut cpu = CPU::new();
+        // HALT, INC A
+        cpu.write_bytes(&[0x76, 0x3C], 0).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 1);
+        // With nothing pending, the CPU stays halted.
+        cpu.cycle();
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 1);
+        assert_eq!(cpu.regs.a, 0);
+        // A pending interrupt wakes the CPU even though IME is clear.
+        cpu.bus.memory[IE_ADDR as usize] = TIMER_INTERRUPT;
+        cpu.bus.memory[IF_ADDR as usize] = TIMER_INTERRUPT;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 1);
+        assert_eq!(cpu.regs.pc, 2);
+    }
 
     // 6x
     #[test]
-    fn add_r8_r8() {}
-
-    #[test]
-    fn add_r8_rd16() {}
+    fn add_r8_r8() {
+        let mut cpu = CPU::new();
+        // ADD A,B, ADD A,A
+        cpu.write_bytes(&[0x80, 0x87], 0).unwrap();
+        cpu.regs.a = 0x3A;
+        cpu.regs.set8(Reg8::B, 0xC6);
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x00);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: true, carry: true }));
+        cpu.regs.a = 0x08;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x10);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: false, half_carry: true, carry: false }));
+    }
 
     #[test]
-    fn adc_r8_r8() {}
+    fn add_r8_rd16() {
+        let mut cpu = CPU::new();
+        // ADD A,(HL)
+        cpu.bus.memory[0] = 0x86;
+        cpu.bus.memory[0xC000] = 0x12;
+        cpu.regs.set16(Reg16::HL, 0xC000);
+        cpu.regs.a = 0x01;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x13);
+        assert_eq!(cpu.cycles, 8);
+    }
 
     #[test]
-    fn adc_r8_rd16() {}
+    fn adc_r8_r8() {
+        let mut cpu = CPU::new();
+        // ADC A,C
+        cpu.bus.memory[0] = 0x89;
+        cpu.regs.a = 0xE1;
+        cpu.regs.set8(Reg8::C, 0x0F);
+        cpu.regs.flags.carry = true;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0xF1);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: false, half_carry: true, carry: false }));
+    }
 
     // 7x
     #[test]
-    fn sub_r8() {}
-
-    #[test]
-    fn sub_rd16() {}
-
-    #[test]
-    fn subc_r8_r8() {}
+    fn sub_r8() {
+        let mut cpu = CPU::new();
+        // SUB E
+        cpu.bus.memory[0] = 0x93;
+        cpu.regs.a = 0x3E;
+        cpu.regs.set8(Reg8::E, 0x3E);
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: true, half_carry: false, carry: false }));
+    }
 
     #[test]
-    fn subc_r8_rd16() {}
+    fn subc_r8_r8() {
+        let mut cpu = CPU::new();
+        // SBC A,H
+        cpu.bus.memory[0] = 0x9C;
+        cpu.regs.a = 0x3B;
+        cpu.regs.set8(Reg8::H, 0x2A);
+        cpu.regs.flags.carry = true;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x10);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: false, carry: false }));
+        // Borrowing from bit 4 and from bit 8 in the same instruction.
+        cpu.bus.memory[1] = 0x9C;
+        cpu.regs.a = 0x00;
+        cpu.regs.set8(Reg8::H, 0x00);
+        cpu.regs.flags.carry = true;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0xFF);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: true, carry: true }));
+    }
 
     // 8x
     #[test]
-    fn and_r8() {}
-
-    #[test]
-    fn and_rd16() {}
-
-    #[test]
-    fn xor_r8() {}
+    fn and_r8() {
+        let mut cpu = CPU::new();
+        // AND L
+        cpu.bus.memory[0] = 0xA5;
+        cpu.regs.a = 0x5A;
+        cpu.regs.set8(Reg8::L, 0x3F);
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x1A);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: false, half_carry: true, carry: false }));
+    }
 
     #[test]
-    fn xor_rd16() {}
+    fn xor_r8() {
+        let mut cpu = CPU::new();
+        // XOR A
+        cpu.bus.memory[0] = 0xAF;
+        cpu.regs.a = 0xFF;
+        cpu.regs.flags.carry = true;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: false, carry: false }));
+    }
 
     // 9x
     #[test]
-    fn or_r8() {}
-
-    #[test]
-    fn or_rd16() {}
-
-    #[test]
-    fn cp_r8() {}
+    fn or_r8() {
+        let mut cpu = CPU::new();
+        // OR B
+        cpu.bus.memory[0] = 0xB0;
+        cpu.regs.a = 0x5A;
+        cpu.regs.set8(Reg8::B, 0x0F);
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x5F);
+        assert!(cpu.regs.flags.test_flags(&Flags::new()));
+    }
 
     #[test]
-    fn cp_rd16() {}
+    fn cp_r8() {
+        let mut cpu = CPU::new();
+        // CP B
+        cpu.bus.memory[0] = 0xB8;
+        cpu.regs.a = 0x3C;
+        cpu.regs.set8(Reg8::B, 0x40);
+        cpu.cycle();
+        // The accumulator is left as it was.
+        assert_eq!(cpu.regs.a, 0x3C);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: false, carry: true }));
+    }
 
     // Ax
     #[test]
-    fn ret_b() {}
-
-    #[test]
-    fn pop_r16() {}
+    fn ret_b() {
+        let mut cpu = CPU::new();
+        // RET NC, RET C
+        cpu.write_bytes(&[0xD0, 0xD8], 0).unwrap();
+        cpu.write_bytes(&[0x00, 0x40], 0xFFFC).unwrap();
+        cpu.regs.sp = 0xFFFC;
+        cpu.regs.flags.carry = true;
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 1);
+        assert_eq!(cpu.cycles, 8);
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x4000);
+        assert_eq!(cpu.regs.sp, 0xFFFE);
+        assert_eq!(cpu.cycles, 28);
+    }
 
     #[test]
-    fn jp_b_a16() {}
+    fn pop_r16() {
+        let mut cpu = CPU::new();
+        cpu.regs.sp = 0xFFF0;
+        cpu.write_bytes(&[0x34, 0x12, 0xFF, 0xAB], 0xFFF0).unwrap();
+        // POP BC, POP AF
+        cpu.write_bytes(&[0xC1, 0xF1], 0).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.get16(Reg16::BC), 0x1234);
+        assert_eq!(cpu.regs.sp, 0xFFF2);
+        cpu.cycle();
+        // The lower nibble of F can never be set.
+        assert_eq!(cpu.regs.get16(Reg16::AF), 0xABF0);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: true, half_carry: true, carry: true }));
+        assert_eq!(cpu.regs.sp, 0xFFF4);
+        assert_eq!(cpu.cycles, 24);
+    }
 
     #[test]
-    fn jp_a16() {}
+    fn jp_b_a16() {
+        let mut cpu = CPU::new();
+        // JP Z,0x1234, JP NZ,0x4321
+        cpu.write_bytes(&[0xCA, 0x34, 0x12, 0xC2, 0x21, 0x43], 0).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 3);
+        assert_eq!(cpu.cycles, 12);
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x4321);
+        assert_eq!(cpu.cycles, 28);
+    }
 
     #[test]
-    fn call_b_a16() {}
+    fn jp_a16() {
+        let mut cpu = CPU::new();
+        // JP 0x0150, then JP HL
+        cpu.write_bytes(&[0xC3, 0x50, 0x01], 0).unwrap();
+        cpu.bus.memory[0x0150] = 0xE9;
+        cpu.regs.set16(Reg16::HL, 0x2000);
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x0150);
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x2000);
+        assert_eq!(cpu.cycles, 20);
+    }
 
     #[test]
-    fn push_r16() {}
+    fn call_b_a16() {
+        let mut cpu = CPU::new();
+        // CALL C,0x1234, CALL NC,0x1234
+        cpu.write_bytes(&[0xDC, 0x34, 0x12, 0xD4, 0x34, 0x12], 0).unwrap();
+        cpu.regs.sp = 0xFFFE;
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 3);
+        assert_eq!(cpu.regs.sp, 0xFFFE);
+        assert_eq!(cpu.cycles, 12);
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x1234);
+        assert_eq!(cpu.regs.sp, 0xFFFC);
+        assert_eq!(cpu.cycles, 36);
+    }
 
     #[test]
-    fn add_r8_d8() {}
+    fn push_r16() {
+        let mut cpu = CPU::new();
+        cpu.regs.sp = 0xFFFE;
+        cpu.regs.set16(Reg16::BC, 0x1234);
+        cpu.regs.set16(Reg16::AF, 0xAB50);
+        // PUSH BC, PUSH AF, POP DE, POP HL
+        cpu.write_bytes(&[0xC5, 0xF5, 0xD1, 0xE1], 0).unwrap();
+        cpu.cycle();
+        assert_eq!(cpu.regs.sp, 0xFFFC);
+        assert_eq!(cpu.bus.memory[0xFFFD], 0x12);
+        assert_eq!(cpu.bus.memory[0xFFFC], 0x34);
+        cpu.cycle();
+        assert_eq!(cpu.regs.sp, 0xFFFA);
+        assert_eq!(cpu.cycles, 32);
+        cpu.cycle();
+        cpu.cycle();
+        assert_eq!(cpu.regs.get16(Reg16::DE), 0xAB50);
+        assert_eq!(cpu.regs.get16(Reg16::HL), 0x1234);
+        assert_eq!(cpu.regs.sp, 0xFFFE);
+    }
 
     #[test]
-    fn rst() {}
+    fn add_r8_d8() {
+        let mut cpu = CPU::new();
+        // ADD A,0xFF
+        cpu.write_bytes(&[0xC6, 0xFF], 0).unwrap();
+        cpu.regs.a = 0x01;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x00);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: true, carry: true }));
+        assert_eq!(cpu.regs.pc, 2);
+    }
 
     #[test]
-    fn ret() {}
+    fn rst() {
+        let mut cpu = CPU::new();
+        // RST 0x38
+        cpu.bus.memory[0x0100] = 0xFF;
+        cpu.regs.pc = 0x0100;
+        cpu.regs.sp = 0xFFFE;
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x0038);
+        assert_eq!(cpu.bus.memory[0xFFFD], 0x01);
+        assert_eq!(cpu.bus.memory[0xFFFC], 0x01);
+        assert_eq!(cpu.cycles, 16);
+    }
 
     #[test]
-    fn call_a16() {}
+    fn ret() {
+        let mut cpu = CPU::new();
+        // RET
+        cpu.bus.memory[0] = 0xC9;
+        cpu.write_bytes(&[0x50, 0x01], 0xFFFC).unwrap();
+        cpu.regs.sp = 0xFFFC;
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x0150);
+        assert_eq!(cpu.regs.sp, 0xFFFE);
+        assert_eq!(cpu.cycles, 16);
+    }
 
     #[test]
-    fn adc_r8_d8() {}
+    fn call_a16() {
+        let mut cpu = CPU::new();
+        // CALL 0x0200, which immediately returns.
+        cpu.write_bytes(&[0xCD, 0x00, 0x02], 0).unwrap();
+        cpu.bus.memory[0x0200] = 0xC9;
+        cpu.regs.sp = 0xFFFE;
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x0200);
+        assert_eq!(cpu.bus.memory[0xFFFC], 0x03);
+        assert_eq!(cpu.bus.memory[0xFFFD], 0x00);
+        assert_eq!(cpu.cycles, 24);
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x0003);
+        assert_eq!(cpu.regs.sp, 0xFFFE);
+    }
 
     // Bx
     #[test]
-    fn sub_d8() {}
-
-    #[test]
-    fn reti() {}
+    fn sub_d8() {
+        let mut cpu = CPU::new();
+        // SUB 0x0F
+        cpu.write_bytes(&[0xD6, 0x0F], 0).unwrap();
+        cpu.regs.a = 0x3E;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x2F);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: true, carry: false }));
+    }
 
     #[test]
-    fn sbc_r8_d8() {}
+    fn reti() {
+        let mut cpu = CPU::new();
+        // RETI
+        cpu.bus.memory[0] = 0xD9;
+        cpu.write_bytes(&[0x34, 0x12], 0xFFFC).unwrap();
+        cpu.regs.sp = 0xFFFC;
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x1234);
+        // Interrupts are enabled straight away.
+        cpu.bus.memory[IE_ADDR as usize] = VBLANK_INTERRUPT;
+        cpu.bus.memory[IF_ADDR as usize] = VBLANK_INTERRUPT;
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x0040);
+    }
 
     // Cx
     #[test]
-    fn ld_a8_r8() {}
+    fn ld_a8_r8() {
+        let mut cpu = CPU::new();
+        // LDH (0x80),A
+        cpu.write_bytes(&[0xE0, 0x80], 0).unwrap();
+        cpu.regs.a = 0x42;
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xFF80], 0x42);
+        assert_eq!(cpu.cycles, 12);
+    }
 
     #[test]
-    fn ld_rd8_r8() {}
+    fn ld_rd8_r8() {
+        let mut cpu = CPU::new();
+        // LD (C),A, LD A,(C)
+        cpu.write_bytes(&[0xE2, 0xF2], 0).unwrap();
+        cpu.regs.set8(Reg8::C, 0x81);
+        cpu.regs.a = 0x24;
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xFF81], 0x24);
+        cpu.regs.a = 0;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x24);
+    }
 
     #[test]
-    fn and_d8() {}
+    fn and_d8() {
+        let mut cpu = CPU::new();
+        // AND 0x00
+        cpu.write_bytes(&[0xE6, 0x00], 0).unwrap();
+        cpu.regs.a = 0xFF;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: true, carry: false }));
+    }
 
     #[test]
-    fn add_sp_s8() {}
+    fn add_sp_s8() {
+        let mut cpu = CPU::new();
+        // ADD SP,-1, LD HL,SP+1
+        cpu.write_bytes(&[0xE8, 0xFF, 0xF8, 0x01], 0).unwrap();
+        cpu.regs.sp = 0xFFF8;
+        cpu.cycle();
+        assert_eq!(cpu.regs.sp, 0xFFF7);
+        // The flags come from adding 0xFF to the lower byte of SP.
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: false, half_carry: true, carry: true }));
+        cpu.cycle();
+        assert_eq!(cpu.regs.get16(Reg16::HL), 0xFFF8);
+        assert_eq!(cpu.regs.sp, 0xFFF7);
+        assert!(cpu.regs.flags.test_flags(&Flags::new()));
+        assert_eq!(cpu.cycles, 28);
+    }
 
     #[test]
-    fn ld_a16_r8() {}
+    fn ld_a16_r8() {
+        let mut cpu = CPU::new();
+        // LD (0xC123),A
+        cpu.write_bytes(&[0xEA, 0x23, 0xC1], 0).unwrap();
+        cpu.regs.a = 0x99;
+        cpu.cycle();
+        assert_eq!(cpu.bus.memory[0xC123], 0x99);
+        assert_eq!(cpu.cycles, 16);
+    }
 
     #[test]
-    fn xor_d8() {}
+    fn xor_d8() {
+        let mut cpu = CPU::new();
+        // XOR 0x0F
+        cpu.write_bytes(&[0xEE, 0x0F], 0).unwrap();
+        cpu.regs.a = 0xFF;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0xF0);
+    }
 
     // Dx
     #[test]
-    fn ld_r8_a8() {}
-
-    #[test]
-    fn di() {}
+    fn ld_r8_a8() {
+        let mut cpu = CPU::new();
+        // LDH A,(0x90)
+        cpu.write_bytes(&[0xF0, 0x90], 0).unwrap();
+        cpu.bus.memory[0xFF90] = 0x37;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x37);
+    }
 
     #[test]
-    fn or_d8() {}
+    fn di() {
+        let mut cpu = CPU::new();
+        // EI, DI, NOP
+        cpu.write_bytes(&[0xFB, 0xF3, 0x00], 0).unwrap();
+        cpu.bus.memory[IE_ADDR as usize] = VBLANK_INTERRUPT;
+        cpu.bus.memory[IF_ADDR as usize] = VBLANK_INTERRUPT;
+        cpu.cycle();
+        cpu.cycle();
+        cpu.cycle();
+        // DI straight after EI means interrupts are never enabled.
+        assert_eq!(cpu.regs.pc, 3);
+    }
 
     #[test]
-    fn ld_r16_sp() {}
+    fn or_d8() {
+        let mut cpu = CPU::new();
+        // OR 0x00
+        cpu.write_bytes(&[0xF6, 0x00], 0).unwrap();
+        cpu.cycle();
+        assert!(cpu.regs.flags.zero);
+    }
 
     #[test]
-    fn ld_sp_hl() {}
+    fn ld_sp_hl() {
+        let mut cpu = CPU::new();
+        // LD SP,HL
+        cpu.bus.memory[0] = 0xF9;
+        cpu.regs.set16(Reg16::HL, 0xDFFF);
+        cpu.cycle();
+        assert_eq!(cpu.regs.sp, 0xDFFF);
+        assert_eq!(cpu.cycles, 8);
+    }
 
     #[test]
-    fn ld_r8_a16() {}
+    fn ld_r8_a16() {
+        let mut cpu = CPU::new();
+        // LD A,(0xC000)
+        cpu.write_bytes(&[0xFA, 0x00, 0xC0], 0).unwrap();
+        cpu.bus.memory[0xC000] = 0x5C;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x5C);
+        assert_eq!(cpu.cycles, 16);
+    }
 
     #[test]
-    fn ei() {}
+    fn ei() {
+        let mut cpu = CPU::new();
+        // EI, NOP, NOP
+        cpu.write_bytes(&[0xFB, 0x00, 0x00], 0).unwrap();
+        cpu.regs.sp = 0xFFFE;
+        cpu.bus.memory[IE_ADDR as usize] = VBLANK_INTERRUPT | TIMER_INTERRUPT;
+        cpu.bus.memory[IF_ADDR as usize] = TIMER_INTERRUPT;
+        cpu.cycle();
+        // The instruction after EI still runs before the interrupt is taken.
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 2);
+        cpu.cycle();
+        assert_eq!(cpu.regs.pc, 0x0050);
+        assert_eq!(cpu.bus.memory[0xFFFC], 0x02);
+        assert_eq!(cpu.bus.memory[IF_ADDR as usize], 0);
+        assert_eq!(cpu.cycles, 28);
+    }
 
     #[test]
-    fn cp_d8() {}
+    fn cp_d8() {
+        let mut cpu = CPU::new();
+        // CP 0x2F
+        cpu.write_bytes(&[0xFE, 0x2F], 0).unwrap();
+        cpu.regs.a = 0x3C;
+        cpu.cycle();
+        assert_eq!(cpu.regs.a, 0x3C);
+        assert!(cpu.regs.flags.test_flags(&Flags { zero: false, subtraction: true, half_carry: true, carry: false }));
+    }
 }
+
+#[cfg(test)]
+mod single_step_tests;
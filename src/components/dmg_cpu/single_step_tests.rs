@@ -0,0 +1,321 @@
+//! Checks every base and CB opcode against the SingleStepTests (sm83) JSON test vectors, found
+//! under `test-roms/sm83/`. Each file holds cases for a single opcode, giving the state of the CPU
+//! and memory before and after the instruction, along with what was on the bus during every
+//! M-cycle of it. The vectors aren't included; without them these tests pass trivially.
+//!
+//! The CPU is run with a flat bus, so that every address is plain memory and no other component
+//! gets in the way of what the vectors expect.
+
+use std::convert::TryFrom;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde_json::Value;
+use super::{BusCycle, CPU};
+use crate::components::dmg_cpu::IE_ADDR;
+use crate::components::register::Reg8;
+
+/// Where the vectors are looked for, relative to the crate root.
+const VECTOR_DIR: &str = "test-roms/sm83";
+/// How many failing files are listed in full before the rest are just counted.
+const REPORTED_FILES: usize = 20;
+
+/// The registers and memory before or after a test case.
+struct State {
+    pc: u16,
+    sp: u16,
+    /// A, F, B, C, D, E, H and L.
+    regs: [(Reg8, u8); 8],
+    ime: bool,
+    /// Whether an EI is waiting to take effect, where the vectors say.
+    ei: Option<bool>,
+    /// The IE register, where the vectors give it separately from `ram`.
+    ie: Option<u8>,
+    ram: Vec<(u16, u8)>,
+}
+
+struct Case {
+    name: String,
+    initial: State,
+    expected: State,
+    cycles: Vec<BusCycle>,
+}
+
+fn field<'a>(value: &'a Value, key: &str) -> Result<&'a Value, String> {
+    value.get(key).ok_or_else(|| format!("missing field `{}`", key))
+}
+
+fn number(value: &Value) -> Result<u64, String> {
+    value.as_u64().ok_or_else(|| format!("expected a number, found {}", value))
+}
+
+fn byte(value: &Value) -> Result<u8, String> {
+    let n = number(value)?;
+    u8::try_from(n).map_err(|_| format!("{} doesn't fit in a byte", n))
+}
+
+fn word(value: &Value) -> Result<u16, String> {
+    let n = number(value)?;
+    u16::try_from(n).map_err(|_| format!("{} doesn't fit in a word", n))
+}
+
+fn optional<T>(value: &Value, key: &str, parse: fn(&Value) -> Result<T, String>) -> Result<Option<T>, String> {
+    value.get(key).map(parse).transpose()
+}
+
+fn parse_state(value: &Value) -> Result<State, String> {
+    let reg = |reg: Reg8, key: &str| -> Result<(Reg8, u8), String> { Ok((reg, byte(field(value, key)?)?)) };
+    let ram = field(value, "ram")?.as_array().ok_or("`ram` isn't a list")?
+        .iter()
+        .map(|entry| match entry.as_array().map(Vec::as_slice) {
+            Some([addr, val]) => Ok((word(addr)?, byte(val)?)),
+            _ => Err(format!("bad RAM entry {}", entry)),
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(State {
+        pc: word(field(value, "pc")?)?,
+        sp: word(field(value, "sp")?)?,
+        regs: [
+            reg(Reg8::A, "a")?, reg(Reg8::F, "f")?, reg(Reg8::B, "b")?, reg(Reg8::C, "c")?,
+            reg(Reg8::D, "d")?, reg(Reg8::E, "e")?, reg(Reg8::H, "h")?, reg(Reg8::L, "l")?,
+        ],
+        ime: number(field(value, "ime")?)? != 0,
+        ei: optional(value, "ei", |v| number(v).map(|n| n != 0))?,
+        ie: optional(value, "ie", byte)?,
+        ram,
+    })
+}
+
+/// An M-cycle in the vectors is `[address, value, pins]`, where the pins read `r-m` for a read and
+/// `-wm` for a write. Internal cycles are `null`, or have neither pin set.
+fn parse_cycle(value: &Value) -> Result<BusCycle, String> {
+    let entry = match value.as_array().map(Vec::as_slice) {
+        None if value.is_null() => return Ok(BusCycle::Idle),
+        Some([addr, val, pins]) => (addr, val, pins.as_str().unwrap_or("")),
+        _ => return Err(format!("bad cycle {}", value)),
+    };
+    let (addr, val, pins) = entry;
+    if addr.is_null() || val.is_null() {
+        return Ok(BusCycle::Idle);
+    }
+    if pins.contains('r') {
+        Ok(BusCycle::Read(word(addr)?, byte(val)?))
+    } else if pins.contains('w') {
+        Ok(BusCycle::Write(word(addr)?, byte(val)?))
+    } else {
+        Ok(BusCycle::Idle)
+    }
+}
+
+fn parse_case(value: &Value) -> Result<Case, String> {
+    let name = field(value, "name")?.as_str().unwrap_or("unnamed").to_string();
+    let cycles = field(value, "cycles")?.as_array().ok_or("`cycles` isn't a list")?
+        .iter()
+        .map(parse_cycle)
+        .collect::<Result<_, String>>()
+        .map_err(|e| format!("{}: {}", name, e))?;
+    Ok(Case {
+        initial: parse_state(field(value, "initial")?).map_err(|e| format!("{}: {}", name, e))?,
+        expected: parse_state(field(value, "final")?).map_err(|e| format!("{}: {}", name, e))?,
+        name,
+        cycles,
+    })
+}
+
+fn parse_cases(text: &str) -> Result<Vec<Case>, String> {
+    let json: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    json.as_array().ok_or("the file isn't a list of cases")?.iter().map(parse_case).collect()
+}
+
+fn load_cases(path: &Path) -> Result<Vec<Case>, String> {
+    parse_cases(&fs::read_to_string(path).map_err(|e| e.to_string())?)
+}
+
+fn find_vectors(dir: &Path, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+        if path.is_dir() {
+            find_vectors(&path, found);
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            found.push(path);
+        }
+    }
+}
+
+/// Run the instruction of a single case, from its initial state, recording every M-cycle.
+fn run_case(case: &Case) -> CPU {
+    let mut cpu = CPU::new();
+    cpu.bus.flat = true;
+    let initial = &case.initial;
+    cpu.regs.pc = initial.pc;
+    cpu.regs.sp = initial.sp;
+    for &(reg, val) in &initial.regs {
+        cpu.regs.set8(reg, val);
+    }
+    cpu.ime = initial.ime;
+    cpu.ime_scheduled = initial.ei.unwrap_or(false);
+    if let Some(ie) = initial.ie {
+        cpu.bus.memory[IE_ADDR as usize] = ie;
+    }
+    for &(addr, val) in &initial.ram {
+        cpu.bus.memory[addr as usize] = val;
+    }
+    cpu.cycle_log = Some(Vec::new());
+    cpu.cycle();
+    cpu
+}
+
+/// Describe how the CPU's state differs from what the case expects, if it does.
+fn state_mismatch(cpu: &CPU, expected: &State) -> Option<String> {
+    let mut problems = Vec::new();
+    let mut check = |name: &str, actual: u16, expected: u16| {
+        if actual != expected {
+            problems.push(format!("{} is {:02X}, expected {:02X}", name, actual, expected));
+        }
+    };
+    check("PC", cpu.regs.pc, expected.pc);
+    check("SP", cpu.regs.sp, expected.sp);
+    for &(reg, val) in &expected.regs {
+        check(&format!("{:?}", reg), cpu.regs.get8(reg) as u16, val as u16);
+    }
+    check("IME", cpu.ime as u16, expected.ime as u16);
+    if let Some(ei) = expected.ei {
+        check("EI", cpu.ime_scheduled as u16, ei as u16);
+    }
+    if let Some(ie) = expected.ie {
+        check("IE", cpu.bus.memory[IE_ADDR as usize] as u16, ie as u16);
+    }
+    for &(addr, val) in &expected.ram {
+        check(&format!("({:04X})", addr), cpu.bus.memory[addr as usize] as u16, val as u16);
+    }
+    (!problems.is_empty()).then(|| problems.join(", "))
+}
+
+fn describe_cycles(cycles: &[BusCycle]) -> String {
+    let cycles: Vec<String> = cycles.iter()
+        .map(|cycle| match cycle {
+            BusCycle::Idle => "-".to_string(),
+            BusCycle::Read(addr, val) => format!("r {:04X}={:02X}", addr, val),
+            BusCycle::Write(addr, val) => format!("w {:04X}={:02X}", addr, val),
+        })
+        .collect();
+    format!("[{}]", cycles.join(", "))
+}
+
+/// Describe how the M-cycles the CPU spent differ from what the case expects, if they do.
+fn cycle_mismatch(cpu: &CPU, expected: &[BusCycle]) -> Option<String> {
+    let actual = cpu.cycle_log.as_deref().unwrap_or_default();
+    (actual != expected)
+        .then(|| format!("bus cycles were {}, expected {}", describe_cycles(actual), describe_cycles(expected)))
+}
+
+/// Run every case of every vector file through `check`, failing with a summary of the files which
+/// had failing cases, along with the first failure in each.
+fn check_vectors(check: fn(&CPU, &Case) -> Option<String>) {
+    let mut files = Vec::new();
+    find_vectors(&Path::new(env!("CARGO_MANIFEST_DIR")).join(VECTOR_DIR), &mut files);
+    if files.is_empty() {
+        eprintln!("No SingleStepTests vectors found under {}; skipping", VECTOR_DIR);
+        return;
+    }
+    files.sort();
+
+    let mut cases = 0;
+    let mut failures = Vec::new();
+    for path in &files {
+        let name = path.file_name().unwrap().to_string_lossy();
+        let file_cases = match load_cases(path) {
+            Ok(file_cases) => file_cases,
+            Err(e) => {
+                failures.push(format!("{}: couldn't be loaded: {}", name, e));
+                continue;
+            }
+        };
+        cases += file_cases.len();
+        let mut failed = file_cases.iter()
+            .filter_map(|case| check(&run_case(case), case).map(|problem| (&case.name, problem)));
+        if let Some((first, problem)) = failed.next() {
+            let others = failed.count();
+            failures.push(format!("{}: {} ({}), and {} other cases", name, first, problem, others));
+        }
+    }
+
+    if !failures.is_empty() {
+        let mut summary = format!("{} of {} vector files failed ({} cases in all):\n", failures.len(), files.len(), cases);
+        for failure in failures.iter().take(REPORTED_FILES) {
+            writeln!(summary, "  {}", failure).unwrap();
+        }
+        if failures.len() > REPORTED_FILES {
+            writeln!(summary, "  ...and {} more", failures.len() - REPORTED_FILES).unwrap();
+        }
+        panic!("{}", summary);
+    }
+}
+
+#[test]
+fn single_step_state() {
+    check_vectors(|cpu, case| state_mismatch(cpu, &case.expected));
+}
+
+#[test]
+fn single_step_bus_cycles() {
+    check_vectors(|cpu, case| cycle_mismatch(cpu, &case.cycles));
+}
+
+/// A few cases written out in the vectors' format, so that the loader and the comparisons are
+/// checked even where the vectors themselves aren't around.
+const INLINE_CASES: &str = r#"[
+    {
+        "name": "00 NOP",
+        "initial": {"pc": 256, "sp": 65534, "a": 1, "f": 176, "b": 0, "c": 19, "d": 0, "e": 216,
+                    "h": 1, "l": 77, "ime": 0, "ie": 0, "ram": [[256, 0]]},
+        "final": {"pc": 257, "sp": 65534, "a": 1, "f": 176, "b": 0, "c": 19, "d": 0, "e": 216,
+                  "h": 1, "l": 77, "ime": 0, "ie": 0, "ram": [[256, 0]]},
+        "cycles": [[256, 0, "r-m"]]
+    },
+    {
+        "name": "77 LD (HL),A",
+        "initial": {"pc": 256, "sp": 65534, "a": 66, "f": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                    "h": 192, "l": 16, "ime": 0, "ram": [[256, 119], [49168, 0]]},
+        "final": {"pc": 257, "sp": 65534, "a": 66, "f": 0, "b": 0, "c": 0, "d": 0, "e": 0,
+                  "h": 192, "l": 16, "ime": 0, "ram": [[256, 119], [49168, 66]]},
+        "cycles": [[256, 119, "r-m"], [49168, 66, "-wm"]]
+    },
+    {
+        "name": "03 INC BC",
+        "initial": {"pc": 256, "sp": 65534, "a": 0, "f": 0, "b": 18, "c": 255, "d": 0, "e": 0,
+                    "h": 0, "l": 0, "ime": 0, "ram": [[256, 3]]},
+        "final": {"pc": 257, "sp": 65534, "a": 0, "f": 0, "b": 19, "c": 0, "d": 0, "e": 0,
+                  "h": 0, "l": 0, "ime": 0, "ram": [[256, 3]]},
+        "cycles": [[256, 3, "r-m"], null]
+    }
+]"#;
+
+#[test]
+fn inline_cases() {
+    let cases = parse_cases(INLINE_CASES).unwrap();
+    assert_eq!(cases.len(), 3);
+    assert_eq!(cases[0].initial.ie, Some(0));
+    assert_eq!(cases[1].expected.ram, vec![(0x0100, 0x77), (0xC010, 0x42)]);
+    assert_eq!(cases[1].cycles, vec![BusCycle::Read(0x0100, 0x77), BusCycle::Write(0xC010, 0x42)]);
+    assert_eq!(cases[2].cycles, vec![BusCycle::Read(0x0100, 0x03), BusCycle::Idle]);
+    for case in &cases {
+        let cpu = run_case(case);
+        assert_eq!(state_mismatch(&cpu, &case.expected), None, "{}", case.name);
+        assert_eq!(cycle_mismatch(&cpu, &case.cycles), None, "{}", case.name);
+    }
+
+    // A case the CPU doesn't live up to is caught, both in its state and on the bus.
+    let mut wrong = parse_cases(INLINE_CASES).unwrap().remove(1);
+    wrong.expected.ram[1].1 = 0x43;
+    wrong.cycles[1] = BusCycle::Write(0xC010, 0x43);
+    let cpu = run_case(&wrong);
+    assert_eq!(state_mismatch(&cpu, &wrong.expected).unwrap(), "(C010) is 42, expected 43");
+    assert_eq!(
+        cycle_mismatch(&cpu, &wrong.cycles).unwrap(),
+        "bus cycles were [r 0100=77, w C010=42], expected [r 0100=77, w C010=43]",
+    );
+}
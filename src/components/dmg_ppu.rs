@@ -1,8 +1,60 @@
-#[allow(dead_code)]
+use crate::components::dmg_cpu::{STAT_INTERRUPT, VBLANK_INTERRUPT};
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter};
 
-use ux::{u1, u2};
-use std::collections::HashMap;
-use crate::components::graphics_components::Tile;
+/// The address of the LCD control register (LCDC).
+pub const LCDC_ADDR: u16 = 0xFF40;
+/// The address of the LCD status register (STAT).
+pub const STAT_ADDR: u16 = 0xFF41;
+/// The address of the background scroll Y register (SCY).
+pub const SCY_ADDR: u16 = 0xFF42;
+/// The address of the background scroll X register (SCX).
+pub const SCX_ADDR: u16 = 0xFF43;
+/// The address of the current scanline (LY).
+pub const LY_ADDR: u16 = 0xFF44;
+/// The address of the scanline compare register (LYC).
+pub const LYC_ADDR: u16 = 0xFF45;
+/// The address of the background palette (BGP).
+pub const BGP_ADDR: u16 = 0xFF47;
+/// The address of the first sprite palette (OBP0).
+pub const OBP0_ADDR: u16 = 0xFF48;
+/// The address of the second sprite palette (OBP1).
+pub const OBP1_ADDR: u16 = 0xFF49;
+/// The address of the window Y position (WY).
+pub const WY_ADDR: u16 = 0xFF4A;
+/// The address of the window X position plus 7 (WX).
+pub const WX_ADDR: u16 = 0xFF4B;
+
+/// The start of VRAM in the address space.
+pub const VRAM_START: u16 = 0x8000;
+/// The start of OAM in the address space.
+pub const OAM_START: u16 = 0xFE00;
+/// The size of OAM: 40 entries of 4 bytes each.
+pub const OAM_SIZE: usize = 0xA0;
+
+/// Each scanline takes 456 dots (T-cycles), including the horizontal blank.
+pub const DOTS_PER_LINE: u32 = 456;
+/// There are 144 visible lines, followed by 10 lines of vertical blank.
+pub const LINES_PER_FRAME: u8 = 154;
+/// The first line of the vertical blank.
+const VBLANK_LINE: u8 = 144;
+/// OAM scan takes the first 80 dots of each visible line.
+const OAM_SCAN_DOTS: u32 = 80;
+/// Pixel transfer takes at least 172 dots; the variable length of this mode isn't emulated.
+const DRAWING_DOTS: u32 = 172;
+
+/// The width of the LCD, in pixels.
+pub const SCREEN_WIDTH: usize = 160;
+/// The height of the LCD, in pixels.
+pub const SCREEN_HEIGHT: usize = 144;
+/// At most ten sprites can be drawn on each scanline.
+const SPRITES_PER_LINE: usize = 10;
+
+/// The bits of a framebuffer pixel which hold its shade, from 0 (lightest) to 3 (darkest).
+pub const PIXEL_SHADE_MASK: u8 = 0b0011;
+/// A framebuffer pixel with this bit set came from a sprite using OBP0.
+pub const PIXEL_OBP0: u8 = 0b0100;
+/// A framebuffer pixel with this bit set came from a sprite using OBP1.
+pub const PIXEL_OBP1: u8 = 0b1000;
 
 enum Mode {
     DMG,
@@ -10,6 +62,7 @@ enum Mode {
 }
 
 /// This determines which background map the the Window / Background should use for rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum WindowBGArea {
     /// Refers to RESET bit.
     Base = 0x9800,
@@ -17,17 +70,32 @@ enum WindowBGArea {
     Offset = 0x9C00,
 }
 
+/// This determines where the Background and Window find their tile data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum AddressingMode {
-    // TODO: Understand why we use these blocks, and hence what enum values to assign.
+    /// Refers to SET bit: tiles 0-255 are found from 0x8000, as with sprites.
     Unsigned,
+    /// Refers to RESET bit: tiles -128-127 are found either side of 0x9000.
     Signed
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum ObjSize {
+    /// 8x8 sprites.
     Square,
+    /// 8x16 sprites.
     Double,
 }
 
+/// The mode the PPU is in, as reported in the lower two bits of STAT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LcdMode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
 /// # Game Boy PPU
 /// The PPU is used to organise the various I/O devices which are
 /// responsible for driving video output on the Game Boy. These are
@@ -35,15 +103,17 @@ enum ObjSize {
 /// for a high-level API which can be called by the CPU or any other
 /// user, such as for debugging purposes.
 /// ## PPU Components
-/// - Sprite attribute table (OAM)
+/// - VRAM and the sprite attribute table (OAM)
 /// - LCD control
 /// - LCD status
 /// - LCD position and scrolling
 /// - Palettes
-/// - Pixel FIFO
-pub struct PPU<'a> {
+pub struct PPU {
     mode: Mode,
-    oam: OAM<'a>,
+    /// Video RAM, mapped to 0x8000-0x9FFF.
+    vram: [u8; 0x2000],
+    /// The sprite attribute table, mapped to 0xFE00-0xFE9F.
+    oam: [u8; OAM_SIZE],
     //LCD Control - enables
     /// Determines if the LCD and PPU are on/active.
     /// Turning off allows immediate and full access to VRAM, OAM, etc.
@@ -60,27 +130,566 @@ pub struct PPU<'a> {
     window_tile_area: WindowBGArea,
     bg_tile_area: WindowBGArea,
     obj_size: ObjSize,
+    // LCD Status - interrupt sources
+    /// STAT bit 6: request the STAT interrupt when LY == LYC.
+    lyc_interrupt: bool,
+    /// STAT bit 5: request the STAT interrupt on entering OAM scan.
+    oam_interrupt: bool,
+    /// STAT bit 4: request the STAT interrupt on entering the vertical blank.
+    vblank_interrupt: bool,
+    /// STAT bit 3: request the STAT interrupt on entering the horizontal blank.
+    hblank_interrupt: bool,
+    // LCD position and scrolling
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    wy: u8,
+    wx: u8,
+    // Palettes
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    /// The mode the PPU is currently in.
+    lcd_mode: LcdMode,
+    /// How far through the current scanline the PPU is, in dots.
+    dots: u32,
+    /// The STAT interrupt is only requested when this goes from low to high, so that several
+    /// sources being active at once only cause a single interrupt.
+    stat_line: bool,
+    /// The window keeps its own line counter, which only advances on lines it was drawn on.
+    window_line: u8,
+    /// The finished picture, one byte per pixel. The lower two bits are the shade after the
+    /// palette has been applied, and `PIXEL_OBP0`/`PIXEL_OBP1` mark pixels drawn by sprites, so
+    /// that frontends can colour the layers separately.
+    framebuffer: [u8; SCREEN_WIDTH * SCREEN_HEIGHT],
+    /// Set on entering the vertical blank, once the framebuffer holds a complete frame.
+    frame_ready: bool,
+}
+
+impl PPU {
+    pub fn new() -> Self {
+        PPU {
+            mode: Mode::DMG,
+            vram: [0; 0x2000],
+            oam: [0; OAM_SIZE],
+            lcd_enable: false,
+            window_enable: false,
+            obj_enable: false,
+            bg_window_priority: false,
+            bg_window_tile_area: AddressingMode::Signed,
+            window_tile_area: WindowBGArea::Base,
+            bg_tile_area: WindowBGArea::Base,
+            obj_size: ObjSize::Square,
+            lyc_interrupt: false,
+            oam_interrupt: false,
+            vblank_interrupt: false,
+            hblank_interrupt: false,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            wy: 0,
+            wx: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            lcd_mode: LcdMode::HBlank,
+            dots: 0,
+            stat_line: false,
+            window_line: 0,
+            framebuffer: [0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_ready: false,
+        }
+    }
+
+    /// The most recently drawn picture, row by row. See `framebuffer` for the format of a pixel.
+    pub fn framebuffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    /// Returns true, once, after each frame has been completed.
+    pub fn take_frame_ready(&mut self) -> bool {
+        std::mem::replace(&mut self.frame_ready, false)
+    }
+
+    /// Returns true if the LCD is switched on.
+    pub fn lcd_enabled(&self) -> bool {
+        self.lcd_enable
+    }
+
+    /// The mode the PPU is currently in.
+    pub fn lcd_mode(&self) -> LcdMode {
+        self.lcd_mode
+    }
+
+    /// The scanline currently being drawn.
+    pub fn ly(&self) -> u8 {
+        self.ly
+    }
+
+    /// Read VRAM, OAM or one of the LCD registers, as the CPU would see it. The CPU can't get at
+    /// VRAM whilst the PPU is drawing, nor OAM whilst the PPU is scanning or drawing.
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x8000..=0x9FFF if !self.vram_blocked() => self.vram[(addr - VRAM_START) as usize],
+            0xFE00..=0xFE9F if !self.oam_blocked() => self.oam[(addr - OAM_START) as usize],
+            LCDC_ADDR => self.lcdc(),
+            // Bit 7 of STAT is unused and always reads back as set.
+            STAT_ADDR => {
+                0b1000_0000
+                    | (self.lyc_interrupt as u8) << 6
+                    | (self.oam_interrupt as u8) << 5
+                    | (self.vblank_interrupt as u8) << 4
+                    | (self.hblank_interrupt as u8) << 3
+                    | ((self.ly == self.lyc) as u8) << 2
+                    | self.lcd_mode as u8
+            }
+            SCY_ADDR => self.scy,
+            SCX_ADDR => self.scx,
+            LY_ADDR => self.ly,
+            LYC_ADDR => self.lyc,
+            BGP_ADDR => self.bgp,
+            OBP0_ADDR => self.obp0,
+            OBP1_ADDR => self.obp1,
+            WY_ADDR => self.wy,
+            WX_ADDR => self.wx,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write to VRAM, OAM or one of the LCD registers, as the CPU would.
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x8000..=0x9FFF if !self.vram_blocked() => self.vram[(addr - VRAM_START) as usize] = val,
+            0xFE00..=0xFE9F if !self.oam_blocked() => self.oam[(addr - OAM_START) as usize] = val,
+            LCDC_ADDR => self.set_lcdc(val),
+            // Only the interrupt sources can be written; the rest of STAT is read-only.
+            STAT_ADDR => {
+                self.lyc_interrupt = (val & 0b0100_0000) != 0;
+                self.oam_interrupt = (val & 0b0010_0000) != 0;
+                self.vblank_interrupt = (val & 0b0001_0000) != 0;
+                self.hblank_interrupt = (val & 0b0000_1000) != 0;
+            }
+            SCY_ADDR => self.scy = val,
+            SCX_ADDR => self.scx = val,
+            // LY is read-only.
+            LYC_ADDR => self.lyc = val,
+            BGP_ADDR => self.bgp = val,
+            OBP0_ADDR => self.obp0 = val,
+            OBP1_ADDR => self.obp1 = val,
+            WY_ADDR => self.wy = val,
+            WX_ADDR => self.wx = val,
+            _ => {}
+        }
+    }
+
+    /// All of VRAM, from $8000 to $9FFF, regardless of what the PPU is doing.
+    pub fn vram(&self) -> &[u8] {
+        &self.vram
+    }
+
+    /// Read VRAM directly, regardless of what the PPU is doing.
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        self.vram[(addr - VRAM_START) as usize]
+    }
+
+    /// Write directly to OAM, as OAM DMA does, regardless of what the PPU is doing.
+    pub fn write_oam(&mut self, index: usize, val: u8) {
+        self.oam[index] = val;
+    }
+
+    /// Advance the PPU by the given number of dots (T-cycles).
+    /// Returns the interrupts (V-blank and/or STAT) which should be requested as a result.
+    pub fn tick(&mut self, cycles: u32) -> u8 {
+        if !self.lcd_enable {
+            return 0;
+        }
+
+        let mut interrupts = 0;
+        for _ in 0..cycles {
+            self.dots += 1;
+            if self.dots == DOTS_PER_LINE {
+                self.dots = 0;
+                self.ly = (self.ly + 1) % LINES_PER_FRAME;
+                if self.ly == 0 {
+                    self.window_line = 0;
+                }
+            }
+
+            let mode = if self.ly >= VBLANK_LINE {
+                LcdMode::VBlank
+            } else if self.dots < OAM_SCAN_DOTS {
+                LcdMode::OamScan
+            } else if self.dots < OAM_SCAN_DOTS + DRAWING_DOTS {
+                LcdMode::Drawing
+            } else {
+                LcdMode::HBlank
+            };
+            if mode != self.lcd_mode {
+                match mode {
+                    // The whole line is drawn at once, as pixel transfer finishes.
+                    LcdMode::HBlank => self.render_line(),
+                    LcdMode::VBlank => {
+                        interrupts |= VBLANK_INTERRUPT;
+                        self.frame_ready = true;
+                    }
+                    _ => {}
+                }
+                self.lcd_mode = mode;
+            }
+
+            if self.update_stat_line() {
+                interrupts |= STAT_INTERRUPT;
+            }
+        }
+        interrupts
+    }
+
+    /// Work out the combined STAT interrupt line, returning true on a rising edge.
+    fn update_stat_line(&mut self) -> bool {
+        let line = (self.lyc_interrupt && self.ly == self.lyc)
+            || match self.lcd_mode {
+                LcdMode::HBlank => self.hblank_interrupt,
+                LcdMode::VBlank => self.vblank_interrupt,
+                LcdMode::OamScan => self.oam_interrupt,
+                LcdMode::Drawing => false,
+            };
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        rising
+    }
+
+    /// Draw the current scanline into the framebuffer: the background, then the window over it,
+    /// then the sprites.
+    fn render_line(&mut self) {
+        let mut line = [0u8; SCREEN_WIDTH];
+        // The colour index of the background/window under each pixel, before the palette.
+        let mut bg_index = [0u8; SCREEN_WIDTH];
+
+        if self.bg_window_priority {
+            let y = self.ly.wrapping_add(self.scy);
+            for (x, index) in bg_index.iter_mut().enumerate() {
+                let map_x = (x as u8).wrapping_add(self.scx);
+                *index = self.map_pixel(self.bg_tile_area, map_x, y);
+            }
+
+            let window_x = self.wx as i16 - 7;
+            if self.window_enable && self.ly >= self.wy && window_x < SCREEN_WIDTH as i16 {
+                for (x, index) in bg_index.iter_mut().enumerate().skip(window_x.max(0) as usize) {
+                    let map_x = (x as i16 - window_x) as u8;
+                    *index = self.map_pixel(self.window_tile_area, map_x, self.window_line);
+                }
+                self.window_line += 1;
+            }
+        }
+        for (pixel, &index) in line.iter_mut().zip(bg_index.iter()) {
+            *pixel = palette_shade(self.bgp, index);
+        }
+
+        if self.obj_enable {
+            self.render_sprites(&mut line, &bg_index);
+        }
+
+        let start = self.ly as usize * SCREEN_WIDTH;
+        self.framebuffer[start..start + SCREEN_WIDTH].copy_from_slice(&line);
+    }
+
+    fn render_sprites(&self, line: &mut [u8; SCREEN_WIDTH], bg_index: &[u8; SCREEN_WIDTH]) {
+        let height = if self.obj_size == ObjSize::Double { 16 } else { 8 };
+        let ly = self.ly as i16;
+        // The first ten sprites in OAM which overlap this line are the ones drawn.
+        let mut sprites: Vec<&[u8]> = self.oam
+            .chunks(4)
+            .filter(|sprite| {
+                let top = sprite[0] as i16 - 16;
+                ly >= top && ly < top + height
+            })
+            .take(SPRITES_PER_LINE)
+            .collect();
+        // Sprites further left take priority, then those earlier in OAM. Drawing them in reverse
+        // order of priority means the highest priority ends up on top.
+        sprites.sort_by_key(|sprite| sprite[1]);
+        for sprite in sprites.iter().rev() {
+            let (top, left, mut tile, flags) = (sprite[0] as i16 - 16, sprite[1] as i16 - 8, sprite[2], sprite[3]);
+            let mut row = (ly - top) as u8;
+            if flags & 0b0100_0000 != 0 {
+                row = height as u8 - 1 - row;
+            }
+            if height == 16 {
+                tile &= 0xFE;
+            }
+            let addr = VRAM_START + tile as u16 * 16 + row as u16 * 2;
+            let (palette, source) = if flags & 0b0001_0000 != 0 { (self.obp1, PIXEL_OBP1) } else { (self.obp0, PIXEL_OBP0) };
+            for i in 0..8 {
+                let x = left + i;
+                if x < 0 || x >= SCREEN_WIDTH as i16 {
+                    continue;
+                }
+                let bit = if flags & 0b0010_0000 != 0 { i } else { 7 - i } as u8;
+                let index = self.tile_pixel(addr, bit);
+                // Colour 0 is transparent, and the background can be given priority over sprites.
+                if index == 0 || (flags & 0b1000_0000 != 0 && bg_index[x as usize] != 0) {
+                    continue;
+                }
+                line[x as usize] = palette_shade(palette, index) | source;
+            }
+        }
+    }
+
+    /// The colour index of a pixel of the background or window map.
+    fn map_pixel(&self, map: WindowBGArea, x: u8, y: u8) -> u8 {
+        let map_addr = map as u16 + (y as u16 / 8) * 32 + x as u16 / 8;
+        let tile = self.read_vram(map_addr);
+        let tile_addr = match self.bg_window_tile_area {
+            AddressingMode::Unsigned => VRAM_START + tile as u16 * 16,
+            AddressingMode::Signed => (0x9000 + (tile as i8 as i32) * 16) as u16,
+        };
+        self.tile_pixel(tile_addr + (y as u16 % 8) * 2, 7 - x % 8)
+    }
+
+    /// The colour index of a pixel in a row of tile data, where bit 7 is the leftmost pixel.
+    fn tile_pixel(&self, row_addr: u16, bit: u8) -> u8 {
+        let low = self.read_vram(row_addr);
+        let high = self.read_vram(row_addr + 1);
+        ((high >> bit) & 1) << 1 | ((low >> bit) & 1)
+    }
+
+    fn vram_blocked(&self) -> bool {
+        self.lcd_enable && self.lcd_mode == LcdMode::Drawing
+    }
+
+    fn oam_blocked(&self) -> bool {
+        self.lcd_enable && (self.lcd_mode == LcdMode::OamScan || self.lcd_mode == LcdMode::Drawing)
+    }
+
+    /// Pack the LCD control fields back into LCDC.
+    fn lcdc(&self) -> u8 {
+        (self.lcd_enable as u8) << 7
+            | ((self.window_tile_area == WindowBGArea::Offset) as u8) << 6
+            | (self.window_enable as u8) << 5
+            | ((self.bg_window_tile_area == AddressingMode::Unsigned) as u8) << 4
+            | ((self.bg_tile_area == WindowBGArea::Offset) as u8) << 3
+            | ((self.obj_size == ObjSize::Double) as u8) << 2
+            | (self.obj_enable as u8) << 1
+            | self.bg_window_priority as u8
+    }
+
+    fn set_lcdc(&mut self, val: u8) {
+        let enable = (val & 0b1000_0000) != 0;
+        if self.lcd_enable && !enable {
+            // Turning the LCD off resets it to the start of the frame.
+            self.ly = 0;
+            self.dots = 0;
+            self.lcd_mode = LcdMode::HBlank;
+            self.stat_line = false;
+        } else if !self.lcd_enable && enable {
+            self.lcd_mode = LcdMode::OamScan;
+        }
+        self.apply_lcdc(val);
+    }
+
+    /// Unpack LCDC into the LCD control fields, without any of the side effects of writing it.
+    fn apply_lcdc(&mut self, val: u8) {
+        self.lcd_enable = (val & 0b1000_0000) != 0;
+        self.window_tile_area = if (val & 0b0100_0000) != 0 { WindowBGArea::Offset } else { WindowBGArea::Base };
+        self.window_enable = (val & 0b0010_0000) != 0;
+        self.bg_window_tile_area = if (val & 0b0001_0000) != 0 { AddressingMode::Unsigned } else { AddressingMode::Signed };
+        self.bg_tile_area = if (val & 0b0000_1000) != 0 { WindowBGArea::Offset } else { WindowBGArea::Base };
+        self.obj_size = if (val & 0b0000_0100) != 0 { ObjSize::Double } else { ObjSize::Square };
+        self.obj_enable = (val & 0b0000_0010) != 0;
+        self.bg_window_priority = (val & 0b0000_0001) != 0;
+    }
+}
+
+impl Snapshot for PPU {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.bytes(&self.vram);
+        w.bytes(&self.oam);
+        w.u8(self.lcdc());
+        w.u8(self.read(STAT_ADDR));
+        for &reg in [self.scy, self.scx, self.ly, self.lyc, self.wy, self.wx, self.bgp, self.obp0, self.obp1].iter() {
+            w.u8(reg);
+        }
+        w.u8(self.lcd_mode as u8);
+        w.u32(self.dots);
+        w.bool(self.stat_line);
+        w.u8(self.window_line);
+        w.bytes(&self.framebuffer);
+        w.bool(self.frame_ready);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        r.bytes(&mut self.vram)?;
+        r.bytes(&mut self.oam)?;
+        let lcdc = r.u8()?;
+        self.apply_lcdc(lcdc);
+        let stat = r.u8()?;
+        self.lyc_interrupt = (stat & 0b0100_0000) != 0;
+        self.oam_interrupt = (stat & 0b0010_0000) != 0;
+        self.vblank_interrupt = (stat & 0b0001_0000) != 0;
+        self.hblank_interrupt = (stat & 0b0000_1000) != 0;
+        for reg in [&mut self.scy, &mut self.scx, &mut self.ly, &mut self.lyc, &mut self.wy, &mut self.wx, &mut self.bgp, &mut self.obp0, &mut self.obp1].iter_mut() {
+            **reg = r.u8()?;
+        }
+        self.lcd_mode = match r.u8()? {
+            0 => LcdMode::HBlank,
+            1 => LcdMode::VBlank,
+            2 => LcdMode::OamScan,
+            3 => LcdMode::Drawing,
+            _ => return Err(StateError::Corrupt("invalid LCD mode")),
+        };
+        self.dots = r.u32()?;
+        if self.dots >= DOTS_PER_LINE || self.ly >= LINES_PER_FRAME {
+            return Err(StateError::Corrupt("LCD position out of range"));
+        }
+        self.stat_line = r.bool()?;
+        self.window_line = r.u8()?;
+        r.bytes(&mut self.framebuffer)?;
+        self.frame_ready = r.bool()?;
+        Ok(())
+    }
 }
 
-/// # OAM
-/// The OAM is used to organise sprites and their attributes within VRAM.
-/// Within the Game Boy, only 40 sprites may be on screen at any one point in time. There are 40
-/// entries in the OAM, each with a 4 bytes to represent their attributes.
-pub struct OAM<'a> {
-    /// Hashmap to store the sprites from ROM.
-    rom_sprites: HashMap<u8, TableEntry<'a>>
+/// Look up a colour index in one of the palette registers.
+fn palette_shade(palette: u8, index: u8) -> u8 {
+    (palette >> (index * 2)) & PIXEL_SHADE_MASK
 }
 
-/// Each entry in the OAM contains a set of attributes.
-pub struct TableEntry<'a> {
-    y_pos: u8,
-    x_pos: u8,
-    index: u8,
-    over_obj: bool,
-    y_flip: bool,
-    x_flip: bool,
-    palette: ux::u1, // Non-CGB Mode only
-    vram_bank: ux::u1,
-    cgb_palette: ux::u2,
-    tile: Tile<'a>
-}
\ No newline at end of file
+impl Default for PPU {
+    fn default() -> Self {
+        PPU::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lcdc_round_trip() {
+        let mut ppu = PPU::new();
+        for &val in [0x00, 0x91, 0xFF, 0b0101_1010].iter() {
+            ppu.write(LCDC_ADDR, val);
+            assert_eq!(ppu.read(LCDC_ADDR), val);
+        }
+    }
+
+    #[test]
+    fn line_timing() {
+        let mut ppu = PPU::new();
+        ppu.write(LCDC_ADDR, 0x80);
+        assert_eq!(ppu.lcd_mode(), LcdMode::OamScan);
+        ppu.tick(OAM_SCAN_DOTS);
+        assert_eq!(ppu.lcd_mode(), LcdMode::Drawing);
+        ppu.tick(DRAWING_DOTS);
+        assert_eq!(ppu.lcd_mode(), LcdMode::HBlank);
+        ppu.tick(DOTS_PER_LINE - OAM_SCAN_DOTS - DRAWING_DOTS);
+        assert_eq!(ppu.ly(), 1);
+        assert_eq!(ppu.lcd_mode(), LcdMode::OamScan);
+    }
+
+    #[test]
+    fn vblank() {
+        let mut ppu = PPU::new();
+        ppu.write(LCDC_ADDR, 0x80);
+        let interrupts = ppu.tick(DOTS_PER_LINE * VBLANK_LINE as u32 - 4);
+        assert_eq!(interrupts & VBLANK_INTERRUPT, 0);
+        assert_eq!(ppu.tick(4), VBLANK_INTERRUPT);
+        assert_eq!(ppu.lcd_mode(), LcdMode::VBlank);
+        // A whole frame later, we're back at the start.
+        ppu.tick(DOTS_PER_LINE * (LINES_PER_FRAME - VBLANK_LINE) as u32);
+        assert_eq!(ppu.ly(), 0);
+        assert_eq!(ppu.lcd_mode(), LcdMode::OamScan);
+    }
+
+    #[test]
+    fn lyc_interrupt() {
+        let mut ppu = PPU::new();
+        ppu.write(LCDC_ADDR, 0x80);
+        ppu.write(LYC_ADDR, 2);
+        ppu.write(STAT_ADDR, 0b0100_0000);
+        assert_eq!(ppu.tick(DOTS_PER_LINE * 2 - 4), 0);
+        assert_eq!(ppu.tick(4), STAT_INTERRUPT);
+        assert_eq!(ppu.read(STAT_ADDR) & 0b0100_0100, 0b0100_0100);
+        // The line stays high for the rest of the scanline, so no more interrupts.
+        assert_eq!(ppu.tick(DOTS_PER_LINE - 4), 0);
+    }
+
+    #[test]
+    fn vram_access() {
+        let mut ppu = PPU::new();
+        // With the LCD off, VRAM and OAM are always accessible.
+        ppu.write(0x8000, 0x12);
+        ppu.write(0xFE00, 0x34);
+        assert_eq!(ppu.read(0x8000), 0x12);
+        assert_eq!(ppu.read(0xFE00), 0x34);
+        ppu.write(LCDC_ADDR, 0x80);
+        // OAM is blocked during OAM scan, but VRAM isn't.
+        assert_eq!(ppu.read(0x8000), 0x12);
+        assert_eq!(ppu.read(0xFE00), 0xFF);
+        ppu.tick(OAM_SCAN_DOTS);
+        // Both are blocked whilst drawing, and writes are ignored.
+        ppu.write(0x8000, 0x56);
+        assert_eq!(ppu.read(0x8000), 0xFF);
+        ppu.tick(DRAWING_DOTS);
+        assert_eq!(ppu.read(0x8000), 0x12);
+        assert_eq!(ppu.read(0xFE00), 0x34);
+    }
+
+    /// Fill a tile with a single colour index.
+    fn solid_tile(ppu: &mut PPU, addr: u16, index: u8) {
+        for row in 0..8 {
+            ppu.write(addr + row * 2, if index & 1 != 0 { 0xFF } else { 0x00 });
+            ppu.write(addr + row * 2 + 1, if index & 2 != 0 { 0xFF } else { 0x00 });
+        }
+    }
+
+    #[test]
+    fn render_background_and_window() {
+        let mut ppu = PPU::new();
+        // Tile 1 is colour 1 everywhere, and the window map uses tile 2 (colour 3).
+        solid_tile(&mut ppu, 0x8010, 1);
+        solid_tile(&mut ppu, 0x8020, 3);
+        ppu.write(0x9800, 1);
+        for i in 0..0x400 {
+            ppu.write(0x9C00 + i, 2);
+        }
+        ppu.write(BGP_ADDR, 0b1110_0100);
+        ppu.write(WY_ADDR, 8);
+        ppu.write(WX_ADDR, 7 + 80);
+        // LCD, window (from 0x9C00), unsigned tile data and the background on.
+        ppu.write(LCDC_ADDR, 0b1111_0001);
+        ppu.tick(DOTS_PER_LINE * VBLANK_LINE as u32);
+        assert!(ppu.take_frame_ready());
+        assert!(!ppu.take_frame_ready());
+
+        let fb = ppu.framebuffer();
+        assert_eq!(fb[0], 1);
+        assert_eq!(fb[8], 0);
+        assert_eq!(fb[8 * SCREEN_WIDTH + 79], 0);
+        assert_eq!(fb[8 * SCREEN_WIDTH + 80], 3);
+        assert_eq!(fb[143 * SCREEN_WIDTH + 159], 3);
+    }
+
+    #[test]
+    fn render_sprites() {
+        let mut ppu = PPU::new();
+        solid_tile(&mut ppu, 0x8010, 2);
+        ppu.write(OBP0_ADDR, 0b0011_0000);
+        ppu.write(OBP1_ADDR, 0b0010_0000);
+        // One sprite at the top left, and another overlapping it from the right using OBP1.
+        for (i, &byte) in [16, 8, 1, 0, 16, 12, 1, 0b0001_0000].iter().enumerate() {
+            ppu.write(OAM_START + i as u16, byte);
+        }
+        ppu.write(LCDC_ADDR, 0b1000_0011);
+        ppu.tick(DOTS_PER_LINE);
+
+        let fb = ppu.framebuffer();
+        assert_eq!(fb[0], 3 | PIXEL_OBP0);
+        // The sprite further left wins where they overlap.
+        assert_eq!(fb[4], 3 | PIXEL_OBP0);
+        assert_eq!(fb[8], 2 | PIXEL_OBP1);
+        assert_eq!(fb[12], 0);
+    }
+}
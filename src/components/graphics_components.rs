@@ -6,6 +6,7 @@ use sdl2::render::WindowCanvas;
 /// For DMG units, this is likely to be the same palette for all tiles. For GBC units however,
 /// multiple palettes can be used throughout the program lifecycle. This allows GBC units to emulate
 /// DMG games in monochrome, at a software level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct GBPalette {
     pub col1: Color,
     pub col2: Color,
@@ -80,6 +81,11 @@ impl<'a> Tile<'a> {
         }
     }
 
+    /// The colour of the pixel `x` across and `y` down from the tile's top left corner.
+    pub fn colour(&self, x: usize, y: usize) -> Color {
+        self.palette.col_id(self.points[y * 8 + x])
+    }
+
     pub fn paint(&self, origin: sdl2::rect::Point, canvas: &mut WindowCanvas) {
         for pixel in self.points.chunks(8).enumerate() {
             let (i, x) = pixel;
@@ -90,4 +96,31 @@ impl<'a> Tile<'a> {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tile_decoding() {
+        let palette = GBPalette::new(
+            Color::RGB(0, 0, 0),
+            Color::RGB(1, 1, 1),
+            Color::RGB(2, 2, 2),
+            Color::RGB(3, 3, 3),
+        );
+        // The low bit of each pixel's colour is in the first byte of the row, and the high bit in
+        // the second, with the leftmost pixel in bit 7.
+        let mut bytes = [0; 16];
+        bytes[0] = 0b1010_0000;
+        bytes[1] = 0b0110_0000;
+        bytes[15] = 0b0000_0001;
+        let tile = Tile::new(&palette, bytes);
+        assert_eq!(tile.colour(0, 0), Color::RGB(1, 1, 1));
+        assert_eq!(tile.colour(1, 0), Color::RGB(2, 2, 2));
+        assert_eq!(tile.colour(2, 0), Color::RGB(3, 3, 3));
+        assert_eq!(tile.colour(3, 0), Color::RGB(0, 0, 0));
+        assert_eq!(tile.colour(7, 7), Color::RGB(2, 2, 2));
+    }
+}
@@ -0,0 +1,152 @@
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// The address of the joypad register (P1).
+pub const P1_ADDR: u16 = 0xFF00;
+
+/// The buttons on the Game Boy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    /// Every button, in the order of their bits in the joypad's button state.
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    /// The bit which represents this button. The directions are in the lower nibble, and the
+    /// action buttons in the upper nibble, each in the order they appear in P1.
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+/// # Joypad
+/// Representation of the joypad register (P1). The buttons are wired up as a 2x4 matrix: the game
+/// selects the directions (bit 4) and/or the action buttons (bit 5) by writing a zero, and then
+/// reads the state of the selected buttons from the lower four bits, where a pressed button
+/// reads as zero.
+pub struct Joypad {
+    /// Bits 4 and 5 of P1, which select the rows of the matrix to read.
+    select: u8,
+    /// One bit per button, set whilst it is held down.
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Joypad {
+            select: 0b0011_0000,
+            pressed: 0,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        // Bits 6 and 7 are unused and always read back as set.
+        0b1100_0000 | self.select | self.lines()
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.select = val & 0b0011_0000;
+    }
+
+    /// Returns true whilst the given button is held down.
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.mask() != 0
+    }
+
+    /// Press or release a button. Returns true if this pulled one of the selected lines low, in
+    /// which case the joypad interrupt should be requested.
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let before = self.lines();
+        if pressed {
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+        before & !self.lines() != 0
+    }
+
+    /// The state of the lower four bits of P1, where a selected button that is pressed reads as 0.
+    fn lines(&self) -> u8 {
+        let mut lines = 0;
+        if self.select & 0b0001_0000 == 0 {
+            lines |= self.pressed & 0x0F;
+        }
+        if self.select & 0b0010_0000 == 0 {
+            lines |= self.pressed >> 4;
+        }
+        !lines & 0x0F
+    }
+}
+
+impl Snapshot for Joypad {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.select);
+        w.u8(self.pressed);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.select = r.u8()? & 0b0011_0000;
+        self.pressed = r.u8()?;
+        Ok(())
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matrix() {
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::Down, true);
+        joypad.set_button(Button::A, true);
+        // Nothing selected.
+        assert_eq!(joypad.read(), 0xFF);
+        // Directions selected.
+        joypad.write(0b0010_0000);
+        assert_eq!(joypad.read(), 0b1110_0111);
+        // Action buttons selected.
+        joypad.write(0b0001_0000);
+        assert_eq!(joypad.read(), 0b1101_1110);
+        joypad.set_button(Button::A, false);
+        assert_eq!(joypad.read(), 0b1101_1111);
+        assert!(joypad.is_pressed(Button::Down));
+    }
+
+    #[test]
+    fn interrupt() {
+        let mut joypad = Joypad::new();
+        // Pressing a button which isn't selected doesn't change any lines.
+        assert!(!joypad.set_button(Button::Start, true));
+        joypad.write(0b0001_0000);
+        assert!(!joypad.set_button(Button::Start, false));
+        assert!(joypad.set_button(Button::Start, true));
+        // Nor does pressing a button on a line which is already low.
+        joypad.write(0);
+        assert!(!joypad.set_button(Button::Right, false));
+        assert!(!joypad.set_button(Button::Down, true) && joypad.is_pressed(Button::Down));
+    }
+}
@@ -0,0 +1,361 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::Path;
+use std::time::Duration;
+use crate::components::serial::SerialLink;
+
+/// Sent by the side driving the clock, followed by the byte it shifted out.
+const MSG_CLOCK: u8 = 0x01;
+/// Sent in response to MSG_CLOCK, followed by the byte that was shifted back.
+const MSG_REPLY: u8 = 0x02;
+
+/// How long a master will wait for the other side to answer a byte before reading it as 0xFF.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The underlying connection to the other emulator.
+enum LinkStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl LinkStream {
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            LinkStream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.set_nonblocking(nonblocking),
+        }
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            LinkStream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+}
+
+impl Read for LinkStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            LinkStream::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for LinkStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            LinkStream::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            LinkStream::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            LinkStream::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+/// # Link cable
+/// A virtual link cable which connects two emulators over a local TCP or Unix domain socket.
+///
+/// Every byte is exchanged as a pair of messages: whichever side drives the clock (the master,
+/// SC = 0x81) sends the byte it shifted out, and blocks until the other side (the slave,
+/// SC = 0x80) answers with the byte it shifted back. Since the master can't continue until the
+/// slave has answered, the two emulators are held in lockstep for each byte exchanged, which is
+/// what trading and two-player games rely on.
+///
+/// If the slave isn't ready for a transfer when the master clocks a byte, it answers with 0xFF
+/// straight away, as would happen with a real cable. If the other side is too slow to answer, as it
+/// may be whilst its window is being dragged, the byte reads as 0xFF but the cable stays plugged
+/// in, and the answer is thrown away when it does arrive. Only once the other side has hung up is
+/// the cable treated as unplugged.
+pub struct SocketLink {
+    stream: LinkStream,
+    /// Bytes which have arrived but don't yet make up a whole message.
+    pending: Vec<u8>,
+    /// How long to wait for a reply before giving up on the other side.
+    timeout: Duration,
+    /// Set once the other side has gone away, after which the link acts as though unplugged.
+    disconnected: bool,
+    /// How many bytes the other side took too long to answer, whose answers are still to come.
+    late_replies: u32,
+}
+
+impl SocketLink {
+    fn new(stream: LinkStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(SocketLink {
+            stream,
+            pending: Vec::new(),
+            timeout: DEFAULT_TIMEOUT,
+            disconnected: false,
+            late_replies: 0,
+        })
+    }
+
+    /// Wrap an already connected TCP stream.
+    pub fn from_tcp(stream: TcpStream) -> io::Result<Self> {
+        // Each message is tiny and latency is everything, so don't let them be batched up.
+        stream.set_nodelay(true)?;
+        SocketLink::new(LinkStream::Tcp(stream))
+    }
+
+    /// Wait for the other emulator to connect on the given address.
+    pub fn listen_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let (stream, _) = listener.accept()?;
+        SocketLink::from_tcp(stream)
+    }
+
+    /// Connect to another emulator which is listening on the given address.
+    pub fn connect_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        SocketLink::from_tcp(TcpStream::connect(addr)?)
+    }
+
+    /// Wrap an already connected Unix domain socket.
+    #[cfg(unix)]
+    pub fn from_unix(stream: UnixStream) -> io::Result<Self> {
+        SocketLink::new(LinkStream::Unix(stream))
+    }
+
+    /// Wait for the other emulator to connect on the Unix domain socket at the given path. The
+    /// socket file is removed again once the other side has connected, or failed to, so that the
+    /// same path can be listened on next time.
+    #[cfg(unix)]
+    pub fn listen_unix<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let listener = UnixListener::bind(&path)?;
+        let accepted = listener.accept();
+        let _ = fs::remove_file(path);
+        SocketLink::from_unix(accepted?.0)
+    }
+
+    /// Connect to another emulator listening on the Unix domain socket at the given path.
+    #[cfg(unix)]
+    pub fn connect_unix<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        SocketLink::from_unix(UnixStream::connect(path)?)
+    }
+
+    /// Set how long a master will wait for the other side to answer each byte.
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    /// Returns true once the other side has gone away.
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    fn send(&mut self, kind: u8, byte: u8) {
+        if self.disconnected {
+            return;
+        }
+        // The socket is non-blocking, but two bytes will always fit in an empty enough buffer.
+        if self.stream.write_all(&[kind, byte]).is_err() {
+            self.disconnected = true;
+        }
+    }
+
+    /// Pull in whatever has arrived on the socket, blocking for up to the timeout if asked to.
+    /// Returns the next whole message, if there is one.
+    fn receive(&mut self, block: bool) -> Option<(u8, u8)> {
+        while !self.disconnected && self.pending.len() < 2 {
+            let mut buf = [0u8; 2];
+            if block {
+                let result = self.stream.set_nonblocking(false)
+                    .and_then(|_| self.stream.set_read_timeout(Some(self.timeout)));
+                if result.is_err() {
+                    self.disconnected = true;
+                    break;
+                }
+            }
+            let read = self.stream.read(&mut buf[..2 - self.pending.len()]);
+            if block {
+                // If this fails then the next read will too, and that is where it gets handled.
+                let _ = self.stream.set_nonblocking(true);
+            }
+            match read {
+                // A read of zero bytes means the other side hung up.
+                Ok(0) => self.disconnected = true,
+                Ok(n) => self.pending.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::TimedOut => {
+                    // Without blocking there's nothing yet, and when blocking the other side has
+                    // taken too long to answer.
+                    return None;
+                }
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => self.disconnected = true,
+            }
+        }
+
+        if self.pending.len() < 2 {
+            return None;
+        }
+        let message = (self.pending[0], self.pending[1]);
+        self.pending.clear();
+        Some(message)
+    }
+}
+
+impl SerialLink for SocketLink {
+    fn transfer(&mut self, out: u8) -> u8 {
+        self.send(MSG_CLOCK, out);
+        loop {
+            match self.receive(true) {
+                // The answer to a byte which was given up on.
+                Some((MSG_REPLY, _)) if self.late_replies > 0 => self.late_replies -= 1,
+                Some((MSG_REPLY, byte)) => return byte,
+                // Both sides are trying to drive the clock at once, which means neither of them is
+                // listening; answer as a disconnected cable would so that the other side isn't stuck.
+                Some((MSG_CLOCK, _)) => self.send(MSG_REPLY, 0xFF),
+                Some(_) => {}
+                // With nothing on the other end, the data line is pulled high. If the other side
+                // is only being slow, its answer will turn up later.
+                None => {
+                    if !self.disconnected {
+                        self.late_replies += 1;
+                    }
+                    return 0xFF;
+                }
+            }
+        }
+    }
+
+    fn poll_external(&mut self, out: Option<u8>) -> Option<u8> {
+        match self.receive(false) {
+            Some((MSG_CLOCK, byte)) => {
+                // If we're not ready for the transfer then nothing gets shifted, and the master sees
+                // the line pulled high.
+                self.send(MSG_REPLY, out.unwrap_or(0xFF));
+                out.map(|_| byte)
+            }
+            Some((MSG_REPLY, _)) => {
+                self.late_replies = self.late_replies.saturating_sub(1);
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::components::serial::{Serial, SB_ADDR, SC_ADDR};
+
+    /// Run a single transfer between a master and a slave, each in their own thread as though they
+    /// were two separate emulators. Returns the bytes each side ended up with in SB.
+    fn exchange(master: SocketLink, slave: SocketLink) -> (u8, u8) {
+        let slave = thread::spawn(move || {
+            let mut serial = Serial::with_link(Box::new(slave));
+            serial.write(SB_ADDR, 0x22);
+            serial.write(SC_ADDR, 0x80);
+            // Keep ticking, as the emulator would, until the master clocks the byte across.
+            while !serial.tick(4) {}
+            serial.read(SB_ADDR)
+        });
+
+        let mut serial = Serial::with_link(Box::new(master));
+        serial.write(SB_ADDR, 0x11);
+        serial.write(SC_ADDR, 0x81);
+        while !serial.tick(4) {}
+        (serial.read(SB_ADDR), slave.join().unwrap())
+    }
+
+    #[test]
+    fn tcp_exchange() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let slave = SocketLink::connect_tcp(addr).unwrap();
+        let master = SocketLink::from_tcp(listener.accept().unwrap().0).unwrap();
+        assert_eq!(exchange(master, slave), (0x22, 0x11));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_exchange() {
+        let (a, b) = UnixStream::pair().unwrap();
+        let master = SocketLink::from_unix(a).unwrap();
+        let slave = SocketLink::from_unix(b).unwrap();
+        assert_eq!(exchange(master, slave), (0x22, 0x11));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_listen_twice() {
+        let path = std::env::temp_dir().join(format!("patchwork-link-{}.sock", std::process::id()));
+        for _ in 0..2 {
+            let connect_path = path.clone();
+            let slave = thread::spawn(move || loop {
+                // Keep trying until the listener is there.
+                match SocketLink::connect_unix(&connect_path) {
+                    Ok(link) => break link,
+                    Err(_) => thread::sleep(Duration::from_millis(1)),
+                }
+            });
+            let master = SocketLink::listen_unix(&path).unwrap();
+            assert!(!path.exists());
+            assert_eq!(exchange(master, slave.join().unwrap()), (0x22, 0x11));
+        }
+    }
+
+    #[test]
+    fn slave_not_ready() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut slave = SocketLink::connect_tcp(addr).unwrap();
+        let mut master = SocketLink::from_tcp(listener.accept().unwrap().0).unwrap();
+        let master = thread::spawn(move || master.transfer(0x11));
+        // The slave never starts a transfer, so it must neither accept the byte nor leave the
+        // master waiting.
+        while !master.is_finished() {
+            assert_eq!(slave.poll_external(None), None);
+        }
+        assert_eq!(master.join().unwrap(), 0xFF);
+        assert!(!slave.is_disconnected());
+    }
+
+    #[test]
+    fn slow_reply() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut other = TcpStream::connect(addr).unwrap();
+        let mut master = SocketLink::from_tcp(listener.accept().unwrap().0).unwrap();
+        master.set_timeout(Duration::from_millis(50));
+        assert_eq!(master.transfer(0x11), 0xFF);
+        assert!(!master.is_disconnected());
+
+        // The answer to the first byte turns up late, along with the answer to the second.
+        other.write_all(&[MSG_REPLY, 0x33, MSG_REPLY, 0x44]).unwrap();
+        assert_eq!(master.transfer(0x12), 0x44);
+        let mut sent = [0; 4];
+        other.read_exact(&mut sent).unwrap();
+        assert_eq!(sent, [MSG_CLOCK, 0x11, MSG_CLOCK, 0x12]);
+    }
+
+    #[test]
+    fn disconnected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let other = TcpStream::connect(addr).unwrap();
+        let mut master = SocketLink::from_tcp(listener.accept().unwrap().0).unwrap();
+        drop(other);
+        assert_eq!(master.transfer(0x11), 0xFF);
+        assert!(master.is_disconnected());
+    }
+}
@@ -1,4 +1,13 @@
 pub mod dmg_cpu;
 pub mod register;
 pub mod dmg_ppu;
-pub mod graphics_components;
\ No newline at end of file
+pub mod graphics_components;
+pub mod serial;
+pub mod link;
+pub mod bus;
+pub mod timer;
+pub mod opcodes;
+pub mod cartridge;
+pub mod joypad;
+pub mod dmg_apu;
+pub mod state;
@@ -0,0 +1,736 @@
+use std::fmt;
+use std::fmt::Formatter;
+use crate::components::register::{Reg16, Reg8};
+use Condition::*;
+use Operand::*;
+use Reg16::*;
+use Reg8::*;
+
+/// The instruction families of the SM83. The CPU uses these to pick the handler for an opcode,
+/// with the operands in the table telling the handler what to operate on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Nop, Ld, Ldh, Inc, Dec, Add, Adc, Sub, Sbc, And, Xor, Or, Cp,
+    Rlca, Rrca, Rla, Rra, Daa, Cpl, Scf, Ccf,
+    Jr, Jp, Call, Ret, Reti, Rst, Push, Pop,
+    Stop, Halt, Di, Ei, Prefix, Illegal,
+    // CB-prefixed instructions.
+    Rlc, Rrc, Rl, Rr, Sla, Sra, Swap, Srl, Bit, Res, Set,
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_uppercase())
+    }
+}
+
+/// The conditions which conditional jumps, calls and returns can test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    NotZero,
+    Zero,
+    NotCarry,
+    Carry,
+}
+
+/// The kinds of operand an instruction can take.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    /// An 8-bit register, e.g. B.
+    R8(Reg8),
+    /// A 16-bit register, e.g. BC.
+    R16(Reg16),
+    /// The byte at the address held in a 16-bit register, e.g. (HL).
+    Ind(Reg16),
+    /// The byte at HL, which is incremented afterwards: (HL+).
+    HlInc,
+    /// The byte at HL, which is decremented afterwards: (HL-).
+    HlDec,
+    /// The I/O register at 0xFF00 + C: (C).
+    IndC,
+    /// An 8-bit immediate value.
+    D8,
+    /// A 16-bit immediate value.
+    D16,
+    /// The I/O register at 0xFF00 + an 8-bit immediate: (a8).
+    A8,
+    /// The byte at a 16-bit immediate address: (a16). Used as a jump or call target, this is
+    /// simply the address itself.
+    A16,
+    /// A signed 8-bit immediate, used as a relative jump offset or added to SP.
+    S8,
+    /// SP plus a signed 8-bit immediate: SP+e8.
+    SpOffset,
+    /// A branch condition.
+    Cond(Condition),
+    /// The fixed call target of an RST instruction.
+    Vector(u8),
+    /// A bit number, used by BIT, RES and SET.
+    Bit(u8),
+}
+
+impl Operand {
+    /// The number of bytes this operand takes up after the opcode.
+    pub fn size(&self) -> u8 {
+        match self {
+            D8 | A8 | S8 | SpOffset => 1,
+            D16 | A16 => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// How an instruction affects a single flag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagEffect {
+    /// The flag is left as it was.
+    Unaffected,
+    /// The flag is always cleared.
+    Reset,
+    /// The flag is always set.
+    Set,
+    /// The flag depends on the result.
+    Affected,
+}
+
+/// How an instruction affects each of the status flags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FlagEffects {
+    pub zero: FlagEffect,
+    pub subtraction: FlagEffect,
+    pub half_carry: FlagEffect,
+    pub carry: FlagEffect,
+}
+
+/// # Instruction
+/// The metadata for a single opcode. The CPU uses the length to fetch operands and advance the PC,
+/// and the cycle counts to time the instruction, so that individual handlers only need to worry
+/// about what the instruction actually does.
+#[derive(Debug, Clone, Copy)]
+pub struct Instruction {
+    pub mnemonic: Mnemonic,
+    pub operands: &'static [Operand],
+    /// The length in bytes, including the opcode (and the 0xCB prefix, if there is one).
+    pub length: u8,
+    /// The number of T-cycles taken, or taken when a conditional branch is not taken.
+    pub cycles: u8,
+    /// The number of T-cycles taken when a conditional branch is taken.
+    pub branch_cycles: u8,
+    pub flags: FlagEffects,
+}
+
+impl Instruction {
+    /// Returns true if this instruction may or may not branch depending on the flags.
+    pub fn is_conditional(&self) -> bool {
+        self.operands.iter().any(|operand| matches!(operand, Cond(_)))
+    }
+}
+
+/// Returns the instruction table entry for an opcode, looking in the CB table if it followed the
+/// 0xCB prefix.
+pub fn lookup(opcode: u8, prefixed: bool) -> &'static Instruction {
+    if prefixed {
+        &CB_OPCODES[opcode as usize]
+    } else {
+        &OPCODES[opcode as usize]
+    }
+}
+
+/// Parse a flag specification such as "Z0H-", given in the order Z, N, H, C.
+/// '-' means unaffected, '0' reset, '1' set and anything else means the flag depends on the result.
+const fn flags(spec: &str) -> FlagEffects {
+    let spec = spec.as_bytes();
+    FlagEffects {
+        zero: flag(spec[0]),
+        subtraction: flag(spec[1]),
+        half_carry: flag(spec[2]),
+        carry: flag(spec[3]),
+    }
+}
+
+const fn flag(spec: u8) -> FlagEffect {
+    match spec {
+        b'-' => FlagEffect::Unaffected,
+        b'0' => FlagEffect::Reset,
+        b'1' => FlagEffect::Set,
+        _ => FlagEffect::Affected,
+    }
+}
+
+/// Build an instruction table entry: mnemonic, operands, length, cycles, (cycles when the branch
+/// is taken,) flags.
+macro_rules! op {
+    ($mnemonic:ident, [$($operand:expr),*], $length:expr, $cycles:expr, $flags:expr) => {
+        op!($mnemonic, [$($operand),*], $length, $cycles, $cycles, $flags)
+    };
+    ($mnemonic:ident, [$($operand:expr),*], $length:expr, $cycles:expr, $branch_cycles:expr, $flags:expr) => {
+        Instruction {
+            mnemonic: Mnemonic::$mnemonic,
+            operands: &[$($operand),*],
+            length: $length,
+            cycles: $cycles,
+            branch_cycles: $branch_cycles,
+            flags: flags($flags),
+        }
+    };
+}
+
+/// The instruction table for unprefixed opcodes.
+pub static OPCODES: [Instruction; 256] = [
+    op!(Nop, [], 1, 4, "----"), // 0x00
+    op!(Ld, [R16(BC), D16], 3, 12, "----"), // 0x01
+    op!(Ld, [Ind(BC), R8(A)], 1, 8, "----"), // 0x02
+    op!(Inc, [R16(BC)], 1, 8, "----"), // 0x03
+    op!(Inc, [R8(B)], 1, 4, "Z0H-"), // 0x04
+    op!(Dec, [R8(B)], 1, 4, "Z1H-"), // 0x05
+    op!(Ld, [R8(B), D8], 2, 8, "----"), // 0x06
+    op!(Rlca, [], 1, 4, "000C"), // 0x07
+    op!(Ld, [A16, R16(SP)], 3, 20, "----"), // 0x08
+    op!(Add, [R16(HL), R16(BC)], 1, 8, "-0HC"), // 0x09
+    op!(Ld, [R8(A), Ind(BC)], 1, 8, "----"), // 0x0A
+    op!(Dec, [R16(BC)], 1, 8, "----"), // 0x0B
+    op!(Inc, [R8(C)], 1, 4, "Z0H-"), // 0x0C
+    op!(Dec, [R8(C)], 1, 4, "Z1H-"), // 0x0D
+    op!(Ld, [R8(C), D8], 2, 8, "----"), // 0x0E
+    op!(Rrca, [], 1, 4, "000C"), // 0x0F
+    op!(Stop, [D8], 2, 4, "----"), // 0x10
+    op!(Ld, [R16(DE), D16], 3, 12, "----"), // 0x11
+    op!(Ld, [Ind(DE), R8(A)], 1, 8, "----"), // 0x12
+    op!(Inc, [R16(DE)], 1, 8, "----"), // 0x13
+    op!(Inc, [R8(D)], 1, 4, "Z0H-"), // 0x14
+    op!(Dec, [R8(D)], 1, 4, "Z1H-"), // 0x15
+    op!(Ld, [R8(D), D8], 2, 8, "----"), // 0x16
+    op!(Rla, [], 1, 4, "000C"), // 0x17
+    op!(Jr, [S8], 2, 12, "----"), // 0x18
+    op!(Add, [R16(HL), R16(DE)], 1, 8, "-0HC"), // 0x19
+    op!(Ld, [R8(A), Ind(DE)], 1, 8, "----"), // 0x1A
+    op!(Dec, [R16(DE)], 1, 8, "----"), // 0x1B
+    op!(Inc, [R8(E)], 1, 4, "Z0H-"), // 0x1C
+    op!(Dec, [R8(E)], 1, 4, "Z1H-"), // 0x1D
+    op!(Ld, [R8(E), D8], 2, 8, "----"), // 0x1E
+    op!(Rra, [], 1, 4, "000C"), // 0x1F
+    op!(Jr, [Cond(NotZero), S8], 2, 8, 12, "----"), // 0x20
+    op!(Ld, [R16(HL), D16], 3, 12, "----"), // 0x21
+    op!(Ld, [HlInc, R8(A)], 1, 8, "----"), // 0x22
+    op!(Inc, [R16(HL)], 1, 8, "----"), // 0x23
+    op!(Inc, [R8(H)], 1, 4, "Z0H-"), // 0x24
+    op!(Dec, [R8(H)], 1, 4, "Z1H-"), // 0x25
+    op!(Ld, [R8(H), D8], 2, 8, "----"), // 0x26
+    op!(Daa, [], 1, 4, "Z-0C"), // 0x27
+    op!(Jr, [Cond(Zero), S8], 2, 8, 12, "----"), // 0x28
+    op!(Add, [R16(HL), R16(HL)], 1, 8, "-0HC"), // 0x29
+    op!(Ld, [R8(A), HlInc], 1, 8, "----"), // 0x2A
+    op!(Dec, [R16(HL)], 1, 8, "----"), // 0x2B
+    op!(Inc, [R8(L)], 1, 4, "Z0H-"), // 0x2C
+    op!(Dec, [R8(L)], 1, 4, "Z1H-"), // 0x2D
+    op!(Ld, [R8(L), D8], 2, 8, "----"), // 0x2E
+    op!(Cpl, [], 1, 4, "-11-"), // 0x2F
+    op!(Jr, [Cond(NotCarry), S8], 2, 8, 12, "----"), // 0x30
+    op!(Ld, [R16(SP), D16], 3, 12, "----"), // 0x31
+    op!(Ld, [HlDec, R8(A)], 1, 8, "----"), // 0x32
+    op!(Inc, [R16(SP)], 1, 8, "----"), // 0x33
+    op!(Inc, [Ind(HL)], 1, 12, "Z0H-"), // 0x34
+    op!(Dec, [Ind(HL)], 1, 12, "Z1H-"), // 0x35
+    op!(Ld, [Ind(HL), D8], 2, 12, "----"), // 0x36
+    op!(Scf, [], 1, 4, "-001"), // 0x37
+    op!(Jr, [Cond(Carry), S8], 2, 8, 12, "----"), // 0x38
+    op!(Add, [R16(HL), R16(SP)], 1, 8, "-0HC"), // 0x39
+    op!(Ld, [R8(A), HlDec], 1, 8, "----"), // 0x3A
+    op!(Dec, [R16(SP)], 1, 8, "----"), // 0x3B
+    op!(Inc, [R8(A)], 1, 4, "Z0H-"), // 0x3C
+    op!(Dec, [R8(A)], 1, 4, "Z1H-"), // 0x3D
+    op!(Ld, [R8(A), D8], 2, 8, "----"), // 0x3E
+    op!(Ccf, [], 1, 4, "-00C"), // 0x3F
+    op!(Ld, [R8(B), R8(B)], 1, 4, "----"), // 0x40
+    op!(Ld, [R8(B), R8(C)], 1, 4, "----"), // 0x41
+    op!(Ld, [R8(B), R8(D)], 1, 4, "----"), // 0x42
+    op!(Ld, [R8(B), R8(E)], 1, 4, "----"), // 0x43
+    op!(Ld, [R8(B), R8(H)], 1, 4, "----"), // 0x44
+    op!(Ld, [R8(B), R8(L)], 1, 4, "----"), // 0x45
+    op!(Ld, [R8(B), Ind(HL)], 1, 8, "----"), // 0x46
+    op!(Ld, [R8(B), R8(A)], 1, 4, "----"), // 0x47
+    op!(Ld, [R8(C), R8(B)], 1, 4, "----"), // 0x48
+    op!(Ld, [R8(C), R8(C)], 1, 4, "----"), // 0x49
+    op!(Ld, [R8(C), R8(D)], 1, 4, "----"), // 0x4A
+    op!(Ld, [R8(C), R8(E)], 1, 4, "----"), // 0x4B
+    op!(Ld, [R8(C), R8(H)], 1, 4, "----"), // 0x4C
+    op!(Ld, [R8(C), R8(L)], 1, 4, "----"), // 0x4D
+    op!(Ld, [R8(C), Ind(HL)], 1, 8, "----"), // 0x4E
+    op!(Ld, [R8(C), R8(A)], 1, 4, "----"), // 0x4F
+    op!(Ld, [R8(D), R8(B)], 1, 4, "----"), // 0x50
+    op!(Ld, [R8(D), R8(C)], 1, 4, "----"), // 0x51
+    op!(Ld, [R8(D), R8(D)], 1, 4, "----"), // 0x52
+    op!(Ld, [R8(D), R8(E)], 1, 4, "----"), // 0x53
+    op!(Ld, [R8(D), R8(H)], 1, 4, "----"), // 0x54
+    op!(Ld, [R8(D), R8(L)], 1, 4, "----"), // 0x55
+    op!(Ld, [R8(D), Ind(HL)], 1, 8, "----"), // 0x56
+    op!(Ld, [R8(D), R8(A)], 1, 4, "----"), // 0x57
+    op!(Ld, [R8(E), R8(B)], 1, 4, "----"), // 0x58
+    op!(Ld, [R8(E), R8(C)], 1, 4, "----"), // 0x59
+    op!(Ld, [R8(E), R8(D)], 1, 4, "----"), // 0x5A
+    op!(Ld, [R8(E), R8(E)], 1, 4, "----"), // 0x5B
+    op!(Ld, [R8(E), R8(H)], 1, 4, "----"), // 0x5C
+    op!(Ld, [R8(E), R8(L)], 1, 4, "----"), // 0x5D
+    op!(Ld, [R8(E), Ind(HL)], 1, 8, "----"), // 0x5E
+    op!(Ld, [R8(E), R8(A)], 1, 4, "----"), // 0x5F
+    op!(Ld, [R8(H), R8(B)], 1, 4, "----"), // 0x60
+    op!(Ld, [R8(H), R8(C)], 1, 4, "----"), // 0x61
+    op!(Ld, [R8(H), R8(D)], 1, 4, "----"), // 0x62
+    op!(Ld, [R8(H), R8(E)], 1, 4, "----"), // 0x63
+    op!(Ld, [R8(H), R8(H)], 1, 4, "----"), // 0x64
+    op!(Ld, [R8(H), R8(L)], 1, 4, "----"), // 0x65
+    op!(Ld, [R8(H), Ind(HL)], 1, 8, "----"), // 0x66
+    op!(Ld, [R8(H), R8(A)], 1, 4, "----"), // 0x67
+    op!(Ld, [R8(L), R8(B)], 1, 4, "----"), // 0x68
+    op!(Ld, [R8(L), R8(C)], 1, 4, "----"), // 0x69
+    op!(Ld, [R8(L), R8(D)], 1, 4, "----"), // 0x6A
+    op!(Ld, [R8(L), R8(E)], 1, 4, "----"), // 0x6B
+    op!(Ld, [R8(L), R8(H)], 1, 4, "----"), // 0x6C
+    op!(Ld, [R8(L), R8(L)], 1, 4, "----"), // 0x6D
+    op!(Ld, [R8(L), Ind(HL)], 1, 8, "----"), // 0x6E
+    op!(Ld, [R8(L), R8(A)], 1, 4, "----"), // 0x6F
+    op!(Ld, [Ind(HL), R8(B)], 1, 8, "----"), // 0x70
+    op!(Ld, [Ind(HL), R8(C)], 1, 8, "----"), // 0x71
+    op!(Ld, [Ind(HL), R8(D)], 1, 8, "----"), // 0x72
+    op!(Ld, [Ind(HL), R8(E)], 1, 8, "----"), // 0x73
+    op!(Ld, [Ind(HL), R8(H)], 1, 8, "----"), // 0x74
+    op!(Ld, [Ind(HL), R8(L)], 1, 8, "----"), // 0x75
+    op!(Halt, [], 1, 4, "----"), // 0x76
+    op!(Ld, [Ind(HL), R8(A)], 1, 8, "----"), // 0x77
+    op!(Ld, [R8(A), R8(B)], 1, 4, "----"), // 0x78
+    op!(Ld, [R8(A), R8(C)], 1, 4, "----"), // 0x79
+    op!(Ld, [R8(A), R8(D)], 1, 4, "----"), // 0x7A
+    op!(Ld, [R8(A), R8(E)], 1, 4, "----"), // 0x7B
+    op!(Ld, [R8(A), R8(H)], 1, 4, "----"), // 0x7C
+    op!(Ld, [R8(A), R8(L)], 1, 4, "----"), // 0x7D
+    op!(Ld, [R8(A), Ind(HL)], 1, 8, "----"), // 0x7E
+    op!(Ld, [R8(A), R8(A)], 1, 4, "----"), // 0x7F
+    op!(Add, [R8(A), R8(B)], 1, 4, "Z0HC"), // 0x80
+    op!(Add, [R8(A), R8(C)], 1, 4, "Z0HC"), // 0x81
+    op!(Add, [R8(A), R8(D)], 1, 4, "Z0HC"), // 0x82
+    op!(Add, [R8(A), R8(E)], 1, 4, "Z0HC"), // 0x83
+    op!(Add, [R8(A), R8(H)], 1, 4, "Z0HC"), // 0x84
+    op!(Add, [R8(A), R8(L)], 1, 4, "Z0HC"), // 0x85
+    op!(Add, [R8(A), Ind(HL)], 1, 8, "Z0HC"), // 0x86
+    op!(Add, [R8(A), R8(A)], 1, 4, "Z0HC"), // 0x87
+    op!(Adc, [R8(A), R8(B)], 1, 4, "Z0HC"), // 0x88
+    op!(Adc, [R8(A), R8(C)], 1, 4, "Z0HC"), // 0x89
+    op!(Adc, [R8(A), R8(D)], 1, 4, "Z0HC"), // 0x8A
+    op!(Adc, [R8(A), R8(E)], 1, 4, "Z0HC"), // 0x8B
+    op!(Adc, [R8(A), R8(H)], 1, 4, "Z0HC"), // 0x8C
+    op!(Adc, [R8(A), R8(L)], 1, 4, "Z0HC"), // 0x8D
+    op!(Adc, [R8(A), Ind(HL)], 1, 8, "Z0HC"), // 0x8E
+    op!(Adc, [R8(A), R8(A)], 1, 4, "Z0HC"), // 0x8F
+    op!(Sub, [R8(A), R8(B)], 1, 4, "Z1HC"), // 0x90
+    op!(Sub, [R8(A), R8(C)], 1, 4, "Z1HC"), // 0x91
+    op!(Sub, [R8(A), R8(D)], 1, 4, "Z1HC"), // 0x92
+    op!(Sub, [R8(A), R8(E)], 1, 4, "Z1HC"), // 0x93
+    op!(Sub, [R8(A), R8(H)], 1, 4, "Z1HC"), // 0x94
+    op!(Sub, [R8(A), R8(L)], 1, 4, "Z1HC"), // 0x95
+    op!(Sub, [R8(A), Ind(HL)], 1, 8, "Z1HC"), // 0x96
+    op!(Sub, [R8(A), R8(A)], 1, 4, "Z1HC"), // 0x97
+    op!(Sbc, [R8(A), R8(B)], 1, 4, "Z1HC"), // 0x98
+    op!(Sbc, [R8(A), R8(C)], 1, 4, "Z1HC"), // 0x99
+    op!(Sbc, [R8(A), R8(D)], 1, 4, "Z1HC"), // 0x9A
+    op!(Sbc, [R8(A), R8(E)], 1, 4, "Z1HC"), // 0x9B
+    op!(Sbc, [R8(A), R8(H)], 1, 4, "Z1HC"), // 0x9C
+    op!(Sbc, [R8(A), R8(L)], 1, 4, "Z1HC"), // 0x9D
+    op!(Sbc, [R8(A), Ind(HL)], 1, 8, "Z1HC"), // 0x9E
+    op!(Sbc, [R8(A), R8(A)], 1, 4, "Z1HC"), // 0x9F
+    op!(And, [R8(A), R8(B)], 1, 4, "Z010"), // 0xA0
+    op!(And, [R8(A), R8(C)], 1, 4, "Z010"), // 0xA1
+    op!(And, [R8(A), R8(D)], 1, 4, "Z010"), // 0xA2
+    op!(And, [R8(A), R8(E)], 1, 4, "Z010"), // 0xA3
+    op!(And, [R8(A), R8(H)], 1, 4, "Z010"), // 0xA4
+    op!(And, [R8(A), R8(L)], 1, 4, "Z010"), // 0xA5
+    op!(And, [R8(A), Ind(HL)], 1, 8, "Z010"), // 0xA6
+    op!(And, [R8(A), R8(A)], 1, 4, "Z010"), // 0xA7
+    op!(Xor, [R8(A), R8(B)], 1, 4, "Z000"), // 0xA8
+    op!(Xor, [R8(A), R8(C)], 1, 4, "Z000"), // 0xA9
+    op!(Xor, [R8(A), R8(D)], 1, 4, "Z000"), // 0xAA
+    op!(Xor, [R8(A), R8(E)], 1, 4, "Z000"), // 0xAB
+    op!(Xor, [R8(A), R8(H)], 1, 4, "Z000"), // 0xAC
+    op!(Xor, [R8(A), R8(L)], 1, 4, "Z000"), // 0xAD
+    op!(Xor, [R8(A), Ind(HL)], 1, 8, "Z000"), // 0xAE
+    op!(Xor, [R8(A), R8(A)], 1, 4, "Z000"), // 0xAF
+    op!(Or, [R8(A), R8(B)], 1, 4, "Z000"), // 0xB0
+    op!(Or, [R8(A), R8(C)], 1, 4, "Z000"), // 0xB1
+    op!(Or, [R8(A), R8(D)], 1, 4, "Z000"), // 0xB2
+    op!(Or, [R8(A), R8(E)], 1, 4, "Z000"), // 0xB3
+    op!(Or, [R8(A), R8(H)], 1, 4, "Z000"), // 0xB4
+    op!(Or, [R8(A), R8(L)], 1, 4, "Z000"), // 0xB5
+    op!(Or, [R8(A), Ind(HL)], 1, 8, "Z000"), // 0xB6
+    op!(Or, [R8(A), R8(A)], 1, 4, "Z000"), // 0xB7
+    op!(Cp, [R8(A), R8(B)], 1, 4, "Z1HC"), // 0xB8
+    op!(Cp, [R8(A), R8(C)], 1, 4, "Z1HC"), // 0xB9
+    op!(Cp, [R8(A), R8(D)], 1, 4, "Z1HC"), // 0xBA
+    op!(Cp, [R8(A), R8(E)], 1, 4, "Z1HC"), // 0xBB
+    op!(Cp, [R8(A), R8(H)], 1, 4, "Z1HC"), // 0xBC
+    op!(Cp, [R8(A), R8(L)], 1, 4, "Z1HC"), // 0xBD
+    op!(Cp, [R8(A), Ind(HL)], 1, 8, "Z1HC"), // 0xBE
+    op!(Cp, [R8(A), R8(A)], 1, 4, "Z1HC"), // 0xBF
+    op!(Ret, [Cond(NotZero)], 1, 8, 20, "----"), // 0xC0
+    op!(Pop, [R16(BC)], 1, 12, "----"), // 0xC1
+    op!(Jp, [Cond(NotZero), A16], 3, 12, 16, "----"), // 0xC2
+    op!(Jp, [A16], 3, 16, "----"), // 0xC3
+    op!(Call, [Cond(NotZero), A16], 3, 12, 24, "----"), // 0xC4
+    op!(Push, [R16(BC)], 1, 16, "----"), // 0xC5
+    op!(Add, [R8(A), D8], 2, 8, "Z0HC"), // 0xC6
+    op!(Rst, [Vector(0x00)], 1, 16, "----"), // 0xC7
+    op!(Ret, [Cond(Zero)], 1, 8, 20, "----"), // 0xC8
+    op!(Ret, [], 1, 16, "----"), // 0xC9
+    op!(Jp, [Cond(Zero), A16], 3, 12, 16, "----"), // 0xCA
+    op!(Prefix, [], 1, 4, "----"), // 0xCB
+    op!(Call, [Cond(Zero), A16], 3, 12, 24, "----"), // 0xCC
+    op!(Call, [A16], 3, 24, "----"), // 0xCD
+    op!(Adc, [R8(A), D8], 2, 8, "Z0HC"), // 0xCE
+    op!(Rst, [Vector(0x08)], 1, 16, "----"), // 0xCF
+    op!(Ret, [Cond(NotCarry)], 1, 8, 20, "----"), // 0xD0
+    op!(Pop, [R16(DE)], 1, 12, "----"), // 0xD1
+    op!(Jp, [Cond(NotCarry), A16], 3, 12, 16, "----"), // 0xD2
+    op!(Illegal, [], 1, 4, "----"), // 0xD3
+    op!(Call, [Cond(NotCarry), A16], 3, 12, 24, "----"), // 0xD4
+    op!(Push, [R16(DE)], 1, 16, "----"), // 0xD5
+    op!(Sub, [R8(A), D8], 2, 8, "Z1HC"), // 0xD6
+    op!(Rst, [Vector(0x10)], 1, 16, "----"), // 0xD7
+    op!(Ret, [Cond(Carry)], 1, 8, 20, "----"), // 0xD8
+    op!(Reti, [], 1, 16, "----"), // 0xD9
+    op!(Jp, [Cond(Carry), A16], 3, 12, 16, "----"), // 0xDA
+    op!(Illegal, [], 1, 4, "----"), // 0xDB
+    op!(Call, [Cond(Carry), A16], 3, 12, 24, "----"), // 0xDC
+    op!(Illegal, [], 1, 4, "----"), // 0xDD
+    op!(Sbc, [R8(A), D8], 2, 8, "Z1HC"), // 0xDE
+    op!(Rst, [Vector(0x18)], 1, 16, "----"), // 0xDF
+    op!(Ldh, [A8, R8(A)], 2, 12, "----"), // 0xE0
+    op!(Pop, [R16(HL)], 1, 12, "----"), // 0xE1
+    op!(Ld, [IndC, R8(A)], 1, 8, "----"), // 0xE2
+    op!(Illegal, [], 1, 4, "----"), // 0xE3
+    op!(Illegal, [], 1, 4, "----"), // 0xE4
+    op!(Push, [R16(HL)], 1, 16, "----"), // 0xE5
+    op!(And, [R8(A), D8], 2, 8, "Z010"), // 0xE6
+    op!(Rst, [Vector(0x20)], 1, 16, "----"), // 0xE7
+    op!(Add, [R16(SP), S8], 2, 16, "00HC"), // 0xE8
+    op!(Jp, [R16(HL)], 1, 4, "----"), // 0xE9
+    op!(Ld, [A16, R8(A)], 3, 16, "----"), // 0xEA
+    op!(Illegal, [], 1, 4, "----"), // 0xEB
+    op!(Illegal, [], 1, 4, "----"), // 0xEC
+    op!(Illegal, [], 1, 4, "----"), // 0xED
+    op!(Xor, [R8(A), D8], 2, 8, "Z000"), // 0xEE
+    op!(Rst, [Vector(0x28)], 1, 16, "----"), // 0xEF
+    op!(Ldh, [R8(A), A8], 2, 12, "----"), // 0xF0
+    op!(Pop, [R16(AF)], 1, 12, "ZNHC"), // 0xF1
+    op!(Ld, [R8(A), IndC], 1, 8, "----"), // 0xF2
+    op!(Di, [], 1, 4, "----"), // 0xF3
+    op!(Illegal, [], 1, 4, "----"), // 0xF4
+    op!(Push, [R16(AF)], 1, 16, "----"), // 0xF5
+    op!(Or, [R8(A), D8], 2, 8, "Z000"), // 0xF6
+    op!(Rst, [Vector(0x30)], 1, 16, "----"), // 0xF7
+    op!(Ld, [R16(HL), SpOffset], 2, 12, "00HC"), // 0xF8
+    op!(Ld, [R16(SP), R16(HL)], 1, 8, "----"), // 0xF9
+    op!(Ld, [R8(A), A16], 3, 16, "----"), // 0xFA
+    op!(Ei, [], 1, 4, "----"), // 0xFB
+    op!(Illegal, [], 1, 4, "----"), // 0xFC
+    op!(Illegal, [], 1, 4, "----"), // 0xFD
+    op!(Cp, [R8(A), D8], 2, 8, "Z1HC"), // 0xFE
+    op!(Rst, [Vector(0x38)], 1, 16, "----"), // 0xFF
+];
+
+/// The instruction table for opcodes following the 0xCB prefix. Lengths and cycles include the prefix.
+pub static CB_OPCODES: [Instruction; 256] = [
+    op!(Rlc, [R8(B)], 2, 8, "Z00C"), // 0xCB00
+    op!(Rlc, [R8(C)], 2, 8, "Z00C"), // 0xCB01
+    op!(Rlc, [R8(D)], 2, 8, "Z00C"), // 0xCB02
+    op!(Rlc, [R8(E)], 2, 8, "Z00C"), // 0xCB03
+    op!(Rlc, [R8(H)], 2, 8, "Z00C"), // 0xCB04
+    op!(Rlc, [R8(L)], 2, 8, "Z00C"), // 0xCB05
+    op!(Rlc, [Ind(HL)], 2, 16, "Z00C"), // 0xCB06
+    op!(Rlc, [R8(A)], 2, 8, "Z00C"), // 0xCB07
+    op!(Rrc, [R8(B)], 2, 8, "Z00C"), // 0xCB08
+    op!(Rrc, [R8(C)], 2, 8, "Z00C"), // 0xCB09
+    op!(Rrc, [R8(D)], 2, 8, "Z00C"), // 0xCB0A
+    op!(Rrc, [R8(E)], 2, 8, "Z00C"), // 0xCB0B
+    op!(Rrc, [R8(H)], 2, 8, "Z00C"), // 0xCB0C
+    op!(Rrc, [R8(L)], 2, 8, "Z00C"), // 0xCB0D
+    op!(Rrc, [Ind(HL)], 2, 16, "Z00C"), // 0xCB0E
+    op!(Rrc, [R8(A)], 2, 8, "Z00C"), // 0xCB0F
+    op!(Rl, [R8(B)], 2, 8, "Z00C"), // 0xCB10
+    op!(Rl, [R8(C)], 2, 8, "Z00C"), // 0xCB11
+    op!(Rl, [R8(D)], 2, 8, "Z00C"), // 0xCB12
+    op!(Rl, [R8(E)], 2, 8, "Z00C"), // 0xCB13
+    op!(Rl, [R8(H)], 2, 8, "Z00C"), // 0xCB14
+    op!(Rl, [R8(L)], 2, 8, "Z00C"), // 0xCB15
+    op!(Rl, [Ind(HL)], 2, 16, "Z00C"), // 0xCB16
+    op!(Rl, [R8(A)], 2, 8, "Z00C"), // 0xCB17
+    op!(Rr, [R8(B)], 2, 8, "Z00C"), // 0xCB18
+    op!(Rr, [R8(C)], 2, 8, "Z00C"), // 0xCB19
+    op!(Rr, [R8(D)], 2, 8, "Z00C"), // 0xCB1A
+    op!(Rr, [R8(E)], 2, 8, "Z00C"), // 0xCB1B
+    op!(Rr, [R8(H)], 2, 8, "Z00C"), // 0xCB1C
+    op!(Rr, [R8(L)], 2, 8, "Z00C"), // 0xCB1D
+    op!(Rr, [Ind(HL)], 2, 16, "Z00C"), // 0xCB1E
+    op!(Rr, [R8(A)], 2, 8, "Z00C"), // 0xCB1F
+    op!(Sla, [R8(B)], 2, 8, "Z00C"), // 0xCB20
+    op!(Sla, [R8(C)], 2, 8, "Z00C"), // 0xCB21
+    op!(Sla, [R8(D)], 2, 8, "Z00C"), // 0xCB22
+    op!(Sla, [R8(E)], 2, 8, "Z00C"), // 0xCB23
+    op!(Sla, [R8(H)], 2, 8, "Z00C"), // 0xCB24
+    op!(Sla, [R8(L)], 2, 8, "Z00C"), // 0xCB25
+    op!(Sla, [Ind(HL)], 2, 16, "Z00C"), // 0xCB26
+    op!(Sla, [R8(A)], 2, 8, "Z00C"), // 0xCB27
+    op!(Sra, [R8(B)], 2, 8, "Z00C"), // 0xCB28
+    op!(Sra, [R8(C)], 2, 8, "Z00C"), // 0xCB29
+    op!(Sra, [R8(D)], 2, 8, "Z00C"), // 0xCB2A
+    op!(Sra, [R8(E)], 2, 8, "Z00C"), // 0xCB2B
+    op!(Sra, [R8(H)], 2, 8, "Z00C"), // 0xCB2C
+    op!(Sra, [R8(L)], 2, 8, "Z00C"), // 0xCB2D
+    op!(Sra, [Ind(HL)], 2, 16, "Z00C"), // 0xCB2E
+    op!(Sra, [R8(A)], 2, 8, "Z00C"), // 0xCB2F
+    op!(Swap, [R8(B)], 2, 8, "Z000"), // 0xCB30
+    op!(Swap, [R8(C)], 2, 8, "Z000"), // 0xCB31
+    op!(Swap, [R8(D)], 2, 8, "Z000"), // 0xCB32
+    op!(Swap, [R8(E)], 2, 8, "Z000"), // 0xCB33
+    op!(Swap, [R8(H)], 2, 8, "Z000"), // 0xCB34
+    op!(Swap, [R8(L)], 2, 8, "Z000"), // 0xCB35
+    op!(Swap, [Ind(HL)], 2, 16, "Z000"), // 0xCB36
+    op!(Swap, [R8(A)], 2, 8, "Z000"), // 0xCB37
+    op!(Srl, [R8(B)], 2, 8, "Z00C"), // 0xCB38
+    op!(Srl, [R8(C)], 2, 8, "Z00C"), // 0xCB39
+    op!(Srl, [R8(D)], 2, 8, "Z00C"), // 0xCB3A
+    op!(Srl, [R8(E)], 2, 8, "Z00C"), // 0xCB3B
+    op!(Srl, [R8(H)], 2, 8, "Z00C"), // 0xCB3C
+    op!(Srl, [R8(L)], 2, 8, "Z00C"), // 0xCB3D
+    op!(Srl, [Ind(HL)], 2, 16, "Z00C"), // 0xCB3E
+    op!(Srl, [R8(A)], 2, 8, "Z00C"), // 0xCB3F
+    op!(Bit, [Bit(0), R8(B)], 2, 8, "Z01-"), // 0xCB40
+    op!(Bit, [Bit(0), R8(C)], 2, 8, "Z01-"), // 0xCB41
+    op!(Bit, [Bit(0), R8(D)], 2, 8, "Z01-"), // 0xCB42
+    op!(Bit, [Bit(0), R8(E)], 2, 8, "Z01-"), // 0xCB43
+    op!(Bit, [Bit(0), R8(H)], 2, 8, "Z01-"), // 0xCB44
+    op!(Bit, [Bit(0), R8(L)], 2, 8, "Z01-"), // 0xCB45
+    op!(Bit, [Bit(0), Ind(HL)], 2, 12, "Z01-"), // 0xCB46
+    op!(Bit, [Bit(0), R8(A)], 2, 8, "Z01-"), // 0xCB47
+    op!(Bit, [Bit(1), R8(B)], 2, 8, "Z01-"), // 0xCB48
+    op!(Bit, [Bit(1), R8(C)], 2, 8, "Z01-"), // 0xCB49
+    op!(Bit, [Bit(1), R8(D)], 2, 8, "Z01-"), // 0xCB4A
+    op!(Bit, [Bit(1), R8(E)], 2, 8, "Z01-"), // 0xCB4B
+    op!(Bit, [Bit(1), R8(H)], 2, 8, "Z01-"), // 0xCB4C
+    op!(Bit, [Bit(1), R8(L)], 2, 8, "Z01-"), // 0xCB4D
+    op!(Bit, [Bit(1), Ind(HL)], 2, 12, "Z01-"), // 0xCB4E
+    op!(Bit, [Bit(1), R8(A)], 2, 8, "Z01-"), // 0xCB4F
+    op!(Bit, [Bit(2), R8(B)], 2, 8, "Z01-"), // 0xCB50
+    op!(Bit, [Bit(2), R8(C)], 2, 8, "Z01-"), // 0xCB51
+    op!(Bit, [Bit(2), R8(D)], 2, 8, "Z01-"), // 0xCB52
+    op!(Bit, [Bit(2), R8(E)], 2, 8, "Z01-"), // 0xCB53
+    op!(Bit, [Bit(2), R8(H)], 2, 8, "Z01-"), // 0xCB54
+    op!(Bit, [Bit(2), R8(L)], 2, 8, "Z01-"), // 0xCB55
+    op!(Bit, [Bit(2), Ind(HL)], 2, 12, "Z01-"), // 0xCB56
+    op!(Bit, [Bit(2), R8(A)], 2, 8, "Z01-"), // 0xCB57
+    op!(Bit, [Bit(3), R8(B)], 2, 8, "Z01-"), // 0xCB58
+    op!(Bit, [Bit(3), R8(C)], 2, 8, "Z01-"), // 0xCB59
+    op!(Bit, [Bit(3), R8(D)], 2, 8, "Z01-"), // 0xCB5A
+    op!(Bit, [Bit(3), R8(E)], 2, 8, "Z01-"), // 0xCB5B
+    op!(Bit, [Bit(3), R8(H)], 2, 8, "Z01-"), // 0xCB5C
+    op!(Bit, [Bit(3), R8(L)], 2, 8, "Z01-"), // 0xCB5D
+    op!(Bit, [Bit(3), Ind(HL)], 2, 12, "Z01-"), // 0xCB5E
+    op!(Bit, [Bit(3), R8(A)], 2, 8, "Z01-"), // 0xCB5F
+    op!(Bit, [Bit(4), R8(B)], 2, 8, "Z01-"), // 0xCB60
+    op!(Bit, [Bit(4), R8(C)], 2, 8, "Z01-"), // 0xCB61
+    op!(Bit, [Bit(4), R8(D)], 2, 8, "Z01-"), // 0xCB62
+    op!(Bit, [Bit(4), R8(E)], 2, 8, "Z01-"), // 0xCB63
+    op!(Bit, [Bit(4), R8(H)], 2, 8, "Z01-"), // 0xCB64
+    op!(Bit, [Bit(4), R8(L)], 2, 8, "Z01-"), // 0xCB65
+    op!(Bit, [Bit(4), Ind(HL)], 2, 12, "Z01-"), // 0xCB66
+    op!(Bit, [Bit(4), R8(A)], 2, 8, "Z01-"), // 0xCB67
+    op!(Bit, [Bit(5), R8(B)], 2, 8, "Z01-"), // 0xCB68
+    op!(Bit, [Bit(5), R8(C)], 2, 8, "Z01-"), // 0xCB69
+    op!(Bit, [Bit(5), R8(D)], 2, 8, "Z01-"), // 0xCB6A
+    op!(Bit, [Bit(5), R8(E)], 2, 8, "Z01-"), // 0xCB6B
+    op!(Bit, [Bit(5), R8(H)], 2, 8, "Z01-"), // 0xCB6C
+    op!(Bit, [Bit(5), R8(L)], 2, 8, "Z01-"), // 0xCB6D
+    op!(Bit, [Bit(5), Ind(HL)], 2, 12, "Z01-"), // 0xCB6E
+    op!(Bit, [Bit(5), R8(A)], 2, 8, "Z01-"), // 0xCB6F
+    op!(Bit, [Bit(6), R8(B)], 2, 8, "Z01-"), // 0xCB70
+    op!(Bit, [Bit(6), R8(C)], 2, 8, "Z01-"), // 0xCB71
+    op!(Bit, [Bit(6), R8(D)], 2, 8, "Z01-"), // 0xCB72
+    op!(Bit, [Bit(6), R8(E)], 2, 8, "Z01-"), // 0xCB73
+    op!(Bit, [Bit(6), R8(H)], 2, 8, "Z01-"), // 0xCB74
+    op!(Bit, [Bit(6), R8(L)], 2, 8, "Z01-"), // 0xCB75
+    op!(Bit, [Bit(6), Ind(HL)], 2, 12, "Z01-"), // 0xCB76
+    op!(Bit, [Bit(6), R8(A)], 2, 8, "Z01-"), // 0xCB77
+    op!(Bit, [Bit(7), R8(B)], 2, 8, "Z01-"), // 0xCB78
+    op!(Bit, [Bit(7), R8(C)], 2, 8, "Z01-"), // 0xCB79
+    op!(Bit, [Bit(7), R8(D)], 2, 8, "Z01-"), // 0xCB7A
+    op!(Bit, [Bit(7), R8(E)], 2, 8, "Z01-"), // 0xCB7B
+    op!(Bit, [Bit(7), R8(H)], 2, 8, "Z01-"), // 0xCB7C
+    op!(Bit, [Bit(7), R8(L)], 2, 8, "Z01-"), // 0xCB7D
+    op!(Bit, [Bit(7), Ind(HL)], 2, 12, "Z01-"), // 0xCB7E
+    op!(Bit, [Bit(7), R8(A)], 2, 8, "Z01-"), // 0xCB7F
+    op!(Res, [Bit(0), R8(B)], 2, 8, "----"), // 0xCB80
+    op!(Res, [Bit(0), R8(C)], 2, 8, "----"), // 0xCB81
+    op!(Res, [Bit(0), R8(D)], 2, 8, "----"), // 0xCB82
+    op!(Res, [Bit(0), R8(E)], 2, 8, "----"), // 0xCB83
+    op!(Res, [Bit(0), R8(H)], 2, 8, "----"), // 0xCB84
+    op!(Res, [Bit(0), R8(L)], 2, 8, "----"), // 0xCB85
+    op!(Res, [Bit(0), Ind(HL)], 2, 16, "----"), // 0xCB86
+    op!(Res, [Bit(0), R8(A)], 2, 8, "----"), // 0xCB87
+    op!(Res, [Bit(1), R8(B)], 2, 8, "----"), // 0xCB88
+    op!(Res, [Bit(1), R8(C)], 2, 8, "----"), // 0xCB89
+    op!(Res, [Bit(1), R8(D)], 2, 8, "----"), // 0xCB8A
+    op!(Res, [Bit(1), R8(E)], 2, 8, "----"), // 0xCB8B
+    op!(Res, [Bit(1), R8(H)], 2, 8, "----"), // 0xCB8C
+    op!(Res, [Bit(1), R8(L)], 2, 8, "----"), // 0xCB8D
+    op!(Res, [Bit(1), Ind(HL)], 2, 16, "----"), // 0xCB8E
+    op!(Res, [Bit(1), R8(A)], 2, 8, "----"), // 0xCB8F
+    op!(Res, [Bit(2), R8(B)], 2, 8, "----"), // 0xCB90
+    op!(Res, [Bit(2), R8(C)], 2, 8, "----"), // 0xCB91
+    op!(Res, [Bit(2), R8(D)], 2, 8, "----"), // 0xCB92
+    op!(Res, [Bit(2), R8(E)], 2, 8, "----"), // 0xCB93
+    op!(Res, [Bit(2), R8(H)], 2, 8, "----"), // 0xCB94
+    op!(Res, [Bit(2), R8(L)], 2, 8, "----"), // 0xCB95
+    op!(Res, [Bit(2), Ind(HL)], 2, 16, "----"), // 0xCB96
+    op!(Res, [Bit(2), R8(A)], 2, 8, "----"), // 0xCB97
+    op!(Res, [Bit(3), R8(B)], 2, 8, "----"), // 0xCB98
+    op!(Res, [Bit(3), R8(C)], 2, 8, "----"), // 0xCB99
+    op!(Res, [Bit(3), R8(D)], 2, 8, "----"), // 0xCB9A
+    op!(Res, [Bit(3), R8(E)], 2, 8, "----"), // 0xCB9B
+    op!(Res, [Bit(3), R8(H)], 2, 8, "----"), // 0xCB9C
+    op!(Res, [Bit(3), R8(L)], 2, 8, "----"), // 0xCB9D
+    op!(Res, [Bit(3), Ind(HL)], 2, 16, "----"), // 0xCB9E
+    op!(Res, [Bit(3), R8(A)], 2, 8, "----"), // 0xCB9F
+    op!(Res, [Bit(4), R8(B)], 2, 8, "----"), // 0xCBA0
+    op!(Res, [Bit(4), R8(C)], 2, 8, "----"), // 0xCBA1
+    op!(Res, [Bit(4), R8(D)], 2, 8, "----"), // 0xCBA2
+    op!(Res, [Bit(4), R8(E)], 2, 8, "----"), // 0xCBA3
+    op!(Res, [Bit(4), R8(H)], 2, 8, "----"), // 0xCBA4
+    op!(Res, [Bit(4), R8(L)], 2, 8, "----"), // 0xCBA5
+    op!(Res, [Bit(4), Ind(HL)], 2, 16, "----"), // 0xCBA6
+    op!(Res, [Bit(4), R8(A)], 2, 8, "----"), // 0xCBA7
+    op!(Res, [Bit(5), R8(B)], 2, 8, "----"), // 0xCBA8
+    op!(Res, [Bit(5), R8(C)], 2, 8, "----"), // 0xCBA9
+    op!(Res, [Bit(5), R8(D)], 2, 8, "----"), // 0xCBAA
+    op!(Res, [Bit(5), R8(E)], 2, 8, "----"), // 0xCBAB
+    op!(Res, [Bit(5), R8(H)], 2, 8, "----"), // 0xCBAC
+    op!(Res, [Bit(5), R8(L)], 2, 8, "----"), // 0xCBAD
+    op!(Res, [Bit(5), Ind(HL)], 2, 16, "----"), // 0xCBAE
+    op!(Res, [Bit(5), R8(A)], 2, 8, "----"), // 0xCBAF
+    op!(Res, [Bit(6), R8(B)], 2, 8, "----"), // 0xCBB0
+    op!(Res, [Bit(6), R8(C)], 2, 8, "----"), // 0xCBB1
+    op!(Res, [Bit(6), R8(D)], 2, 8, "----"), // 0xCBB2
+    op!(Res, [Bit(6), R8(E)], 2, 8, "----"), // 0xCBB3
+    op!(Res, [Bit(6), R8(H)], 2, 8, "----"), // 0xCBB4
+    op!(Res, [Bit(6), R8(L)], 2, 8, "----"), // 0xCBB5
+    op!(Res, [Bit(6), Ind(HL)], 2, 16, "----"), // 0xCBB6
+    op!(Res, [Bit(6), R8(A)], 2, 8, "----"), // 0xCBB7
+    op!(Res, [Bit(7), R8(B)], 2, 8, "----"), // 0xCBB8
+    op!(Res, [Bit(7), R8(C)], 2, 8, "----"), // 0xCBB9
+    op!(Res, [Bit(7), R8(D)], 2, 8, "----"), // 0xCBBA
+    op!(Res, [Bit(7), R8(E)], 2, 8, "----"), // 0xCBBB
+    op!(Res, [Bit(7), R8(H)], 2, 8, "----"), // 0xCBBC
+    op!(Res, [Bit(7), R8(L)], 2, 8, "----"), // 0xCBBD
+    op!(Res, [Bit(7), Ind(HL)], 2, 16, "----"), // 0xCBBE
+    op!(Res, [Bit(7), R8(A)], 2, 8, "----"), // 0xCBBF
+    op!(Set, [Bit(0), R8(B)], 2, 8, "----"), // 0xCBC0
+    op!(Set, [Bit(0), R8(C)], 2, 8, "----"), // 0xCBC1
+    op!(Set, [Bit(0), R8(D)], 2, 8, "----"), // 0xCBC2
+    op!(Set, [Bit(0), R8(E)], 2, 8, "----"), // 0xCBC3
+    op!(Set, [Bit(0), R8(H)], 2, 8, "----"), // 0xCBC4
+    op!(Set, [Bit(0), R8(L)], 2, 8, "----"), // 0xCBC5
+    op!(Set, [Bit(0), Ind(HL)], 2, 16, "----"), // 0xCBC6
+    op!(Set, [Bit(0), R8(A)], 2, 8, "----"), // 0xCBC7
+    op!(Set, [Bit(1), R8(B)], 2, 8, "----"), // 0xCBC8
+    op!(Set, [Bit(1), R8(C)], 2, 8, "----"), // 0xCBC9
+    op!(Set, [Bit(1), R8(D)], 2, 8, "----"), // 0xCBCA
+    op!(Set, [Bit(1), R8(E)], 2, 8, "----"), // 0xCBCB
+    op!(Set, [Bit(1), R8(H)], 2, 8, "----"), // 0xCBCC
+    op!(Set, [Bit(1), R8(L)], 2, 8, "----"), // 0xCBCD
+    op!(Set, [Bit(1), Ind(HL)], 2, 16, "----"), // 0xCBCE
+    op!(Set, [Bit(1), R8(A)], 2, 8, "----"), // 0xCBCF
+    op!(Set, [Bit(2), R8(B)], 2, 8, "----"), // 0xCBD0
+    op!(Set, [Bit(2), R8(C)], 2, 8, "----"), // 0xCBD1
+    op!(Set, [Bit(2), R8(D)], 2, 8, "----"), // 0xCBD2
+    op!(Set, [Bit(2), R8(E)], 2, 8, "----"), // 0xCBD3
+    op!(Set, [Bit(2), R8(H)], 2, 8, "----"), // 0xCBD4
+    op!(Set, [Bit(2), R8(L)], 2, 8, "----"), // 0xCBD5
+    op!(Set, [Bit(2), Ind(HL)], 2, 16, "----"), // 0xCBD6
+    op!(Set, [Bit(2), R8(A)], 2, 8, "----"), // 0xCBD7
+    op!(Set, [Bit(3), R8(B)], 2, 8, "----"), // 0xCBD8
+    op!(Set, [Bit(3), R8(C)], 2, 8, "----"), // 0xCBD9
+    op!(Set, [Bit(3), R8(D)], 2, 8, "----"), // 0xCBDA
+    op!(Set, [Bit(3), R8(E)], 2, 8, "----"), // 0xCBDB
+    op!(Set, [Bit(3), R8(H)], 2, 8, "----"), // 0xCBDC
+    op!(Set, [Bit(3), R8(L)], 2, 8, "----"), // 0xCBDD
+    op!(Set, [Bit(3), Ind(HL)], 2, 16, "----"), // 0xCBDE
+    op!(Set, [Bit(3), R8(A)], 2, 8, "----"), // 0xCBDF
+    op!(Set, [Bit(4), R8(B)], 2, 8, "----"), // 0xCBE0
+    op!(Set, [Bit(4), R8(C)], 2, 8, "----"), // 0xCBE1
+    op!(Set, [Bit(4), R8(D)], 2, 8, "----"), // 0xCBE2
+    op!(Set, [Bit(4), R8(E)], 2, 8, "----"), // 0xCBE3
+    op!(Set, [Bit(4), R8(H)], 2, 8, "----"), // 0xCBE4
+    op!(Set, [Bit(4), R8(L)], 2, 8, "----"), // 0xCBE5
+    op!(Set, [Bit(4), Ind(HL)], 2, 16, "----"), // 0xCBE6
+    op!(Set, [Bit(4), R8(A)], 2, 8, "----"), // 0xCBE7
+    op!(Set, [Bit(5), R8(B)], 2, 8, "----"), // 0xCBE8
+    op!(Set, [Bit(5), R8(C)], 2, 8, "----"), // 0xCBE9
+    op!(Set, [Bit(5), R8(D)], 2, 8, "----"), // 0xCBEA
+    op!(Set, [Bit(5), R8(E)], 2, 8, "----"), // 0xCBEB
+    op!(Set, [Bit(5), R8(H)], 2, 8, "----"), // 0xCBEC
+    op!(Set, [Bit(5), R8(L)], 2, 8, "----"), // 0xCBED
+    op!(Set, [Bit(5), Ind(HL)], 2, 16, "----"), // 0xCBEE
+    op!(Set, [Bit(5), R8(A)], 2, 8, "----"), // 0xCBEF
+    op!(Set, [Bit(6), R8(B)], 2, 8, "----"), // 0xCBF0
+    op!(Set, [Bit(6), R8(C)], 2, 8, "----"), // 0xCBF1
+    op!(Set, [Bit(6), R8(D)], 2, 8, "----"), // 0xCBF2
+    op!(Set, [Bit(6), R8(E)], 2, 8, "----"), // 0xCBF3
+    op!(Set, [Bit(6), R8(H)], 2, 8, "----"), // 0xCBF4
+    op!(Set, [Bit(6), R8(L)], 2, 8, "----"), // 0xCBF5
+    op!(Set, [Bit(6), Ind(HL)], 2, 16, "----"), // 0xCBF6
+    op!(Set, [Bit(6), R8(A)], 2, 8, "----"), // 0xCBF7
+    op!(Set, [Bit(7), R8(B)], 2, 8, "----"), // 0xCBF8
+    op!(Set, [Bit(7), R8(C)], 2, 8, "----"), // 0xCBF9
+    op!(Set, [Bit(7), R8(D)], 2, 8, "----"), // 0xCBFA
+    op!(Set, [Bit(7), R8(E)], 2, 8, "----"), // 0xCBFB
+    op!(Set, [Bit(7), R8(H)], 2, 8, "----"), // 0xCBFC
+    op!(Set, [Bit(7), R8(L)], 2, 8, "----"), // 0xCBFD
+    op!(Set, [Bit(7), Ind(HL)], 2, 16, "----"), // 0xCBFE
+    op!(Set, [Bit(7), R8(A)], 2, 8, "----"), // 0xCBFF
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lengths_match_operands() {
+        for (prefixed, table) in [(false, &OPCODES), (true, &CB_OPCODES)].iter() {
+            for (opcode, instr) in table.iter().enumerate() {
+                let operand_bytes: u8 = instr.operands.iter().map(|operand| operand.size()).sum();
+                let expected = if *prefixed { 2 } else { 1 } + operand_bytes;
+                assert_eq!(instr.length, expected, "{:#04x} ({})", opcode, instr.mnemonic);
+            }
+        }
+    }
+
+    #[test]
+    fn branch_timing() {
+        for instr in OPCODES.iter().chain(CB_OPCODES.iter()) {
+            // Only conditional instructions can take a different amount of time.
+            if instr.is_conditional() {
+                assert!(instr.branch_cycles > instr.cycles);
+            } else {
+                assert_eq!(instr.branch_cycles, instr.cycles);
+            }
+            assert_eq!(instr.cycles % 4, 0);
+        }
+    }
+
+    #[test]
+    fn lookup_tables() {
+        assert_eq!(lookup(0x00, false).mnemonic, Mnemonic::Nop);
+        assert_eq!(lookup(0x00, true).mnemonic, Mnemonic::Rlc);
+        assert_eq!(lookup(0x7C, true).operands, &[Bit(7), R8(H)]);
+        assert_eq!(lookup(0x20, false).operands, &[Cond(NotZero), S8]);
+        assert_eq!(format!("{}", lookup(0xE0, false).mnemonic), "LDH");
+        assert_eq!(format!("{}", lookup(0xD3, false).mnemonic), "ILLEGAL");
+        assert_eq!(lookup(0xAF, false).flags, flags("Z000"));
+    }
+}
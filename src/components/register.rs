@@ -1,7 +1,7 @@
 use std::fmt::{Display, Formatter};
-use std::num::ParseIntError;
 
 /// An object that represents a "register pair" which is found in the DMG unit.
+#[derive(Default)]
 pub struct RegPair {
     high_bits: u8,
     low_bits: u8,
@@ -25,19 +25,19 @@ impl RegPair {
     }
 
     /// Set the bits in the "high" register to a BCD encoding of the given value.
-    pub fn set_high_bcd(&mut self, val: u8) -> Result<u8, ParseIntError> {
+    pub fn set_high_bcd(&mut self, val: u8) -> u8 {
         // Collect the BCD representation of the input number.
-        let bcd = RegPair::decimal_to_bcd(val)?; // If there is an error, exit early.
+        let bcd = RegPair::decimal_to_bcd(val);
         self.high_bits = bcd;
-        Ok(bcd)
+        bcd
     }
 
     /// Set the bits in the "low" register to a BCD encoding of the given value.
-    pub fn set_low_bcd(&mut self, val: u8) -> Result<u8, ParseIntError> {
+    pub fn set_low_bcd(&mut self, val: u8) -> u8 {
         // Collect the BCD representation of the input number.
-        let bcd = RegPair::decimal_to_bcd(val)?; // Exit early if there are any errors.
+        let bcd = RegPair::decimal_to_bcd(val);
         self.low_bits = bcd;
-        Ok(bcd)
+        bcd
     }
 
     pub fn set_high_bin(&mut self, val: u8) {
@@ -65,11 +65,9 @@ impl RegPair {
     }
 
     /// Returns the 16-bit integer value held across this register pair.
+    /// Registers always hold binary values, so this is the same as `get_wide`.
     pub fn to_int(&self) -> u16 {
-        // Convert the 2-digit BCD value in each register to decimal.
-        let hi_dec = RegPair::bcd_to_decimal(self.high_bits);
-        let lo_dec = RegPair::bcd_to_decimal(self.low_bits);
-        (hi_dec * 100 + lo_dec) as u16
+        self.get_wide()
     }
 
     /// Returns the 8-bit integer value stored in the low register.
@@ -92,19 +90,12 @@ impl RegPair {
     }
 
     /// This will convert a decimal representation of an 8-bit integer into BCD.
-    pub fn decimal_to_bcd(dec: u8) -> Result<u8, ParseIntError> {
-        // Store digits in array. There are 4 bits per digit- and hence only 2 real digits stored.
-        let mut dig_array: [u8; 2] = [0; 2];
-        dig_array[1] = dec % 10;
-        dig_array[0] = (dec / 10) % 10;
-        // Debug line.
-        // println!("{} {} {:04b} {:04b}", dig_array[0], dig_array[1], dig_array[0], dig_array[1]);
-
-        // Use the str_radix method to get the correct BCD number. However, this is quite unideal.
-        let mut bcd_1 = format!("{:04b}", dig_array[0]);
-        let bcd_2 = format!("{:04b}", dig_array[1]);
-        bcd_1.push_str(bcd_2.as_str());
-        u8::from_str_radix(bcd_1.as_str(), 2)
+    /// Only two digits fit into a byte, so any hundreds are discarded.
+    pub fn decimal_to_bcd(dec: u8) -> u8 {
+        // There are 4 bits per digit, with the tens in the upper nibble and the units in the lower.
+        let tens = (dec / 10) % 10;
+        let units = dec % 10;
+        (tens << 4) | units
     }
 
     /// Get the 16-bit integer stored across this register pair.
@@ -120,16 +111,212 @@ impl Display for RegPair {
     }
 }
 
+/// Representation of the status flags within the CPU.
+/// These occupy the upper nibble of the F register; the lower nibble of F always reads as zero.
+/// - (Z) Zero flag
+/// - (N) Subtraction flag for BCD
+/// - (H) Half-carry flag for BCD
+/// - (C) Carry flag
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    /// Z flag
+    pub zero: bool,
+    /// N flag, for BCD
+    pub subtraction: bool,
+    /// H flag, for BCD
+    pub half_carry: bool,
+    /// C flag
+    pub carry: bool,
+}
+
+/// The bit within F which holds the Z flag.
+pub const ZERO_BIT: u8 = 0b1000_0000;
+/// The bit within F which holds the N flag.
+pub const SUBTRACTION_BIT: u8 = 0b0100_0000;
+/// The bit within F which holds the H flag.
+pub const HALF_CARRY_BIT: u8 = 0b0010_0000;
+/// The bit within F which holds the C flag.
+pub const CARRY_BIT: u8 = 0b0001_0000;
+
+impl Flags {
+    pub fn new() -> Self {
+        Flags {
+            zero: false,
+            subtraction: false,
+            half_carry: false,
+            carry: false
+        }
+    }
+
+    pub fn test_flags(&self, sample: &Flags) -> bool {
+        self.zero == sample.zero &&
+        self.subtraction == sample.subtraction &&
+        self.half_carry == sample.half_carry &&
+        self.carry == sample.carry
+    }
+
+    pub fn reset(&mut self) {
+        self.zero = false;
+        self.subtraction = false;
+        self.half_carry = false;
+        self.carry = false;
+    }
+}
+
+impl From<u8> for Flags {
+    /// Unpack the flags from an F register value. The lower nibble is ignored.
+    fn from(f: u8) -> Self {
+        Flags {
+            zero: (f & ZERO_BIT) == ZERO_BIT,
+            subtraction: (f & SUBTRACTION_BIT) == SUBTRACTION_BIT,
+            half_carry: (f & HALF_CARRY_BIT) == HALF_CARRY_BIT,
+            carry: (f & CARRY_BIT) == CARRY_BIT,
+        }
+    }
+}
+
+impl From<Flags> for u8 {
+    /// Pack the flags into an F register value, with the lower nibble cleared.
+    fn from(flags: Flags) -> Self {
+        let mut f = 0;
+        if flags.zero { f |= ZERO_BIT; }
+        if flags.subtraction { f |= SUBTRACTION_BIT; }
+        if flags.half_carry { f |= HALF_CARRY_BIT; }
+        if flags.carry { f |= CARRY_BIT; }
+        f
+    }
+}
+
+/// The 8-bit registers, used to select a register from the register file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg8 {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+}
+
+/// The 16-bit registers, used to select a register (pair) from the register file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg16 {
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+/// # Register file
+/// All of the programmer-visible registers in the SM83 core. A and F are held separately, as F is
+/// really the packed form of the status flags, but they can be read and written together as AF.
+/// Opcode implementations should generally access registers through `get8`/`set8` and
+/// `get16`/`set16` so that they can operate on any register.
+#[derive(Default)]
+pub struct RegisterFile {
+    /// The accumulator register.
+    pub a: u8,
+    /// The status flags, which make up the F register.
+    pub flags: Flags,
+    /// The BC register pair.
+    pub bc: RegPair,
+    /// The DE register pair.
+    pub de: RegPair,
+    /// The HL register pair.
+    pub hl: RegPair,
+    /// The stack pointer.
+    pub sp: u16,
+    /// The program counter.
+    pub pc: u16,
+}
+
+impl RegisterFile {
+    pub fn new() -> Self {
+        RegisterFile {
+            a: 0,
+            flags: Flags::new(),
+            bc: RegPair::new(),
+            de: RegPair::new(),
+            hl: RegPair::new(),
+            sp: 0,
+            pc: 0,
+        }
+    }
+
+    /// Read an 8-bit register.
+    pub fn get8(&self, reg: Reg8) -> u8 {
+        match reg {
+            Reg8::A => self.a,
+            Reg8::F => u8::from(self.flags),
+            Reg8::B => self.bc.get_high(),
+            Reg8::C => self.bc.get_low(),
+            Reg8::D => self.de.get_high(),
+            Reg8::E => self.de.get_low(),
+            Reg8::H => self.hl.get_high(),
+            Reg8::L => self.hl.get_low(),
+        }
+    }
+
+    /// Write an 8-bit register. Writes to F will discard the lower nibble.
+    pub fn set8(&mut self, reg: Reg8, val: u8) {
+        match reg {
+            Reg8::A => self.a = val,
+            Reg8::F => self.flags = Flags::from(val),
+            Reg8::B => self.bc.set_high_bin(val),
+            Reg8::C => self.bc.set_low_bin(val),
+            Reg8::D => self.de.set_high_bin(val),
+            Reg8::E => self.de.set_low_bin(val),
+            Reg8::H => self.hl.set_high_bin(val),
+            Reg8::L => self.hl.set_low_bin(val),
+        }
+    }
+
+    /// Read a 16-bit register.
+    pub fn get16(&self, reg: Reg16) -> u16 {
+        match reg {
+            Reg16::AF => ((self.a as u16) << 8) | u8::from(self.flags) as u16,
+            Reg16::BC => self.bc.get_wide(),
+            Reg16::DE => self.de.get_wide(),
+            Reg16::HL => self.hl.get_wide(),
+            Reg16::SP => self.sp,
+            Reg16::PC => self.pc,
+        }
+    }
+
+    /// Write a 16-bit register. Writes to AF will discard the lower nibble of F.
+    pub fn set16(&mut self, reg: Reg16, val: u16) {
+        match reg {
+            Reg16::AF => {
+                self.a = (val >> 8) as u8;
+                self.flags = Flags::from(val as u8);
+            }
+            Reg16::BC => { self.bc.set_wide(val); }
+            Reg16::DE => { self.de.set_wide(val); }
+            Reg16::HL => { self.hl.set_wide(val); }
+            Reg16::SP => self.sp = val,
+            Reg16::PC => self.pc = val,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn bcd() {
-        let bcd = RegPair::decimal_to_bcd(32);
-        let bcd_val = bcd.unwrap();
+        let bcd_val = RegPair::decimal_to_bcd(32);
         println!("{:#04b}", bcd_val);
         assert_eq!(bcd_val, 0b0011_0010);
+        assert_eq!(RegPair::decimal_to_bcd(0), 0x00);
+        assert_eq!(RegPair::decimal_to_bcd(9), 0x09);
+        assert_eq!(RegPair::decimal_to_bcd(99), 0x99);
+        // Hundreds don't fit, so 255 is stored as 55.
+        assert_eq!(RegPair::decimal_to_bcd(255), 0x55);
     }
 
     #[test]
@@ -142,14 +329,14 @@ mod tests {
     #[test]
     fn set_high() {
         let mut reg = RegPair::new();
-        reg.set_high_bcd(32).unwrap();
+        reg.set_high_bcd(32);
         assert_eq!(reg.get_high(), 0b0011_0010);
     }
 
     #[test]
     fn set_low() {
         let mut reg = RegPair::new();
-        reg.set_low_bcd(32).unwrap();
+        reg.set_low_bcd(32);
         assert_eq!(reg.get_low(), 0b0011_0010);
     }
 
@@ -173,4 +360,45 @@ mod tests {
         reg.set_wide(0b0010_0101_1100_0110);
         assert_eq!(0b0010_0101_1100_0110, reg.get_wide());
     }
+
+    #[test]
+    fn flags_packing() {
+        let flags = Flags::from(0b1011_1111);
+        assert!(flags.test_flags(&Flags { zero: true, subtraction: false, half_carry: true, carry: true }));
+        // The lower nibble is lost when packing the flags back up.
+        assert_eq!(u8::from(flags), 0b1011_0000);
+        assert_eq!(u8::from(Flags::new()), 0);
+    }
+
+    #[test]
+    fn af_pair() {
+        let mut regs = RegisterFile::new();
+        regs.set16(Reg16::AF, 0x12FF);
+        assert_eq!(regs.get8(Reg8::A), 0x12);
+        assert_eq!(regs.get8(Reg8::F), 0xF0);
+        assert_eq!(regs.get16(Reg16::AF), 0x12F0);
+        regs.set8(Reg8::F, 0x5A);
+        assert_eq!(regs.get16(Reg16::AF), 0x1250);
+        assert!(regs.flags.subtraction && regs.flags.carry);
+        assert!(!regs.flags.zero && !regs.flags.half_carry);
+    }
+
+    #[test]
+    fn register_access() {
+        let mut regs = RegisterFile::new();
+        let r8 = [Reg8::B, Reg8::C, Reg8::D, Reg8::E, Reg8::H, Reg8::L];
+        for (i, &reg) in r8.iter().enumerate() {
+            regs.set8(reg, 0x10 + i as u8);
+        }
+        assert_eq!(regs.get16(Reg16::BC), 0x1011);
+        assert_eq!(regs.get16(Reg16::DE), 0x1213);
+        assert_eq!(regs.get16(Reg16::HL), 0x1415);
+        regs.set16(Reg16::SP, 0xFFFE);
+        regs.set16(Reg16::PC, 0x0100);
+        assert_eq!(regs.sp, 0xFFFE);
+        assert_eq!(regs.pc, 0x0100);
+        regs.set16(Reg16::HL, 0xBEEF);
+        assert_eq!(regs.get8(Reg8::H), 0xBE);
+        assert_eq!(regs.get8(Reg8::L), 0xEF);
+    }
 }
\ No newline at end of file
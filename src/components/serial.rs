@@ -0,0 +1,270 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// The address of the serial transfer data register (SB).
+pub const SB_ADDR: u16 = 0xFF01;
+/// The address of the serial transfer control register (SC).
+pub const SC_ADDR: u16 = 0xFF02;
+
+/// When using the internal clock, the DMG shifts one bit every 512 T-cycles (8192Hz).
+const CYCLES_PER_BIT: u32 = 512;
+/// A full transfer shifts all eight bits of SB.
+const CYCLES_PER_TRANSFER: u32 = CYCLES_PER_BIT * 8;
+
+/// A SerialLink is whatever happens to be plugged into the other end of the link port.
+/// The serial port itself only cares about the byte it shifts out and the byte it receives in
+/// return, so a backend can be anything from a buffer to another emulator on the network.
+pub trait SerialLink {
+    /// Called once a transfer clocked by this Game Boy (the internal clock) has completed.
+    /// `out` is the byte that has been shifted out of SB, and the returned byte is the one that
+    /// was shifted in from the other side.
+    fn transfer(&mut self, out: u8) -> u8;
+
+    /// Called periodically whenever this Game Boy is not driving the clock itself, so that the
+    /// other side has a chance to clock a byte across. `out` is SB if a transfer is waiting on the
+    /// external clock, or None if the serial port has not been made ready. Returns the byte the
+    /// other side clocked in, if there was one; it is only accepted whilst a transfer is waiting.
+    /// Nothing ever drives the clock by default, so these transfers never complete.
+    fn poll_external(&mut self, _out: Option<u8>) -> Option<u8> {
+        None
+    }
+}
+
+/// The default link backend, which behaves as though no cable is connected (every transfer reads
+/// back 0xFF) whilst capturing all outgoing bytes. Test ROMs such as Blargg's report their results
+/// this way, so the captured bytes can be inspected, or echoed to stdout as they arrive.
+///
+/// Clones share the same buffer, so a clone can be kept to read the output after the original has
+/// been handed to the CPU.
+#[derive(Clone, Default)]
+pub struct CaptureLink {
+    output: Arc<Mutex<Vec<u8>>>,
+    echo: bool,
+}
+
+impl CaptureLink {
+    pub fn new() -> Self {
+        CaptureLink {
+            output: Arc::new(Mutex::new(Vec::new())),
+            echo: false,
+        }
+    }
+
+    /// Create a capture link which will also print each outgoing byte to stdout.
+    pub fn stdout() -> Self {
+        CaptureLink {
+            output: Arc::new(Mutex::new(Vec::new())),
+            echo: true,
+        }
+    }
+
+    /// Returns a copy of every byte that has been sent so far.
+    pub fn output(&self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+
+    /// Returns the bytes sent so far as text, which is how test ROMs use the serial port.
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output.lock().unwrap()).into_owned()
+    }
+
+    /// Discard everything captured so far.
+    pub fn clear(&self) {
+        self.output.lock().unwrap().clear();
+    }
+}
+
+impl SerialLink for CaptureLink {
+    fn transfer(&mut self, out: u8) -> u8 {
+        self.output.lock().unwrap().push(out);
+        if self.echo {
+            let mut stdout = std::io::stdout();
+            // There's not much we can do if stdout has gone away, so the byte is simply dropped.
+            let _ = stdout.write_all(&[out]).and_then(|_| stdout.flush());
+        }
+        // With nothing connected, the data line is pulled high.
+        0xFF
+    }
+}
+
+/// # Serial port
+/// Representation of the serial port, made up of the SB (data) and SC (control) registers.
+/// Writing SC with bit 7 set will begin a transfer; when using the internal clock this completes
+/// after 4096 T-cycles, at which point SB holds the byte received and the serial interrupt is
+/// requested.
+pub struct Serial {
+    /// SB: the byte that is shifted out as the incoming byte is shifted in.
+    data: u8,
+    /// SC bit 7: set whilst a transfer is requested or in progress.
+    transfer_active: bool,
+    /// SC bit 0: whether this Game Boy drives the clock (master) or waits for the other side.
+    internal_clock: bool,
+    /// T-cycles elapsed in the current transfer.
+    counter: u32,
+    /// T-cycles since the link was last polled for an externally clocked byte.
+    poll_counter: u32,
+    /// Whatever is plugged into the link port.
+    link: Box<dyn SerialLink + Send>,
+}
+
+impl Serial {
+    pub fn new() -> Self {
+        Serial::with_link(Box::new(CaptureLink::new()))
+    }
+
+    pub fn with_link(link: Box<dyn SerialLink + Send>) -> Self {
+        Serial {
+            data: 0,
+            transfer_active: false,
+            internal_clock: false,
+            counter: 0,
+            poll_counter: 0,
+            link,
+        }
+    }
+
+    /// Replace the device connected to the link port.
+    pub fn set_link(&mut self, link: Box<dyn SerialLink + Send>) {
+        self.link = link;
+    }
+
+    /// Unplug the device connected to the link port, leaving nothing connected in its place.
+    pub fn take_link(&mut self) -> Box<dyn SerialLink + Send> {
+        std::mem::replace(&mut self.link, Box::new(CaptureLink::new()))
+    }
+
+    /// Read one of the serial registers.
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            SB_ADDR => self.data,
+            // Bits 1-6 of SC are unused and always read back as set.
+            SC_ADDR => {
+                0b0111_1110
+                    | if self.transfer_active { 0b1000_0000 } else { 0 }
+                    | if self.internal_clock { 0b0000_0001 } else { 0 }
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Write to one of the serial registers.
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            SB_ADDR => self.data = val,
+            SC_ADDR => {
+                self.transfer_active = (val & 0b1000_0000) == 0b1000_0000;
+                self.internal_clock = (val & 0b0000_0001) == 0b0000_0001;
+                // Starting a transfer always begins from the first bit.
+                self.counter = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Advance the serial port by the given number of T-cycles.
+    /// Returns true if a transfer completed, in which case the serial interrupt should be requested.
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        let incoming = if self.transfer_active && self.internal_clock {
+            self.counter += cycles;
+            if self.counter < CYCLES_PER_TRANSFER {
+                return false;
+            }
+            self.link.transfer(self.data)
+        } else {
+            // An external clock can't shift bits any faster than a DMG's internal clock, so the
+            // link only needs polling once per bit rather than on every instruction.
+            self.poll_counter += cycles;
+            if self.poll_counter < CYCLES_PER_BIT {
+                return false;
+            }
+            self.poll_counter = 0;
+            let waiting = if self.transfer_active { Some(self.data) } else { None };
+            match self.link.poll_external(waiting) {
+                Some(byte) if self.transfer_active => byte,
+                _ => return false,
+            }
+        };
+
+        self.data = incoming;
+        self.transfer_active = false;
+        self.counter = 0;
+        true
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Serial::new()
+    }
+}
+
+impl Snapshot for Serial {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u8(self.data);
+        w.bool(self.transfer_active);
+        w.bool(self.internal_clock);
+        w.u32(self.counter);
+        w.u32(self.poll_counter);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.data = r.u8()?;
+        self.transfer_active = r.bool()?;
+        self.internal_clock = r.bool()?;
+        self.counter = r.u32()?;
+        self.poll_counter = r.u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internal_clock_timing() {
+        let link = CaptureLink::new();
+        let mut serial = Serial::with_link(Box::new(link.clone()));
+        serial.write(SB_ADDR, b'P');
+        serial.write(SC_ADDR, 0x81);
+        // Nothing should be sent until all eight bits have been shifted.
+        assert!(!serial.tick(CYCLES_PER_TRANSFER - 4));
+        assert_eq!(serial.read(SC_ADDR), 0xFF);
+        assert!(link.output().is_empty());
+        assert!(serial.tick(4));
+        assert_eq!(link.output_string(), "P");
+        // The transfer has completed, so SC bit 7 is cleared and SB holds the incoming byte.
+        assert_eq!(serial.read(SC_ADDR), 0x7F);
+        assert_eq!(serial.read(SB_ADDR), 0xFF);
+        // No further interrupts once the transfer is done.
+        assert!(!serial.tick(CYCLES_PER_TRANSFER));
+    }
+
+    #[test]
+    fn external_clock_waits() {
+        let link = CaptureLink::new();
+        let mut serial = Serial::with_link(Box::new(link.clone()));
+        serial.write(SB_ADDR, 0x42);
+        serial.write(SC_ADDR, 0x80);
+        // Nothing is driving the clock, so the transfer never completes.
+        assert!(!serial.tick(CYCLES_PER_TRANSFER * 4));
+        assert_eq!(serial.read(SC_ADDR), 0xFE);
+        assert_eq!(serial.read(SB_ADDR), 0x42);
+        assert!(link.output().is_empty());
+    }
+
+    #[test]
+    fn capture_shared_between_clones() {
+        let link = CaptureLink::new();
+        let mut serial = Serial::with_link(Box::new(link.clone()));
+        for &byte in b"Passed" {
+            serial.write(SB_ADDR, byte);
+            serial.write(SC_ADDR, 0x81);
+            serial.tick(CYCLES_PER_TRANSFER);
+        }
+        assert_eq!(link.output_string(), "Passed");
+        link.clear();
+        assert!(link.output().is_empty());
+    }
+}
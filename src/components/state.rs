@@ -0,0 +1,170 @@
+use thiserror::Error;
+
+/// Every save state starts with these bytes.
+pub const STATE_MAGIC: &[u8; 8] = b"PWDMGSTA";
+/// The version of the save state format. This must go up whenever the layout of any component's
+/// state changes, so that older states are rejected rather than loaded wrongly.
+pub const STATE_VERSION: u16 = 1;
+
+#[derive(Debug, Error)]
+pub enum StateError {
+    #[error("This isn't a save state")]
+    NotAState,
+    #[error("Save state version {0} isn't supported; only version {} can be loaded", STATE_VERSION)]
+    UnsupportedVersion(u16),
+    #[error("The save state is for a different ROM (global checksum {found:#06x}, but this ROM's is {expected:#06x})")]
+    WrongRom { expected: u16, found: u16 },
+    #[error("The save state ends early")]
+    Truncated,
+    #[error("The save state is corrupt: {0}")]
+    Corrupt(&'static str),
+}
+
+/// Builds up a save state. Everything is written in little-endian order, with no padding or
+/// field names, so the reader must read things back in exactly the order they were written.
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter { buf: Vec::new() }
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.buf.push(val as u8);
+    }
+
+    pub fn u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    /// Write a block of bytes whose length the reader already knows.
+    pub fn bytes(&mut self, val: &[u8]) {
+        self.buf.extend_from_slice(val);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// Reads back a save state written by `StateWriter`.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        StateReader { data, pos: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.data.len()).ok_or(StateError::Truncated)?;
+        let slice = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt("invalid boolean")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let mut bytes = [0; 2];
+        bytes.copy_from_slice(self.take(2)?);
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Fill the given buffer from the state.
+    pub fn bytes(&mut self, buf: &mut [u8]) -> Result<(), StateError> {
+        buf.copy_from_slice(self.take(buf.len())?);
+        Ok(())
+    }
+
+    /// The number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+
+    /// Check that the whole state has been read.
+    pub fn finish(&self) -> Result<(), StateError> {
+        if self.pos == self.data.len() {
+            Ok(())
+        } else {
+            Err(StateError::Corrupt("unexpected data at the end"))
+        }
+    }
+}
+
+/// A component whose state can be saved and restored. Only the state of the emulated hardware is
+/// saved; configuration, such as what is plugged into the link port, is left as it is.
+pub trait Snapshot {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut w = StateWriter::new();
+        w.u8(0x12);
+        w.bool(true);
+        w.u16(0x3456);
+        w.u32(0x789A_BCDE);
+        w.u64(0x0102_0304_0506_0708);
+        w.bytes(&[1, 2, 3]);
+        let data = w.into_bytes();
+        assert_eq!(data.len(), 1 + 1 + 2 + 4 + 8 + 3);
+
+        let mut r = StateReader::new(&data);
+        assert_eq!(r.u8().unwrap(), 0x12);
+        assert!(r.bool().unwrap());
+        assert_eq!(r.u16().unwrap(), 0x3456);
+        assert_eq!(r.u32().unwrap(), 0x789A_BCDE);
+        assert_eq!(r.u64().unwrap(), 0x0102_0304_0506_0708);
+        let mut buf = [0; 3];
+        r.bytes(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+        assert_eq!(r.remaining(), 0);
+        r.finish().unwrap();
+        assert!(matches!(r.u8(), Err(StateError::Truncated)));
+    }
+}
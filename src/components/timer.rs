@@ -0,0 +1,208 @@
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter};
+
+/// The address of the divider register (DIV).
+pub const DIV_ADDR: u16 = 0xFF04;
+/// The address of the timer counter (TIMA).
+pub const TIMA_ADDR: u16 = 0xFF05;
+/// The address of the timer modulo (TMA), which TIMA is reloaded from when it overflows.
+pub const TMA_ADDR: u16 = 0xFF06;
+/// The address of the timer control register (TAC).
+pub const TAC_ADDR: u16 = 0xFF07;
+
+/// # Timer
+/// Representation of the timer, made up of DIV, TIMA, TMA and TAC.
+///
+/// DIV is really the upper byte of a 16-bit counter which goes up every T-cycle. Rather than having
+/// a clock of its own, TIMA is incremented whenever the bit of that counter selected by TAC goes
+/// from 1 to 0. Modelling it this way means that the quirks of the real hardware (writing DIV or TAC
+/// causing TIMA to tick early) fall out naturally.
+///
+/// When TIMA overflows it reads as 0x00 for one M-cycle, after which it is reloaded from TMA and the
+/// timer interrupt is requested. Writing TIMA during that M-cycle cancels the reload.
+pub struct Timer {
+    /// The internal counter, of which DIV is the upper byte.
+    counter: u16,
+    /// TIMA: incremented at the frequency selected by TAC.
+    tima: u8,
+    /// TMA: the value TIMA is reloaded with when it overflows.
+    tma: u8,
+    /// TAC: bit 2 enables TIMA, and bits 0-1 select its frequency.
+    tac: u8,
+    /// Set when TIMA has overflowed and is waiting to be reloaded at the end of the next M-cycle.
+    reload_pending: bool,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            reload_pending: false,
+        }
+    }
+
+    /// Set the internal counter directly, such as to start from the state left by the boot ROM.
+    pub fn set_counter(&mut self, counter: u16) {
+        self.counter = counter;
+    }
+
+    /// Read one of the timer registers.
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            DIV_ADDR => (self.counter >> 8) as u8,
+            TIMA_ADDR => self.tima,
+            TMA_ADDR => self.tma,
+            // Only the lower three bits of TAC are used; the rest read back as set.
+            TAC_ADDR => self.tac | 0b1111_1000,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write to one of the timer registers.
+    pub fn write(&mut self, addr: u16, val: u8) {
+        let before = self.signal();
+        match addr {
+            // Any write to DIV resets the whole counter.
+            DIV_ADDR => self.counter = 0,
+            TIMA_ADDR => {
+                self.tima = val;
+                self.reload_pending = false;
+            }
+            TMA_ADDR => self.tma = val,
+            TAC_ADDR => self.tac = val & 0b0000_0111,
+            _ => {}
+        }
+        // Both of the above can pull the selected bit low, which counts as a tick.
+        if before && !self.signal() {
+            self.increment();
+        }
+    }
+
+    /// Advance the timer by the given number of T-cycles, which should be a multiple of four.
+    /// Returns true if TIMA was reloaded, in which case the timer interrupt should be requested.
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        let mut interrupt = false;
+        for _ in 0..cycles / 4 {
+            if self.reload_pending {
+                self.reload_pending = false;
+                self.tima = self.tma;
+                interrupt = true;
+            }
+            let before = self.signal();
+            self.counter = self.counter.wrapping_add(4);
+            if before && !self.signal() {
+                self.increment();
+            }
+        }
+        interrupt
+    }
+
+    /// The bit of the internal counter which TIMA follows, gated by the enable bit of TAC.
+    fn signal(&self) -> bool {
+        let bit = match self.tac & 0b11 {
+            0b00 => 9, // 4096Hz
+            0b01 => 3, // 262144Hz
+            0b10 => 5, // 65536Hz
+            _ => 7,    // 16384Hz
+        };
+        (self.tac & 0b100) != 0 && (self.counter >> bit) & 1 == 1
+    }
+
+    fn increment(&mut self) {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.reload_pending = overflow;
+    }
+}
+
+impl Snapshot for Timer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.u16(self.counter);
+        w.u8(self.tima);
+        w.u8(self.tma);
+        w.u8(self.tac);
+        w.bool(self.reload_pending);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateError> {
+        self.counter = r.u16()?;
+        self.tima = r.u8()?;
+        self.tma = r.u8()?;
+        self.tac = r.u8()? & 0b0000_0111;
+        self.reload_pending = r.bool()?;
+        Ok(())
+    }
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Timer::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn div() {
+        let mut timer = Timer::new();
+        timer.tick(252);
+        assert_eq!(timer.read(DIV_ADDR), 0);
+        timer.tick(4);
+        assert_eq!(timer.read(DIV_ADDR), 1);
+        timer.write(DIV_ADDR, 0x55);
+        assert_eq!(timer.read(DIV_ADDR), 0);
+    }
+
+    #[test]
+    fn tima_frequency() {
+        let mut timer = Timer::new();
+        // Enabled, 262144Hz: one increment every 16 T-cycles.
+        timer.write(TAC_ADDR, 0b101);
+        timer.tick(12);
+        assert_eq!(timer.read(TIMA_ADDR), 0);
+        timer.tick(4);
+        assert_eq!(timer.read(TIMA_ADDR), 1);
+        timer.tick(16 * 9);
+        assert_eq!(timer.read(TIMA_ADDR), 10);
+        assert_eq!(timer.read(TAC_ADDR), 0xFD);
+    }
+
+    #[test]
+    fn overflow_reload() {
+        let mut timer = Timer::new();
+        timer.write(TMA_ADDR, 0xAB);
+        timer.write(TIMA_ADDR, 0xFF);
+        timer.write(TAC_ADDR, 0b101);
+        assert!(!timer.tick(16));
+        // TIMA reads as zero for one M-cycle before it is reloaded.
+        assert_eq!(timer.read(TIMA_ADDR), 0x00);
+        assert!(timer.tick(4));
+        assert_eq!(timer.read(TIMA_ADDR), 0xAB);
+    }
+
+    #[test]
+    fn reload_cancelled() {
+        let mut timer = Timer::new();
+        timer.write(TMA_ADDR, 0xAB);
+        timer.write(TIMA_ADDR, 0xFF);
+        timer.write(TAC_ADDR, 0b101);
+        timer.tick(16);
+        timer.write(TIMA_ADDR, 0x12);
+        assert!(!timer.tick(4));
+        assert_eq!(timer.read(TIMA_ADDR), 0x12);
+    }
+
+    #[test]
+    fn div_write_glitch() {
+        let mut timer = Timer::new();
+        timer.write(TAC_ADDR, 0b101);
+        timer.tick(8);
+        // Bit 3 of the counter is set, so resetting it counts as a falling edge.
+        timer.write(DIV_ADDR, 0);
+        assert_eq!(timer.read(TIMA_ADDR), 1);
+    }
+}
@@ -0,0 +1,452 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use crate::components::opcodes::{lookup, Mnemonic};
+use crate::components::register::{Reg16, Reg8, RegisterFile};
+use crate::gameboy::GameBoy;
+
+/// A register which can be inspected or changed, or tested by a breakpoint condition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    R8(Reg8),
+    R16(Reg16),
+}
+
+impl Register {
+    pub fn get(self, regs: &RegisterFile) -> u16 {
+        match self {
+            Register::R8(reg) => regs.get8(reg) as u16,
+            Register::R16(reg) => regs.get16(reg),
+        }
+    }
+
+    /// Set the register. 8-bit registers only take the lower byte of the value.
+    pub fn set(self, regs: &mut RegisterFile, val: u16) {
+        match self {
+            Register::R8(reg) => regs.set8(reg, val as u8),
+            Register::R16(reg) => regs.set16(reg, val),
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = DebuggerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "a" => Register::R8(Reg8::A),
+            "f" => Register::R8(Reg8::F),
+            "b" => Register::R8(Reg8::B),
+            "c" => Register::R8(Reg8::C),
+            "d" => Register::R8(Reg8::D),
+            "e" => Register::R8(Reg8::E),
+            "h" => Register::R8(Reg8::H),
+            "l" => Register::R8(Reg8::L),
+            "af" => Register::R16(Reg16::AF),
+            "bc" => Register::R16(Reg16::BC),
+            "de" => Register::R16(Reg16::DE),
+            "hl" => Register::R16(Reg16::HL),
+            "sp" => Register::R16(Reg16::SP),
+            "pc" => Register::R16(Reg16::PC),
+            _ => return Err(DebuggerError::UnknownRegister(s.to_string())),
+        })
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::R8(reg) => write!(f, "{:?}", reg),
+            Register::R16(reg) => write!(f, "{:?}", reg),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    /// The operators, longest first so that "<=" isn't mistaken for "<".
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+}
+
+/// A test of a register against a value, such as `A == 0x10`, which a breakpoint can be made to
+/// depend on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BreakCondition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl BreakCondition {
+    pub fn holds(&self, regs: &RegisterFile) -> bool {
+        let reg = self.register.get(regs);
+        match self.comparison {
+            Comparison::Eq => reg == self.value,
+            Comparison::Ne => reg != self.value,
+            Comparison::Lt => reg < self.value,
+            Comparison::Le => reg <= self.value,
+            Comparison::Gt => reg > self.value,
+            Comparison::Ge => reg >= self.value,
+        }
+    }
+}
+
+impl FromStr for BreakCondition {
+    type Err = DebuggerError;
+
+    /// Parse a condition such as `a == 0x10` or `hl>=$C000`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        for &(op, comparison) in Comparison::OPERATORS.iter() {
+            if let Some(index) = s.find(op) {
+                let register = s[..index].trim().parse()?;
+                let value = parse_number(s[index + op.len()..].trim())?;
+                return Ok(BreakCondition { register, comparison, value });
+            }
+        }
+        Err(DebuggerError::InvalidCondition(s.to_string()))
+    }
+}
+
+impl fmt::Display for BreakCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = Comparison::OPERATORS.iter().find(|(_, c)| *c == self.comparison).map_or("?", |(op, _)| op);
+        write!(f, "{} {} {:#06x}", self.register, op, self.value)
+    }
+}
+
+/// Parse a number, in hex if it starts with `0x` or `$` and in decimal otherwise.
+pub fn parse_number(s: &str) -> Result<u16, DebuggerError> {
+    let parsed = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        u16::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+    parsed.map_err(|_| DebuggerError::InvalidNumber(s.to_string()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DebuggerError {
+    #[error("Unknown register `{0}`")]
+    UnknownRegister(String),
+    #[error("`{0}` isn't a number; use 0x or $ for hex")]
+    InvalidNumber(String),
+    #[error("`{0}` isn't a condition such as `a == 0x10`")]
+    InvalidCondition(String),
+}
+
+/// Stops execution when the PC reaches an address, if its condition (if any) holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<BreakCondition>,
+}
+
+/// Which kinds of access a watchpoint stops on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// Stops execution when the CPU reads and/or writes an address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub(crate) fn matches(&self, addr: u16, write: bool) -> bool {
+        self.addr == addr && match self.kind {
+            WatchKind::Read => !write,
+            WatchKind::Write => write,
+            WatchKind::ReadWrite => true,
+        }
+    }
+}
+
+/// A memory access which set off a watchpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub addr: u16,
+    /// The value read or written.
+    pub value: u8,
+    pub write: bool,
+}
+
+/// Why the debugger handed control back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// A single step finished.
+    Stepped,
+    /// The PC reached a breakpoint.
+    Breakpoint(u16),
+    /// The instruction which just ran set off a watchpoint.
+    Watchpoint(WatchHit),
+    /// The PC reached the address being run to, or a stepped-over call returned.
+    Reached(u16),
+    /// An illegal opcode has hung the CPU, so running any further is pointless.
+    Locked,
+    /// The cycle limit given was reached.
+    Limit,
+    /// The interrupt flag was set, such as by Ctrl-C.
+    Interrupted,
+}
+
+/// # Debugger
+/// Runs a `GameBoy` an instruction at a time, stopping at breakpoints and watchpoints. The state
+/// of the machine can be inspected and changed between runs through `GameBoy::cpu` and
+/// `GameBoy::cpu_mut`.
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    /// Set from outside, such as by a Ctrl-C handler, to stop whatever is running.
+    interrupt: Arc<AtomicBool>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger { breakpoints: Vec::new(), watchpoints: Vec::new(), interrupt: Arc::new(AtomicBool::new(false)) }
+    }
+
+    /// A flag which, when set, stops the run in progress after the current instruction with
+    /// `StopReason::Interrupted`. Setting it whilst nothing is running has no effect.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.interrupt)
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Remove the breakpoint at the given index in `breakpoints`.
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        if index < self.breakpoints.len() {
+            Some(self.breakpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    /// Remove the watchpoint at the given index in `watchpoints`.
+    pub fn remove_watchpoint(&mut self, index: usize) -> Option<Watchpoint> {
+        if index < self.watchpoints.len() {
+            Some(self.watchpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// Run a single instruction.
+    pub fn step(&mut self, gb: &mut GameBoy) -> StopReason {
+        self.run_until(gb, None, |_| Some(StopReason::Stepped))
+    }
+
+    /// Run a single instruction, except that calls (including RST) are run until they return.
+    pub fn step_over(&mut self, gb: &mut GameBoy) -> StopReason {
+        let regs = gb.cpu().registers();
+        let (pc, sp) = (regs.pc, regs.sp);
+        let instr = lookup(gb.cpu().read_byte(pc), false);
+        if instr.mnemonic != Mnemonic::Call && instr.mnemonic != Mnemonic::Rst {
+            return self.step(gb);
+        }
+        let return_addr = pc.wrapping_add(instr.length as u16);
+        // Checking SP as well means a recursive call to the same place doesn't count as returning.
+        self.run_until(gb, None, |gb| {
+            let regs = gb.cpu().registers();
+            if regs.pc == return_addr && regs.sp >= sp {
+                Some(StopReason::Reached(return_addr))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Run until the PC reaches the given address, or something else stops execution first.
+    pub fn run_to(&mut self, gb: &mut GameBoy, addr: u16) -> StopReason {
+        self.run_until(gb, None, |gb| {
+            if gb.cpu().registers().pc == addr {
+                Some(StopReason::Reached(addr))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Run until a breakpoint or watchpoint is hit, or until `limit` T-cycles have passed.
+    pub fn continue_running(&mut self, gb: &mut GameBoy, limit: Option<u64>) -> StopReason {
+        self.run_until(gb, limit, |_| None)
+    }
+
+    /// Step until `stop` gives a reason to stop, or a breakpoint or watchpoint is hit. At least one
+    /// instruction is always run, so that continuing from a breakpoint gets past it.
+    fn run_until<F>(&mut self, gb: &mut GameBoy, limit: Option<u64>, mut stop: F) -> StopReason
+    where
+        F: FnMut(&GameBoy) -> Option<StopReason>,
+    {
+        gb.cpu_mut().watchpoints = self.watchpoints.clone();
+        gb.cpu_mut().watch_hit = None;
+        self.interrupt.store(false, Ordering::Relaxed);
+        let start = gb.cpu().cycles;
+
+        let reason = loop {
+            gb.step();
+            if let Some(hit) = gb.cpu_mut().watch_hit.take() {
+                break StopReason::Watchpoint(hit);
+            }
+            if gb.cpu().is_locked() {
+                break StopReason::Locked;
+            }
+            if let Some(reason) = stop(gb) {
+                break reason;
+            }
+            let regs = gb.cpu().registers();
+            let hit = self.breakpoints.iter()
+                .any(|bp| bp.addr == regs.pc && bp.condition.is_none_or(|c| c.holds(regs)));
+            if hit {
+                break StopReason::Breakpoint(regs.pc);
+            }
+            if limit.is_some_and(|limit| gb.cpu().cycles - start >= limit) {
+                break StopReason::Limit;
+            }
+            if self.interrupt.swap(false, Ordering::Relaxed) {
+                break StopReason::Interrupted;
+            }
+        };
+
+        gb.cpu_mut().watchpoints.clear();
+        reason
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::cartridge::Cartridge;
+
+    /// A Game Boy running the given code from the entry point.
+    fn gameboy(code: &[u8]) -> GameBoy {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+        GameBoy::new(Cartridge::from_bytes(rom).unwrap())
+    }
+
+    /// 0x0100: LD A,0; CALL 0x0110; INC A; JR -5 (back to the CALL)
+    /// 0x0110: LD (0xC000),A; RET
+    fn program() -> GameBoy {
+        let mut code = vec![0x3E, 0x00, 0xCD, 0x10, 0x01, 0x3C, 0x18, 0xFA];
+        code.resize(0x10, 0x00);
+        code.extend_from_slice(&[0xEA, 0x00, 0xC0, 0xC9]);
+        gameboy(&code)
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!("HL".parse::<Register>().unwrap(), Register::R16(Reg16::HL));
+        assert!("x".parse::<Register>().is_err());
+        assert_eq!(parse_number("0x1F").unwrap(), 0x1F);
+        assert_eq!(parse_number("$c000").unwrap(), 0xC000);
+        assert_eq!(parse_number("42").unwrap(), 42);
+        assert!(parse_number("C000").is_err());
+        let condition: BreakCondition = "a <= 0x10".parse().unwrap();
+        assert_eq!(condition, BreakCondition { register: Register::R8(Reg8::A), comparison: Comparison::Le, value: 0x10 });
+        assert!("a = 1".parse::<BreakCondition>().is_err());
+    }
+
+    #[test]
+    fn stepping() {
+        let mut gb = program();
+        let mut debugger = Debugger::new();
+        assert_eq!(debugger.step(&mut gb), StopReason::Stepped);
+        assert_eq!(gb.cpu().registers().pc, 0x0102);
+        // Stepping over the call runs the whole subroutine.
+        assert_eq!(debugger.step_over(&mut gb), StopReason::Reached(0x0105));
+        assert_eq!(gb.cpu().read_byte(0xC000), 0x00);
+        assert_eq!(debugger.step(&mut gb), StopReason::Stepped);
+        assert_eq!(gb.cpu().registers().a, 1);
+        // Stepping into it doesn't.
+        debugger.step(&mut gb);
+        debugger.step(&mut gb);
+        assert_eq!(gb.cpu().registers().pc, 0x0110);
+        assert_eq!(debugger.run_to(&mut gb, 0x0113), StopReason::Reached(0x0113));
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut gb = program();
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(Breakpoint { addr: 0x0110, condition: "a == 3".parse().ok() });
+        assert_eq!(debugger.continue_running(&mut gb, None), StopReason::Breakpoint(0x0110));
+        assert_eq!(gb.cpu().registers().a, 3);
+        debugger.remove_breakpoint(0);
+        assert_eq!(debugger.continue_running(&mut gb, Some(1000)), StopReason::Limit);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut gb = program();
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint { addr: 0xC000, kind: WatchKind::Write });
+        let hit = WatchHit { addr: 0xC000, value: 0, write: true };
+        assert_eq!(debugger.continue_running(&mut gb, None), StopReason::Watchpoint(hit));
+        // It stops straight after the instruction which did the write.
+        assert_eq!(gb.cpu().registers().pc, 0x0113);
+        debugger.remove_watchpoint(0);
+        debugger.add_watchpoint(Watchpoint { addr: 0xC000, kind: WatchKind::Read });
+        assert_eq!(debugger.continue_running(&mut gb, Some(1000)), StopReason::Limit);
+    }
+
+    #[test]
+    fn interrupted() {
+        // JR -2, forever.
+        let mut gb = gameboy(&[0x18, 0xFE]);
+        let mut debugger = Debugger::new();
+        let interrupt = debugger.interrupt_flag();
+        // Set before running, it's forgotten.
+        interrupt.store(true, Ordering::Relaxed);
+        assert_eq!(debugger.step(&mut gb), StopReason::Stepped);
+        let setter = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            interrupt.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(debugger.run_to(&mut gb, 0x4000), StopReason::Interrupted);
+        setter.join().unwrap();
+    }
+
+    #[test]
+    fn locked() {
+        let mut gb = gameboy(&[0xD3]);
+        assert_eq!(Debugger::new().continue_running(&mut gb, None), StopReason::Locked);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt;
+use crate::components::opcodes::{lookup, Condition, Instruction, Mnemonic, Operand};
+
+/// The names of the I/O registers, for showing in place of their addresses.
+const IO_REGISTERS: [(u16, &str); 41] = [
+    (0xFF00, "P1"), (0xFF01, "SB"), (0xFF02, "SC"), (0xFF04, "DIV"),
+    (0xFF05, "TIMA"), (0xFF06, "TMA"), (0xFF07, "TAC"), (0xFF0F, "IF"),
+    (0xFF10, "NR10"), (0xFF11, "NR11"), (0xFF12, "NR12"), (0xFF13, "NR13"), (0xFF14, "NR14"),
+    (0xFF16, "NR21"), (0xFF17, "NR22"), (0xFF18, "NR23"), (0xFF19, "NR24"),
+    (0xFF1A, "NR30"), (0xFF1B, "NR31"), (0xFF1C, "NR32"), (0xFF1D, "NR33"), (0xFF1E, "NR34"),
+    (0xFF20, "NR41"), (0xFF21, "NR42"), (0xFF22, "NR43"), (0xFF23, "NR44"),
+    (0xFF24, "NR50"), (0xFF25, "NR51"), (0xFF26, "NR52"),
+    (0xFF40, "LCDC"), (0xFF41, "STAT"), (0xFF42, "SCY"), (0xFF43, "SCX"), (0xFF44, "LY"),
+    (0xFF45, "LYC"), (0xFF46, "DMA"), (0xFF47, "BGP"), (0xFF48, "OBP0"), (0xFF49, "OBP1"),
+    (0xFF4A, "WY"), (0xFF4B, "WX"),
+];
+
+/// The name of the I/O register at the given address, if it has one. Unused addresses, wave RAM
+/// and HRAM don't.
+pub fn io_register_name(addr: u16) -> Option<&'static str> {
+    match addr {
+        0xFF50 => Some("BOOT"),
+        0xFFFF => Some("IE"),
+        _ => IO_REGISTERS.iter().find(|&&(reg, _)| reg == addr).map(|&(_, name)| name),
+    }
+}
+
+/// A single decoded instruction.
+#[derive(Debug, Clone)]
+pub struct Disassembly {
+    /// The address the instruction was read from.
+    pub addr: u16,
+    pub instruction: &'static Instruction,
+    bytes: [u8; 3],
+    text: String,
+}
+
+impl Disassembly {
+    /// The bytes making up the instruction, including any 0xCB prefix.
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes[..self.instruction.length as usize]
+    }
+
+    /// The address of the instruction after this one.
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.instruction.length as u16)
+    }
+
+    /// The instruction as assembly, e.g. `LD A,($C000)`.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl fmt::Display for Disassembly {
+    /// Formats as the address, the bytes and then the assembly, e.g. `0150: 3E 12     LD A,$12`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes: Vec<String> = self.bytes().iter().map(|byte| format!("{:02X}", byte)).collect();
+        write!(f, "{:04X}: {:<9} {}", self.addr, bytes.join(" "), self.text)
+    }
+}
+
+/// Decode the instruction at `addr`, reading memory through `read`. This can read from a running
+/// Game Boy (`|addr| gb.cpu().read_byte(addr)`) just as well as from a ROM file.
+pub fn disassemble<F: Fn(u16) -> u8>(read: F, addr: u16) -> Disassembly {
+    let opcode = read(addr);
+    let instruction = if opcode == 0xCB {
+        lookup(read(addr.wrapping_add(1)), true)
+    } else {
+        lookup(opcode, false)
+    };
+    let mut bytes = [0; 3];
+    for (i, byte) in bytes.iter_mut().enumerate().take(instruction.length as usize) {
+        *byte = read(addr.wrapping_add(i as u16));
+    }
+    let next = addr.wrapping_add(instruction.length as u16);
+
+    let text = match instruction.mnemonic {
+        // STOP is followed by a byte which is skipped over but means nothing.
+        Mnemonic::Stop => "STOP".to_string(),
+        Mnemonic::Illegal => format!("DB ${:02X}", opcode),
+        mnemonic => {
+            let operands: Vec<String> = instruction.operands.iter()
+                .map(|&operand| format_operand(operand, mnemonic, &bytes, next))
+                .collect();
+            if operands.is_empty() {
+                mnemonic.to_string()
+            } else {
+                format!("{} {}", mnemonic, operands.join(","))
+            }
+        }
+    };
+    Disassembly { addr, instruction, bytes, text }
+}
+
+/// Format an operand, taking any immediate value from the bytes of the instruction. `next` is the
+/// address after the instruction, which relative jumps are taken from.
+fn format_operand(operand: Operand, mnemonic: Mnemonic, bytes: &[u8; 3], next: u16) -> String {
+    let d8 = bytes[1];
+    let d16 = u16::from_le_bytes([bytes[1], bytes[2]]);
+    match operand {
+        Operand::R8(reg) => format!("{:?}", reg),
+        Operand::R16(reg) => format!("{:?}", reg),
+        Operand::Ind(reg) => format!("({:?})", reg),
+        Operand::HlInc => "(HL+)".to_string(),
+        Operand::HlDec => "(HL-)".to_string(),
+        Operand::IndC => "(C)".to_string(),
+        Operand::D8 => format!("${:02X}", d8),
+        Operand::D16 => format!("${:04X}", d16),
+        Operand::A8 => format!("({})", format_address(0xFF00 | d8 as u16)),
+        // Jump and call targets are addresses to go to rather than memory to access.
+        Operand::A16 if mnemonic == Mnemonic::Jp || mnemonic == Mnemonic::Call => format!("${:04X}", d16),
+        Operand::A16 => format!("({})", format_address(d16)),
+        Operand::S8 if mnemonic == Mnemonic::Jr => format!("${:04X}", next.wrapping_add(d8 as i8 as u16)),
+        Operand::S8 => format!("{}", d8 as i8),
+        Operand::SpOffset => format!("SP{:+}", d8 as i8),
+        Operand::Cond(condition) => match condition {
+            Condition::NotZero => "NZ",
+            Condition::Zero => "Z",
+            Condition::NotCarry => "NC",
+            Condition::Carry => "C",
+        }.to_string(),
+        Operand::Vector(vector) => format!("${:02X}", vector),
+        Operand::Bit(bit) => bit.to_string(),
+    }
+}
+
+fn format_address(addr: u16) -> String {
+    match io_register_name(addr) {
+        Some(name) => name.to_string(),
+        None => format!("${:04X}", addr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Disassemble the given bytes as if they were at 0x0150.
+    fn text(code: &[u8]) -> String {
+        let read = |addr: u16| code.get((addr - 0x0150) as usize).copied().unwrap_or(0);
+        disassemble(read, 0x0150).text().to_string()
+    }
+
+    #[test]
+    fn operands() {
+        assert_eq!(text(&[0x00]), "NOP");
+        assert_eq!(text(&[0x3E, 0x12]), "LD A,$12");
+        assert_eq!(text(&[0x21, 0x34, 0x12]), "LD HL,$1234");
+        assert_eq!(text(&[0xFA, 0x00, 0xC0]), "LD A,($C000)");
+        assert_eq!(text(&[0x08, 0x00, 0xC0]), "LD ($C000),SP");
+        assert_eq!(text(&[0x22]), "LD (HL+),A");
+        assert_eq!(text(&[0xE2]), "LD (C),A");
+        assert_eq!(text(&[0xE8, 0xFE]), "ADD SP,-2");
+        assert_eq!(text(&[0xF8, 0x05]), "LD HL,SP+5");
+        assert_eq!(text(&[0xFF]), "RST $38");
+        assert_eq!(text(&[0x10, 0x00]), "STOP");
+        assert_eq!(text(&[0xD3]), "DB $D3");
+    }
+
+    #[test]
+    fn jumps() {
+        assert_eq!(text(&[0x18, 0xFE]), "JR $0150");
+        assert_eq!(text(&[0x20, 0x10]), "JR NZ,$0162");
+        assert_eq!(text(&[0xC3, 0x00, 0x40]), "JP $4000");
+        assert_eq!(text(&[0xDC, 0x00, 0x40]), "CALL C,$4000");
+        assert_eq!(text(&[0xE9]), "JP HL");
+    }
+
+    #[test]
+    fn io_registers() {
+        assert_eq!(text(&[0xE0, 0x44]), "LDH (LY),A");
+        assert_eq!(text(&[0xF0, 0x80]), "LDH A,($FF80)");
+        assert_eq!(text(&[0xEA, 0xFF, 0xFF]), "LD (IE),A");
+    }
+
+    #[test]
+    fn prefixed() {
+        let code = [0xCB, 0x7C];
+        let dis = disassemble(|addr| code[addr as usize], 0);
+        assert_eq!(dis.text(), "BIT 7,H");
+        assert_eq!(dis.bytes(), &[0xCB, 0x7C]);
+        assert_eq!(dis.next_addr(), 2);
+        assert_eq!(dis.to_string(), "0000: CB 7C     BIT 7,H");
+    }
+}
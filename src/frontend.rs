@@ -0,0 +1,278 @@
+use std::thread;
+use std::time::Instant;
+use anyhow::{anyhow, Result};
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod, Scancode};
+use sdl2::pixels::PixelFormatEnum;
+use patchwork_dmg::video::{PaletteSet, SCREEN_HEIGHT, SCREEN_WIDTH};
+use patchwork_dmg::{Button, GameBoy, Rewind, CYCLES_PER_FRAME};
+use crate::capture::Capture;
+use crate::cli::Args;
+use crate::movies::MovieSession;
+use crate::pacing::{frames_per_present, FramePacer};
+use crate::saves::SaveFiles;
+use crate::tile_viewer::TileViewer;
+
+/// The rate audio is played back at, if the audio device will accept it.
+const SAMPLE_RATE: i32 = 48_000;
+/// Once this many bytes of audio are waiting to be played, new samples are dropped rather than
+/// letting the sound fall further and further behind.
+const MAX_QUEUED_AUDIO: u32 = SAMPLE_RATE as u32 * 2 * 4 / 10;
+
+/// The keyboard controls.
+fn button_for_key(key: Keycode) -> Option<Button> {
+    match key {
+        Keycode::Right => Some(Button::Right),
+        Keycode::Left => Some(Button::Left),
+        Keycode::Up => Some(Button::Up),
+        Keycode::Down => Some(Button::Down),
+        Keycode::Z => Some(Button::A),
+        Keycode::X => Some(Button::B),
+        Keycode::Backspace | Keycode::RShift => Some(Button::Select),
+        Keycode::Return => Some(Button::Start),
+        _ => None,
+    }
+}
+
+/// The save state slot for one of the function keys: F1 is slot 1, up to F9.
+fn slot_for_key(key: Keycode) -> Option<u8> {
+    match key {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        Keycode::F9 => Some(9),
+        _ => None,
+    }
+}
+
+/// Saves a screenshot.
+const SCREENSHOT_KEY: Keycode = Keycode::F12;
+/// Starts or stops recording.
+const RECORD_KEY: Keycode = Keycode::F11;
+/// Switches to the next palette.
+const PALETTE_KEY: Keycode = Keycode::P;
+/// Opens or closes the tile viewer.
+const TILE_VIEWER_KEY: Keycode = Keycode::V;
+/// Switches the tile viewer to its next palette.
+const TILE_PALETTE_KEY: Keycode = Keycode::C;
+
+/// Held down to run faster than normal.
+const FAST_FORWARD_KEY: Scancode = Scancode::Tab;
+/// Held down to run slower than normal.
+const SLOW_MOTION_KEY: Scancode = Scancode::Grave;
+/// Held down to step back through the rewind buffer.
+const REWIND_KEY: Scancode = Scancode::R;
+
+/// Run the Game Boy in a window until it is closed, or until the number of frames asked for have
+/// been run. The function keys load save states from `saves`, or save them whilst Shift is held,
+/// F12 and F11 take screenshots and recordings with `capture`, and P cycles through `palettes`,
+/// starting from the one at `current`. V opens the tile viewer. Whilst `movie` is being recorded or
+/// played, states can't be loaded and rewinding is off.
+pub fn run(
+    gb: &mut GameBoy,
+    args: &Args,
+    saves: &SaveFiles,
+    capture: &mut Capture,
+    movie: &mut Option<MovieSession>,
+    palettes: &[(String, PaletteSet)],
+    mut current: usize,
+) -> Result<()> {
+    let sdl_context = sdl2::init().map_err(|e| anyhow!("Couldn't initialise SDL: {}", e))?;
+    let video_subsystem = sdl_context.video().map_err(|e| anyhow!("Couldn't initialise video: {}", e))?;
+
+    let title = format!("Patchwork DMG - {}", gb.cartridge().title());
+    let mut window_builder = video_subsystem.window(&title, SCREEN_WIDTH as u32 * args.scale, SCREEN_HEIGHT as u32 * args.scale);
+    window_builder.position_centered();
+    if args.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder.build()?;
+    let mut canvas_builder = window.into_canvas();
+    if args.vsync {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    let mut canvas = canvas_builder.build()?;
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_streaming(PixelFormatEnum::RGB24, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32)?;
+
+    let audio = if args.mute { None } else { open_audio(&sdl_context, gb) };
+    // Started once the sample rate is settled.
+    if let Some(path) = &args.record {
+        capture.start_recording(gb, path)?;
+    }
+    let mut tile_viewer = if args.tile_viewer { Some(TileViewer::new(&video_subsystem, args.scale)?) } else { None };
+    let mut event_pump = sdl_context.event_pump().map_err(|e| anyhow!(e))?;
+    let mut rewind = Rewind::with_duration(args.rewind_seconds, args.rewind_interval);
+    let mut pacer = FramePacer::new(Instant::now());
+    let mut frames = 0;
+
+    'running: loop {
+        for event in event_pump.poll_iter() {
+            if let Some(viewer) = &mut tile_viewer {
+                viewer.handle_event(&event);
+            }
+            match event {
+                Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => break 'running,
+                // With the tile viewer open, closing a window doesn't quit by itself.
+                Event::Window { window_id, win_event: WindowEvent::Close, .. } => {
+                    if tile_viewer.as_ref().map(TileViewer::window_id) == Some(window_id) {
+                        tile_viewer = None;
+                    } else {
+                        break 'running;
+                    }
+                }
+                Event::KeyDown { keycode: Some(key), keymod, repeat: false, .. } => {
+                    if let Some(button) = button_for_key(key) {
+                        gb.press(button);
+                    } else if key == SCREENSHOT_KEY {
+                        match capture.screenshot(gb) {
+                            Ok(path) => println!("Saved screenshot to {}", path.display()),
+                            Err(e) => eprintln!("{:#}", e),
+                        }
+                    } else if key == RECORD_KEY {
+                        if let Err(e) = capture.toggle_recording(gb) {
+                            eprintln!("{:#}", e);
+                        }
+                    } else if key == PALETTE_KEY {
+                        current = (current + 1) % palettes.len();
+                        capture.set_palettes(palettes[current].1);
+                        println!("Palette: {}", palettes[current].0);
+                    } else if key == TILE_VIEWER_KEY {
+                        tile_viewer = match tile_viewer {
+                            Some(_) => None,
+                            None => Some(TileViewer::new(&video_subsystem, args.scale)?),
+                        };
+                    } else if key == TILE_PALETTE_KEY {
+                        if let Some(viewer) = &mut tile_viewer {
+                            viewer.next_palette();
+                        }
+                    } else if let Some(slot) = slot_for_key(key) {
+                        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                        if !shift && movie.is_some() {
+                            eprintln!("States can't be loaded whilst a movie is being recorded or played");
+                            continue;
+                        }
+                        let result = if shift { saves.save_state(gb, slot) } else { saves.load_state(gb, slot) };
+                        match result {
+                            Ok(()) if shift => println!("Saved state to slot {}", slot),
+                            Ok(()) => {
+                                // The history leads up to a different point now.
+                                rewind.clear();
+                                println!("Loaded state from slot {}", slot);
+                            }
+                            Err(e) => eprintln!("{:#}", e),
+                        }
+                    }
+                }
+                Event::KeyUp { keycode: Some(key), .. } => {
+                    if let Some(button) = button_for_key(key) {
+                        gb.release(button);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let keyboard = event_pump.keyboard_state();
+        let speed = if keyboard.is_scancode_pressed(FAST_FORWARD_KEY) {
+            args.fast_forward
+        } else if keyboard.is_scancode_pressed(SLOW_MOTION_KEY) {
+            args.slow_motion
+        } else {
+            args.speed
+        };
+
+        // With vsync, presenting a frame waits for the display, so going faster than it means
+        // running more than one frame each time, and only drawing the last.
+        let mut cycles = 0;
+        let mut finished = false;
+        for _ in 0..frames_per_present(speed, args.vsync) {
+            // Whilst rewinding, the game goes back a frame at a time, showing each snapshot until
+            // the one before it is due. The framebuffer is part of the state, so loading one is
+            // enough to show it.
+            cycles += if let Some(cycles) = movie.as_mut().and_then(|movie| movie.run_frame(gb)) {
+                cycles
+            } else if keyboard.is_scancode_pressed(REWIND_KEY) && movie.is_none() {
+                rewind.rewind(gb);
+                CYCLES_PER_FRAME
+            } else {
+                let cycles = gb.run_frame();
+                if movie.is_none() {
+                    rewind.record(gb);
+                }
+                cycles
+            };
+
+            let samples = gb.take_audio_samples();
+            if let Err(e) = capture.frame(gb, &samples) {
+                eprintln!("{:#}", e);
+            }
+            if let Some(queue) = &audio {
+                if queue.size() < MAX_QUEUED_AUDIO {
+                    queue.queue_audio(&samples).map_err(|e| anyhow!(e))?;
+                }
+            }
+
+            frames += 1;
+            if Some(frames) == args.frames {
+                finished = true;
+                break;
+            }
+        }
+
+        texture.with_lock(None, |buffer, pitch| draw_frame(gb.framebuffer(), &palettes[current].1, buffer, pitch))
+            .map_err(|e| anyhow!(e))?;
+        canvas.clear();
+        canvas.copy(&texture, None, None).map_err(|e| anyhow!(e))?;
+        canvas.present();
+        if let Some(viewer) = &mut tile_viewer {
+            viewer.draw(gb, &palettes[current].1)?;
+        }
+
+        if finished {
+            break;
+        }
+        thread::sleep(pacer.advance(cycles, speed, Instant::now()));
+    }
+    Ok(())
+}
+
+/// Open the audio device, and have the Game Boy produce samples at whatever rate it runs at.
+/// Failing to open it isn't fatal; the game just runs without sound.
+fn open_audio(sdl_context: &sdl2::Sdl, gb: &mut GameBoy) -> Option<AudioQueue<f32>> {
+    let spec = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE),
+        channels: Some(2),
+        samples: Some(1024),
+    };
+    let queue = sdl_context.audio()
+        .and_then(|audio| audio.open_queue::<f32, _>(None, &spec));
+    match queue {
+        Ok(queue) => {
+            gb.set_sample_rate(queue.spec().freq as u32);
+            queue.resume();
+            Some(queue)
+        }
+        Err(e) => {
+            eprintln!("Couldn't open the audio device, so there will be no sound: {}", e);
+            None
+        }
+    }
+}
+
+/// Colour the framebuffer into an RGB24 texture.
+fn draw_frame(framebuffer: &[u8], palettes: &PaletteSet, buffer: &mut [u8], pitch: usize) {
+    for (y, row) in framebuffer.chunks(SCREEN_WIDTH).enumerate() {
+        for (x, &pixel) in row.iter().enumerate() {
+            let colour = palettes.colour(pixel);
+            let offset = y * pitch + x * 3;
+            buffer[offset..offset + 3].copy_from_slice(&[colour.r, colour.g, colour.b]);
+        }
+    }
+}
@@ -0,0 +1,430 @@
+use crate::components::cartridge::Cartridge;
+use crate::components::dmg_cpu::{CPU, JOYPAD_INTERRUPT};
+use crate::components::joypad::Button;
+use crate::components::dmg_ppu::{LcdMode, BGP_ADDR, OBP0_ADDR, OBP1_ADDR};
+use crate::components::serial::SerialLink;
+use crate::components::state::{Snapshot, StateError, StateReader, StateWriter, STATE_MAGIC, STATE_VERSION};
+use crate::tracer::Tracer;
+use anyhow::Result;
+
+/// The number of T-cycles the PPU takes to draw a whole frame: 154 lines of 456 dots.
+pub const CYCLES_PER_FRAME: u32 = 70224;
+/// The number of T-cycles in a second: the DMG's clock runs at 4.194304MHz.
+pub const CYCLES_PER_SECOND: u32 = 4_194_304;
+/// The rate at which the LCD refreshes, around 59.7275Hz.
+pub const FRAME_RATE: f64 = CYCLES_PER_SECOND as f64 / CYCLES_PER_FRAME as f64;
+
+/// # Game Boy
+/// The whole system: the CPU, and through its bus the cartridge, PPU, APU, timer, joypad and serial
+/// port. This is the one object a frontend or test needs to drive.
+///
+/// The CPU ticks every other component along with each M-cycle it spends, so stepping the CPU is
+/// all it takes to keep the whole system in step.
+pub struct GameBoy {
+    cpu: CPU,
+    /// The boot ROM, if one was given, so that a reset can run it again.
+    boot_rom: Option<Vec<u8>>,
+}
+
+impl GameBoy {
+    /// Create a Game Boy with the given cartridge inserted, starting from the state the boot ROM
+    /// leaves behind.
+    pub fn new(cartridge: Cartridge) -> Self {
+        let mut cpu = CPU::post_boot();
+        cpu.bus.cartridge = Some(cartridge);
+        GameBoy { cpu, boot_rom: None }
+    }
+
+    /// Create a Game Boy with the given cartridge inserted, which will start by running the boot
+    /// ROM.
+    pub fn with_boot_rom(cartridge: Cartridge, boot_rom: &[u8]) -> Result<Self> {
+        let mut cpu = CPU::with_boot_rom(boot_rom)?;
+        cpu.bus.cartridge = Some(cartridge);
+        Ok(GameBoy { cpu, boot_rom: Some(boot_rom.to_vec()) })
+    }
+
+    /// Run a single instruction (or interrupt dispatch), returning the number of T-cycles it took.
+    pub fn step(&mut self) -> u32 {
+        let start = self.cpu.cycles;
+        self.cpu.cycle();
+        (self.cpu.cycles - start) as u32
+    }
+
+    /// Run until the PPU has finished drawing a frame, returning the number of T-cycles it took.
+    /// Whilst the LCD is off no frames are drawn, so this instead stops after a frame's worth of
+    /// cycles.
+    pub fn run_frame(&mut self) -> u32 {
+        let mut cycles = 0;
+        loop {
+            cycles += self.step();
+            if self.cpu.bus.ppu.take_frame_ready() {
+                break;
+            }
+            if cycles >= CYCLES_PER_FRAME && !self.cpu.bus.ppu.lcd_enabled() {
+                break;
+            }
+        }
+        cycles
+    }
+
+    /// Power cycle the Game Boy, keeping the cartridge (and its RAM), whatever is plugged into the
+    /// link port, the audio sample rate, whether LY is stubbed and any tracer.
+    pub fn reset(&mut self) {
+        let mut cpu = match &self.boot_rom {
+            Some(rom) => CPU::with_boot_rom(rom).expect("boot ROM was already validated"),
+            None => CPU::post_boot(),
+        };
+        let mut cartridge = self.cpu.bus.cartridge.take();
+        if let Some(cartridge) = &mut cartridge {
+            cartridge.reset();
+        }
+        cpu.bus.cartridge = cartridge;
+        cpu.bus.serial.set_link(self.cpu.bus.serial.take_link());
+        cpu.bus.apu.set_sample_rate(self.cpu.bus.apu.sample_rate());
+        cpu.bus.stub_ly = self.cpu.bus.stub_ly;
+        cpu.tracer = self.cpu.tracer.take();
+        self.cpu = cpu;
+    }
+
+    /// Save the state of the whole machine. The state starts with a header giving the format
+    /// version and the global checksum of the ROM, so that it can't be loaded into the wrong game.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(STATE_MAGIC);
+        w.u16(STATE_VERSION);
+        w.u16(self.cartridge().global_checksum());
+        self.cpu.save_state(&mut w);
+        w.into_bytes()
+    }
+
+    /// Restore a state saved by `save_state`. If the state can't be loaded, the machine is left
+    /// exactly as it was.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data);
+        let mut magic = [0; STATE_MAGIC.len()];
+        r.bytes(&mut magic).map_err(|_| StateError::NotAState)?;
+        if &magic != STATE_MAGIC {
+            return Err(StateError::NotAState);
+        }
+        let version = r.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let expected = self.cartridge().global_checksum();
+        let found = r.u16()?;
+        if found != expected {
+            return Err(StateError::WrongRom { expected, found });
+        }
+
+        // Load into a fresh CPU, so that a bad state can't leave the machine half loaded. The
+        // cartridge has to move across, so keep its state in case it needs to be put back.
+        let mut cartridge = self.cpu.bus.cartridge.take();
+        let mut backup = StateWriter::new();
+        if let Some(cartridge) = &cartridge {
+            cartridge.save_state(&mut backup);
+        }
+        let mut cpu = CPU::new();
+        if let Some(rom) = &self.boot_rom {
+            cpu.bus.map_boot_rom(rom.clone());
+        }
+        cpu.bus.cartridge = cartridge;
+
+        match cpu.load_state(&mut r).and_then(|_| r.finish()) {
+            Ok(()) => {
+                cpu.bus.serial.set_link(self.cpu.bus.serial.take_link());
+                cpu.bus.apu.set_sample_rate(self.cpu.bus.apu.sample_rate());
+                cpu.bus.stub_ly = self.cpu.bus.stub_ly;
+                cpu.tracer = self.cpu.tracer.take();
+                self.cpu = cpu;
+                Ok(())
+            }
+            Err(e) => {
+                cartridge = cpu.bus.cartridge.take();
+                if let Some(cartridge) = &mut cartridge {
+                    let backup = backup.into_bytes();
+                    cartridge.load_state(&mut StateReader::new(&backup)).expect("the cartridge's own state can be restored");
+                }
+                self.cpu.bus.cartridge = cartridge;
+                Err(e)
+            }
+        }
+    }
+
+    /// The most recently completed frame, as 160x144 pixels row by row. The lower two bits of each
+    /// pixel are its shade, from 0 (lightest) to 3 (darkest); see `dmg_ppu::PIXEL_OBP0` and
+    /// `dmg_ppu::PIXEL_OBP1` for the rest.
+    pub fn framebuffer(&self) -> &[u8] {
+        self.cpu.bus.ppu.framebuffer()
+    }
+
+    /// Returns true if the LCD is switched on.
+    pub fn lcd_enabled(&self) -> bool {
+        self.cpu.bus.ppu.lcd_enabled()
+    }
+
+    /// The mode the PPU is currently in.
+    pub fn lcd_mode(&self) -> LcdMode {
+        self.cpu.bus.ppu.lcd_mode()
+    }
+
+    /// The scanline the PPU is currently drawing.
+    pub fn ly(&self) -> u8 {
+        self.cpu.bus.ppu.ly()
+    }
+
+    /// Video RAM, from $8000 to $9FFF: the tile data followed by the two tile maps. Unlike reads
+    /// from the CPU, this is available whatever the PPU is doing.
+    pub fn vram(&self) -> &[u8] {
+        self.cpu.bus.ppu.vram()
+    }
+
+    /// The palette registers BGP, OBP0 and OBP1, which map the colour numbers in tiles to shades.
+    pub fn palette_registers(&self) -> [u8; 3] {
+        let ppu = &self.cpu.bus.ppu;
+        [ppu.read(BGP_ADDR), ppu.read(OBP0_ADDR), ppu.read(OBP1_ADDR)]
+    }
+
+    /// Take the audio produced since this was last called, as interleaved left and right samples.
+    /// Audio which isn't taken doesn't pile up; only about the last second of it is kept.
+    pub fn take_audio_samples(&mut self) -> Vec<f32> {
+        self.cpu.bus.apu.take_samples()
+    }
+
+    /// Set the rate at which audio samples are produced.
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        self.cpu.bus.apu.set_sample_rate(rate);
+    }
+
+    /// The rate at which audio samples are produced.
+    pub fn sample_rate(&self) -> u32 {
+        self.cpu.bus.apu.sample_rate()
+    }
+
+    /// Press or release a button, requesting the joypad interrupt if the game is watching for it.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.cpu.bus.joypad.set_button(button, pressed) {
+            self.cpu.bus.request_interrupt(JOYPAD_INTERRUPT);
+        }
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.set_button(button, true);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.set_button(button, false);
+    }
+
+    /// Returns true whilst the given button is held down.
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.cpu.bus.joypad.is_pressed(button)
+    }
+
+    /// The cartridge that is inserted.
+    pub fn cartridge(&self) -> &Cartridge {
+        self.cpu.bus.cartridge.as_ref().expect("a Game Boy always has a cartridge")
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut Cartridge {
+        self.cpu.bus.cartridge.as_mut().expect("a Game Boy always has a cartridge")
+    }
+
+    /// The boot ROM the Game Boy starts with, if it was given one.
+    pub fn boot_rom(&self) -> Option<&[u8]> {
+        self.boot_rom.as_deref()
+    }
+
+    /// Plug a device into the link port, replacing whatever was connected before.
+    pub fn set_serial_link(&mut self, link: Box<dyn SerialLink + Send>) {
+        self.cpu.set_serial_link(link);
+    }
+
+    /// Make LY (0xFF44) always read as 0x90 to the CPU, as Gameboy Doctor's reference logs are made
+    /// that way. Without it, any game which waits for a particular line soon goes out of step with
+    /// them. The PPU itself carries on as normal.
+    pub fn set_stub_ly(&mut self, stub: bool) {
+        self.cpu.bus.stub_ly = stub;
+    }
+
+    /// Log every instruction from now on. See `Tracer`.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.cpu.set_tracer(tracer);
+    }
+
+    /// Stop tracing, handing back the tracer so it can be flushed or inspected.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.cpu.take_tracer()
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::dmg_cpu::{IE_ADDR, IF_ADDR};
+    use crate::components::dmg_ppu::LY_ADDR;
+    use crate::components::joypad::P1_ADDR;
+    use crate::components::serial::CaptureLink;
+
+    /// A 32KB ROM-only cartridge with the given code at the entry point.
+    fn cartridge(code: &[u8]) -> Cartridge {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + code.len()].copy_from_slice(code);
+        Cartridge::from_bytes(rom).unwrap()
+    }
+
+    #[test]
+    fn step() {
+        // NOP; LD A,0x42; JP 0x0150
+        let mut gb = GameBoy::new(cartridge(&[0x00, 0x3E, 0x42, 0xC3, 0x50, 0x01]));
+        assert_eq!(gb.step(), 4);
+        assert_eq!(gb.step(), 8);
+        assert_eq!(gb.step(), 16);
+        assert_eq!(gb.cpu().read_byte(0x0102), 0x42);
+    }
+
+    #[test]
+    fn run_frame() {
+        // JR -2, forever.
+        let mut gb = GameBoy::new(cartridge(&[0x18, 0xFE]));
+        // The LCD is on after booting, so frames are delivered by the PPU.
+        gb.run_frame();
+        gb.take_audio_samples();
+        let cycles = gb.run_frame();
+        assert!((CYCLES_PER_FRAME..CYCLES_PER_FRAME + 12).contains(&cycles));
+        assert_eq!(gb.framebuffer().len(), 160 * 144);
+        // Roughly a sixtieth of a second of stereo audio.
+        let samples = gb.take_audio_samples().len();
+        assert!((1600..1620).contains(&samples));
+
+        // With the LCD off, a frame's worth of cycles is run instead.
+        assert!(gb.lcd_enabled());
+        gb.cpu_mut().write_byte(0xFF40, 0x00);
+        assert!(!gb.lcd_enabled());
+        assert_eq!((gb.lcd_mode(), gb.ly()), (LcdMode::HBlank, 0));
+        let cycles = gb.run_frame();
+        assert!((CYCLES_PER_FRAME..CYCLES_PER_FRAME + 12).contains(&cycles));
+    }
+
+    #[test]
+    fn vram_and_palettes() {
+        let mut gb = GameBoy::new(cartridge(&[0x18, 0xFE]));
+        gb.cpu_mut().write_byte(0xFF40, 0x00);
+        gb.cpu_mut().write_byte(0x8010, 0xAB);
+        gb.cpu_mut().write_byte(BGP_ADDR, 0xE4);
+        gb.cpu_mut().write_byte(OBP1_ADDR, 0x1B);
+        assert_eq!(gb.vram().len(), 0x2000);
+        assert_eq!(gb.vram()[0x10], 0xAB);
+        assert_eq!(gb.palette_registers()[0], 0xE4);
+        assert_eq!(gb.palette_registers()[2], 0x1B);
+    }
+
+    #[test]
+    fn stub_ly() {
+        let mut gb = GameBoy::new(cartridge(&[0x18, 0xFE]));
+        // Frames end as line 0x90 starts, so go on to another line.
+        gb.run_frame();
+        while gb.ly() == 0x90 {
+            gb.step();
+        }
+        assert_eq!(gb.cpu().read_byte(LY_ADDR), gb.ly());
+        gb.set_stub_ly(true);
+        assert_eq!(gb.cpu().read_byte(LY_ADDR), 0x90);
+        // The PPU still knows where it really is.
+        assert_ne!(gb.ly(), 0x90);
+        // It stays stubbed across a reset and loading a state.
+        gb.load_state(&gb.save_state()).unwrap();
+        gb.reset();
+        assert_eq!(gb.cpu().read_byte(LY_ADDR), 0x90);
+        gb.set_stub_ly(false);
+        assert_eq!(gb.cpu().read_byte(LY_ADDR), gb.ly());
+    }
+
+    #[test]
+    fn joypad() {
+        let mut gb = GameBoy::new(cartridge(&[0x18, 0xFE]));
+        gb.cpu_mut().write_byte(IF_ADDR, 0);
+        gb.cpu_mut().write_byte(IE_ADDR, 0);
+        gb.cpu_mut().write_byte(P1_ADDR, 0x10);
+        gb.press(Button::Start);
+        assert_eq!(gb.cpu().read_byte(P1_ADDR) & 0x0F, 0b0111);
+        assert_eq!(gb.cpu().read_byte(IF_ADDR) & JOYPAD_INTERRUPT, JOYPAD_INTERRUPT);
+        gb.release(Button::Start);
+        assert_eq!(gb.cpu().read_byte(P1_ADDR) & 0x0F, 0b1111);
+    }
+
+    #[test]
+    fn save_state() {
+        // INC A; JR -3
+        let mut gb = GameBoy::new(cartridge(&[0x3C, 0x18, 0xFD]));
+        gb.run_frame();
+        let state = gb.save_state();
+        let a = gb.cpu().registers().a;
+        let framebuffer = gb.framebuffer().to_vec();
+        gb.run_frame();
+        assert_ne!(gb.cpu().registers().a, a);
+
+        gb.load_state(&state).unwrap();
+        assert_eq!(gb.cpu().registers().a, a);
+        assert_eq!(gb.framebuffer(), &framebuffer[..]);
+        // The same state saved again is identical, so nothing was missed.
+        assert_eq!(gb.save_state(), state);
+        // And it carries on exactly as it would have.
+        gb.run_frame();
+        let mut other = GameBoy::new(cartridge(&[0x3C, 0x18, 0xFD]));
+        other.load_state(&state).unwrap();
+        other.run_frame();
+        assert_eq!(gb.save_state(), other.save_state());
+    }
+
+    #[test]
+    fn bad_states() {
+        let mut gb = GameBoy::new(cartridge(&[0x3C, 0x18, 0xFD]));
+        let state = gb.save_state();
+        gb.run_frame();
+        let before = gb.save_state();
+
+        assert!(matches!(gb.load_state(b"nonsense"), Err(StateError::NotAState)));
+        let mut old = state.clone();
+        old[STATE_MAGIC.len()] = 0;
+        assert!(matches!(gb.load_state(&old), Err(StateError::UnsupportedVersion(0))));
+        assert!(matches!(gb.load_state(&state[..state.len() - 1]), Err(StateError::Truncated)));
+        // A failed load leaves everything as it was.
+        assert_eq!(gb.save_state(), before);
+
+        let mut rom = vec![0; 0x8000];
+        rom[0x014E] = 0x12;
+        let mut wrong = GameBoy::new(Cartridge::from_bytes(rom).unwrap());
+        assert!(matches!(wrong.load_state(&state), Err(StateError::WrongRom { expected: 0x1200, found: 0 })));
+    }
+
+    #[test]
+    fn reset() {
+        // LD A,0x42; LD (0xC000),A; LD A,0x01; LD (0xFF01),A; LD A,0x81; LD (0xFF02),A
+        let code = [0x3E, 0x42, 0xEA, 0x00, 0xC0, 0x3E, 0x01, 0xEA, 0x01, 0xFF, 0x3E, 0x81, 0xEA, 0x02, 0xFF];
+        let mut gb = GameBoy::new(cartridge(&code));
+        let link = CaptureLink::new();
+        gb.set_serial_link(Box::new(link.clone()));
+        gb.set_sample_rate(44_100);
+        // Run long enough for the serial transfer to finish.
+        for _ in 0..1100 {
+            gb.step();
+        }
+        assert_eq!(gb.cpu().read_byte(0xC000), 0x42);
+        gb.reset();
+        assert_eq!(gb.cpu().read_byte(0xC000), 0x00);
+        assert_eq!(gb.sample_rate(), 44_100);
+        // The link port survives the reset.
+        for _ in 0..1100 {
+            gb.step();
+        }
+        assert_eq!(link.output(), vec![0x01, 0x01]);
+    }
+}
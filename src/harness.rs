@@ -0,0 +1,245 @@
+use crate::components::cartridge::Cartridge;
+use crate::components::register::RegisterFile;
+use crate::components::serial::CaptureLink;
+use crate::gameboy::{GameBoy, CYCLES_PER_SECOND};
+use crate::palette::PaletteSet;
+use crate::screenshot::{reference_palette, Image, ImageDiff};
+
+/// How long a test ROM is given by default: long enough for the whole of Blargg's cpu_instrs.
+pub const DEFAULT_TIMEOUT_SECONDS: u64 = 120;
+/// How many frames a ROM judged by a screenshot is run for by default. dmg-acid2 has finished
+/// drawing well within this.
+pub const DEFAULT_SCREENSHOT_FRAMES: u32 = 120;
+
+/// How a test ROM finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestResult {
+    Passed,
+    Failed,
+    /// The ROM ran out of time without reporting a result.
+    TimedOut,
+    /// The CPU hung on an illegal opcode.
+    Locked,
+}
+
+/// The result of a test ROM, along with what it printed.
+#[derive(Debug, Clone)]
+pub struct TestOutcome {
+    pub result: TestResult,
+    /// Everything the ROM sent over the serial port.
+    pub serial: String,
+    /// The number of T-cycles run.
+    pub cycles: u64,
+}
+
+/// The result of a test ROM judged by a screenshot.
+#[derive(Debug, Clone)]
+pub struct ScreenshotOutcome {
+    /// Passed if the screenshot matched the reference, and Failed if it didn't.
+    pub result: TestResult,
+    /// The screen, coloured with `reference_palette`.
+    pub screenshot: Image,
+    /// How the screenshot differed from the reference, if it did.
+    pub diff: Option<ImageDiff>,
+}
+
+/// # Test runner
+/// Runs a test ROM without any video or sound until it reports a result or runs out of time.
+///
+/// Two ways of reporting are understood, and checked for once per frame:
+/// - Blargg's tests print "Passed" or "Failed" over the serial port.
+/// - Mooneye's tests execute LD B,B once finished, having loaded B, C, D, E, H and L with the
+///   Fibonacci numbers 3, 5, 8, 13, 21 and 34 if they passed.
+///
+/// Tests of what is drawn, such as dmg-acid2, are instead run for a number of frames, after which
+/// the screen is compared against a reference image.
+pub struct TestRunner {
+    gb: GameBoy,
+    serial: CaptureLink,
+    max_cycles: u64,
+}
+
+impl TestRunner {
+    pub fn new(cartridge: Cartridge) -> Self {
+        let mut gb = GameBoy::new(cartridge);
+        let serial = CaptureLink::new();
+        gb.set_serial_link(Box::new(serial.clone()));
+        TestRunner {
+            gb,
+            serial,
+            max_cycles: DEFAULT_TIMEOUT_SECONDS * CYCLES_PER_SECOND as u64,
+        }
+    }
+
+    /// Give up on the ROM after the given number of seconds of emulated time.
+    pub fn with_timeout(mut self, seconds: u64) -> Self {
+        self.max_cycles = seconds * CYCLES_PER_SECOND as u64;
+        self
+    }
+
+    /// The Game Boy the ROM is running on, to look at once it has finished.
+    pub fn gameboy(&self) -> &GameBoy {
+        &self.gb
+    }
+
+    /// Run the ROM until it reports a result or runs out of time.
+    pub fn run(&mut self) -> TestOutcome {
+        let mut cycles = 0;
+        let result = loop {
+            cycles += self.gb.run_frame() as u64;
+            if self.gb.cpu_mut().take_ld_b_b() {
+                break fibonacci_result(self.gb.cpu().registers());
+            }
+            if let Some(result) = serial_result(&self.serial.output_string()) {
+                break result;
+            }
+            if self.gb.cpu().is_locked() {
+                break TestResult::Locked;
+            }
+            if cycles >= self.max_cycles {
+                break TestResult::TimedOut;
+            }
+        };
+        TestOutcome { result, serial: self.serial.output_string(), cycles }
+    }
+
+    /// Run for the given number of frames, then compare the screen against a reference image taken
+    /// in `reference_palette`.
+    pub fn run_screenshot(&mut self, frames: u32, reference: &Image) -> ScreenshotOutcome {
+        self.run_frames(frames);
+        let screenshot = self.screenshot();
+        let diff = screenshot.diff(reference);
+        let result = if self.gb.cpu().is_locked() {
+            TestResult::Locked
+        } else if diff.is_some() {
+            TestResult::Failed
+        } else {
+            TestResult::Passed
+        };
+        ScreenshotOutcome { result, screenshot, diff }
+    }
+
+    /// Run for the given number of frames, stopping early if the CPU locks up.
+    pub fn run_frames(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.gb.run_frame();
+            if self.gb.cpu().is_locked() {
+                break;
+            }
+        }
+    }
+
+    /// The screen as it is now, coloured with `reference_palette`.
+    pub fn screenshot(&self) -> Image {
+        Image::from_framebuffer(self.gb.framebuffer(), &PaletteSet::uniform(reference_palette()))
+    }
+}
+
+/// Look for Blargg's verdict in the serial output.
+fn serial_result(output: &str) -> Option<TestResult> {
+    if output.contains("Passed") {
+        Some(TestResult::Passed)
+    } else if output.contains("Failed") {
+        Some(TestResult::Failed)
+    } else {
+        None
+    }
+}
+
+/// Check the registers for Mooneye's pass signal, once LD B,B has been executed.
+fn fibonacci_result(regs: &RegisterFile) -> TestResult {
+    let values = [
+        regs.bc.get_high(), regs.bc.get_low(),
+        regs.de.get_high(), regs.de.get_low(),
+        regs.hl.get_high(), regs.hl.get_low(),
+    ];
+    if values == [3, 5, 8, 13, 21, 34] {
+        TestResult::Passed
+    } else {
+        TestResult::Failed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gameboy::CYCLES_PER_FRAME;
+
+    /// A ROM which prints the given text over the serial port and then loops forever.
+    fn printing_rom(text: &str) -> Cartridge {
+        let mut code = vec![0x21, 0x00, 0x02]; // LD HL,0x0200
+        code.extend_from_slice(&[
+            0x2A,             // LD A,(HL+)
+            0xB7,             // OR A
+            0x28, 0x0D,       // JR Z,done
+            0xE0, 0x01,       // LDH (SB),A
+            0x3E, 0x81,       // LD A,0x81
+            0xE0, 0x02,       // LDH (SC),A
+            0xF0, 0x02,       // wait: LDH A,(SC)
+            0x17,             // RLA
+            0x38, 0xFB,       // JR C,wait
+            0x18, 0xEF,       // JR loop (back to LD A,(HL+))
+            0x18, 0xFE,       // done: JR done
+        ]);
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + code.len()].copy_from_slice(&code);
+        rom[0x0200..0x0200 + text.len()].copy_from_slice(text.as_bytes());
+        Cartridge::from_bytes(rom).unwrap()
+    }
+
+    #[test]
+    fn serial_verdicts() {
+        let outcome = TestRunner::new(printing_rom("cpu_instrs\n\nPassed\n")).run();
+        assert_eq!(outcome.result, TestResult::Passed);
+        assert_eq!(outcome.serial, "cpu_instrs\n\nPassed\n");
+
+        let outcome = TestRunner::new(printing_rom("Failed #2\n")).run();
+        assert_eq!(outcome.result, TestResult::Failed);
+    }
+
+    /// A ROM which loads the given values into B, C, D, E, H and L, runs LD B,B and loops forever.
+    fn mooneye_rom(values: [u8; 6]) -> Cartridge {
+        let mut code = Vec::new();
+        // LD B,n; LD C,n; LD D,n; LD E,n; LD H,n; LD L,n
+        for (&opcode, &value) in [0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E].iter().zip(values.iter()) {
+            code.extend_from_slice(&[opcode, value]);
+        }
+        code.extend_from_slice(&[0x40, 0x18, 0xFE]); // LD B,B; JR -2
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + code.len()].copy_from_slice(&code);
+        Cartridge::from_bytes(rom).unwrap()
+    }
+
+    #[test]
+    fn fibonacci_verdicts() {
+        let outcome = TestRunner::new(mooneye_rom([3, 5, 8, 13, 21, 34])).run();
+        assert_eq!(outcome.result, TestResult::Passed);
+        assert!(outcome.cycles < 2 * CYCLES_PER_FRAME as u64);
+
+        let outcome = TestRunner::new(mooneye_rom([0x42; 6])).run();
+        assert_eq!(outcome.result, TestResult::Failed);
+    }
+
+    #[test]
+    fn screenshots() {
+        // The post-boot LCD setup shows the all-zero tile map in BGP 0xFC: everything is white.
+        let white = Image { width: 160, height: 144, rgb: vec![0xFF; 160 * 144 * 3] };
+        let mut runner = TestRunner::new(mooneye_rom([0; 6]));
+        let outcome = runner.run_screenshot(2, &white);
+        assert_eq!(outcome.result, TestResult::Passed);
+        assert!(outcome.diff.is_none());
+
+        let mut black_corner = white.clone();
+        black_corner.rgb[..3].copy_from_slice(&[0, 0, 0]);
+        let outcome = runner.run_screenshot(1, &black_corner);
+        assert_eq!(outcome.result, TestResult::Failed);
+        assert_eq!(outcome.diff.unwrap().differing, 1);
+    }
+
+    #[test]
+    fn timeout() {
+        let outcome = TestRunner::new(printing_rom("Still going")).with_timeout(1).run();
+        assert_eq!(outcome.result, TestResult::TimedOut);
+        assert!(outcome.cycles >= CYCLES_PER_SECOND as u64);
+    }
+}
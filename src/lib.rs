@@ -1 +1,82 @@
-pub mod components;
\ No newline at end of file
+//! # Patchwork DMG
+//! A Game Boy (DMG) emulator. The whole system is driven through [`GameBoy`], which owns the CPU
+//! and, through the CPU's memory bus, every other component.
+//!
+//! ```no_run
+//! use patchwork_dmg::{Button, Cartridge, GameBoy};
+//!
+//! let cartridge = Cartridge::from_file("tetris.gb").unwrap();
+//! let mut gb = GameBoy::new(cartridge);
+//! gb.press(Button::Start);
+//! gb.run_frame();
+//! let pixels = gb.framebuffer();
+//! ```
+//!
+//! The components themselves are internal. What frontends and tools need from them is re-exported
+//! here, grouped by what it is for.
+
+mod components;
+mod debugger;
+mod disassembler;
+mod gameboy;
+mod harness;
+mod movie;
+mod palette;
+mod recorder;
+mod rewind;
+mod screenshot;
+mod tracer;
+
+pub use crate::components::cartridge::{Cartridge, CartridgeError};
+pub use crate::components::joypad::Button;
+pub use crate::components::state::StateError;
+pub use crate::gameboy::{GameBoy, CYCLES_PER_FRAME, CYCLES_PER_SECOND, FRAME_RATE};
+pub use crate::movie::{Movie, MovieError, MoviePlayer};
+pub use crate::rewind::Rewind;
+
+/// The CPU, its registers and instruction set, for debuggers and other tools which need to look
+/// inside the running system.
+pub mod cpu {
+    pub use crate::components::dmg_cpu::{
+        BootRomError, OpcodeError, BOOT_ROM_SIZE, CPU, IE_ADDR, IF_ADDR, JOYPAD_INTERRUPT,
+        SERIAL_INTERRUPT, STAT_INTERRUPT, TIMER_INTERRUPT, VBLANK_INTERRUPT,
+    };
+    pub use crate::components::opcodes::{lookup, Condition, Instruction, Mnemonic, Operand};
+    pub use crate::components::register::{Flags, Reg16, Reg8, RegPair, RegisterFile};
+}
+
+/// Stepping through code with breakpoints and watchpoints, disassembling it, and tracing it.
+pub mod debug {
+    pub use crate::debugger::{
+        parse_number, BreakCondition, Breakpoint, Comparison, Debugger, DebuggerError, Register,
+        StopReason, WatchHit, WatchKind, Watchpoint,
+    };
+    pub use crate::disassembler::{disassemble, io_register_name, Disassembly};
+    pub use crate::tracer::Tracer;
+}
+
+/// Running test ROMs without a window, for automated testing.
+pub mod testing {
+    pub use crate::harness::{
+        ScreenshotOutcome, TestOutcome, TestResult, TestRunner, DEFAULT_SCREENSHOT_FRAMES,
+        DEFAULT_TIMEOUT_SECONDS,
+    };
+}
+
+/// The format of the framebuffer, the palettes it is drawn in, screenshots and recordings, and the
+/// SDL helpers for drawing tiles.
+pub mod video {
+    pub use crate::palette::{PaletteError, PaletteSet};
+    pub use crate::recorder::{Recorder, RecordingError};
+    pub use crate::screenshot::{reference_palette, Image, ImageDiff, ImageError};
+    pub use crate::components::dmg_ppu::{
+        LcdMode, PIXEL_OBP0, PIXEL_OBP1, PIXEL_SHADE_MASK, SCREEN_HEIGHT, SCREEN_WIDTH,
+    };
+    pub use crate::components::graphics_components::{GBPalette, Tile};
+}
+
+/// Devices which can be plugged into the link port.
+pub mod link {
+    pub use crate::components::link::SocketLink;
+    pub use crate::components::serial::{CaptureLink, SerialLink};
+}
@@ -1,81 +1,144 @@
-mod components;
+mod capture;
+mod cli;
+mod commands;
+mod frontend;
+mod movies;
+mod pacing;
+mod repl;
+mod saves;
+mod tile_viewer;
+mod trace_diff;
 
-#[macro_use]
-extern crate derive_builder;
+use std::fs::{self, File};
+use std::path::Path;
+use anyhow::{Context, Result};
+use clap::Parser;
+use patchwork_dmg::debug::Tracer;
+use patchwork_dmg::link::SocketLink;
+use patchwork_dmg::{Cartridge, GameBoy};
+use crate::capture::Capture;
+use crate::cli::{Args, LinkAddress};
+use crate::movies::MovieSession;
+use crate::saves::SaveFiles;
 
-use derive_builder::Builder;
-
-use sdl2::pixels::Palette;
-use sdl2::pixels::Color;
-use sdl2::event::Event;
-use sdl2::rect::Point;
-use sdl2::keyboard::Keycode;
-use sdl2::surface::Surface;
-use sdl2::video::{Window, WindowContext};
-use std::time::Duration;
-use ux::{i2, u2};
-use sdl2::render::{Texture, Canvas, TextureCreator, WindowCanvas};
-use sdl2::Sdl;
-use patchwork_dmg::components::graphics_components::{GBPalette, Tile};
-
-fn main() {
-    let scale = 6;
-    let framerate = 75;
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-
-    let window = video_subsystem.window("Patchwork DMG",  160 * scale, 144 * scale)
-        .position_centered()
-        .build()
-        .unwrap();
-
-    let mut canvas = window.into_canvas().build().unwrap();
-    canvas.set_scale(scale as f32, scale as f32).unwrap();
+fn main() -> Result<()> {
+    let args = Args::parse();
+    if let Some(command) = &args.command {
+        return commands::run(command);
+    }
+    let mut gb = load(&args)?;
+    let saves = SaveFiles::new(args.rom(), args.save_dir.as_deref());
+    let mut movie = MovieSession::start(&args, &mut gb)?;
+    if movie.is_none() {
+        saves.load_battery(&mut gb)?;
+    }
+    if let Some(path) = &args.trace {
+        gb.set_tracer(tracer(path, &args)?);
+        gb.set_stub_ly(args.trace_stub_ly);
+    }
+    if let Some(mut link) = link(&args)? {
+        link.set_timeout(args.link_timeout);
+        gb.set_serial_link(Box::new(link));
+    }
 
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    canvas.clear();
-    canvas.present();
-    let mut event_pump = sdl_context.event_pump().unwrap();
-    let mut i = 0;
+    let (palettes, current) = args.palettes()?;
+    let mut capture = Capture::new(&args, palettes[current].1);
 
-    // Creating a test palette.
-    // let mut pal = Palette::with_colors(&[C1, C2, C3, C4]).unwrap();
-    let mut pal = GBPalette::new(C1, C2, C3, C4);
-    let mut pal2 = GBPalette::new(C1, C7, C6, C5);
-    let mut tile = Tile::new(&pal, [0x3C, 0x7E, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7E, 0x5E, 0x7E, 0x0A, 0x7C, 0x56, 0x38, 0x7C]);
-    let mut tile2 = Tile::new(&pal2, [0x3C, 0x7E, 0x42, 0x42, 0x42, 0x42, 0x42, 0x42, 0x7E, 0x5E, 0x7E, 0x0A, 0x7C, 0x56, 0x38, 0x7C]);
-    let mut tile3 = Tile::new(&pal, [0xFF, 0x00, 0x7E, 0xFF, 0x85, 0x81, 0x89, 0x83, 0x93, 0x85, 0xA5, 0x8B, 0xC9, 0x97, 0x7E, 0xFF]);
-    tile.paint(Point::new(50, 50), &mut canvas);
-    'running: loop {
-        i = (i + 1) % (160 as i32);
-        tile.paint(Point::new(0 + i, 50), &mut canvas);
-        tile2.paint(Point::new(0 + i, i), &mut canvas);
-        for i in 0..21 {
-            tile3.paint(Point::new(8 * i, 0), &mut canvas);
+    if args.headless {
+        if let Some(path) = &args.record {
+            capture.start_recording(&gb, path)?;
         }
-        canvas.set_draw_color(Color::RGB(255, 255, 255));
-        for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit {..} |
-                Event::KeyDown { keycode: Some(Keycode::Escape), .. }  => {
-                    break 'running
-                },
-                _ => {}
+        // --headless requires --frames, so there is always a count here.
+        for _ in 0..args.frames.unwrap_or(0) {
+            match &mut movie {
+                Some(movie) => {
+                    if movie.run_frame(&mut gb).is_none() {
+                        break;
+                    }
+                }
+                None => {
+                    gb.run_frame();
+                }
+            }
+            if capture.is_recording() {
+                let samples = gb.take_audio_samples();
+                capture.frame(&gb, &samples)?;
             }
         }
+    } else if args.debug {
+        repl::run(&mut gb)?;
+    } else {
+        frontend::run(&mut gb, &args, &saves, &mut capture, &mut movie, &palettes, current)?;
+    }
+
+    if let Some(path) = capture.stop_recording()? {
+        println!("Saved recording to {}", path.display());
+    }
+    if let Some(path) = &args.screenshot {
+        capture.save_screenshot(&gb, path)?;
+    }
+
+    if let Some(mut tracer) = gb.take_tracer() {
+        if let Some(e) = tracer.error() {
+            eprintln!("Tracing stopped early, as writing the trace failed: {}", e);
+        }
+        tracer.flush().context("Couldn't write the trace")?;
+    }
+    match movie {
+        Some(movie) => movie.finish(),
+        None => saves.write_battery(&gb),
+    }
+}
 
-        canvas.present();
-        std::thread::sleep(Duration::new(0, 1_000_000_000u32 / framerate));
-        canvas.clear();
+/// Create the tracer asked for by the --trace options.
+fn tracer(path: &Path, args: &Args) -> Result<Tracer> {
+    let file = File::create(path).with_context(|| format!("Couldn't create trace file {}", path.display()))?;
+    let mut tracer = Tracer::new(file).skip(args.trace_skip);
+    if let Some(range) = &args.trace_range {
+        tracer = tracer.address_range(range.clone());
+    }
+    if let Some(limit) = args.trace_limit {
+        tracer = tracer.limit(limit);
     }
+    Ok(tracer)
+}
 
-    println!("Hello, world!");
+/// Plug in the link cable asked for by --link-listen or --link-connect, if either was given.
+fn link(args: &Args) -> Result<Option<SocketLink>> {
+    if let Some(address) = &args.link_listen {
+        println!("Waiting for the other emulator to connect to {}...", address);
+        let link = match address {
+            LinkAddress::Tcp(addr) => SocketLink::listen_tcp(addr.as_str()),
+            #[cfg(unix)]
+            LinkAddress::Unix(path) => SocketLink::listen_unix(path),
+        };
+        let link = link.with_context(|| format!("Couldn't listen for a link cable on {}", address))?;
+        println!("Link cable connected");
+        return Ok(Some(link));
+    }
+    if let Some(address) = &args.link_connect {
+        let link = match address {
+            LinkAddress::Tcp(addr) => SocketLink::connect_tcp(addr.as_str()),
+            #[cfg(unix)]
+            LinkAddress::Unix(path) => SocketLink::connect_unix(path),
+        };
+        let link = link.with_context(|| format!("Couldn't connect a link cable to {}", address))?;
+        return Ok(Some(link));
+    }
+    Ok(None)
 }
 
-const C1: Color = Color::RGB(255, 255, 255);
-const C2: Color = Color::RGB(190, 190, 190);
-const C3: Color = Color::RGB(130, 130, 130);
-const C4: Color = Color::RGB(82, 82, 82);
-const C5: Color = Color::RGB(181, 170, 140);
-const C6: Color = Color::RGB(130, 130, 130);
-const C7: Color = Color::RGB(255, 0, 0);
\ No newline at end of file
+/// Load the cartridge, and the boot ROM if one was given.
+fn load(args: &Args) -> Result<GameBoy> {
+    let cartridge = Cartridge::from_file(args.rom())?;
+    if !cartridge.header_checksum_valid() {
+        eprintln!("Warning: the header checksum of {} is wrong, so a real Game Boy would refuse to run it", args.rom().display());
+    }
+    match &args.boot_rom {
+        Some(path) => {
+            let boot_rom = fs::read(path).with_context(|| format!("Couldn't read boot ROM {}", path.display()))?;
+            GameBoy::with_boot_rom(cartridge, &boot_rom).with_context(|| format!("Couldn't use boot ROM {}", path.display()))
+        }
+        None => Ok(GameBoy::new(cartridge)),
+    }
+}
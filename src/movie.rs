@@ -0,0 +1,433 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+use crate::components::joypad::Button;
+use crate::components::state::{StateError, StateReader, StateWriter};
+use crate::gameboy::GameBoy;
+
+/// Every movie file starts with these bytes.
+const MOVIE_MAGIC: &[u8; 8] = b"PWDMGMOV";
+/// The version of the movie file format.
+const MOVIE_VERSION: u16 = 2;
+/// Work RAM, which is checked along with the screen to catch desyncs before they are visible.
+const WRAM: std::ops::RangeInclusive<u16> = 0xC000..=0xDFFF;
+
+#[derive(Debug, Error)]
+pub enum MovieError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("This isn't a movie file")]
+    NotAMovie,
+    #[error("Movie version {0} isn't supported; only version {} can be played", MOVIE_VERSION)]
+    UnsupportedVersion(u16),
+    #[error("The movie file is corrupt: {0}")]
+    Corrupt(#[from] StateError),
+    #[error("The movie was recorded with {title} (global checksum {found:#06x}), but this ROM's checksum is {expected:#06x}")]
+    WrongRom { title: String, expected: u16, found: u16 },
+    #[error("The movie was recorded {}, but the Game Boy is running {}", boot_rom_name(.recorded), boot_rom_name(.running))]
+    BootRomMismatch { recorded: Option<u32>, running: Option<u32> },
+    #[error("Couldn't load the save state the movie starts from: {0}")]
+    StartState(StateError),
+}
+
+/// The input and the resulting state for a single frame of a movie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    /// One bit per button held, in the order of `Button::ALL`.
+    buttons: u8,
+    /// A checksum of the screen and work RAM at the end of the frame.
+    checksum: u32,
+}
+
+/// # Movie
+/// The joypad input for every frame from some starting point, so that a run can be played back
+/// exactly. A movie starts either from power-on, with empty cartridge RAM, or from a save state
+/// kept in the movie. The header records the ROM, the boot ROM if there was one, and the version of
+/// the emulator it was recorded with, and every frame records a checksum of the state it should
+/// have led to, so that playback can tell when it has gone differently to the recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    emulator_version: String,
+    rom_title: String,
+    rom_checksum: u16,
+    /// A hash of the boot ROM, for movies recorded with one.
+    boot_rom: Option<u32>,
+    start_state: Option<Vec<u8>>,
+    frames: Vec<Frame>,
+}
+
+impl Movie {
+    /// Put the Game Boy at the start of a new movie, which starts from the given save state or,
+    /// without one, from power-on. Frames are then added with `record_frame`.
+    pub fn record(gb: &mut GameBoy, start_state: Option<Vec<u8>>) -> Result<Self, MovieError> {
+        let movie = Movie {
+            emulator_version: env!("CARGO_PKG_VERSION").to_string(),
+            rom_title: gb.cartridge().title().to_string(),
+            rom_checksum: gb.cartridge().global_checksum(),
+            boot_rom: boot_rom_hash(gb),
+            start_state,
+            frames: Vec::new(),
+        };
+        movie.start(gb)?;
+        Ok(movie)
+    }
+
+    /// The version of the emulator the movie was recorded with.
+    pub fn emulator_version(&self) -> &str {
+        &self.emulator_version
+    }
+
+    pub fn rom_title(&self) -> &str {
+        &self.rom_title
+    }
+
+    /// The number of frames in the movie.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Returns true if the movie starts from a save state rather than power-on.
+    pub fn starts_from_state(&self) -> bool {
+        self.start_state.is_some()
+    }
+
+    /// Put the Game Boy where the movie starts, after checking it is running the right ROM and boot
+    /// ROM.
+    fn start(&self, gb: &mut GameBoy) -> Result<(), MovieError> {
+        let expected = gb.cartridge().global_checksum();
+        if self.rom_checksum != expected {
+            return Err(MovieError::WrongRom { title: self.rom_title.clone(), expected, found: self.rom_checksum });
+        }
+        let running = boot_rom_hash(gb);
+        if self.boot_rom != running {
+            return Err(MovieError::BootRomMismatch { recorded: self.boot_rom, running });
+        }
+        match &self.start_state {
+            Some(state) => gb.load_state(state).map_err(MovieError::StartState)?,
+            None => {
+                gb.reset();
+                let empty = vec![0; gb.cartridge().ram().len()];
+                gb.cartridge_mut().load_ram(&empty).expect("the RAM is the cartridge's own size");
+            }
+        }
+        for button in Button::ALL {
+            gb.release(button);
+        }
+        Ok(())
+    }
+
+    /// Add the frame just run: the buttons held during it, and the state it led to.
+    pub fn record_frame(&mut self, gb: &GameBoy) {
+        let buttons = Button::ALL.iter().enumerate()
+            .filter(|&(_, &button)| gb.is_pressed(button))
+            .fold(0, |buttons, (i, _)| buttons | 1 << i);
+        self.frames.push(Frame { buttons, checksum: checksum(gb) });
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+        w.bytes(MOVIE_MAGIC);
+        w.u16(MOVIE_VERSION);
+        write_string(&mut w, &self.emulator_version);
+        write_string(&mut w, &self.rom_title);
+        w.u16(self.rom_checksum);
+        w.bool(self.boot_rom.is_some());
+        w.u32(self.boot_rom.unwrap_or(0));
+        w.bool(self.start_state.is_some());
+        if let Some(state) = &self.start_state {
+            w.u32(state.len() as u32);
+            w.bytes(state);
+        }
+        w.u32(self.frames.len() as u32);
+        for frame in &self.frames {
+            w.u8(frame.buttons);
+            w.u32(frame.checksum);
+        }
+        w.into_bytes()
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, MovieError> {
+        let mut r = StateReader::new(data);
+        let mut magic = [0; MOVIE_MAGIC.len()];
+        r.bytes(&mut magic).map_err(|_| MovieError::NotAMovie)?;
+        if &magic != MOVIE_MAGIC {
+            return Err(MovieError::NotAMovie);
+        }
+        let version = r.u16()?;
+        if version != MOVIE_VERSION {
+            return Err(MovieError::UnsupportedVersion(version));
+        }
+        let emulator_version = read_string(&mut r)?;
+        let rom_title = read_string(&mut r)?;
+        let rom_checksum = r.u16()?;
+        let has_boot_rom = r.bool()?;
+        let boot_rom = Some(r.u32()?).filter(|_| has_boot_rom);
+        let start_state = if r.bool()? {
+            let len = r.u32()? as usize;
+            Some(read_bytes(&mut r, len)?)
+        } else {
+            None
+        };
+        let count = r.u32()? as usize;
+        if count > r.remaining() / 5 {
+            return Err(StateError::Truncated.into());
+        }
+        let frames = (0..count)
+            .map(|_| Ok(Frame { buttons: r.u8()?, checksum: r.u32()? }))
+            .collect::<Result<_, StateError>>()?;
+        r.finish()?;
+        Ok(Movie { emulator_version, rom_title, rom_checksum, boot_rom, start_state, frames })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, MovieError> {
+        Movie::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), MovieError> {
+        Ok(fs::write(path, self.to_bytes())?)
+    }
+}
+
+fn read_bytes(r: &mut StateReader, len: usize) -> Result<Vec<u8>, StateError> {
+    if len > r.remaining() {
+        return Err(StateError::Truncated);
+    }
+    let mut buf = vec![0; len];
+    r.bytes(&mut buf)?;
+    Ok(buf)
+}
+
+fn write_string(w: &mut StateWriter, s: &str) {
+    w.u16(s.len() as u16);
+    w.bytes(s.as_bytes());
+}
+
+fn read_string(r: &mut StateReader) -> Result<String, StateError> {
+    let len = r.u16()? as usize;
+    String::from_utf8(read_bytes(r, len)?).map_err(|_| StateError::Corrupt("invalid text"))
+}
+
+/// A 32-bit FNV-1a hash. This is part of the file format, so it has to stay the same from one build
+/// to the next.
+fn fnv1a(bytes: impl Iterator<Item = u8>) -> u32 {
+    bytes.fold(0x811C_9DC5, |hash, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// A hash of the screen and work RAM.
+fn checksum(gb: &GameBoy) -> u32 {
+    let wram = WRAM.map(|addr| gb.cpu().read_byte(addr));
+    fnv1a(gb.framebuffer().iter().copied().chain(wram))
+}
+
+fn boot_rom_hash(gb: &GameBoy) -> Option<u32> {
+    gb.boot_rom().map(|rom| fnv1a(rom.iter().copied()))
+}
+
+fn boot_rom_name(hash: &Option<u32>) -> String {
+    match hash {
+        Some(hash) => format!("with a boot ROM (hash {:08x})", hash),
+        None => "without a boot ROM".to_string(),
+    }
+}
+
+/// # Movie player
+/// Plays a movie back, pressing and releasing buttons as they were when it was recorded, and
+/// checking every frame against the recording.
+pub struct MoviePlayer {
+    movie: Movie,
+    /// The number of frames played so far.
+    frame: usize,
+    /// The first frame (counting from 0) which didn't match the recording.
+    desync: Option<usize>,
+}
+
+impl MoviePlayer {
+    /// Put the Game Boy at the start of the movie, ready to play it.
+    pub fn new(movie: Movie, gb: &mut GameBoy) -> Result<Self, MovieError> {
+        movie.start(gb)?;
+        Ok(MoviePlayer { movie, frame: 0, desync: None })
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    /// The number of frames played so far.
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.movie.len()
+    }
+
+    /// The first frame (counting from 0) which led somewhere different to the recording, if any
+    /// has yet.
+    pub fn desync(&self) -> Option<usize> {
+        self.desync
+    }
+
+    /// Play the next frame, returning the number of T-cycles it took, or `None` once the movie is
+    /// over. All the buttons are released after the last frame.
+    pub fn run_frame(&mut self, gb: &mut GameBoy) -> Option<u32> {
+        let frame = *self.movie.frames.get(self.frame)?;
+        for (i, &button) in Button::ALL.iter().enumerate() {
+            gb.set_button(button, frame.buttons & 1 << i != 0);
+        }
+        let cycles = gb.run_frame();
+        if self.desync.is_none() && checksum(gb) != frame.checksum {
+            self.desync = Some(self.frame);
+        }
+        self.frame += 1;
+        if self.is_finished() {
+            for button in Button::ALL {
+                gb.release(button);
+            }
+        }
+        Some(cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::cartridge::Cartridge;
+
+    /// A ROM which keeps a count in work RAM of the frames Start has been held for, so that what
+    /// happens depends on the input.
+    fn cartridge() -> Cartridge {
+        let code = [
+            0x3E, 0x10,       // LD A,0x10 (select the action buttons)
+            0xE0, 0x00,       // LDH (P1),A
+            0x21, 0x00, 0xC0, // LD HL,0xC000
+            0xF0, 0x44,       // wait: LDH A,(LY)
+            0xFE, 0x90,       // CP 144
+            0x20, 0xFA,       // JR NZ,wait
+            0xF0, 0x00,       // LDH A,(P1)
+            0xE6, 0x08,       // AND 0x08 (Start, which reads as 0 when pressed)
+            0x20, 0x01,       // JR NZ,skip
+            0x34,             // INC (HL)
+            0xF0, 0x44,       // skip: LDH A,(LY)
+            0xFE, 0x90,       // CP 144
+            0x28, 0xFA,       // JR Z,skip
+            0x18, 0xEB,       // JR wait
+        ];
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0100 + code.len()].copy_from_slice(&code);
+        Cartridge::from_bytes(rom).unwrap()
+    }
+
+    fn gameboy() -> GameBoy {
+        GameBoy::new(cartridge())
+    }
+
+    /// A boot ROM which does nothing but turn the screen on and hand over to the cartridge, with
+    /// `fill` in an unused byte so that different boot ROMs can be made.
+    fn with_boot_rom(fill: u8) -> GameBoy {
+        let mut boot_rom = vec![0; 0x100];
+        boot_rom[0xF0] = fill;
+        boot_rom[0xF8..].copy_from_slice(&[
+            0x3E, 0x91, // LD A,0x91
+            0xE0, 0x40, // LDH (LCDC),A
+            0x3E, 0x01, // LD A,1
+            0xE0, 0x50, // LDH (0x50),A, which unmaps the boot ROM with PC at 0x0100
+        ]);
+        GameBoy::with_boot_rom(cartridge(), &boot_rom).unwrap()
+    }
+
+    /// Record 10 frames, holding Start for the middle 4.
+    fn record(gb: &mut GameBoy, start_state: Option<Vec<u8>>) -> Movie {
+        let mut movie = Movie::record(gb, start_state).unwrap();
+        for frame in 0..10 {
+            gb.set_button(Button::Start, (3..7).contains(&frame));
+            gb.run_frame();
+            movie.record_frame(gb);
+        }
+        movie
+    }
+
+    fn play(movie: Movie, gb: &mut GameBoy) -> MoviePlayer {
+        let mut player = MoviePlayer::new(movie, gb).unwrap();
+        while player.run_frame(gb).is_some() {}
+        player
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut gb = gameboy();
+        let movie = Movie::from_bytes(&record(&mut gb, None).to_bytes()).unwrap();
+        assert_eq!(movie.len(), 10);
+        assert_eq!(gb.cpu().read_byte(0xC000), 4);
+
+        // Replaying from power-on gets to the same place, however the Game Boy was left.
+        let player = play(movie, &mut gb);
+        assert_eq!(player.frame(), 10);
+        assert_eq!(player.desync(), None);
+        assert_eq!(gb.cpu().read_byte(0xC000), 4);
+        assert!(!gb.is_pressed(Button::Start));
+    }
+
+    #[test]
+    fn from_save_state() {
+        let mut gb = gameboy();
+        for _ in 0..5 {
+            gb.press(Button::Start);
+            gb.run_frame();
+        }
+        let state = gb.save_state();
+        let movie = record(&mut gb, Some(state));
+        assert!(movie.starts_from_state());
+        let count = gb.cpu().read_byte(0xC000);
+
+        let mut other = gameboy();
+        let player = play(movie, &mut other);
+        assert_eq!(player.desync(), None);
+        assert_eq!(other.cpu().read_byte(0xC000), count);
+    }
+
+    #[test]
+    fn desync() {
+        let mut gb = gameboy();
+        let mut movie = record(&mut gb, None);
+        // Say Start wasn't held on the fourth frame after all.
+        movie.frames[3].buttons = 0;
+        let player = play(movie, &mut gb);
+        assert_eq!(player.desync(), Some(3));
+    }
+
+    #[test]
+    fn wrong_rom_and_corrupt_files() {
+        let mut gb = gameboy();
+        let mut movie = record(&mut gb, None);
+        movie.rom_checksum ^= 1;
+        assert!(matches!(MoviePlayer::new(movie.clone(), &mut gb), Err(MovieError::WrongRom { .. })));
+
+        let bytes = movie.to_bytes();
+        assert!(matches!(Movie::from_bytes(&bytes[..bytes.len() - 1]), Err(MovieError::Corrupt(_))));
+        assert!(matches!(Movie::from_bytes(b"PWDMGSTA"), Err(MovieError::NotAMovie)));
+    }
+
+    #[test]
+    fn boot_rom() {
+        let mut gb = with_boot_rom(0);
+        let movie = Movie::from_bytes(&record(&mut gb, None).to_bytes()).unwrap();
+        assert_eq!(gb.cpu().read_byte(0xC000), 4);
+        assert_eq!(play(movie.clone(), &mut with_boot_rom(0)).desync(), None);
+
+        let error = MoviePlayer::new(movie.clone(), &mut gameboy()).err().unwrap();
+        assert!(matches!(error, MovieError::BootRomMismatch { recorded: Some(_), running: None }));
+        assert!(error.to_string().ends_with("but the Game Boy is running without a boot ROM"));
+        assert!(matches!(MoviePlayer::new(movie, &mut with_boot_rom(1)), Err(MovieError::BootRomMismatch { .. })));
+
+        let movie = record(&mut gameboy(), None);
+        assert!(matches!(
+            MoviePlayer::new(movie, &mut with_boot_rom(0)),
+            Err(MovieError::BootRomMismatch { recorded: None, running: Some(_) }),
+        ));
+    }
+}
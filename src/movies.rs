@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::PathBuf;
+use anyhow::{bail, Context, Result};
+use patchwork_dmg::{GameBoy, Movie, MoviePlayer};
+use crate::cli::Args;
+
+/// The movie being recorded or played, if one was asked for on the command line. Whilst there is
+/// one, the Game Boy only moves forward a frame at a time: save states can't be loaded, rewinding
+/// is off, and battery saves are neither loaded nor written.
+pub enum MovieSession {
+    Recording { movie: Movie, path: PathBuf },
+    Playing(MoviePlayer),
+}
+
+impl MovieSession {
+    /// Start recording or playing a movie, if the arguments ask for one, which puts the Game Boy at
+    /// the start of the movie.
+    pub fn start(args: &Args, gb: &mut GameBoy) -> Result<Option<Self>> {
+        if let Some(path) = &args.record_movie {
+            let start_state = match &args.movie_start {
+                Some(state) => Some(fs::read(state).with_context(|| format!("Couldn't read save state {}", state.display()))?),
+                None => None,
+            };
+            let movie = Movie::record(gb, start_state).context("Couldn't start recording the movie")?;
+            return Ok(Some(MovieSession::Recording { movie, path: path.clone() }));
+        }
+        if let Some(path) = &args.play_movie {
+            let movie = Movie::load(path).with_context(|| format!("Couldn't load movie {}", path.display()))?;
+            if movie.emulator_version() != env!("CARGO_PKG_VERSION") {
+                eprintln!(
+                    "Warning: the movie was recorded with version {} of the emulator, so it may not play back the same",
+                    movie.emulator_version(),
+                );
+            }
+            let player = MoviePlayer::new(movie, gb).with_context(|| format!("Couldn't play movie {}", path.display()))?;
+            return Ok(Some(MovieSession::Playing(player)));
+        }
+        Ok(None)
+    }
+
+    /// Run a frame of the movie, returning the number of T-cycles it took, or `None` if a movie
+    /// being played has finished.
+    pub fn run_frame(&mut self, gb: &mut GameBoy) -> Option<u32> {
+        match self {
+            MovieSession::Recording { movie, .. } => {
+                let cycles = gb.run_frame();
+                movie.record_frame(gb);
+                Some(cycles)
+            }
+            MovieSession::Playing(player) => {
+                let synced = player.desync().is_none();
+                let cycles = player.run_frame(gb)?;
+                if synced {
+                    if let Some(frame) = player.desync() {
+                        eprintln!("Desync: frame {} of the movie didn't go as it did when it was recorded", frame + 1);
+                    }
+                }
+                if player.is_finished() {
+                    println!("The movie has finished, after {} frames", player.frame());
+                }
+                Some(cycles)
+            }
+        }
+    }
+
+    /// Save the movie being recorded, or fail if the movie being played desynced.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            MovieSession::Recording { movie, path } => {
+                movie.save(&path).with_context(|| format!("Couldn't save movie {}", path.display()))?;
+                println!("Saved movie of {} frames to {}", movie.len(), path.display());
+            }
+            MovieSession::Playing(player) => {
+                if let Some(frame) = player.desync() {
+                    bail!("The movie desynced on frame {} of {}", frame + 1, player.movie().len());
+                }
+                println!("Played {} of the movie's {} frames without a desync", player.frame(), player.movie().len());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::{Duration, Instant};
+use patchwork_dmg::CYCLES_PER_SECOND;
+
+/// If emulation falls this far behind the clock (such as whilst the window is being dragged), the
+/// pacer gives up on catching up rather than running flat out until it has.
+const MAX_LAG: Duration = Duration::from_millis(100);
+
+/// Keeps emulation running in step with real time. Rather than sleeping for a fixed time after each
+/// frame, the pacer keeps track of how much emulated time has passed in total, and waits until the
+/// monotonic clock has caught up with it. Time spent emulating and drawing is accounted for, and
+/// rounding errors don't build up over time.
+pub struct FramePacer {
+    /// The moment the emulated time should next line up with.
+    target: Instant,
+}
+
+impl FramePacer {
+    pub fn new(now: Instant) -> Self {
+        FramePacer { target: now }
+    }
+
+    /// Account for the given number of T-cycles having been emulated at the given speed, and
+    /// work out how long to wait before starting the next frame.
+    pub fn advance(&mut self, cycles: u32, speed: f64, now: Instant) -> Duration {
+        let emulated = Duration::from_secs_f64(cycles as f64 / CYCLES_PER_SECOND as f64 / speed);
+        self.target += emulated;
+        if now > self.target + MAX_LAG {
+            self.target = now;
+        }
+        self.target.saturating_duration_since(now)
+    }
+}
+
+/// How many frames to run for each one presented. Presenting with vsync waits for the display, so
+/// to run faster than it, several frames are run and only the last is shown. Without vsync, every
+/// frame is shown and the pacer alone decides how fast they go.
+pub fn frames_per_present(speed: f64, vsync: bool) -> u32 {
+    if vsync && speed > 1.0 {
+        speed.round() as u32
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patchwork_dmg::{CYCLES_PER_FRAME, FRAME_RATE};
+
+    #[test]
+    fn paces_to_frame_rate() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(start);
+        let mut now = start;
+        for _ in 0..600 {
+            now += pacer.advance(CYCLES_PER_FRAME, 1.0, now);
+        }
+        // Ten seconds' worth of frames, without any drift.
+        let elapsed = (now - start).as_secs_f64();
+        assert!((elapsed - 600.0 / FRAME_RATE).abs() < 1e-6);
+    }
+
+    #[test]
+    fn accounts_for_work() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(start);
+        // Time spent emulating the frame comes off the wait.
+        let wait = pacer.advance(CYCLES_PER_FRAME, 1.0, start + Duration::from_millis(10));
+        assert!(wait > Duration::from_millis(6) && wait < Duration::from_millis(7));
+        // At double speed, frames take half as long.
+        let wait = pacer.advance(CYCLES_PER_FRAME, 2.0, start + Duration::from_millis(10) + wait);
+        assert!(wait > Duration::from_millis(8) && wait < Duration::from_millis(9));
+    }
+
+    #[test]
+    fn frames_per_vsync() {
+        assert_eq!(frames_per_present(4.0, false), 1);
+        assert_eq!(frames_per_present(4.0, true), 4);
+        assert_eq!(frames_per_present(2.6, true), 3);
+        assert_eq!(frames_per_present(1.0, true), 1);
+        assert_eq!(frames_per_present(1.4, true), 1);
+        assert_eq!(frames_per_present(0.25, true), 1);
+    }
+
+    #[test]
+    fn gives_up_when_far_behind() {
+        let start = Instant::now();
+        let mut pacer = FramePacer::new(start);
+        let late = start + Duration::from_secs(1);
+        assert_eq!(pacer.advance(CYCLES_PER_FRAME, 1.0, late), Duration::ZERO);
+        // The next frame is paced from now, rather than rushing to make up the lost second.
+        let wait = pacer.advance(CYCLES_PER_FRAME, 1.0, late);
+        assert!(wait > Duration::from_millis(16));
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use sdl2::pixels::Color;
+use serde_json::Value;
+use thiserror::Error;
+use ux::u2;
+use crate::components::dmg_ppu::{PIXEL_OBP0, PIXEL_OBP1, PIXEL_SHADE_MASK};
+use crate::components::graphics_components::GBPalette;
+
+#[derive(Debug, Error)]
+pub enum PaletteError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Couldn't parse the JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Line {line}: {message}")]
+    Line { line: usize, message: String },
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// # Palette set
+/// The colours the framebuffer is drawn in: one palette for the background and window, and one for
+/// each of the two sprite palettes, OBP0 and OBP1.
+///
+/// Palette files give each palette as four colours, lightest first, either as text:
+///
+/// ```text
+/// # Lines starting with # are comments.
+/// bg   = #E0F8D0 #88C070 #346856 #081820
+/// obj0 = #FFFFFF #FFAD63 #833100 #000000
+/// obj1 = #FFFFFF #63A5FF #0000FF #000000
+/// ```
+///
+/// or as JSON, with the same names: `{"bg": ["#E0F8D0", "#88C070", "#346856", "#081820"]}`.
+/// Only `bg` is needed; without `obj0` the sprites use the background colours, and without `obj1`
+/// they use `obj0`'s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaletteSet {
+    pub bg: GBPalette,
+    pub obj0: GBPalette,
+    pub obj1: GBPalette,
+}
+
+impl PaletteSet {
+    /// Use the same colours for the background and both sprite palettes.
+    pub fn uniform(palette: GBPalette) -> Self {
+        PaletteSet { bg: palette, obj0: palette, obj1: palette }
+    }
+
+    /// The colour of a pixel from the framebuffer.
+    pub fn colour(&self, pixel: u8) -> Color {
+        let palette = if pixel & PIXEL_OBP1 != 0 {
+            &self.obj1
+        } else if pixel & PIXEL_OBP0 != 0 {
+            &self.obj0
+        } else {
+            &self.bg
+        };
+        palette.col_id(u2::new(pixel & PIXEL_SHADE_MASK))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PaletteError> {
+        fs::read_to_string(path)?.parse()
+    }
+
+    /// Put the palettes together from those named in a file, filling in any missing sprite palette.
+    fn from_named(bg: Option<GBPalette>, obj0: Option<GBPalette>, obj1: Option<GBPalette>) -> Result<Self, PaletteError> {
+        let bg = bg.ok_or_else(|| PaletteError::Invalid("There's no `bg` palette".to_string()))?;
+        let obj0 = obj0.unwrap_or(bg);
+        Ok(PaletteSet { bg, obj0, obj1: obj1.unwrap_or(obj0) })
+    }
+
+    fn parse_json(s: &str) -> Result<Self, PaletteError> {
+        let json: Value = serde_json::from_str(s)?;
+        let object = json.as_object().ok_or_else(|| PaletteError::Invalid("Expected an object of palettes".to_string()))?;
+        let mut palettes = [None; 3];
+        for (name, colours) in object {
+            let colours: Vec<&str> = colours.as_array()
+                .and_then(|colours| colours.iter().map(Value::as_str).collect())
+                .ok_or_else(|| PaletteError::Invalid(format!("`{}` should be a list of colours", name)))?;
+            palettes[palette_index(name).map_err(PaletteError::Invalid)?] = Some(parse_palette(&colours).map_err(PaletteError::Invalid)?);
+        }
+        let [bg, obj0, obj1] = palettes;
+        PaletteSet::from_named(bg, obj0, obj1)
+    }
+
+    fn parse_text(s: &str) -> Result<Self, PaletteError> {
+        let mut palettes = [None; 3];
+        for (i, line) in s.lines().enumerate() {
+            let error = |message: String| PaletteError::Line { line: i + 1, message };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, colours) = line.split_once('=').ok_or_else(|| error("Expected `name = colours`".to_string()))?;
+            let colours: Vec<&str> = colours.split_whitespace().collect();
+            palettes[palette_index(name.trim()).map_err(error)?] = Some(parse_palette(&colours).map_err(error)?);
+        }
+        let [bg, obj0, obj1] = palettes;
+        PaletteSet::from_named(bg, obj0, obj1)
+    }
+}
+
+impl FromStr for PaletteSet {
+    type Err = PaletteError;
+
+    /// Parse a palette file, as JSON if it is an object and as text otherwise.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.trim_start().starts_with('{') {
+            PaletteSet::parse_json(s)
+        } else {
+            PaletteSet::parse_text(s)
+        }
+    }
+}
+
+fn palette_index(name: &str) -> Result<usize, String> {
+    match name.to_ascii_lowercase().as_str() {
+        "bg" => Ok(0),
+        "obj0" => Ok(1),
+        "obj1" => Ok(2),
+        _ => Err(format!("Unknown palette `{}`; expected bg, obj0 or obj1", name)),
+    }
+}
+
+fn parse_palette(colours: &[&str]) -> Result<GBPalette, String> {
+    let colours = colours.iter().map(|colour| parse_colour(colour)).collect::<Result<Vec<_>, _>>()?;
+    match colours[..] {
+        [col1, col2, col3, col4] => Ok(GBPalette::new(col1, col2, col3, col4)),
+        _ => Err(format!("Expected 4 colours, found {}", colours.len())),
+    }
+}
+
+/// Parse a colour written as six hex digits, such as `#9BBC0F`. The # is optional.
+fn parse_colour(s: &str) -> Result<Color, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let rgb = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("`{}` isn't a colour such as #9BBC0F", s))?;
+    Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(shades: [u8; 4]) -> GBPalette {
+        let [a, b, c, d] = shades.map(|s| Color::RGB(s, s, s));
+        GBPalette::new(a, b, c, d)
+    }
+
+    #[test]
+    fn text_and_json() {
+        let text = "\
+# Greys, darker for the sprites.
+bg   = #FFFFFF #AAAAAA #555555 #000000
+obj0 = EEEEEE 999999 444444 000000
+";
+        let palettes: PaletteSet = text.parse().unwrap();
+        assert_eq!(palettes.bg, grey([0xFF, 0xAA, 0x55, 0x00]));
+        assert_eq!(palettes.obj0, grey([0xEE, 0x99, 0x44, 0x00]));
+        assert_eq!(palettes.obj1, palettes.obj0);
+
+        let json = r##"{"bg": ["#FFFFFF", "#AAAAAA", "#555555", "#000000"], "obj1": ["#EEEEEE", "#999999", "#444444", "#000000"]}"##;
+        let palettes: PaletteSet = json.parse().unwrap();
+        assert_eq!(palettes.obj0, palettes.bg);
+        assert_eq!(palettes.obj1, grey([0xEE, 0x99, 0x44, 0x00]));
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!("obj0 = #FFFFFF #AAAAAA #555555 #000000".parse::<PaletteSet>(), Err(PaletteError::Invalid(_))));
+        assert!(matches!("bg = #FFFFFF #AAAAAA #555555".parse::<PaletteSet>(), Err(PaletteError::Line { line: 1, .. })));
+        assert!(matches!("\nbg = #FFFFFF #AAAAAA #555555 #GGGGGG".parse::<PaletteSet>(), Err(PaletteError::Line { line: 2, .. })));
+        assert!(matches!("{\"sprites\": []}".parse::<PaletteSet>(), Err(PaletteError::Invalid(_))));
+        assert!(matches!("{".parse::<PaletteSet>(), Err(PaletteError::Json(_))));
+    }
+
+    #[test]
+    fn pixel_colours() {
+        let palettes = PaletteSet {
+            bg: grey([0xFF, 0xAA, 0x55, 0x00]),
+            obj0: grey([0xF0, 0xA0, 0x50, 0x01]),
+            obj1: grey([0xE0, 0x90, 0x40, 0x02]),
+        };
+        assert_eq!(palettes.colour(1), Color::RGB(0xAA, 0xAA, 0xAA));
+        assert_eq!(palettes.colour(PIXEL_OBP0 | 3), Color::RGB(0x01, 0x01, 0x01));
+        assert_eq!(palettes.colour(PIXEL_OBP1 | 2), Color::RGB(0x40, 0x40, 0x40));
+    }
+}
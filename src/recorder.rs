@@ -0,0 +1,230 @@
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use crate::gameboy::FRAME_RATE;
+use crate::screenshot::Image;
+
+/// GIF frame delays are counted in hundredths of a second, too coarse for the Game Boy's 59.7
+/// frames a second (and many viewers slow down anything faster than 50), so only every other frame
+/// is kept.
+const GIF_FRAME_STEP: u64 = 2;
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Couldn't encode the GIF: {0}")]
+    Gif(#[from] gif::EncodingError),
+    #[error("Couldn't write the WAV: {0}")]
+    Wav(#[from] hound::Error),
+    #[error("A recording can't itself be a .wav file, as that is where its audio goes")]
+    WavVideo,
+    #[error("A frame has more than 256 colours, too many for a GIF")]
+    TooManyColours,
+}
+
+/// # Recorder
+/// Records frames and sound as the emulator runs. The frames go to an animated GIF if the path
+/// ends in `.gif`, and otherwise to a raw sequence of 8-bit RGB frames, one after another with
+/// nothing in between. The sound goes alongside, as a 16-bit stereo WAV with the same name.
+pub struct Recorder {
+    video: Video,
+    audio: hound::WavWriter<BufWriter<File>>,
+    frames: u64,
+}
+
+enum Video {
+    Gif(GifWriter),
+    Raw(BufWriter<File>),
+}
+
+impl Recorder {
+    /// Start a recording, with sound at the given sample rate.
+    pub fn new<P: AsRef<Path>>(path: P, sample_rate: u32) -> Result<Self, RecordingError> {
+        let path = path.as_ref();
+        let extension = path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+        let video = match extension.as_deref() {
+            Some("wav") => return Err(RecordingError::WavVideo),
+            Some("gif") => Video::Gif(GifWriter { out: Some(BufWriter::new(File::create(path)?)), encoder: None, pending: None }),
+            _ => Video::Raw(BufWriter::new(File::create(path)?)),
+        };
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let audio = hound::WavWriter::create(Recorder::audio_path(path), spec)?;
+        Ok(Recorder { video, audio, frames: 0 })
+    }
+
+    /// Where the sound of a recording is saved.
+    pub fn audio_path(path: &Path) -> PathBuf {
+        path.with_extension("wav")
+    }
+
+    /// The number of frames recorded so far, including any left out of a GIF.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn add_frame(&mut self, image: &Image) -> Result<(), RecordingError> {
+        match &mut self.video {
+            Video::Gif(gif) => gif.add_frame(image, self.frames)?,
+            Video::Raw(out) => out.write_all(&image.rgb)?,
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Add interleaved left and right samples, as produced by `GameBoy::take_audio_samples`.
+    pub fn add_audio(&mut self, samples: &[f32]) -> Result<(), RecordingError> {
+        for &sample in samples {
+            self.audio.write_sample((sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16)?;
+        }
+        Ok(())
+    }
+
+    /// Write out whatever is still buffered, and fill in the lengths the file headers need.
+    pub fn finish(self) -> Result<(), RecordingError> {
+        match self.video {
+            Video::Gif(gif) => gif.finish(self.frames)?,
+            Video::Raw(mut out) => out.flush()?,
+        }
+        self.audio.finalize()?;
+        Ok(())
+    }
+}
+
+/// The time at which the given frame is shown, in hundredths of a second.
+fn centiseconds(frame: u64) -> u64 {
+    (frame as f64 * 100.0 / FRAME_RATE).round() as u64
+}
+
+struct GifWriter {
+    /// The file, until it is handed over to the encoder.
+    out: Option<BufWriter<File>>,
+    /// Created along with the first frame, once the size of the frames is known.
+    encoder: Option<gif::Encoder<BufWriter<File>>>,
+    /// The last frame kept, with the number of the frame it was kept from. It isn't written until
+    /// a different frame comes along, as until then it isn't known how long it is shown for.
+    pending: Option<(gif::Frame<'static>, u64)>,
+}
+
+impl GifWriter {
+    fn add_frame(&mut self, image: &Image, number: u64) -> Result<(), RecordingError> {
+        if !number.is_multiple_of(GIF_FRAME_STEP) {
+            return Ok(());
+        }
+        let (pixels, palette) = indexed(image)?;
+        let frame = gif::Frame::from_palette_pixels(image.width as u16, image.height as u16, pixels, palette, None);
+        if let Some((pending, _)) = &self.pending {
+            if pending.buffer == frame.buffer && pending.palette == frame.palette {
+                return Ok(());
+            }
+        }
+        if let Some(out) = self.out.take() {
+            let mut encoder = gif::Encoder::new(out, image.width as u16, image.height as u16, &[])?;
+            encoder.set_repeat(gif::Repeat::Infinite)?;
+            self.encoder = Some(encoder);
+        }
+        self.write_pending(number)?;
+        self.pending = Some((frame, number));
+        Ok(())
+    }
+
+    /// Write the pending frame, which is shown until the given frame.
+    fn write_pending(&mut self, until: u64) -> Result<(), RecordingError> {
+        if let (Some((mut frame, start)), Some(encoder)) = (self.pending.take(), &mut self.encoder) {
+            frame.delay = (centiseconds(until) - centiseconds(start)).min(u16::MAX as u64) as u16;
+            encoder.write_frame(&frame)?;
+        }
+        Ok(())
+    }
+
+    fn finish(mut self, frames: u64) -> Result<(), RecordingError> {
+        self.write_pending(frames)?;
+        if let Some(encoder) = self.encoder {
+            encoder.into_inner()?.flush()?;
+        }
+        // Otherwise nothing was recorded, and the file is left empty.
+        Ok(())
+    }
+}
+
+/// Turn an image into indices into a palette of the colours it uses, as a GIF frame needs.
+fn indexed(image: &Image) -> Result<(Vec<u8>, Vec<u8>), RecordingError> {
+    let mut colours: Vec<&[u8]> = Vec::new();
+    let mut pixels = Vec::with_capacity(image.rgb.len() / 3);
+    for pixel in image.rgb.chunks(3) {
+        let index = match colours.iter().position(|&colour| colour == pixel) {
+            Some(index) => index,
+            None => {
+                colours.push(pixel);
+                colours.len() - 1
+            }
+        };
+        pixels.push(u8::try_from(index).map_err(|_| RecordingError::TooManyColours)?);
+    }
+    Ok((pixels, colours.concat()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    /// A 2x1 image, white on the left and the given shade of grey on the right.
+    fn image(shade: u8) -> Image {
+        Image { width: 2, height: 1, rgb: vec![0xFF, 0xFF, 0xFF, shade, shade, shade] }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("patchwork_dmg_{}_{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn gif_and_wav() {
+        let path = temp_path("recording.gif");
+        let mut recorder = Recorder::new(&path, 48_000).unwrap();
+        // Frames 0 and 2 are the same, so only 0 and 4 are kept; the odd frames are left out.
+        for shade in [0x00, 0x11, 0x00, 0x33, 0x55, 0x66] {
+            recorder.add_frame(&image(shade)).unwrap();
+        }
+        recorder.add_audio(&[0.0, 0.5, -1.0, 2.0]).unwrap();
+        recorder.finish().unwrap();
+
+        let mut decoder = gif::DecodeOptions::new().read_info(File::open(&path).unwrap()).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.palette.clone().unwrap()));
+        }
+        assert_eq!(frames.len(), 2);
+        // 4 frames is 6.7 hundredths of a second, and the 2 after that take it to 10.0.
+        assert_eq!(frames[0], (7, vec![0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00]));
+        assert_eq!(frames[1].0, 3);
+
+        let wav = Recorder::audio_path(&path);
+        let samples: Vec<i16> = hound::WavReader::open(&wav).unwrap().samples().map(Result::unwrap).collect();
+        assert_eq!(samples, [0, 16383, -32767, 32767]);
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&wav).unwrap();
+    }
+
+    #[test]
+    fn raw_frames() {
+        let path = temp_path("recording.rgb");
+        let mut recorder = Recorder::new(&path, 48_000).unwrap();
+        recorder.add_frame(&image(0x00)).unwrap();
+        recorder.add_frame(&image(0x00)).unwrap();
+        assert_eq!(recorder.frames(), 2);
+        recorder.finish().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), [image(0x00).rgb, image(0x00).rgb].concat());
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(Recorder::audio_path(&path)).unwrap();
+
+        assert!(matches!(Recorder::new(temp_path("recording.wav"), 48_000), Err(RecordingError::WavVideo)));
+    }
+}
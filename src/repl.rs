@@ -0,0 +1,283 @@
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+use std::sync::atomic::Ordering;
+use anyhow::{Context, Result};
+use patchwork_dmg::cpu::Reg16;
+use patchwork_dmg::debug::{disassemble, parse_number, Breakpoint, Debugger, DebuggerError, Register, StopReason, WatchKind, Watchpoint};
+use patchwork_dmg::{GameBoy, CYCLES_PER_FRAME};
+
+const HELP: &str = "\
+Numbers are decimal, or hex with a 0x or $ prefix.
+  s, step [N]             run N instructions (default 1)
+  n, next                 run one instruction, running calls until they return
+  u, until ADDR           run until the PC reaches ADDR
+  c, continue [FRAMES]    run until a breakpoint or watchpoint, or for FRAMES frames
+  b, break ADDR [if COND] stop when the PC reaches ADDR, e.g. `break $0150 if a == 3`
+  w, watch [r|w|rw] ADDR  stop after ADDR is read and/or written (default w)
+  d, delete b|w N         remove breakpoint or watchpoint N
+  l, list [ADDR] [N]      disassemble N instructions from ADDR (default the PC, 10)
+  i, info                 list breakpoints and watchpoints
+  r, regs                 show the registers
+  set REG VALUE           change a register
+  x ADDR [LEN]            show LEN bytes of memory from ADDR (default 16)
+  poke ADDR VALUE         change a byte of memory
+  h, help                 show this
+  q, quit                 stop debugging
+An empty line repeats the last command. Ctrl-C stops a command which is running.";
+
+/// A command entered at the prompt.
+#[derive(Debug, PartialEq)]
+enum Command {
+    Step(u32),
+    Next,
+    Until(u16),
+    Continue(Option<u32>),
+    Break(Breakpoint),
+    Watch(Watchpoint),
+    DeleteBreak(usize),
+    DeleteWatch(usize),
+    List(Option<u16>, u16),
+    Info,
+    Regs,
+    Set(Register, u16),
+    Examine(u16, u16),
+    Poke(u16, u8),
+    Help,
+    Quit,
+}
+
+impl Command {
+    fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| -> Result<u16, String> {
+            let word = words.get(i).ok_or_else(|| format!("`{}` needs more arguments; see `help`", words[0]))?;
+            parse_number(word).map_err(|e| e.to_string())
+        };
+        let optional = |i: usize| if words.len() > i { number(i).map(Some) } else { Ok(None) };
+
+        let command = match words[0] {
+            "s" | "step" => Command::Step(optional(1)?.unwrap_or(1) as u32),
+            "n" | "next" => Command::Next,
+            "u" | "until" => Command::Until(number(1)?),
+            "c" | "continue" => Command::Continue(optional(1)?.map(u32::from)),
+            "b" | "break" => {
+                let condition = match words.get(2) {
+                    Some(&"if") => Some(words[3..].join(" ").parse().map_err(|e: DebuggerError| e.to_string())?),
+                    Some(_) => return Err("expected `if` after the address".to_string()),
+                    None => None,
+                };
+                Command::Break(Breakpoint { addr: number(1)?, condition })
+            }
+            "w" | "watch" => {
+                let (kind, addr) = match words.get(1).copied() {
+                    Some("r") => (WatchKind::Read, number(2)?),
+                    Some("w") => (WatchKind::Write, number(2)?),
+                    Some("rw") => (WatchKind::ReadWrite, number(2)?),
+                    _ => (WatchKind::Write, number(1)?),
+                };
+                Command::Watch(Watchpoint { addr, kind })
+            }
+            "d" | "delete" => match words.get(1).copied() {
+                Some("b") => Command::DeleteBreak(number(2)? as usize),
+                Some("w") => Command::DeleteWatch(number(2)? as usize),
+                _ => return Err("say which to delete: `delete b N` or `delete w N`".to_string()),
+            },
+            "l" | "list" => Command::List(optional(1)?, optional(2)?.unwrap_or(10)),
+            "i" | "info" => Command::Info,
+            "r" | "regs" => Command::Regs,
+            "set" => {
+                let reg = words.get(1).ok_or("`set` needs a register")?;
+                Command::Set(reg.parse().map_err(|e: DebuggerError| e.to_string())?, number(2)?)
+            }
+            "x" => Command::Examine(number(1)?, optional(2)?.unwrap_or(16)),
+            "poke" => {
+                let val = number(2)?;
+                let val = u8::try_from(val).map_err(|_| format!("{:#x} doesn't fit in a byte", val))?;
+                Command::Poke(number(1)?, val)
+            }
+            "h" | "help" => Command::Help,
+            "q" | "quit" => Command::Quit,
+            other => return Err(format!("Unknown command `{}`; see `help`", other)),
+        };
+        Ok(command)
+    }
+}
+
+/// Debug the Game Boy from the terminal, until `quit` or the end of input.
+pub fn run(gb: &mut GameBoy) -> Result<()> {
+    let mut debugger = Debugger::new();
+    // Ctrl-C stops whatever is running rather than the whole emulator.
+    let interrupt = debugger.interrupt_flag();
+    ctrlc::set_handler(move || interrupt.store(true, Ordering::Relaxed))
+        .context("Couldn't catch Ctrl-C")?;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut last = String::new();
+    println!("Type `help` for the commands.");
+    print_location(gb);
+
+    loop {
+        print!("(dmg) ");
+        io::stdout().flush()?;
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(()),
+        };
+        let line = if line.trim().is_empty() { last.clone() } else { line };
+        if line.trim().is_empty() {
+            continue;
+        }
+        last = line.clone();
+
+        let command = match Command::parse(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+        match command {
+            Command::Step(count) => {
+                for _ in 0..count.max(1) {
+                    let reason = debugger.step(gb);
+                    if reason != StopReason::Stepped {
+                        report(reason);
+                        break;
+                    }
+                }
+                print_location(gb);
+            }
+            Command::Next => stopped(debugger.step_over(gb), gb),
+            Command::Until(addr) => stopped(debugger.run_to(gb, addr), gb),
+            Command::Continue(frames) => {
+                let limit = frames.map(|frames| frames as u64 * CYCLES_PER_FRAME as u64);
+                stopped(debugger.continue_running(gb, limit), gb);
+            }
+            Command::Break(breakpoint) => debugger.add_breakpoint(breakpoint),
+            Command::Watch(watchpoint) => debugger.add_watchpoint(watchpoint),
+            Command::DeleteBreak(index) => {
+                if debugger.remove_breakpoint(index).is_none() {
+                    println!("There's no breakpoint {}", index);
+                }
+            }
+            Command::DeleteWatch(index) => {
+                if debugger.remove_watchpoint(index).is_none() {
+                    println!("There's no watchpoint {}", index);
+                }
+            }
+            Command::List(addr, count) => {
+                let mut addr = addr.unwrap_or(gb.cpu().registers().pc);
+                for _ in 0..count {
+                    let dis = disassemble(|addr| gb.cpu().read_byte(addr), addr);
+                    println!("{}", dis);
+                    addr = dis.next_addr();
+                }
+            }
+            Command::Info => {
+                for (i, bp) in debugger.breakpoints().iter().enumerate() {
+                    match bp.condition {
+                        Some(condition) => println!("b{}: {:#06x} if {}", i, bp.addr, condition),
+                        None => println!("b{}: {:#06x}", i, bp.addr),
+                    }
+                }
+                for (i, wp) in debugger.watchpoints().iter().enumerate() {
+                    println!("w{}: {:#06x} ({:?})", i, wp.addr, wp.kind);
+                }
+            }
+            Command::Regs => print_registers(gb),
+            Command::Set(reg, val) => {
+                reg.set(gb.cpu_mut().registers_mut(), val);
+                print_registers(gb);
+            }
+            Command::Examine(addr, len) => print_memory(gb, addr, len),
+            Command::Poke(addr, val) => gb.cpu_mut().write_byte(addr, val),
+            Command::Help => println!("{}", HELP),
+            Command::Quit => return Ok(()),
+        }
+    }
+}
+
+fn report(reason: StopReason) {
+    match reason {
+        StopReason::Stepped | StopReason::Reached(_) | StopReason::Limit => {}
+        StopReason::Breakpoint(addr) => println!("Breakpoint at {:#06x}", addr),
+        StopReason::Watchpoint(hit) => {
+            let access = if hit.write { "Write of" } else { "Read of" };
+            println!("{} {:#04x} at {:#06x}", access, hit.value, hit.addr);
+        }
+        StopReason::Locked => println!("The CPU has locked up on an illegal opcode"),
+        StopReason::Interrupted => println!("Interrupted"),
+    }
+}
+
+fn stopped(reason: StopReason, gb: &GameBoy) {
+    report(reason);
+    print_location(gb);
+}
+
+/// Show the instruction about to run.
+fn print_location(gb: &GameBoy) {
+    println!("{}", disassemble(|addr| gb.cpu().read_byte(addr), gb.cpu().registers().pc));
+}
+
+fn print_registers(gb: &GameBoy) {
+    let cpu = gb.cpu();
+    let regs = cpu.registers();
+    let flag = |set: bool, name: char| if set { name } else { '-' };
+    println!(
+        "AF: {:04X} [{}{}{}{}]  BC: {:04X}  DE: {:04X}  HL: {:04X}  SP: {:04X}  PC: {:04X}",
+        regs.get16(Reg16::AF),
+        flag(regs.flags.zero, 'Z'),
+        flag(regs.flags.subtraction, 'N'),
+        flag(regs.flags.half_carry, 'H'),
+        flag(regs.flags.carry, 'C'),
+        regs.bc.get_wide(),
+        regs.de.get_wide(),
+        regs.hl.get_wide(),
+        regs.sp,
+        regs.pc,
+    );
+    println!("IME: {}  halted: {}  cycles: {}", cpu.ime() as u8, cpu.is_halted() as u8, cpu.cycles);
+    if gb.lcd_enabled() {
+        println!("LCD: on  mode: {:?}  LY: {}", gb.lcd_mode(), gb.ly());
+    } else {
+        println!("LCD: off");
+    }
+}
+
+fn print_memory(gb: &GameBoy, addr: u16, len: u16) {
+    for row in (0..len).step_by(16) {
+        let start = addr.wrapping_add(row);
+        let bytes: Vec<String> = (0..16.min(len - row))
+            .map(|i| format!("{:02X}", gb.cpu().read_byte(start.wrapping_add(i))))
+            .collect();
+        println!("{:04X}: {}", start, bytes.join(" "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patchwork_dmg::debug::{BreakCondition, Comparison};
+    use patchwork_dmg::cpu::Reg8;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(Command::parse("s").unwrap(), Command::Step(1));
+        assert_eq!(Command::parse("step 10").unwrap(), Command::Step(10));
+        assert_eq!(Command::parse("c").unwrap(), Command::Continue(None));
+        assert_eq!(Command::parse("x $c000 4").unwrap(), Command::Examine(0xC000, 4));
+        assert_eq!(Command::parse("l").unwrap(), Command::List(None, 10));
+        assert_eq!(Command::parse("list $150 3").unwrap(), Command::List(Some(0x150), 3));
+        assert_eq!(Command::parse("watch r 0xff44").unwrap(), Command::Watch(Watchpoint { addr: 0xFF44, kind: WatchKind::Read }));
+        assert_eq!(Command::parse("w $c000").unwrap(), Command::Watch(Watchpoint { addr: 0xC000, kind: WatchKind::Write }));
+        let condition = BreakCondition { register: Register::R8(Reg8::A), comparison: Comparison::Eq, value: 3 };
+        assert_eq!(Command::parse("b $150 if a == 3").unwrap(), Command::Break(Breakpoint { addr: 0x150, condition: Some(condition) }));
+        assert!(Command::parse("b").is_err());
+        assert!(Command::parse("b $150 when a == 3").is_err());
+        assert!(Command::parse("set q 1").is_err());
+        assert_eq!(Command::parse("poke $c000 0xff").unwrap(), Command::Poke(0xC000, 0xFF));
+        assert!(Command::parse("poke $c000 0x1ff").is_err());
+        assert!(Command::parse("frobnicate").is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use crate::gameboy::{GameBoy, FRAME_RATE};
+
+/// # Rewind buffer
+/// A ring buffer of save states taken every few frames, which can be stepped back through a frame
+/// at a time. As there are only snapshots every few frames, each one is held on screen for as many
+/// frames as they were taken apart, so that rewinding goes at the same speed as playing.
+///
+/// Only the most recent snapshot is kept whole. Each older snapshot is stored as the difference
+/// from the one after it: the two are XORed together, which leaves long runs of zeros wherever
+/// memory didn't change, and those runs are then squeezed out. Consecutive snapshots differ very
+/// little, so this keeps a few seconds of history down to a small fraction of the size of the
+/// full states. Since each delta only depends on newer snapshots, the oldest can be dropped
+/// without having to re-encode anything.
+pub struct Rewind {
+    /// The compressed deltas, oldest first. The last one gets from `latest` back to the snapshot
+    /// before it.
+    deltas: VecDeque<Vec<u8>>,
+    /// The most recent snapshot, uncompressed.
+    latest: Option<Vec<u8>>,
+    /// The most snapshots (including `latest`) to keep.
+    capacity: usize,
+    /// How many frames apart snapshots are taken.
+    interval: u32,
+    /// How many frames the Game Boy is ahead of `latest`: frames recorded since it was taken, or
+    /// the gap back to it from the snapshot just rewound to.
+    frames: u32,
+    /// Frames rewound since the Game Boy was last put back to a snapshot, whilst it waits for
+    /// them to add up to `frames`.
+    held: u32,
+}
+
+impl Rewind {
+    /// Create a rewind buffer holding up to `capacity` snapshots, taken every `interval` frames.
+    pub fn new(capacity: usize, interval: u32) -> Self {
+        Rewind {
+            deltas: VecDeque::new(),
+            latest: None,
+            capacity,
+            interval: interval.max(1),
+            frames: 0,
+            held: 0,
+        }
+    }
+
+    /// Create a rewind buffer which can go back the given number of seconds.
+    pub fn with_duration(seconds: f64, interval: u32) -> Self {
+        let capacity = (seconds * FRAME_RATE / interval.max(1) as f64).ceil() as usize;
+        Rewind::new(capacity, interval)
+    }
+
+    /// Call once after every frame; every `interval` frames, a snapshot is taken.
+    pub fn record(&mut self, gb: &GameBoy) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.held = 0;
+        self.frames += 1;
+        if self.frames < self.interval && self.latest.is_some() {
+            return;
+        }
+        self.frames = 0;
+
+        let state = gb.save_state();
+        if let Some(previous) = self.latest.take() {
+            self.deltas.push_back(encode_delta(&state, &previous));
+        }
+        self.latest = Some(state);
+        while self.len() > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    /// Go back a frame. The Game Boy is put back to a snapshot once it is as many frames old as
+    /// have been rewound, and is left as it is until then. Returns false if there is nothing left
+    /// to rewind to.
+    pub fn rewind(&mut self, gb: &mut GameBoy) -> bool {
+        if self.frames == 0 {
+            // The latest snapshot is what is showing, so it's the one before that to go back to.
+            let latest = match self.latest.take() {
+                Some(latest) => latest,
+                None => return false,
+            };
+            self.latest = self.deltas.pop_back().map(|delta| decode_delta(&latest, &delta));
+            self.frames = self.interval;
+        }
+        let latest = match &self.latest {
+            Some(latest) => latest,
+            None => return false,
+        };
+        self.held += 1;
+        if self.held < self.frames {
+            return true;
+        }
+        // A snapshot can only fail to load if the game has been changed underneath us, in which
+        // case the whole history is useless.
+        if gb.load_state(latest).is_err() {
+            self.clear();
+            return false;
+        }
+        self.frames = 0;
+        self.held = 0;
+        true
+    }
+
+    /// The number of snapshots held.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.latest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Throw away every snapshot, such as after loading a save state.
+    pub fn clear(&mut self) {
+        self.deltas.clear();
+        self.latest = None;
+        self.frames = 0;
+        self.held = 0;
+    }
+
+    /// The number of bytes the snapshots take up.
+    pub fn memory_usage(&self) -> usize {
+        self.deltas.iter().map(Vec::len).sum::<usize>() + self.latest.as_ref().map_or(0, Vec::len)
+    }
+}
+
+/// Encode `old` as the difference from `new`. The XOR of the two is written as a series of runs,
+/// each made up of the number of zero bytes to skip, then the number of bytes that follow
+/// verbatim, then those bytes. Both counts are LEB128 varints.
+fn encode_delta(new: &[u8], old: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    // States for the same ROM are always the same size, but be safe if they somehow aren't.
+    write_varint(&mut out, old.len());
+    let xor = |i: usize| old[i] ^ new.get(i).copied().unwrap_or(0);
+
+    let mut i = 0;
+    while i < old.len() {
+        let zeros_start = i;
+        while i < old.len() && xor(i) == 0 {
+            i += 1;
+        }
+        let literal_start = i;
+        // A literal run only ends at a few zero bytes in a row, so that single matching bytes
+        // don't split it up into lots of tiny runs.
+        while i < old.len() && (i + 4 > old.len() || (0..4).any(|j| xor(i + j) != 0)) {
+            i += 1;
+        }
+        write_varint(&mut out, literal_start - zeros_start);
+        write_varint(&mut out, i - literal_start);
+        out.extend((literal_start..i).map(xor));
+    }
+    out
+}
+
+/// Undo `encode_delta`, getting back the old state from the new one and the delta.
+fn decode_delta(new: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut pos = 0;
+    let len = read_varint(delta, &mut pos);
+    let mut old: Vec<u8> = (0..len).map(|i| new.get(i).copied().unwrap_or(0)).collect();
+
+    let mut i = 0;
+    while pos < delta.len() {
+        i += read_varint(delta, &mut pos);
+        let literal_len = read_varint(delta, &mut pos);
+        for byte in &mut old[i..i + literal_len] {
+            *byte ^= delta[pos];
+            pos += 1;
+        }
+        i += literal_len;
+    }
+    old
+}
+
+fn write_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push(val as u8 | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        val |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return val;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::cartridge::Cartridge;
+
+    #[test]
+    fn delta_round_trip() {
+        let old: Vec<u8> = (0..1000).map(|i| (i % 251) as u8).collect();
+        let mut new = old.clone();
+        new[0] ^= 1;
+        new[500] = 0;
+        new[501] = 0;
+        new[999] = 0xFF;
+        let delta = encode_delta(&new, &old);
+        assert!(delta.len() < 20);
+        assert_eq!(decode_delta(&new, &delta), old);
+
+        assert_eq!(decode_delta(&old, &encode_delta(&old, &old)), old);
+        // Different lengths still round trip.
+        assert_eq!(decode_delta(&new[..10], &encode_delta(&new[..10], &old)), old);
+        assert_eq!(decode_delta(&new, &encode_delta(&new, &old[..10])), &old[..10]);
+    }
+
+    #[test]
+    fn rewind() {
+        // INC A; JR -3
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0103].copy_from_slice(&[0x3C, 0x18, 0xFD]);
+        let mut gb = GameBoy::new(Cartridge::from_bytes(rom).unwrap());
+        let mut rewind = Rewind::new(3, 2);
+
+        let mut states = Vec::new();
+        for frame in 0..10 {
+            gb.run_frame();
+            if frame % 2 == 0 {
+                states.push(gb.save_state());
+            }
+            rewind.record(&gb);
+        }
+        // Only the three most recent snapshots are kept, and they're much smaller than whole states.
+        assert_eq!(rewind.len(), 3);
+        assert!(rewind.memory_usage() < states[0].len() * 3 / 2);
+
+        // The last snapshot was a frame ago, so it's shown straight away. After that, each is held
+        // for two frames as it's two frames older than the one before.
+        let mut shown = gb.save_state();
+        for (i, expected) in states.iter().rev().take(3).enumerate() {
+            if i > 0 {
+                assert!(rewind.rewind(&mut gb));
+                assert_eq!(gb.save_state(), shown);
+            }
+            assert!(rewind.rewind(&mut gb));
+            shown = gb.save_state();
+            assert_eq!(&shown, expected);
+        }
+        assert!(!rewind.rewind(&mut gb));
+        assert!(rewind.is_empty());
+
+        // Recording starts again from where rewinding stopped. A snapshot of the frame showing
+        // isn't rewound to, as that would leave the screen as it is.
+        gb.run_frame();
+        rewind.record(&gb);
+        let state = gb.save_state();
+        for _ in 0..2 {
+            gb.run_frame();
+            rewind.record(&gb);
+        }
+        let latest = gb.save_state();
+        assert!(rewind.rewind(&mut gb));
+        assert_eq!(gb.save_state(), latest);
+        assert!(rewind.rewind(&mut gb));
+        assert_eq!(gb.save_state(), state);
+    }
+}
@@ -0,0 +1,67 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{Context, Result};
+use patchwork_dmg::GameBoy;
+
+/// Where the files belonging to a ROM are kept: its battery save and save states, named after the
+/// ROM, in either the ROM's own directory or the directory given on the command line.
+pub struct SaveFiles {
+    dir: PathBuf,
+    stem: String,
+}
+
+impl SaveFiles {
+    pub fn new(rom: &Path, save_dir: Option<&Path>) -> Self {
+        let dir = match save_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => rom.parent().map(Path::to_path_buf).unwrap_or_default(),
+        };
+        let stem = rom.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "rom".to_string());
+        SaveFiles { dir, stem }
+    }
+
+    /// The file battery-backed cartridge RAM is kept in.
+    pub fn battery_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.sav", self.stem))
+    }
+
+    /// The file the save state in the given slot is kept in.
+    pub fn state_path(&self, slot: u8) -> PathBuf {
+        self.dir.join(format!("{}.ss{}", self.stem, slot))
+    }
+
+    /// Save the whole machine into the given slot.
+    pub fn save_state(&self, gb: &GameBoy, slot: u8) -> Result<()> {
+        let path = self.state_path(slot);
+        fs::create_dir_all(&self.dir).with_context(|| format!("Couldn't create save directory {}", self.dir.display()))?;
+        fs::write(&path, gb.save_state()).with_context(|| format!("Couldn't write save state {}", path.display()))
+    }
+
+    /// Restore the whole machine from the given slot.
+    pub fn load_state(&self, gb: &mut GameBoy, slot: u8) -> Result<()> {
+        let path = self.state_path(slot);
+        let data = fs::read(&path).with_context(|| format!("Couldn't read save state {}", path.display()))?;
+        gb.load_state(&data).with_context(|| format!("Couldn't load save state {}", path.display()))
+    }
+
+    /// Restore cartridge RAM from the battery save, if the cartridge has a battery and there is
+    /// one.
+    pub fn load_battery(&self, gb: &mut GameBoy) -> Result<()> {
+        let path = self.battery_path();
+        if !gb.cartridge().has_battery() || !path.exists() {
+            return Ok(());
+        }
+        let data = fs::read(&path).with_context(|| format!("Couldn't read save file {}", path.display()))?;
+        gb.cartridge_mut().load_ram(&data).with_context(|| format!("Couldn't load save file {}", path.display()))
+    }
+
+    /// Write cartridge RAM to the battery save, if the cartridge has a battery.
+    pub fn write_battery(&self, gb: &GameBoy) -> Result<()> {
+        if !gb.cartridge().has_battery() {
+            return Ok(());
+        }
+        let path = self.battery_path();
+        fs::create_dir_all(&self.dir).with_context(|| format!("Couldn't create save directory {}", self.dir.display()))?;
+        fs::write(&path, gb.cartridge().ram()).with_context(|| format!("Couldn't write save file {}", path.display()))
+    }
+}
@@ -0,0 +1,179 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use sdl2::pixels::Color;
+use thiserror::Error;
+use crate::components::dmg_ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::components::graphics_components::GBPalette;
+use crate::palette::PaletteSet;
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Couldn't decode the PNG: {0}")]
+    Decode(#[from] png::DecodingError),
+    #[error("Couldn't encode the PNG: {0}")]
+    Encode(#[from] png::EncodingError),
+    #[error("PNGs with {0:?} pixels aren't supported")]
+    UnsupportedFormat(png::ColorType),
+}
+
+/// The palette screenshots are taken in for comparing against reference images: evenly spaced
+/// greys from white to black, as used by dmg-acid2's reference image.
+pub fn reference_palette() -> GBPalette {
+    GBPalette::new(
+        Color::RGB(0xFF, 0xFF, 0xFF),
+        Color::RGB(0xAA, 0xAA, 0xAA),
+        Color::RGB(0x55, 0x55, 0x55),
+        Color::RGB(0x00, 0x00, 0x00),
+    )
+}
+
+/// # Image
+/// An 8-bit RGB image, such as a screenshot of the LCD, which can be saved as and loaded from PNG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// The pixels, row by row, three bytes each.
+    pub rgb: Vec<u8>,
+}
+
+/// How a screenshot differs from its reference image.
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    /// The number of pixels which differ.
+    pub differing: usize,
+    /// The screenshot faded out, with the pixels which differ in red.
+    pub image: Image,
+}
+
+impl Image {
+    /// Colour a framebuffer from the PPU with the given palettes.
+    pub fn from_framebuffer(framebuffer: &[u8], palettes: &PaletteSet) -> Self {
+        let rgb = framebuffer.iter()
+            .flat_map(|&pixel| {
+                let colour = palettes.colour(pixel);
+                [colour.r, colour.g, colour.b]
+            })
+            .collect();
+        Image { width: SCREEN_WIDTH as u32, height: SCREEN_HEIGHT as u32, rgb }
+    }
+
+    /// Enlarge the image by a whole number, with each pixel becoming a square of that size.
+    pub fn scaled(&self, factor: u32) -> Image {
+        if factor <= 1 {
+            return self.clone();
+        }
+        let factor = factor as usize;
+        let mut rgb = Vec::with_capacity(self.rgb.len() * factor * factor);
+        for row in self.rgb.chunks(self.width as usize * 3) {
+            let start = rgb.len();
+            for pixel in row.chunks(3) {
+                for _ in 0..factor {
+                    rgb.extend_from_slice(pixel);
+                }
+            }
+            let end = rgb.len();
+            for _ in 1..factor {
+                rgb.extend_from_within(start..end);
+            }
+        }
+        Image { width: self.width * factor as u32, height: self.height * factor as u32, rgb }
+    }
+
+    pub fn load_png<P: AsRef<Path>>(path: P) -> Result<Self, ImageError> {
+        let mut decoder = png::Decoder::new(BufReader::new(File::open(path)?));
+        // Palettes are expanded to colours, and 16-bit channels cut down to 8 bits.
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size().unwrap_or(0)];
+        let info = reader.next_frame(&mut buf)?;
+        let pixels = &buf[..info.buffer_size()];
+        let rgb = match info.color_type {
+            png::ColorType::Rgb => pixels.to_vec(),
+            png::ColorType::Rgba => pixels.chunks(4).flat_map(|p| [p[0], p[1], p[2]]).collect(),
+            png::ColorType::Grayscale => pixels.iter().flat_map(|&p| [p, p, p]).collect(),
+            png::ColorType::GrayscaleAlpha => pixels.chunks(2).flat_map(|p| [p[0], p[0], p[0]]).collect(),
+            other => return Err(ImageError::UnsupportedFormat(other)),
+        };
+        Ok(Image { width: info.width, height: info.height, rgb })
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), ImageError> {
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgb)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Compare against a reference image, pixel for pixel. Returns `None` if they are identical.
+    /// Images of different sizes differ at every pixel.
+    pub fn diff(&self, expected: &Image) -> Option<ImageDiff> {
+        let same_size = self.width == expected.width && self.height == expected.height;
+        if same_size && self.rgb == expected.rgb {
+            return None;
+        }
+        let mut differing = 0;
+        let mut rgb = Vec::with_capacity(self.rgb.len());
+        for (i, pixel) in self.rgb.chunks(3).enumerate() {
+            if !same_size || pixel != &expected.rgb[i * 3..i * 3 + 3] {
+                differing += 1;
+                rgb.extend_from_slice(&[0xFF, 0x00, 0x00]);
+            } else {
+                // Fade matching pixels towards white, so the differences stand out.
+                rgb.extend(pixel.iter().map(|&c| 0xC0 + c / 4));
+            }
+        }
+        let image = Image { width: self.width, height: self.height, rgb };
+        Some(ImageDiff { differing, image })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framebuffer_colours() {
+        let mut framebuffer = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        framebuffer[1] = 3;
+        // Only the shade matters, as every palette is the same.
+        framebuffer[2] = 2 | 0b100;
+        let image = Image::from_framebuffer(&framebuffer, &PaletteSet::uniform(reference_palette()));
+        assert_eq!(image.rgb.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        assert_eq!(&image.rgb[..9], &[0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x55, 0x55, 0x55]);
+    }
+
+    #[test]
+    fn png_round_trip_and_diff() {
+        let rgb = (0..4 * 2 * 3).map(|i| (i * 10) as u8).collect();
+        let image = Image { width: 4, height: 2, rgb };
+        let path = std::env::temp_dir().join(format!("patchwork_dmg_image_{}.png", std::process::id()));
+        image.save_png(&path).unwrap();
+        let loaded = Image::load_png(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, image);
+        assert!(image.diff(&loaded).is_none());
+
+        let mut changed = image.clone();
+        changed.rgb[3] ^= 1;
+        let diff = changed.diff(&image).unwrap();
+        assert_eq!(diff.differing, 1);
+        assert_eq!(&diff.image.rgb[3..6], &[0xFF, 0x00, 0x00]);
+        assert_eq!(diff.image.rgb[0], 0xC0);
+    }
+
+    #[test]
+    fn scaling() {
+        let image = Image { width: 2, height: 1, rgb: vec![1, 2, 3, 4, 5, 6] };
+        let scaled = image.scaled(2);
+        assert_eq!((scaled.width, scaled.height), (4, 2));
+        assert_eq!(scaled.rgb, [[1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6]; 2].concat());
+        assert_eq!(image.scaled(1), image);
+    }
+}
@@ -0,0 +1,217 @@
+use std::convert::TryInto;
+use anyhow::Result;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::WindowCanvas;
+use sdl2::VideoSubsystem;
+use patchwork_dmg::video::{GBPalette, PaletteSet, Tile};
+use patchwork_dmg::GameBoy;
+
+/// The tiles in tile data memory, $8000 to $97FF.
+const TILE_COUNT: usize = 384;
+const TILE_BYTES: usize = 16;
+const TILES_PER_ROW: usize = 16;
+const TILE_ROWS: usize = TILE_COUNT / TILES_PER_ROW;
+/// Each tile takes up its 8 pixels plus a line of the grid.
+const CELL_SIZE: usize = 9;
+const WIDTH: u32 = (TILES_PER_ROW * CELL_SIZE + 1) as u32;
+const HEIGHT: u32 = (TILE_ROWS * CELL_SIZE + 1) as u32;
+/// The tile viewer isn't made any larger than this, so that it fits on the screen.
+const MAX_SCALE: u32 = 3;
+
+const GRID_COLOUR: Color = Color::RGB(0x40, 0x40, 0x40);
+const HOVER_COLOUR: Color = Color::RGB(0xFF, 0x00, 0x00);
+
+/// How the colour numbers in the tiles are turned into colours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TilePalette {
+    /// Colour 0 is the lightest shade and 3 the darkest, whatever the game has set.
+    Shades,
+    /// Through the background palette register, as background and window tiles are drawn.
+    Bgp,
+    /// Through OBP0, as sprites using it are drawn.
+    Obp0,
+    /// Through OBP1, as sprites using it are drawn.
+    Obp1,
+}
+
+impl TilePalette {
+    fn next(self) -> Self {
+        match self {
+            TilePalette::Shades => TilePalette::Bgp,
+            TilePalette::Bgp => TilePalette::Obp0,
+            TilePalette::Obp0 => TilePalette::Obp1,
+            TilePalette::Obp1 => TilePalette::Shades,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TilePalette::Shades => "shades",
+            TilePalette::Bgp => "BGP",
+            TilePalette::Obp0 => "OBP0",
+            TilePalette::Obp1 => "OBP1",
+        }
+    }
+
+    /// The colours for each colour number, given the palette registers BGP, OBP0 and OBP1.
+    fn colours(self, registers: [u8; 3], palettes: &PaletteSet) -> GBPalette {
+        let (palette, register) = match self {
+            TilePalette::Shades => (palettes.bg, 0b11_10_01_00),
+            TilePalette::Bgp => (palettes.bg, registers[0]),
+            TilePalette::Obp0 => (palettes.obj0, registers[1]),
+            TilePalette::Obp1 => (palettes.obj1, registers[2]),
+        };
+        let shade = |colour: u8| palette.col_id(ux::u2::new((register >> (colour * 2)) & 0b11));
+        GBPalette::new(shade(0), shade(1), shade(2), shade(3))
+    }
+}
+
+/// The tile at a point in the viewer, in unscaled pixels, or `None` if it's on the grid lines.
+fn tile_at(x: i32, y: i32) -> Option<usize> {
+    if x < 0 || y < 0 {
+        return None;
+    }
+    let (x, y) = (x as usize, y as usize);
+    let (column, row) = (x / CELL_SIZE, y / CELL_SIZE);
+    let on_grid = x % CELL_SIZE == 0 || y % CELL_SIZE == 0;
+    if on_grid || column >= TILES_PER_ROW || row >= TILE_ROWS {
+        return None;
+    }
+    Some(row * TILES_PER_ROW + column)
+}
+
+/// Describe a tile by its number when the tile data is addressed from $8000, and also from $8800
+/// for those tiles which can be reached that way, along with where it is in memory.
+fn describe(index: usize) -> String {
+    let address = 0x8000 + index * TILE_BYTES;
+    match index {
+        0..=127 => format!("tile ${:02X} at ${:04X}", index, address),
+        128..=255 => format!("tile ${:02X} at ${:04X} (from $8000 or $8800)", index, address),
+        _ => format!("tile ${:02X} from $8800 at ${:04X}", index - 256, address),
+    }
+}
+
+/// # Tile viewer
+/// A window showing every tile in VRAM's tile data as a grid, sixteen tiles across, redrawn every
+/// frame so changes show up as the game makes them. Hovering over a tile shows its number and
+/// address in the title bar, and C cycles through the palettes the tiles are coloured with.
+pub struct TileViewer {
+    canvas: WindowCanvas,
+    palette: TilePalette,
+    hovered: Option<usize>,
+    title: String,
+}
+
+impl TileViewer {
+    pub fn new(video: &VideoSubsystem, scale: u32) -> Result<Self> {
+        let scale = scale.min(MAX_SCALE);
+        let window = video.window("Tiles", WIDTH * scale, HEIGHT * scale).build()?;
+        let mut canvas = window.into_canvas().build()?;
+        canvas.set_scale(scale as f32, scale as f32).map_err(anyhow::Error::msg)?;
+        Ok(TileViewer {
+            canvas,
+            palette: TilePalette::Shades,
+            hovered: None,
+            title: String::new(),
+        })
+    }
+
+    pub fn window_id(&self) -> u32 {
+        self.canvas.window().id()
+    }
+
+    /// Colour the tiles with the next palette.
+    pub fn next_palette(&mut self) {
+        self.palette = self.palette.next();
+    }
+
+    /// Follow the mouse over the viewer's window.
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::MouseMotion { window_id, x, y, .. } if window_id == self.window_id() => {
+                let (scale, _) = self.canvas.scale();
+                self.hovered = tile_at((x as f32 / scale) as i32, (y as f32 / scale) as i32);
+            }
+            Event::Window { window_id, win_event: WindowEvent::Leave, .. } if window_id == self.window_id() => {
+                self.hovered = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Draw the tiles as they are now, in the colours of `palettes`.
+    pub fn draw(&mut self, gb: &GameBoy, palettes: &PaletteSet) -> Result<()> {
+        let palette = self.palette.colours(gb.palette_registers(), palettes);
+        self.canvas.set_draw_color(GRID_COLOUR);
+        self.canvas.clear();
+        for (index, bytes) in gb.vram()[..TILE_COUNT * TILE_BYTES].chunks(TILE_BYTES).enumerate() {
+            let tile = Tile::new(&palette, bytes.try_into().expect("tiles are 16 bytes"));
+            tile.paint(cell_origin(index), &mut self.canvas);
+        }
+        if let Some(index) = self.hovered {
+            let origin = cell_origin(index);
+            self.canvas.set_draw_color(HOVER_COLOUR);
+            self.canvas.draw_rect(Rect::new(origin.x() - 1, origin.y() - 1, 10, 10)).map_err(anyhow::Error::msg)?;
+        }
+        self.canvas.present();
+
+        let title = match self.hovered {
+            Some(index) => format!("Tiles ({}) - {}", self.palette.name(), describe(index)),
+            None => format!("Tiles ({})", self.palette.name()),
+        };
+        if title != self.title {
+            self.canvas.window_mut().set_title(&title)?;
+            self.title = title;
+        }
+        Ok(())
+    }
+}
+
+/// Where the top left pixel of a tile goes.
+fn cell_origin(index: usize) -> Point {
+    let column = index % TILES_PER_ROW;
+    let row = index / TILES_PER_ROW;
+    Point::new((column * CELL_SIZE + 1) as i32, (row * CELL_SIZE + 1) as i32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hovering() {
+        assert_eq!(tile_at(0, 5), None);
+        assert_eq!(tile_at(1, 1), Some(0));
+        assert_eq!(tile_at(8, 8), Some(0));
+        assert_eq!(tile_at(9, 1), None);
+        assert_eq!(tile_at(1 + CELL_SIZE as i32, 1 + CELL_SIZE as i32), Some(TILES_PER_ROW + 1));
+        assert_eq!(tile_at(WIDTH as i32 - 2, HEIGHT as i32 - 2), Some(TILE_COUNT - 1));
+        assert_eq!(tile_at(WIDTH as i32 + 5, 1), None);
+        assert_eq!(tile_at(-1, 1), None);
+        for index in [0, 17, TILE_COUNT - 1] {
+            let origin = cell_origin(index);
+            assert_eq!(tile_at(origin.x(), origin.y()), Some(index));
+        }
+    }
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(describe(0x01), "tile $01 at $8010");
+        assert_eq!(describe(0x80), "tile $80 at $8800 (from $8000 or $8800)");
+        assert_eq!(describe(0x17F), "tile $7F from $8800 at $97F0");
+    }
+
+    #[test]
+    fn palettes() {
+        let grey = |s: u8| Color::RGB(s, s, s);
+        let palettes = PaletteSet::uniform(GBPalette::new(grey(0xFF), grey(0xAA), grey(0x55), grey(0x00)));
+        // Colours 0 and 3 swapped.
+        let registers = [0b00_10_01_11, 0, 0];
+        assert_eq!(TilePalette::Shades.colours(registers, &palettes), palettes.bg);
+        let bgp = TilePalette::Bgp.colours(registers, &palettes);
+        assert_eq!((bgp.col1, bgp.col4), (grey(0x00), grey(0xFF)));
+        assert_eq!(TilePalette::Obp0.colours(registers, &palettes).col3, grey(0xFF));
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use anyhow::{Context, Result};
+use patchwork_dmg::debug::{disassemble, Tracer};
+use patchwork_dmg::GameBoy;
+
+/// Where the tracer writes to, so that its lines can be picked up as the emulator runs.
+#[derive(Clone, Default)]
+struct TraceBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl TraceBuffer {
+    /// Take every complete line written so far, leaving any partial line for next time.
+    fn take_lines(&self) -> Vec<String> {
+        let mut buf = self.0.lock().unwrap();
+        let end = match buf.iter().rposition(|&b| b == b'\n') {
+            Some(end) => end + 1,
+            None => return Vec::new(),
+        };
+        let text = String::from_utf8_lossy(&buf[..end]).into_owned();
+        buf.drain(..end);
+        text.lines().map(str::to_string).collect()
+    }
+}
+
+impl Write for TraceBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A trace line, numbered from 1 as in the log files.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub number: u64,
+    pub text: String,
+}
+
+/// Where the trace first went wrong.
+#[derive(Debug)]
+pub struct Mismatch {
+    /// The lines leading up to the mismatch, which both traces agree on.
+    pub before: Vec<Line>,
+    pub expected: Line,
+    pub actual: Line,
+    /// The lines following the mismatch in each trace.
+    pub expected_after: Vec<Line>,
+    pub actual_after: Vec<Line>,
+}
+
+#[derive(Debug)]
+pub enum Outcome {
+    /// Every line of the reference matched.
+    Matched(u64),
+    Mismatch(Mismatch),
+    /// The emulator stopped (by locking up or running out of cycles) before the reference ended.
+    Stopped { matched: u64, next: Line },
+}
+
+/// Run the Game Boy, comparing its trace line by line against the reference until they differ
+/// or the reference ends. `context` lines are kept either side of a mismatch, and at most
+/// `max_cycles` T-cycles are run. LY is stubbed to 0x90, as it is in Gameboy Doctor's logs.
+pub fn compare<R: BufRead>(gb: &mut GameBoy, reference: R, context: usize, max_cycles: u64) -> Result<Outcome> {
+    let mut reference = reference.lines().enumerate()
+        .map(|(i, line)| line.map(|text| Line { number: i as u64 + 1, text }));
+    let buffer = TraceBuffer::default();
+    gb.set_stub_ly(true);
+    gb.set_tracer(Tracer::new(buffer.clone()));
+    let mut emulator = Emulator { gb, buffer, pending: VecDeque::new(), cycles: 0, max_cycles };
+    let mut before = VecDeque::with_capacity(context + 1);
+    let mut matched = 0;
+
+    loop {
+        let expected = match reference.next() {
+            Some(line) => line.context("Couldn't read the reference log")?,
+            None => return Ok(Outcome::Matched(matched)),
+        };
+        let actual = match emulator.next_line() {
+            Some(text) => Line { number: expected.number, text },
+            None => return Ok(Outcome::Stopped { matched, next: expected }),
+        };
+        if actual.text != expected.text {
+            let expected_after = reference.by_ref().take(context).collect::<io::Result<Vec<_>>>()
+                .context("Couldn't read the reference log")?;
+            let actual_after = (1..=context as u64)
+                .map_while(|i| emulator.next_line().map(|text| Line { number: expected.number + i, text }))
+                .collect();
+            return Ok(Outcome::Mismatch(Mismatch {
+                before: before.into(),
+                expected,
+                actual,
+                expected_after,
+                actual_after,
+            }));
+        }
+        matched += 1;
+        if before.len() == context {
+            before.pop_front();
+        }
+        if context > 0 {
+            before.push_back(actual);
+        }
+    }
+}
+
+/// The emulator's side of the comparison, run just far enough to produce each line.
+struct Emulator<'a> {
+    gb: &'a mut GameBoy,
+    buffer: TraceBuffer,
+    pending: VecDeque<String>,
+    cycles: u64,
+    max_cycles: u64,
+}
+
+impl Emulator<'_> {
+    fn next_line(&mut self) -> Option<String> {
+        while self.pending.is_empty() {
+            if self.cycles >= self.max_cycles || self.gb.cpu().is_locked() {
+                // Whatever the tracer still has buffered is all there will be.
+                drop(self.gb.take_tracer());
+                self.pending.extend(self.buffer.take_lines());
+                return self.pending.pop_front();
+            }
+            self.cycles += self.gb.step() as u64;
+            self.pending.extend(self.buffer.take_lines());
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// The names of the fields which differ between two trace lines, such as `F` or `PCMEM`. Fields
+/// missing from either line count as differing.
+pub fn differing_fields<'a>(expected: &'a str, actual: &str) -> Vec<&'a str> {
+    let actual: Vec<(&str, &str)> = actual.split_whitespace().filter_map(|field| field.split_once(':')).collect();
+    expected.split_whitespace()
+        .filter_map(|field| field.split_once(':'))
+        .filter(|(name, value)| !actual.iter().any(|(n, v)| n == name && v == value))
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// Disassemble the instruction a trace line was logged at, from its PC and PCMEM fields.
+fn disassemble_line(line: &str) -> Option<String> {
+    let field = |name: &str| line.split_whitespace().find_map(|field| field.strip_prefix(name));
+    let pc = u16::from_str_radix(field("PC:")?, 16).ok()?;
+    let mem = field("PCMEM:")?.split(',')
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    let read = |addr: u16| mem.get(addr.wrapping_sub(pc) as usize).copied().unwrap_or(0);
+    Some(disassemble(read, pc).text().to_string())
+}
+
+fn print_line(marker: &str, line: &Line) {
+    let dis = disassemble_line(&line.text).unwrap_or_default();
+    println!("{} {:>9}  {}  {}", marker, line.number, line.text, dis);
+}
+
+/// Print how the comparison went.
+pub fn report(outcome: &Outcome) {
+    match outcome {
+        Outcome::Matched(lines) => println!("All {} lines of the reference matched", lines),
+        Outcome::Stopped { matched, next } => {
+            println!("The emulator stopped after {} matching lines, but the reference goes on:", matched);
+            print_line(" ", next);
+        }
+        Outcome::Mismatch(mismatch) => {
+            println!("Mismatch at line {}:", mismatch.expected.number);
+            for line in &mismatch.before {
+                print_line(" ", line);
+            }
+            print_line("-", &mismatch.expected);
+            print_line("+", &mismatch.actual);
+            let fields = differing_fields(&mismatch.expected.text, &mismatch.actual.text);
+            println!("Differing fields: {}", fields.join(", "));
+            println!("Afterwards, the reference has:");
+            for line in &mismatch.expected_after {
+                print_line("-", line);
+            }
+            println!("and the emulator has:");
+            for line in &mismatch.actual_after {
+                print_line("+", line);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use patchwork_dmg::Cartridge;
+
+    /// INC A; JR -3, the first few lines of whose trace are in `REFERENCE`.
+    fn gameboy() -> GameBoy {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0103].copy_from_slice(&[0x3C, 0x18, 0xFD]);
+        GameBoy::new(Cartridge::from_bytes(rom).unwrap())
+    }
+
+    const REFERENCE: &str = "\
+A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:3C,18,FD,00
+A:02 F:10 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:18,FD,00,00
+A:02 F:10 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:3C,18,FD,00
+A:03 F:10 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:18,FD,00,00
+";
+
+    #[test]
+    fn matching() {
+        let outcome = compare(&mut gameboy(), REFERENCE.as_bytes(), 2, u64::MAX).unwrap();
+        assert!(matches!(outcome, Outcome::Matched(4)));
+    }
+
+    #[test]
+    fn mismatch() {
+        let reference = REFERENCE.replace("A:03 F:10", "A:03 F:90");
+        let outcome = compare(&mut gameboy(), reference.as_bytes(), 2, u64::MAX).unwrap();
+        let mismatch = match outcome {
+            Outcome::Mismatch(mismatch) => mismatch,
+            other => panic!("Expected a mismatch, got {:?}", other),
+        };
+        assert_eq!(mismatch.expected.number, 4);
+        assert_eq!(mismatch.before.iter().map(|line| line.number).collect::<Vec<_>>(), [2, 3]);
+        assert!(mismatch.expected_after.is_empty());
+        assert_eq!(mismatch.actual_after.len(), 2);
+        assert_eq!(differing_fields(&mismatch.expected.text, &mismatch.actual.text), ["F"]);
+    }
+
+    #[test]
+    fn stopped() {
+        let outcome = compare(&mut gameboy(), REFERENCE.as_bytes(), 2, 8).unwrap();
+        assert!(matches!(outcome, Outcome::Stopped { matched: 2, .. }));
+    }
+
+    #[test]
+    fn ly_stubbed() {
+        // LDH A,(0x44); JR -4
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0104].copy_from_slice(&[0xF0, 0x44, 0x18, 0xFC]);
+        let mut gb = GameBoy::new(Cartridge::from_bytes(rom).unwrap());
+        let reference = "\
+A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:F0,44,18,FC
+A:90 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102 PCMEM:18,FC,00,00
+";
+        let outcome = compare(&mut gb, reference.as_bytes(), 0, u64::MAX).unwrap();
+        assert!(matches!(outcome, Outcome::Matched(2)), "{:?}", outcome);
+    }
+
+    #[test]
+    fn disassembly() {
+        assert_eq!(disassemble_line(REFERENCE.lines().nth(1).unwrap()).unwrap(), "JR $0100");
+        assert_eq!(disassemble_line("nonsense"), None);
+    }
+}
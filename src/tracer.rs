@@ -0,0 +1,163 @@
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use crate::components::register::{Reg8, RegisterFile};
+
+/// # Tracer
+/// Logs the state of the CPU before each instruction, one line per instruction, in the format
+/// used by Gameboy Doctor:
+///
+/// `A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02`
+///
+/// so that logs can be compared line by line against other emulators. Interrupt dispatches and
+/// time spent halted aren't instructions, so they don't get lines of their own.
+///
+/// Tracing is off unless a tracer is given to the CPU, in which case only a single check is made
+/// per instruction.
+pub struct Tracer {
+    out: BufWriter<Box<dyn Write + Send>>,
+    /// Only instructions at these addresses are logged.
+    range: RangeInclusive<u16>,
+    /// The number of instructions in `range` to pass over before logging starts.
+    skip: u64,
+    /// The most lines to write.
+    limit: Option<u64>,
+    /// Instructions in `range` seen so far, including skipped ones.
+    seen: u64,
+    written: u64,
+    /// The first write to fail. Tracing stops once one has.
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    /// Create a tracer which logs every instruction to `out`. Output is buffered, and flushed when
+    /// the tracer is dropped.
+    pub fn new<W: Write + Send + 'static>(out: W) -> Self {
+        Tracer {
+            out: BufWriter::new(Box::new(out)),
+            range: 0x0000..=0xFFFF,
+            skip: 0,
+            limit: None,
+            seen: 0,
+            written: 0,
+            error: None,
+        }
+    }
+
+    /// Only log instructions whose address is in the given range.
+    pub fn address_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.range = range;
+        self
+    }
+
+    /// Pass over the given number of instructions (within the address range) before logging.
+    pub fn skip(mut self, count: u64) -> Self {
+        self.skip = count;
+        self
+    }
+
+    /// Stop logging once the given number of lines have been written.
+    pub fn limit(mut self, count: u64) -> Self {
+        self.limit = Some(count);
+        self
+    }
+
+    /// The number of lines written so far.
+    pub fn lines_written(&self) -> u64 {
+        self.written
+    }
+
+    /// Returns true once the tracer won't write any more: the limit has been reached, or writing
+    /// has failed.
+    pub fn is_finished(&self) -> bool {
+        self.error.is_some() || self.limit.is_some_and(|limit| self.written >= limit)
+    }
+
+    /// The error which stopped tracing, if writing failed.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Log the instruction about to run at the PC, if it should be. `read` reads memory without
+    /// any time passing.
+    pub(crate) fn trace<F: Fn(u16) -> u8>(&mut self, regs: &RegisterFile, read: F) {
+        let pc = regs.pc;
+        if !self.range.contains(&pc) || self.is_finished() {
+            return;
+        }
+        self.seen += 1;
+        if self.seen <= self.skip {
+            return;
+        }
+        let mem = [0, 1, 2, 3].map(|i| read(pc.wrapping_add(i)));
+        let result = writeln!(
+            self.out,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            regs.a, regs.get8(Reg8::F), regs.get8(Reg8::B), regs.get8(Reg8::C), regs.get8(Reg8::D),
+            regs.get8(Reg8::E), regs.get8(Reg8::H), regs.get8(Reg8::L), regs.sp, pc,
+            mem[0], mem[1], mem[2], mem[3],
+        );
+        match result {
+            Ok(()) => self.written += 1,
+            Err(e) => self.error = Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use super::*;
+    use crate::components::cartridge::Cartridge;
+    use crate::gameboy::GameBoy;
+
+    /// Somewhere to write the trace which the test can still read afterwards.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run INC A; JR -3 with the given tracer, and return what it wrote.
+    fn trace(tracer: impl FnOnce(SharedBuffer) -> Tracer) -> String {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0100..0x0103].copy_from_slice(&[0x3C, 0x18, 0xFD]);
+        let mut gb = GameBoy::new(Cartridge::from_bytes(rom).unwrap());
+        let buffer = SharedBuffer::default();
+        gb.set_tracer(tracer(buffer.clone()));
+        for _ in 0..20 {
+            gb.step();
+        }
+        assert!(gb.take_tracer().unwrap().is_finished());
+        let out = buffer.0.lock().unwrap();
+        String::from_utf8(out.clone()).unwrap()
+    }
+
+    #[test]
+    fn doctor_format() {
+        let log = trace(|out| Tracer::new(out).limit(3));
+        assert_eq!(log, "\
+A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:3C,18,FD,00
+A:02 F:10 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:18,FD,00,00
+A:02 F:10 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:3C,18,FD,00
+");
+    }
+
+    #[test]
+    fn range_and_skip() {
+        let log = trace(|out| Tracer::new(out).address_range(0x0101..=0x0101).skip(1).limit(2));
+        let pcs: Vec<&str> = log.lines().map(|line| &line[..4]).collect();
+        assert_eq!(pcs, ["A:03", "A:04"]);
+        assert!(log.lines().all(|line| line.contains("PC:0101")));
+    }
+}
@@ -0,0 +1,84 @@
+//! Runs every test ROM found under `test-roms/` at the root of the repository, such as Blargg's
+//! cpu_instrs and instr_timing. The ROMs aren't distributed with the emulator, so if the directory
+//! doesn't exist the test passes without doing anything.
+//!
+//! A ROM with a PNG of the same name next to it, such as `dmg-acid2.gb` and `dmg-acid2.png`, is
+//! judged by comparing the screen against that image. When they differ, the screenshot and an
+//! image showing the differences are saved in Cargo's temporary directory for tests.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use patchwork_dmg::testing::{TestResult, TestRunner, DEFAULT_SCREENSHOT_FRAMES};
+use patchwork_dmg::video::Image;
+use patchwork_dmg::Cartridge;
+
+/// Every `.gb` file under `dir`, in order so that the report is stable.
+fn find_roms(dir: &Path) -> Vec<PathBuf> {
+    let mut roms = Vec::new();
+    let mut entries: Vec<PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+        Err(_) => return roms,
+    };
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            roms.extend(find_roms(&path));
+        } else if path.extension().is_some_and(|ext| ext == "gb") {
+            roms.push(path);
+        }
+    }
+    roms
+}
+
+/// Compare the screen against the reference image once the ROM has run for a while.
+fn screenshot_test(runner: &mut TestRunner, name: &str, reference: &Path) -> TestResult {
+    let reference = match Image::load_png(reference) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("{}: couldn't load {}: {}", name, reference.display(), e);
+            return TestResult::Failed;
+        }
+    };
+    let outcome = runner.run_screenshot(DEFAULT_SCREENSHOT_FRAMES, &reference);
+    if let Some(diff) = &outcome.diff {
+        let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join(name.replace(['/', '\\'], "_"));
+        let _ = outcome.screenshot.save_png(out.with_extension("png"));
+        let _ = diff.image.save_png(out.with_extension("diff.png"));
+        eprintln!("{}: {} pixels differ; see {}", name, diff.differing, out.with_extension("diff.png").display());
+    }
+    outcome.result
+}
+
+#[test]
+fn test_roms() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-roms");
+    let roms = find_roms(&dir);
+    if roms.is_empty() {
+        eprintln!("No test ROMs found in {}, skipping", dir.display());
+        return;
+    }
+
+    let mut failures = Vec::new();
+    for rom in &roms {
+        let name = rom.strip_prefix(&dir).unwrap_or(rom).display().to_string();
+        let mut runner = match Cartridge::from_file(rom) {
+            Ok(cartridge) => TestRunner::new(cartridge),
+            Err(e) => {
+                eprintln!("{}: couldn't load: {}", name, e);
+                failures.push(name);
+                continue;
+            }
+        };
+        let reference = rom.with_extension("png");
+        let result = if reference.exists() {
+            screenshot_test(&mut runner, &name, &reference)
+        } else {
+            runner.run().result
+        };
+        eprintln!("{}: {:?}", name, result);
+        if result != TestResult::Passed {
+            failures.push(name);
+        }
+    }
+    assert!(failures.is_empty(), "{} of {} test ROMs failed: {}", failures.len(), roms.len(), failures.join(", "));
+}